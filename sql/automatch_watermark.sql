@@ -0,0 +1,7 @@
+-- Highest entry ID an automatch action has seen for a catalog; `REPLACE INTO` relies on the primary key
+CREATE TABLE IF NOT EXISTS `automatch_watermark` (
+    `catalog_id` INT UNSIGNED NOT NULL,
+    `action` VARCHAR(64) NOT NULL,
+    `max_entry_id` INT UNSIGNED NOT NULL DEFAULT 0,
+    PRIMARY KEY (`catalog_id`,`action`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4;
@@ -0,0 +1,13 @@
+-- Number of entries found by each run or test fetch (`is_test`) of an autoscraper
+CREATE TABLE IF NOT EXISTS `autoscrape_run` (
+    `id` INT UNSIGNED NOT NULL AUTO_INCREMENT,
+    `autoscrape_id` INT UNSIGNED NOT NULL,
+    `entries_found` INT UNSIGNED NOT NULL DEFAULT 0,
+    `is_test` TINYINT UNSIGNED NOT NULL DEFAULT 0,
+    `timestamp` VARCHAR(14) NOT NULL DEFAULT '',
+    PRIMARY KEY (`id`),
+    KEY `autoscrape_id` (`autoscrape_id`,`is_test`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4;
+
+-- `BROKEN` is set by `flag_stale_autoscrapers`; keep any other values of the existing ENUM in this list
+ALTER TABLE `autoscrape` MODIFY COLUMN `status` ENUM('OK','RUNNING','IMPORT','FAILED','BROKEN') NOT NULL DEFAULT 'OK';
@@ -0,0 +1,11 @@
+-- Statements added to every item created from a catalog (see `CatalogDefaultStatement`);
+-- with `as_reference`, the statement is added to the reference of every statement instead
+CREATE TABLE IF NOT EXISTS `catalog_default_statement` (
+    `id` INT UNSIGNED NOT NULL AUTO_INCREMENT,
+    `catalog_id` INT UNSIGNED NOT NULL,
+    `property` INT UNSIGNED NOT NULL,
+    `value` VARCHAR(255) NOT NULL,
+    `as_reference` TINYINT UNSIGNED NOT NULL DEFAULT 0,
+    PRIMARY KEY (`id`),
+    KEY `catalog_id` (`catalog_id`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4;
@@ -0,0 +1,4 @@
+-- Catalog names are unique, so concurrent `create_catalog` calls for the same name can not both insert.
+-- Rename or merge existing duplicates first; they are listed by
+-- SELECT `name`,GROUP_CONCAT(`id`) FROM `catalog` GROUP BY `name` HAVING COUNT(*)>1;
+ALTER TABLE `catalog` ADD UNIQUE KEY `name_unique` (`name`);
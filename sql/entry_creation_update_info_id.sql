@@ -0,0 +1,4 @@
+-- The `update_info` ID of the import that created an entry; NULL for entries from scrapers and other sources
+ALTER TABLE `entry_creation`
+    ADD COLUMN `update_info_id` INT UNSIGNED NULL DEFAULT NULL,
+    ADD KEY `update_info_id` (`update_info_id`);
@@ -0,0 +1,3 @@
+-- `DATE_PRECISION_MISMATCH`: the entry has day-precision person dates that the matched item does not have;
+-- apply after `issues_type_removed_from_source.sql`
+ALTER TABLE `issues` MODIFY COLUMN `type` ENUM('WD_DUPLICATE','MISMATCH','ITEM_DELETED','MISMATCH_DATES','MULTIPLE','REMOVED_FROM_SOURCE','DATE_PRECISION_MISMATCH') NOT NULL;
@@ -0,0 +1,2 @@
+-- `REMOVED_FROM_SOURCE`: the ext_id of an entry is no longer in the source of an incremental catalog update
+ALTER TABLE `issues` MODIFY COLUMN `type` ENUM('WD_DUPLICATE','MISMATCH','ITEM_DELETED','MISMATCH_DATES','MULTIPLE','REMOVED_FROM_SOURCE') NOT NULL;
@@ -0,0 +1,4 @@
+-- Category (see `JobErrorCategory`) and full error text of the last failure of a job
+ALTER TABLE `jobs`
+    ADD COLUMN `error_category` VARCHAR(16) NULL DEFAULT NULL,
+    ADD COLUMN `error_details` MEDIUMTEXT NULL DEFAULT NULL;
@@ -0,0 +1,2 @@
+-- Number of consecutive failures of a job; it is BLOCKED after `max_job_failures` failures in a row
+ALTER TABLE `jobs` ADD COLUMN `failures` INT UNSIGNED NOT NULL DEFAULT 0;
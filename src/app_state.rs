@@ -1,24 +1,29 @@
+use crate::catalog::Catalog;
 use crate::job::Job;
 use crate::job_status::JobStatus;
 use crate::mysql_misc::MySQLMisc;
+use crate::reference_fixer::REFERENCE_FIXER_REPEAT_SEC;
 use crate::storage::Storage;
 use crate::storage_mysql::StorageMySQL;
 use crate::task_size::TaskSize;
 use crate::wdrc::WDRC;
 use crate::wikidata::Wikidata;
 use anyhow::{anyhow, Result};
-use chrono::Local;
+use chrono::{DateTime, Local, NaiveDateTime};
 use dashmap::DashMap;
 use lazy_static::lazy_static;
 use log::{error, info};
 use regex::Regex;
+use serde::Serialize;
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
 use std::fs::File;
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use std::{thread, time};
 use sysinfo::System;
+use tokio::sync::RwLock;
 use tokio::time::sleep;
 use wikimisc::timestamp::TimeStamp;
 
@@ -44,6 +49,234 @@ lazy_static! {
     static ref RE_ITEM2NUMERIC: Regex = Regex::new(r"(-{0,1}\d+)").expect("Regex failure");
 }
 
+/// Catalog key-value pair: minimum delay between two scraper requests to the same host, in milliseconds
+pub const KV_SCRAPE_DELAY_MS: &str = "scrape_delay_ms";
+/// Catalog key-value pair: maximum number of concurrent scraper requests to the same host
+pub const KV_SCRAPE_CONCURRENCY: &str = "scrape_concurrency";
+const DEFAULT_SCRAPE_CONCURRENCY: usize = 4;
+/// Number of times a request is repeated after a 429 response
+const HOST_THROTTLE_MAX_RETRIES: usize = 3;
+/// Wait after a 429 response without a usable `Retry-After` header
+const HOST_THROTTLE_DEFAULT_RETRY_AFTER_SEC: u64 = 10;
+const HOST_THROTTLE_MAX_RETRY_AFTER_SEC: u64 = 300;
+/// Number of hosts `HostThrottle` keeps before it forgets idle ones
+const HOST_THROTTLE_MAX_HOSTS: usize = 1000;
+/// How long the property => catalogs map is used before it is loaded again
+const PROP2CATALOGS_TTL: time::Duration = time::Duration::from_secs(600);
+/// `kv` key for the (local) time the forever loop was last started, in `FOREVER_LOOP_START_FORMAT`
+const KV_FOREVER_LOOP_START: &str = "forever_loop_start";
+const FOREVER_LOOP_START_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// When the forever loop considers itself stuck, and exits so the supervisor restarts it.
+/// Set via the `seppuku` object in the config JSON; missing or invalid values use the defaults.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SeppukuConfig {
+    /// `check_every_minutes`
+    pub check_every_minutes: u64,
+    /// `max_idle_minutes`: jobs are considered stuck without activity within this window
+    pub max_idle_minutes: i64,
+    /// `min_running_jobs`: only exit if at least this many jobs are RUNNING
+    pub min_running_jobs: usize,
+}
+
+impl Default for SeppukuConfig {
+    fn default() -> Self {
+        Self {
+            check_every_minutes: 5,
+            max_idle_minutes: 20,
+            min_running_jobs: 1,
+        }
+    }
+}
+
+impl SeppukuConfig {
+    pub fn from_config(config: &Value) -> Self {
+        let mut ret = Self::default();
+        if let Some(minutes) = config["check_every_minutes"].as_u64().filter(|m| *m > 0) {
+            ret.check_every_minutes = minutes;
+        }
+        if let Some(minutes) = config["max_idle_minutes"].as_i64().filter(|m| *m > 0) {
+            ret.max_idle_minutes = minutes;
+        }
+        if let Some(jobs) = config["min_running_jobs"].as_u64().filter(|j| *j > 0) {
+            ret.min_running_jobs = jobs as usize;
+        }
+        ret
+    }
+
+    /// `running` jobs in total, of which `running_recent` had activity within `max_idle_minutes`
+    pub const fn is_stuck(&self, running: usize, running_recent: usize) -> bool {
+        running >= self.min_running_jobs && running_recent == 0
+    }
+}
+
+/// Politeness limits for requests to a single external host
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HostLimits {
+    pub delay: time::Duration,
+    pub concurrency: usize,
+}
+
+impl Default for HostLimits {
+    fn default() -> Self {
+        Self {
+            delay: time::Duration::ZERO,
+            concurrency: DEFAULT_SCRAPE_CONCURRENCY,
+        }
+    }
+}
+
+impl HostLimits {
+    /// Reads `scrape_delay_ms` and `scrape_concurrency` from catalog key-value pairs; missing or invalid values use the defaults.
+    pub fn from_key_value_pairs(kv: &HashMap<String, String>) -> Self {
+        let mut ret = Self::default();
+        if let Some(ms) = kv
+            .get(KV_SCRAPE_DELAY_MS)
+            .and_then(|v| v.trim().parse().ok())
+        {
+            ret.delay = time::Duration::from_millis(ms);
+        }
+        if let Some(concurrency) = kv
+            .get(KV_SCRAPE_CONCURRENCY)
+            .and_then(|v| v.trim().parse::<usize>().ok())
+            .filter(|c| *c > 0)
+        {
+            ret.concurrency = concurrency;
+        }
+        ret
+    }
+}
+
+#[derive(Debug)]
+struct HostSlot {
+    semaphore: tokio::sync::Semaphore,
+    next_request: tokio::sync::Mutex<tokio::time::Instant>,
+}
+
+impl HostSlot {
+    fn new(concurrency: usize) -> Self {
+        Self {
+            semaphore: tokio::sync::Semaphore::new(concurrency),
+            next_request: tokio::sync::Mutex::new(tokio::time::Instant::now()),
+        }
+    }
+
+    /// Waits until the next request to this host is allowed, and reserves the following slot `delay` later
+    async fn wait_turn(&self, delay: time::Duration) {
+        let mut next_request = self.next_request.lock().await;
+        tokio::time::sleep_until(*next_request).await;
+        *next_request = tokio::time::Instant::now() + delay;
+    }
+
+    /// No request holds or waits for this slot, and it imposes no wait on the next one
+    fn is_idle(self: &Arc<Self>) -> bool {
+        Arc::strong_count(self) == 1
+            && self
+                .next_request
+                .try_lock()
+                .is_ok_and(|next_request| *next_request <= tokio::time::Instant::now())
+    }
+
+    /// Blocks all requests to this host for `wait`, eg after a 429 response
+    async fn postpone(&self, wait: time::Duration) {
+        let mut next_request = self.next_request.lock().await;
+        let until = tokio::time::Instant::now() + wait;
+        if until > *next_request {
+            *next_request = until;
+        }
+    }
+}
+
+/// Per-host rate limiter for external HTTP requests, shared by all scrapers through `AppState::host_throttle`.
+/// The concurrency of a host is fixed by the first request to it; the delay applies per request.
+#[derive(Debug, Default)]
+pub struct HostThrottle {
+    hosts: DashMap<String, Arc<HostSlot>>,
+    client: reqwest::Client,
+}
+
+impl HostThrottle {
+    /// GETs a URL within the limits of its host. 429 responses are retried after their `Retry-After` time,
+    /// up to `HOST_THROTTLE_MAX_RETRIES` times; the last response is returned as-is.
+    pub async fn get(
+        &self,
+        client: &reqwest::Client,
+        url: &str,
+        limits: HostLimits,
+    ) -> Result<reqwest::Response> {
+        let slot = self.slot(url, limits);
+        let _permit = slot.semaphore.acquire().await?;
+        let mut retries = 0;
+        loop {
+            slot.wait_turn(limits.delay).await;
+            let response = client.get(url).send().await?;
+            if response.status() != reqwest::StatusCode::TOO_MANY_REQUESTS
+                || retries >= HOST_THROTTLE_MAX_RETRIES
+            {
+                return Ok(response);
+            }
+            retries += 1;
+            let wait = response
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(Self::parse_retry_after)
+                .unwrap_or(time::Duration::from_secs(
+                    HOST_THROTTLE_DEFAULT_RETRY_AFTER_SEC,
+                ))
+                .min(time::Duration::from_secs(HOST_THROTTLE_MAX_RETRY_AFTER_SEC));
+            info!("HostThrottle: 429 for {url}, retrying in {wait:?}");
+            slot.postpone(wait).await;
+        }
+    }
+
+    /// GETs a URL within the limits of its host, and returns the response text.
+    /// Fails on a non-2xx response, including a 429 that is still there after all retries.
+    pub async fn get_text(
+        &self,
+        client: &reqwest::Client,
+        url: &str,
+        limits: HostLimits,
+    ) -> Result<String> {
+        Ok(self
+            .get(client, url, limits)
+            .await?
+            .error_for_status()?
+            .text()
+            .await?)
+    }
+
+    /// A plain HTTP client, shared by callers that do not need their own settings
+    pub fn client(&self) -> &reqwest::Client {
+        &self.client
+    }
+
+    fn slot(&self, url: &str, limits: HostLimits) -> Arc<HostSlot> {
+        let host = reqwest::Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(|host| host.to_lowercase()))
+            .unwrap_or_default();
+        if self.hosts.len() >= HOST_THROTTLE_MAX_HOSTS && !self.hosts.contains_key(&host) {
+            self.hosts.retain(|_, slot| !slot.is_idle());
+        }
+        self.hosts
+            .entry(host)
+            .or_insert_with(|| Arc::new(HostSlot::new(limits.concurrency)))
+            .clone()
+    }
+
+    /// Parses a `Retry-After` header value, either in seconds or as an HTTP date
+    fn parse_retry_after(value: &str) -> Option<time::Duration> {
+        let value = value.trim();
+        if let Ok(seconds) = value.parse::<u64>() {
+            return Some(time::Duration::from_secs(seconds));
+        }
+        let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+        let wait = date.with_timezone(&chrono::Utc) - chrono::Utc::now();
+        Some(wait.to_std().unwrap_or(time::Duration::ZERO))
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct AppState {
     wikidata: Wikidata,
@@ -52,9 +285,68 @@ pub struct AppState {
     import_file_path: Arc<String>,
     task_specific_usize: Arc<HashMap<String, usize>>,
     max_concurrent_jobs: usize,
+    max_job_failures: usize,
+    seppuku: SeppukuConfig,
+    maintenance_excluded_catalogs: Arc<Vec<usize>>,
+    catalog_cache: Arc<DashMap<usize, (Instant, Catalog)>>,
+    /// Load time, and Wikidata property => active catalogs using it (without qualifier)
+    prop2catalogs: Arc<RwLock<(Option<Instant>, HashMap<usize, Vec<usize>>)>>,
+    host_throttle: Arc<HostThrottle>,
+}
+
+/// A snapshot of the job queue and the process, for monitoring; see `AppState::status_summary`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct StatusSummary {
+    /// Status => number of jobs
+    pub jobs_by_status: BTreeMap<String, usize>,
+    pub running_jobs: usize,
+    /// `last_ts` of the most recently completed job
+    pub last_job_completed: Option<String>,
+    /// Time since the forever loop (`server`) was last started, if it ever was
+    pub uptime_sec: Option<u64>,
+}
+
+impl StatusSummary {
+    pub fn new(
+        status_counts: Vec<(String, usize)>,
+        last_job_completed: Option<String>,
+        uptime_sec: Option<u64>,
+    ) -> Self {
+        let jobs_by_status: BTreeMap<String, usize> = status_counts.into_iter().collect();
+        let running_jobs = jobs_by_status
+            .get(JobStatus::Running.as_str())
+            .copied()
+            .unwrap_or(0);
+        Self {
+            jobs_by_status,
+            running_jobs,
+            last_job_completed,
+            uptime_sec,
+        }
+    }
+
+    /// Seconds from a start time (in `FOREVER_LOOP_START_FORMAT`) until `now`
+    fn uptime_sec_since(start: &str, now: DateTime<Local>) -> Option<u64> {
+        let start = NaiveDateTime::parse_from_str(start, FOREVER_LOOP_START_FORMAT)
+            .ok()?
+            .and_local_timezone(Local)
+            .earliest()?;
+        u64::try_from((now - start).num_seconds()).ok()
+    }
 }
 
 impl AppState {
+    /// Sets up the `tracing` subscriber, which writes to stderr, and also receives the messages of the `log` crate.
+    /// The level is taken from the `RUST_LOG` environment variable, and defaults to `info`.
+    pub fn init_tracing() {
+        let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+            .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info"));
+        let _ = tracing_subscriber::fmt()
+            .with_env_filter(filter)
+            .with_writer(std::io::stderr)
+            .try_init(); // Ignore if already set up
+    }
+
     /// Create an `AppState` object from a config JSON file
     pub fn from_config_file(filename: &str) -> Result<Self> {
         let mut path = env::current_dir()?;
@@ -82,6 +374,17 @@ impl AppState {
             .collect();
         let task_specific_usize = Arc::new(task_specific_usize);
         let max_concurrent_jobs = config["max_concurrent_jobs"].as_u64().unwrap_or(10) as usize;
+        let max_job_failures = config["max_job_failures"].as_u64().unwrap_or(5) as usize;
+        let maintenance_excluded_catalogs = config["maintenance_excluded_catalogs"]
+            .as_array()
+            .map(|a| {
+                a.iter()
+                    .filter_map(|v| v.as_u64())
+                    .map(|id| id as usize)
+                    .collect()
+            })
+            .unwrap_or_default();
+        let maintenance_excluded_catalogs = Arc::new(maintenance_excluded_catalogs);
         let bot_name = config["bot_name"]
             .as_str()
             .ok_or_else(|| anyhow!("config.bot_name not found, or not an object"))?
@@ -105,9 +408,92 @@ impl AppState {
             import_file_path,
             task_specific_usize,
             max_concurrent_jobs,
+            max_job_failures,
+            seppuku: SeppukuConfig::from_config(&config["seppuku"]),
+            maintenance_excluded_catalogs,
+            catalog_cache: Arc::new(DashMap::new()),
+            prop2catalogs: Arc::new(RwLock::new((None, HashMap::new()))),
+            host_throttle: Arc::new(HostThrottle::default()),
         })
     }
 
+    /// Number of consecutive failures after which a job is BLOCKED instead of being retried
+    pub const fn max_job_failures(&self) -> usize {
+        self.max_job_failures
+    }
+
+    /// Job counts by status, the last job completion, and the uptime of the forever loop.
+    /// The uptime is based on the start time the forever loop stores, so it is also correct for the `status` CLI command.
+    pub async fn status_summary(&self) -> Result<StatusSummary> {
+        let status_counts = self.storage().jobs_get_status_counts().await?;
+        let last_job_completed = self.storage().jobs_get_last_done_ts().await?;
+        let uptime_sec = self
+            .storage()
+            .get_kv_value(KV_FOREVER_LOOP_START)
+            .await?
+            .and_then(|start| StatusSummary::uptime_sec_since(&start, Local::now()));
+        Ok(StatusSummary::new(
+            status_counts,
+            last_job_completed,
+            uptime_sec,
+        ))
+    }
+
+    /// Catalogs to exclude from site-wide candidate generation, as set in the config file
+    pub fn maintenance_excluded_catalogs(&self) -> &[usize] {
+        &self.maintenance_excluded_catalogs
+    }
+
+    /// Short-lived cache of catalogs loaded via `Catalog::from_id`, with their load time
+    pub fn catalog_cache(&self) -> &DashMap<usize, (Instant, Catalog)> {
+        &self.catalog_cache
+    }
+
+    /// Returns the active catalogs (without qualifier) that use a Wikidata property.
+    /// Uses a cached map, which is loaded again after `PROP2CATALOGS_TTL`.
+    pub async fn catalogs_for_property(&self, prop: usize) -> Result<Vec<usize>> {
+        self.prop2catalogs_load_if_stale().await?;
+        let prop2catalogs = self.prop2catalogs.read().await;
+        Ok(prop2catalogs.1.get(&prop).cloned().unwrap_or_default())
+    }
+
+    /// Returns the cached Wikidata property => catalogs map, see `catalogs_for_property`
+    pub async fn prop2catalogs(&self) -> Result<HashMap<usize, Vec<usize>>> {
+        self.prop2catalogs_load_if_stale().await?;
+        Ok(self.prop2catalogs.read().await.1.to_owned())
+    }
+
+    /// Loads the property => catalogs map from the database
+    pub async fn refresh_prop2catalogs(&self) -> Result<()> {
+        let rows = self.storage().maintenance_get_prop2catalog_ids().await?;
+        let prop2catalogs = Self::prop2catalogs_from_rows(rows);
+        *self.prop2catalogs.write().await = (Some(Instant::now()), prop2catalogs);
+        Ok(())
+    }
+
+    async fn prop2catalogs_load_if_stale(&self) -> Result<()> {
+        let loaded = self.prop2catalogs.read().await.0;
+        match loaded {
+            Some(loaded) if loaded.elapsed() < PROP2CATALOGS_TTL => Ok(()),
+            _ => self.refresh_prop2catalogs().await,
+        }
+    }
+
+    /// Groups (catalog ID, property) rows by property
+    fn prop2catalogs_from_rows(rows: Vec<(usize, usize)>) -> HashMap<usize, Vec<usize>> {
+        let mut ret: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (catalog_id, property) in rows {
+            ret.entry(property).or_default().push(catalog_id);
+        }
+        ret.values_mut().for_each(|catalog_ids| catalog_ids.sort());
+        ret
+    }
+
+    /// Rate limiter for requests to external websites, shared by all scrapers
+    pub fn host_throttle(&self) -> &HostThrottle {
+        &self.host_throttle
+    }
+
     pub fn storage(&self) -> &Arc<Box<dyn Storage>> {
         &self.storage
     }
@@ -173,19 +559,35 @@ impl AppState {
     // Kills the app if there are jobs running but have no recent activity
     // Toolforge k8s "continuous job" will restart a new instance
     fn seppuku(&self) {
-        let check_every_minutes = 5;
-        let max_age_min = 20;
+        let config = self.seppuku;
+        let max_age_min = config.max_idle_minutes;
         let app = self.clone();
         tokio::spawn(async move {
             loop {
-                sleep(tokio::time::Duration::from_secs(60 * check_every_minutes)).await;
+                sleep(tokio::time::Duration::from_secs(
+                    60 * config.check_every_minutes,
+                ))
+                .await;
                 // println!("seppuku check running");
-                let min = chrono::Duration::try_minutes(max_age_min).unwrap();
+                let min = chrono::Duration::try_minutes(max_age_min).unwrap_or_default();
                 let utc = chrono::Utc::now() - min;
                 let ts = TimeStamp::datetime(&utc);
-                let (running, running_recent) =
-                    app.storage().app_state_seppuku_get_running(&ts).await;
-                if running > 0 && running_recent == 0 {
+                let status_counts = match app.storage().jobs_get_status_counts().await {
+                    Ok(status_counts) => status_counts,
+                    Err(e) => {
+                        error!("seppuku: {e}");
+                        continue;
+                    }
+                };
+                let running = StatusSummary::new(status_counts, None, None).running_jobs;
+                let running_recent = match app.storage().jobs_count_running_since(&ts).await {
+                    Ok(running_recent) => running_recent,
+                    Err(e) => {
+                        error!("seppuku: {e}");
+                        continue;
+                    }
+                };
+                if config.is_stuck(running, running_recent) {
                     error!("seppuku: {running} jobs running but no activity within {max_age_min} minutes, commiting seppuku");
                     std::process::exit(0);
                 }
@@ -228,10 +630,12 @@ impl AppState {
         self.storage().reset_running_jobs().await?;
         self.storage().reset_failed_jobs().await?;
         info!("Old jobs reset, starting bot");
+        self.refresh_prop2catalogs().await?;
+        Job::queue_repeating_job(self, 0, "reference_fixer", REFERENCE_FIXER_REPEAT_SEC).await?;
         self.seppuku();
-        let current_time_str = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+        let current_time_str = Local::now().format(FOREVER_LOOP_START_FORMAT).to_string();
         self.storage()
-            .set_kv_value("forever_loop_start", &current_time_str)
+            .set_kv_value(KV_FOREVER_LOOP_START, &current_time_str)
             .await?;
         Ok(current_jobs)
     }
@@ -373,6 +777,14 @@ impl AppState {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_prop2catalogs_from_rows() {
+        let prop2catalogs = AppState::prop2catalogs_from_rows(vec![(5, 214), (2, 214), (7, 227)]);
+        assert_eq!(prop2catalogs.get(&214), Some(&vec![2, 5]));
+        assert_eq!(prop2catalogs.get(&227), Some(&vec![7]));
+        assert_eq!(prop2catalogs.get(&1), None);
+    }
+
     #[test]
     fn test_item2numeric() {
         assert_eq!(AppState::item2numeric("foobar"), None);
@@ -381,4 +793,104 @@ mod tests {
         assert_eq!(AppState::item2numeric("Q12345X"), Some(12345));
         assert_eq!(AppState::item2numeric("Q12345X6"), Some(12345));
     }
+
+    #[test]
+    fn test_host_limits_from_key_value_pairs() {
+        let kv: HashMap<String, String> = [
+            (KV_SCRAPE_DELAY_MS.to_string(), "1500".to_string()),
+            (KV_SCRAPE_CONCURRENCY.to_string(), "0".to_string()),
+        ]
+        .into_iter()
+        .collect();
+        let limits = HostLimits::from_key_value_pairs(&kv);
+        assert_eq!(limits.delay, time::Duration::from_millis(1500));
+        assert_eq!(limits.concurrency, DEFAULT_SCRAPE_CONCURRENCY);
+        assert_eq!(
+            HostLimits::from_key_value_pairs(&HashMap::new()),
+            HostLimits::default()
+        );
+    }
+
+    #[test]
+    fn test_seppuku_config() {
+        let config = SeppukuConfig::default();
+        assert!(config.is_stuck(3, 0));
+        assert!(!config.is_stuck(3, 1));
+        assert!(!config.is_stuck(0, 0));
+
+        let config = SeppukuConfig::from_config(&serde_json::json!({
+            "max_idle_minutes": 60,
+            "min_running_jobs": 2,
+            "check_every_minutes": 0
+        }));
+        assert_eq!(config.max_idle_minutes, 60);
+        assert_eq!(config.check_every_minutes, 5);
+        assert!(!config.is_stuck(1, 0));
+        assert!(config.is_stuck(2, 0));
+        assert_eq!(
+            SeppukuConfig::from_config(&Value::Null),
+            SeppukuConfig::default()
+        );
+    }
+
+    #[test]
+    fn test_status_summary() {
+        let counts = vec![("DONE".to_string(), 10), ("RUNNING".to_string(), 2)];
+        let summary = StatusSummary::new(counts, Some("20240102030405".to_string()), Some(60));
+        assert_eq!(summary.running_jobs, 2);
+        assert_eq!(summary.jobs_by_status.get("DONE"), Some(&10));
+        assert_eq!(
+            serde_json::to_value(&summary).unwrap()["last_job_completed"],
+            serde_json::json!("20240102030405")
+        );
+        assert_eq!(serde_json::to_value(&summary).unwrap()["uptime_sec"], 60);
+        assert_eq!(StatusSummary::new(vec![], None, None).running_jobs, 0);
+    }
+
+    #[test]
+    fn test_uptime_sec_since() {
+        let now = NaiveDateTime::parse_from_str("2024-01-02 03:05:05", FOREVER_LOOP_START_FORMAT)
+            .unwrap()
+            .and_local_timezone(Local)
+            .unwrap();
+        assert_eq!(
+            StatusSummary::uptime_sec_since("2024-01-02 03:04:05", now),
+            Some(60)
+        );
+        assert_eq!(
+            StatusSummary::uptime_sec_since("2024-01-02 03:06:05", now),
+            None
+        );
+        assert_eq!(StatusSummary::uptime_sec_since("not a time", now), None);
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(
+            HostThrottle::parse_retry_after(" 120"),
+            Some(time::Duration::from_secs(120))
+        );
+        assert_eq!(
+            HostThrottle::parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(time::Duration::ZERO)
+        );
+        assert_eq!(HostThrottle::parse_retry_after("soon"), None);
+    }
+
+    #[tokio::test]
+    async fn test_host_throttle_forgets_idle_hosts() {
+        let throttle = HostThrottle::default();
+        let limits = HostLimits::default();
+        let busy = throttle.slot("https://busy.example.org/", limits);
+        for i in 1..HOST_THROTTLE_MAX_HOSTS {
+            throttle.slot(&format!("https://host{i}.example.org/"), limits);
+        }
+        assert_eq!(throttle.hosts.len(), HOST_THROTTLE_MAX_HOSTS);
+        throttle.slot("https://new.example.org/", limits);
+        assert_eq!(throttle.hosts.len(), 2);
+        assert!(Arc::ptr_eq(
+            &busy,
+            &throttle.slot("https://busy.example.org/", limits)
+        ));
+    }
 }
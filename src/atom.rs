@@ -0,0 +1,295 @@
+use crate::app_state::AppState;
+use crate::entry::{MatchEvent, MatchEventType};
+use crate::quick_compare::QuickCompare;
+use anyhow::Result;
+use quick_xml::events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event};
+use quick_xml::Writer;
+use std::collections::HashMap;
+use std::io::Write;
+use wikimisc::wikibase::entity_container::EntityContainer;
+use wikimisc::wikibase::EntityTrait;
+
+const ATOM_BASE_URL: &str = "https://mix-n-match.toolforge.org";
+/// Number of events in a feed
+const ATOM_MAX_EVENTS: usize = 50;
+const ATOM_THUMBNAIL_WIDTH: usize = 120;
+
+/// Label and image (P18 file name) of a Wikidata item in the feed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AtomItem {
+    pub label: Option<String>,
+    pub image: Option<String>,
+}
+
+/// An Atom 1.0 feed of recent manual matches and match removals, see `Storage::get_recent_match_events`.
+#[derive(Debug, Clone, Default)]
+pub struct AtomFeed {
+    pub catalog_id: Option<usize>,
+    pub events: Vec<MatchEvent>,
+    /// Numeric item ID => item
+    pub items: HashMap<isize, AtomItem>,
+    /// User ID => user name
+    pub users: HashMap<usize, String>,
+}
+
+impl AtomFeed {
+    /// Loads the most recent events, optionally for one catalog, with their items and users
+    pub async fn load(app: &AppState, catalog_id: Option<usize>) -> Result<Self> {
+        let events = app
+            .storage()
+            .get_recent_match_events(catalog_id, None, ATOM_MAX_EVENTS)
+            .await?;
+        let mut ret = Self {
+            catalog_id,
+            events,
+            ..Default::default()
+        };
+        ret.load_items(app).await?;
+        ret.load_users(app).await?;
+        Ok(ret)
+    }
+
+    async fn load_items(&mut self, app: &AppState) -> Result<()> {
+        let items: Vec<String> = self
+            .events
+            .iter()
+            .filter_map(|event| event.q)
+            .filter(|q| *q > 0)
+            .map(|q| format!("Q{q}"))
+            .collect();
+        let api = app.wikidata().get_mw_api().await?;
+        let entities = EntityContainer::new();
+        let _ = entities.load_entities(&api, &items).await;
+        for item in items {
+            if let (Some(entity), Some(q)) = (
+                entities.get_entity(item.to_owned()),
+                AppState::item2numeric(&item),
+            ) {
+                let atom_item = AtomItem {
+                    label: entity.label_in_locale("en").map(|s| s.to_string()),
+                    image: QuickCompare::get_image(&entity),
+                };
+                self.items.insert(q, atom_item);
+            }
+        }
+        Ok(())
+    }
+
+    async fn load_users(&mut self, app: &AppState) -> Result<()> {
+        for user_id in self.events.iter().filter_map(|event| event.user_id) {
+            if self.users.contains_key(&user_id) {
+                continue;
+            }
+            if let Some(user) = app.storage().get_user_by_id(user_id).await? {
+                self.users.insert(user_id, user.name);
+            }
+        }
+        Ok(())
+    }
+
+    /// Renders the feed as Atom 1.0 XML
+    pub fn render(&self) -> Result<String> {
+        let mut writer = Writer::new_with_indent(Vec::new(), b' ', 1);
+        writer.write_event(Event::Decl(BytesDecl::new("1.0", Some("utf-8"), None)))?;
+        let mut feed = BytesStart::new("feed");
+        feed.push_attribute(("xmlns", "http://www.w3.org/2005/Atom"));
+        writer.write_event(Event::Start(feed))?;
+        let (title, url) = match self.catalog_id {
+            Some(catalog_id) => (
+                format!("Mix'n'match: recent changes in catalog {catalog_id}"),
+                format!("{ATOM_BASE_URL}/#/rc/{catalog_id}"),
+            ),
+            None => (
+                "Mix'n'match: recent changes".to_string(),
+                format!("{ATOM_BASE_URL}/#/rc"),
+            ),
+        };
+        let updated = self
+            .events
+            .first()
+            .map(|event| Self::atom_timestamp(&event.timestamp))
+            .unwrap_or_else(|| Self::atom_timestamp(""));
+        Self::write_text_element(&mut writer, "title", &[], &title)?;
+        Self::write_empty_element(&mut writer, "link", &[("href", &url)])?;
+        Self::write_text_element(&mut writer, "id", &[], &url)?;
+        Self::write_text_element(&mut writer, "updated", &[], &updated)?;
+        writer.write_event(Event::Start(BytesStart::new("author")))?;
+        Self::write_text_element(&mut writer, "name", &[], "Mix'n'match")?;
+        writer.write_event(Event::End(BytesEnd::new("author")))?;
+        for event in &self.events {
+            self.write_entry(&mut writer, event)?;
+        }
+        writer.write_event(Event::End(BytesEnd::new("feed")))?;
+        Ok(String::from_utf8(writer.into_inner())?)
+    }
+
+    fn write_entry<W: Write>(&self, writer: &mut Writer<W>, event: &MatchEvent) -> Result<()> {
+        let entry_url = format!("{ATOM_BASE_URL}/#/entry/{}", event.entry_id);
+        let user = event
+            .user_id
+            .and_then(|user_id| self.users.get(&user_id))
+            .cloned()
+            .unwrap_or_else(|| "Mix'n'match".to_string());
+        let item = event.q.and_then(|q| self.items.get(&q));
+        let item_name = match (event.q, item.and_then(|item| item.label.as_ref())) {
+            (Some(q), Some(label)) => format!("{label} (Q{q})"),
+            (Some(q), None) if q > 0 => format!("Q{q}"),
+            _ => "N/A".to_string(),
+        };
+        let (title, event_name) = match event.event_type {
+            MatchEventType::Match => (
+                format!("{} was matched to {item_name}", event.ext_name),
+                "match",
+            ),
+            MatchEventType::RemoveQ => (
+                format!("{} was unmatched from {item_name}", event.ext_name),
+                "remove_q",
+            ),
+        };
+        writer.write_event(Event::Start(BytesStart::new("entry")))?;
+        Self::write_text_element(writer, "title", &[], &title)?;
+        Self::write_empty_element(writer, "link", &[("href", &entry_url)])?;
+        Self::write_text_element(
+            writer,
+            "id",
+            &[],
+            &format!("{entry_url}/{event_name}/{}", event.timestamp),
+        )?;
+        Self::write_text_element(
+            writer,
+            "updated",
+            &[],
+            &Self::atom_timestamp(&event.timestamp),
+        )?;
+        writer.write_event(Event::Start(BytesStart::new("author")))?;
+        Self::write_text_element(writer, "name", &[], &user)?;
+        writer.write_event(Event::End(BytesEnd::new("author")))?;
+        Self::write_text_element(
+            writer,
+            "content",
+            &[("type", "html")],
+            &Self::content_html(event, item, &item_name),
+        )?;
+        writer.write_event(Event::End(BytesEnd::new("entry")))?;
+        Ok(())
+    }
+
+    /// HTML for an entry, with links to the entry and the item, and the item image as a thumbnail
+    fn content_html(event: &MatchEvent, item: Option<&AtomItem>, item_name: &str) -> String {
+        let mut html = format!(
+            "<p>Catalog {}, entry <a href=\"{ATOM_BASE_URL}/#/entry/{}\">{}</a></p>",
+            event.catalog_id,
+            event.entry_id,
+            html_escape::encode_text(&event.ext_name)
+        );
+        if let Some(q) = event.q.filter(|q| *q > 0) {
+            html += &format!(
+                "<p><a href=\"https://www.wikidata.org/wiki/Q{q}\">{}</a></p>",
+                html_escape::encode_text(item_name)
+            );
+        }
+        if let Some(image) = item.and_then(|item| item.image.as_ref()) {
+            html += &format!(
+                "<p><img src=\"https://commons.wikimedia.org/wiki/Special:FilePath/{}?width={ATOM_THUMBNAIL_WIDTH}\" /></p>",
+                urlencoding::encode(&image.replace(' ', "_"))
+            );
+        }
+        html
+    }
+
+    /// Converts a `YYYYMMDDHHMMSS` timestamp to RFC 3339; uses the current time for invalid ones
+    fn atom_timestamp(ts: &str) -> String {
+        match chrono::NaiveDateTime::parse_from_str(ts, "%Y%m%d%H%M%S") {
+            Ok(dt) => dt.and_utc(),
+            Err(_) => chrono::Utc::now(),
+        }
+        .to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+    }
+
+    fn write_text_element<W: Write>(
+        writer: &mut Writer<W>,
+        name: &str,
+        attributes: &[(&str, &str)],
+        text: &str,
+    ) -> Result<()> {
+        let mut start = BytesStart::new(name);
+        for attribute in attributes {
+            start.push_attribute(*attribute);
+        }
+        writer.write_event(Event::Start(start))?;
+        writer.write_event(Event::Text(BytesText::new(text)))?;
+        writer.write_event(Event::End(BytesEnd::new(name)))?;
+        Ok(())
+    }
+
+    fn write_empty_element<W: Write>(
+        writer: &mut Writer<W>,
+        name: &str,
+        attributes: &[(&str, &str)],
+    ) -> Result<()> {
+        let mut element = BytesStart::new(name);
+        for attribute in attributes {
+            element.push_attribute(*attribute);
+        }
+        writer.write_event(Event::Empty(element))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_atom_timestamp() {
+        assert_eq!(
+            AtomFeed::atom_timestamp("20240102030405"),
+            "2024-01-02T03:04:05Z"
+        );
+        assert!(AtomFeed::atom_timestamp("foobar").ends_with('Z'));
+    }
+
+    #[test]
+    fn test_render() {
+        let event = MatchEvent {
+            event_type: MatchEventType::Match,
+            entry_id: 123,
+            catalog_id: 5526,
+            ext_id: "abc".to_string(),
+            ext_name: "Foo & <Bar>".to_string(),
+            q: Some(42),
+            user_id: Some(2),
+            timestamp: "20240102030405".to_string(),
+        };
+        let mut feed = AtomFeed {
+            catalog_id: Some(5526),
+            events: vec![event],
+            ..Default::default()
+        };
+        feed.items.insert(
+            42,
+            AtomItem {
+                label: Some("Douglas Adams".to_string()),
+                image: Some("Douglas adams portrait.jpg".to_string()),
+            },
+        );
+        feed.users.insert(2, "Magnus Manske".to_string());
+        let xml = feed.render().unwrap();
+        let doc = roxmltree::Document::parse(&xml).unwrap();
+        let root = doc.root_element();
+        assert_eq!(root.tag_name().name(), "feed");
+        let entry = root.children().find(|n| n.has_tag_name("entry")).unwrap();
+        let title = entry.children().find(|n| n.has_tag_name("title")).unwrap();
+        assert_eq!(
+            title.text(),
+            Some("Foo & <Bar> was matched to Douglas Adams (Q42)")
+        );
+        let content = entry
+            .children()
+            .find(|n| n.has_tag_name("content"))
+            .unwrap();
+        let html = content.text().unwrap();
+        assert!(html.contains("Foo &amp; &lt;Bar&gt;"));
+        assert!(html.contains("Special:FilePath/Douglas_adams_portrait.jpg?width=120"));
+    }
+}
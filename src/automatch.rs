@@ -3,9 +3,15 @@ use crate::app_state::USER_AUTO;
 use crate::app_state::USER_DATE_MATCH;
 use crate::catalog::*;
 use crate::entry::*;
+use crate::entry_query::{EntryQuery, EntryQueryOrder};
 use crate::issue::*;
 use crate::job::*;
-use crate::person::Person;
+use crate::match_state::MatchState;
+use crate::name_normalize::{self, KV_NORMALIZED_NAME_SEARCH};
+use crate::name_rewrite::NameRewrite;
+use crate::overview_rebuild::OverviewRebuild;
+use crate::person::{OccupationDisambiguation, Person};
+use crate::url_normalize::{ext_url_variants, normalize_ext_url};
 use anyhow::{anyhow, Result};
 use chrono::prelude::*;
 use chrono::{NaiveDateTime, Utc};
@@ -15,7 +21,8 @@ use lazy_static::lazy_static;
 use mediawiki::api::Api;
 use regex::Regex;
 use serde_json::json;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use tracing::{debug, warn};
 
 lazy_static! {
     static ref RE_YEAR: Regex = Regex::new(r"(\d{3,4})").expect("Regexp error");
@@ -71,6 +78,118 @@ pub struct ResultInOtherCatalog {
     pub q: Option<isize>,
 }
 
+/// Rules for picking one of several single-date candidates, if it is the only one that matches both dates.
+/// Off by default; configured via `task_specific_usize` in the config:
+/// `date_match_promotion` (1 to enable), `date_match_promotion_any_precision` (1 to also promote on year-precision dates),
+/// and `date_match_promotion_max_candidates`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DatePromotionRules {
+    pub enabled: bool,
+    /// Only promote if both entry dates have day precision
+    pub require_day_precision: bool,
+    /// Do not promote among more candidates than this
+    pub max_candidates: usize,
+}
+
+impl Default for DatePromotionRules {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            require_day_precision: true,
+            max_candidates: 5,
+        }
+    }
+}
+
+impl DatePromotionRules {
+    pub fn from_app(app: &AppState) -> Self {
+        let config = app.task_specific_usize();
+        let default = Self::default();
+        Self {
+            enabled: config.get("date_match_promotion") == Some(&1),
+            require_day_precision: config.get("date_match_promotion_any_precision") != Some(&1),
+            max_candidates: *config
+                .get("date_match_promotion_max_candidates")
+                .unwrap_or(&default.max_candidates),
+        }
+    }
+}
+
+/// How `automatch_simple` searches for the label and aliases of an entry.
+/// Configured via `task_specific_usize` in the config: `alias_search_weighting` (1 to enable),
+/// `alias_search_max_searches`, and `alias_search_item_aliases` (0 to only match item labels).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AliasSearchRules {
+    /// Rank items that are found via more names (label or aliases) first
+    pub prefer_multiple_hits: bool,
+    /// Maximum number of searches per entry, including the one for the label
+    pub max_searches: usize,
+    /// Also accept items that have a name as an alias, not just as a label
+    pub match_item_aliases: bool,
+}
+
+impl Default for AliasSearchRules {
+    fn default() -> Self {
+        Self {
+            prefer_multiple_hits: false,
+            max_searches: 20,
+            match_item_aliases: true,
+        }
+    }
+}
+
+impl AliasSearchRules {
+    pub fn from_app(app: &AppState) -> Self {
+        let config = app.task_specific_usize();
+        let default = Self::default();
+        Self {
+            prefer_multiple_hits: config.get("alias_search_weighting") == Some(&1),
+            max_searches: *config
+                .get("alias_search_max_searches")
+                .unwrap_or(&default.max_searches),
+            match_item_aliases: config.get("alias_search_item_aliases") != Some(&0),
+        }
+    }
+
+    /// The names to search for: the label, then the distinct non-empty aliases, up to `max_searches`
+    fn names_to_search<'a>(&self, label: &'a str, aliases: &'a str) -> Vec<&'a str> {
+        let mut ret = vec![label];
+        for alias in aliases.split('|').map(|alias| alias.trim()) {
+            if !alias.is_empty() && !ret.contains(&alias) {
+                ret.push(alias);
+            }
+        }
+        ret.truncate(self.max_searches.max(1));
+        ret
+    }
+
+    /// Combines the search results for all names into a list of distinct items.
+    /// Items are sorted, or if `prefer_multiple_hits` is set, ranked by the number of names that found them first.
+    fn rank_items(&self, results: Vec<Vec<String>>) -> Vec<String> {
+        self.rank_items_with_hits(results)
+            .into_iter()
+            .map(|(item, _)| item)
+            .collect()
+    }
+
+    /// As `rank_items`, but with the number of names that found each item
+    fn rank_items_with_hits(&self, results: Vec<Vec<String>>) -> Vec<(String, usize)> {
+        let mut hits: HashMap<String, usize> = HashMap::new();
+        for items in results {
+            for item in items.into_iter().unique() {
+                *hits.entry(item).or_default() += 1;
+            }
+        }
+        let mut items: Vec<(String, usize)> = hits.into_iter().collect();
+        if self.prefer_multiple_hits {
+            items.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        } else {
+            items.sort();
+        }
+        items
+    }
+}
+
 #[derive(Debug, Clone)]
 struct CandidateDates {
     pub entry_id: usize,
@@ -100,6 +219,7 @@ impl CandidateDates {
 pub struct AutoMatch {
     app: AppState,
     job: Option<Job>,
+    overview_rebuild: OverviewRebuild,
 }
 
 impl Jobbable for AutoMatch {
@@ -121,6 +241,7 @@ impl AutoMatch {
         Self {
             app: app.clone(),
             job: None,
+            overview_rebuild: OverviewRebuild::new(app),
         }
     }
 
@@ -155,6 +276,10 @@ impl AutoMatch {
         }
         self.process_automatch_with_sparql(catalog_id, &label2q)
             .await?;
+        self.app
+            .storage()
+            .finalize_catalog_overview(catalog_id)
+            .await?;
         Ok(())
     }
 
@@ -166,32 +291,46 @@ impl AutoMatch {
         if label2q.is_empty() {
             return Ok(());
         }
-        let mut offset = 0;
+        let mut after_entry_id = 0;
         let batch_size = 50000;
         loop {
-            println!("Batch offset {offset}");
-            let mut entry_batch = self
+            debug!(
+                catalog_id,
+                after_entry_id, "automatch_with_sparql: next batch"
+            );
+            let entry_batch = self
                 .app
                 .storage()
-                .get_entry_batch(catalog_id, batch_size, offset)
+                .get_entry_batch_after(catalog_id, after_entry_id, batch_size)
+                .await?;
+            let matches: Vec<(Entry, isize)> = entry_batch
+                .iter()
+                .filter_map(|entry| {
+                    let q = *label2q.get(&entry.ext_name)? as isize;
+                    Some((entry.to_owned(), q))
+                })
+                .collect();
+            self.app
+                .storage()
+                .entry_set_match_batch(&matches, USER_AUTO)
                 .await?;
-            for entry in &mut entry_batch {
-                if let Some(q) = label2q.get(&entry.ext_name) {
-                    entry.set_app(&self.app);
-                    let _ = entry.set_match(&format!("Q{}", q), USER_AUTO).await;
-                }
-            }
             if entry_batch.len() < batch_size {
                 break;
             }
-            offset += entry_batch.len();
+            after_entry_id = match entry_batch.last() {
+                Some(entry) => entry.id,
+                None => break,
+            };
         }
         Ok(())
     }
 
     pub async fn automatch_by_sitelink(&mut self, catalog_id: usize) -> Result<()> {
         let language = Catalog::from_id(catalog_id, &self.app).await?.search_wp;
-        let site = format!("{}wiki", &language);
+        let site = match self.app.wikidata().lang_to_site(&language).await? {
+            Some(site_info) => site_info.site_id,
+            None => return Err(anyhow!("No Wikipedia for language '{language}'")),
+        };
         let mut offset = self.get_last_job_offset().await;
         let batch_size = 5000;
         loop {
@@ -208,7 +347,7 @@ impl AutoMatch {
                 .automatch_by_sitelink_get_wd_matches(&name2entries, &site)
                 .await?;
             self.automatch_by_sitelink_process_wd_matches(wd_matches, name2entries)
-                .await;
+                .await?;
             if entries.len() < batch_size {
                 break;
             }
@@ -216,6 +355,10 @@ impl AutoMatch {
             let _ = self.remember_offset(offset).await;
         }
         let _ = self.clear_offset().await;
+        self.app
+            .storage()
+            .finalize_catalog_overview(catalog_id)
+            .await?;
         Ok(())
     }
 
@@ -223,16 +366,26 @@ impl AutoMatch {
         &mut self,
         wd_matches: Vec<(usize, String)>,
         name2entries: HashMap<String, Vec<usize>>,
-    ) {
-        for (q, title) in wd_matches {
-            if let Some(v) = name2entries.get(&title) {
-                for entry_id in v {
-                    if let Ok(mut entry) = Entry::from_id(*entry_id, &self.app).await {
-                        let _ = entry.set_match(&format!("Q{}", q), USER_AUTO).await;
-                    }
-                }
-            }
-        }
+    ) -> Result<()> {
+        let q_entry_ids: Vec<(isize, usize)> = wd_matches
+            .iter()
+            .filter_map(|(q, title)| Some((*q as isize, name2entries.get(title)?)))
+            .flat_map(|(q, entry_ids)| entry_ids.iter().map(move |entry_id| (q, *entry_id)))
+            .collect();
+        let entry_ids: Vec<usize> = q_entry_ids.iter().map(|(_q, entry_id)| *entry_id).collect();
+        let entries = match self.app.storage().multiple_from_ids(&entry_ids).await {
+            Ok(entries) => entries,
+            Err(_) => return Ok(()), // Ignore error
+        };
+        let matches: Vec<(Entry, isize)> = q_entry_ids
+            .into_iter()
+            .filter_map(|(q, entry_id)| Some((entries.get(&entry_id)?.to_owned(), q)))
+            .collect();
+        self.app
+            .storage()
+            .entry_set_match_batch(&matches, USER_AUTO)
+            .await?;
+        Ok(())
     }
 
     async fn automatch_by_sitelink_get_wd_matches(
@@ -276,6 +429,10 @@ impl AutoMatch {
     ) -> Result<()> {
         let entry_ids: Vec<usize> = entry_id2items.keys().copied().collect();
         let mut entries = Entry::multiple_from_ids(&entry_ids, &self.app).await?;
+        // Without a batch, candidates are scored per entry
+        let batch = MatchCandidateBatch::load(entry_id2items, &self.app)
+            .await
+            .ok();
         let mut futures = vec![];
 
         for (entry_id, entry) in &mut entries {
@@ -283,7 +440,7 @@ impl AutoMatch {
                 Some(items) => items,
                 None => continue,
             };
-            let future = entry.set_auto_and_multi_match(items);
+            let future = entry.set_auto_and_multi_match_with_batch(items, batch.as_ref());
             futures.push(future);
         }
 
@@ -305,7 +462,6 @@ impl AutoMatch {
     // }
 
     pub async fn automatch_by_search(&mut self, catalog_id: usize) -> Result<()> {
-        let mut offset = self.get_last_job_offset().await;
         let batch_size = *self
             .app
             .task_specific_usize()
@@ -317,49 +473,236 @@ impl AutoMatch {
             .get("automatch_by_search_search_batch_size")
             .unwrap_or(&100);
 
-        loop {
-            let results = self
-                .app
-                .storage()
-                .automatch_by_search_get_results(catalog_id, offset, batch_size)
-                .await?;
-            // println!("automatch_by_search [{catalog_id}]:Done.");
+        let name_rewrite = NameRewrite::from_catalog_id(catalog_id, &self.app).await?;
+        let normalized_fallback = Catalog::from_id(catalog_id, &self.app)
+            .await?
+            .get_key_value_pairs()
+            .await?
+            .get(KV_NORMALIZED_NAME_SEARCH)
+            .map(|v| v.trim() == "1")
+            .unwrap_or(false);
 
-            for result_batch in results.chunks(search_batch_size) {
-                self.automatch_by_search_process_results_batch(result_batch)
+        let max_entry_id = self
+            .app
+            .storage()
+            .automatch_get_max_entry_id(catalog_id)
+            .await?;
+        let min_entry_id = self.get_min_entry_id().await;
+        let passes = self.automatch_signal_passes();
+        let (first_pass, mut after_entry_id) =
+            self.get_last_signal_pass_after_id(passes.len()).await;
+        for (pass, has_signal) in passes.into_iter().enumerate().skip(first_pass) {
+            loop {
+                let query = Self::automatch_entry_query(
+                    catalog_id,
+                    has_signal,
+                    batch_size,
+                    after_entry_id.max(min_entry_id),
+                );
+                let mut results = self
+                    .app
+                    .storage()
+                    .automatch_by_search_get_results(&query)
+                    .await?;
+                Self::rewrite_result_names(&name_rewrite, &mut results);
+                // println!("automatch_by_search [{catalog_id}]:Done.");
+
+                for result_batch in results.chunks(search_batch_size) {
+                    self.automatch_by_search_process_results_batch(
+                        result_batch,
+                        normalized_fallback,
+                    )
                     .await;
-            }
-            // println!("automatch_by_search [{catalog_id}]: Batch completed.");
+                }
+                // println!("automatch_by_search [{catalog_id}]: Batch completed.");
 
-            if results.len() < batch_size {
-                break;
+                if results.len() < batch_size {
+                    break;
+                }
+                // println!("automatch_by_search [{catalog_id}]: Another batch...");
+                after_entry_id = results.last().map(|result| result.0);
+                let _ = self
+                    .remember_signal_pass_after_id(pass, after_entry_id, min_entry_id)
+                    .await;
             }
-            // println!("automatch_by_search [{catalog_id}]: Another batch...");
-            offset += results.len();
-            let _ = self.remember_offset(offset).await;
+            after_entry_id = None;
         }
         // println!("automatch_by_search [{catalog_id}]: All batches completed.");
         let _ = self.clear_offset().await;
+        self.set_watermark(catalog_id, "automatch_by_search", max_entry_id)
+            .await?;
         Ok(())
     }
 
+    /// The `has_signal` passes of the batch-wise automatchers.
+    /// With `automatch_signal_first` set to 1, entries that have dates, auxiliary values, a location,
+    /// or a description are processed before the name-only ones, as they are the likeliest to match.
+    fn automatch_signal_passes(&self) -> Vec<Option<bool>> {
+        let signal_first = *self
+            .app
+            .task_specific_usize()
+            .get("automatch_signal_first")
+            .unwrap_or(&0);
+        if signal_first == 1 {
+            vec![Some(true), Some(false)]
+        } else {
+            vec![None]
+        }
+    }
+
+    /// Entries of a catalog after `after_entry_id`, in ID order, so batches can be paged through by
+    /// the last entry ID instead of an ever-growing OFFSET
+    fn automatch_entry_query(
+        catalog_id: usize,
+        has_signal: Option<bool>,
+        batch_size: usize,
+        after_entry_id: Option<usize>,
+    ) -> EntryQuery {
+        EntryQuery::default()
+            .with_catalog_id(catalog_id)
+            .with_match_state(MatchState::not_fully_matched())
+            .with_has_signal(has_signal)
+            .with_min_id_exclusive(after_entry_id)
+            .with_order_by(EntryQueryOrder::Id)
+            .with_limit(batch_size)
+    }
+
+    /// Returns the (pass, last processed entry ID) to resume a batch-wise automatcher from.
+    /// Starts over if the stored pass does not exist (anymore).
+    async fn get_last_signal_pass_after_id(
+        &self,
+        number_of_passes: usize,
+    ) -> (usize, Option<usize>) {
+        let json = self.get_last_job_data().await;
+        let after_entry_id = json
+            .as_ref()
+            .and_then(|json| json.get("after_entry_id").and_then(|v| v.as_u64()))
+            .map(|id| id as usize);
+        let pass = json
+            .as_ref()
+            .and_then(|json| json.get("signal_pass").and_then(|v| v.as_u64()))
+            .unwrap_or(0) as usize;
+        if pass >= number_of_passes {
+            return (0, None);
+        }
+        (pass, after_entry_id)
+    }
+
+    async fn remember_signal_pass_after_id(
+        &mut self,
+        pass: usize,
+        after_entry_id: Option<usize>,
+        min_entry_id: Option<usize>,
+    ) -> Result<()> {
+        let mut json = json!({"after_entry_id": after_entry_id, "signal_pass": pass});
+        if let Some(min_entry_id) = min_entry_id {
+            json["min_entry_id"] = json!(min_entry_id);
+        }
+        self.remember_job_data(&json).await
+    }
+
+    /// Returns the entry ID after which an incremental run starts, as queued by
+    /// `Maintenance::requeue_automatch_for_new_entries`. Full runs have none.
+    async fn get_min_entry_id(&self) -> Option<usize> {
+        self.get_last_job_data()
+            .await?
+            .get("min_entry_id")?
+            .as_u64()
+            .map(|id| id as usize)
+    }
+
+    /// Remembers the highest entry ID of the catalog at the start of the run, so entries added later can be matched incrementally
+    async fn set_watermark(
+        &self,
+        catalog_id: usize,
+        action: &str,
+        max_entry_id: Option<usize>,
+    ) -> Result<()> {
+        match max_entry_id {
+            Some(max_entry_id) => {
+                self.app
+                    .storage()
+                    .automatch_set_watermark(catalog_id, action, max_entry_id)
+                    .await
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Applies the catalog name rewrite rules to the names in (entry_id,name,type,aliases) results
+    fn rewrite_result_names(
+        name_rewrite: &NameRewrite,
+        results: &mut [(usize, String, String, String)],
+    ) {
+        if name_rewrite.is_empty() {
+            return;
+        }
+        for result in results.iter_mut() {
+            result.1 = name_rewrite.rewrite(&result.1);
+        }
+    }
+
     async fn automatch_by_search_process_results_batch(
         &mut self,
         result_batch: &[(usize, String, String, String)],
+        normalized_fallback: bool,
     ) {
         let mut search_results = self
             .automatch_by_search_process_results_batch_process_futures(result_batch)
             .await;
+        let mut fallback_entry_ids = HashSet::new();
+        if normalized_fallback {
+            let mut fallback_results = self
+                .automatch_by_search_normalized_fallback(result_batch, &search_results)
+                .await;
+            fallback_entry_ids = fallback_results.iter().map(|(id, _)| *id).collect();
+            search_results.append(&mut fallback_results);
+        }
         if search_results.is_empty() {
             return;
         }
         self.automatch_by_search_process_results_batch_filter_search_results(&mut search_results)
             .await;
         let mut entry_id2items: HashMap<usize, Vec<String>> = HashMap::new();
+        let mut fallback_entry_id2items: HashMap<usize, Vec<String>> = HashMap::new();
         for (entry_id, q) in search_results {
-            entry_id2items.entry(entry_id).or_default().push(q);
+            match fallback_entry_ids.contains(&entry_id) {
+                true => fallback_entry_id2items.entry(entry_id).or_default().push(q),
+                false => entry_id2items.entry(entry_id).or_default().push(q),
+            }
         }
         let _ = self.match_entries_to_items(&entry_id2items).await;
+        let _ = self
+            .match_entries_to_fallback_items(&fallback_entry_id2items)
+            .await;
+    }
+
+    /// Fallback search hits are weaker evidence than hits for the name itself, so they are not scored
+    /// against each other: an entry is auto-matched only to a single hit, otherwise the hits are its multi-match.
+    async fn match_entries_to_fallback_items(
+        &self,
+        entry_id2items: &HashMap<usize, Vec<String>>,
+    ) -> Result<()> {
+        let entry_ids: Vec<usize> = entry_id2items.keys().copied().collect();
+        let mut entries = Entry::multiple_from_ids(&entry_ids, &self.app).await?;
+        let mut futures = vec![];
+        for (entry_id, entry) in &mut entries {
+            let items = match entry_id2items.get(entry_id) {
+                Some(items) => items.iter().unique().cloned().collect_vec(),
+                None => continue,
+            };
+            futures.push(async move {
+                match items.as_slice() {
+                    [q] if entry.q != AppState::item2numeric(q) => {
+                        entry.set_match(q, USER_AUTO).await.map(|_| ())
+                    }
+                    [_] => Ok(()),
+                    _ => entry.set_multi_match(&items).await,
+                }
+            });
+        }
+        let _ = join_all(futures).await; // Ignore errors
+        Ok(())
     }
 
     async fn automatch_by_search_process_results_batch_filter_search_results(
@@ -404,7 +747,7 @@ impl AutoMatch {
                 // No search results
                 continue;
             }
-            if let Ok(mut entry) = Entry::from_id(object_entry_id, &self.app).await {
+            if let Some(mut entry) = Entry::from_id_if_exists(object_entry_id, &self.app).await? {
                 let _ = entry.set_auto_and_multi_match(&items).await;
             };
         }
@@ -412,74 +755,153 @@ impl AutoMatch {
     }
 
     pub async fn automatch_simple(&mut self, catalog_id: usize) -> Result<()> {
-        let mut offset = self.get_last_job_offset().await;
         let batch_size = 5000;
-        loop {
-            // TODO make this more efficient, too many wd replica queries
-            let results = self
-                .app
-                .storage()
-                .automatch_simple_get_results(catalog_id, offset, batch_size)
-                .await?;
-
-            for result in &results {
-                let (entry_id, items) = match self.automatch_simple_items_from_result(result).await
-                {
-                    Some(value) => value,
-                    None => continue,
-                };
-                self.automatch_simple_set_matches(items, entry_id).await;
-            }
+        let name_rewrite = NameRewrite::from_catalog_id(catalog_id, &self.app).await?;
+        let alias_rules = AliasSearchRules::from_app(&self.app);
+        let max_entry_id = self
+            .app
+            .storage()
+            .automatch_get_max_entry_id(catalog_id)
+            .await?;
+        let min_entry_id = self.get_min_entry_id().await;
+        let passes = self.automatch_signal_passes();
+        let (first_pass, mut after_entry_id) =
+            self.get_last_signal_pass_after_id(passes.len()).await;
+        for (pass, has_signal) in passes.into_iter().enumerate().skip(first_pass) {
+            loop {
+                // TODO make this more efficient, too many wd replica queries
+                let query = Self::automatch_entry_query(
+                    catalog_id,
+                    has_signal,
+                    batch_size,
+                    after_entry_id.max(min_entry_id),
+                );
+                let mut results = self
+                    .app
+                    .storage()
+                    .automatch_simple_get_results(&query)
+                    .await?;
+                Self::rewrite_result_names(&name_rewrite, &mut results);
+
+                let mut entry_id2items = HashMap::new();
+                let mut entry_id2hits = HashMap::new();
+                for result in &results {
+                    if let Some((entry_id, items, hits)) = self
+                        .automatch_simple_items_from_result(result, &alias_rules)
+                        .await
+                    {
+                        entry_id2items.insert(entry_id, items);
+                        entry_id2hits.insert(entry_id, hits);
+                    }
+                }
+                // Without a batch, candidates are scored per entry
+                let batch = MatchCandidateBatch::load(&entry_id2items, &self.app)
+                    .await
+                    .ok();
+                for (entry_id, items) in entry_id2items {
+                    let hits = entry_id2hits.remove(&entry_id).flatten();
+                    self.automatch_simple_set_matches(
+                        items,
+                        entry_id,
+                        batch.as_ref(),
+                        hits.as_ref(),
+                    )
+                    .await;
+                }
 
-            if results.len() < batch_size {
-                break;
+                if results.len() < batch_size {
+                    break;
+                }
+                after_entry_id = results.last().map(|result| result.0);
+                let _ = self
+                    .remember_signal_pass_after_id(pass, after_entry_id, min_entry_id)
+                    .await;
             }
-            offset += results.len();
-            let _ = self.remember_offset(offset).await;
+            after_entry_id = None;
         }
         let _ = self.clear_offset().await;
+        self.set_watermark(catalog_id, "automatch", max_entry_id)
+            .await?;
+        self.app
+            .storage()
+            .finalize_catalog_overview(catalog_id)
+            .await?;
         Ok(())
     }
 
-    async fn automatch_simple_set_matches(&mut self, items: Vec<String>, entry_id: usize) {
-        let item = match items.first() {
-            Some(item) => item,
-            None => return,
-        };
+    /// Auto-matches the entry to the best scored of the items (see `Entry::best_auto_match`), and sets the multi-match.
+    /// `items` are in ranked order; `hits` are the number of names that found each item, see `AliasSearchRules`.
+    async fn automatch_simple_set_matches(
+        &mut self,
+        items: Vec<String>,
+        entry_id: usize,
+        batch: Option<&MatchCandidateBatch>,
+        hits: Option<&HashMap<String, usize>>,
+    ) {
+        let qs_numeric: Vec<isize> = items
+            .iter()
+            .filter_map(|q| AppState::item2numeric(q))
+            .unique()
+            .collect();
         let mut entry = match Entry::from_id(entry_id, &self.app).await {
             Ok(entry) => entry,
             _ => return, // Ignore error
         };
-        if entry.set_match(item, USER_AUTO).await.is_err() {
-            return; // Ignore error
+        let (best, scored) = match qs_numeric.as_slice() {
+            [] => return,
+            [q] => (Some(format!("Q{q}")), vec![]),
+            _ => match entry.best_auto_match(&qs_numeric, batch, hits).await {
+                Ok(ret) => ret,
+                Err(_) => return, // Ignore error
+            },
+        };
+        if let Some(item) = best {
+            if entry
+                .set_match_without_overview(&item, USER_AUTO)
+                .await
+                .is_err()
+            {
+                return; // Ignore error
+            }
+            let _ = self
+                .overview_rebuild
+                .match_set(&self.app, entry.catalog)
+                .await;
         }
         if items.len() > 1 {
             // Multi-match
-            let _ = entry.set_multi_match(&items).await.is_err(); // Ignore error
+            let _ = entry.set_multi_match_scored(&items, &scored).await; // Ignore error
         }
     }
 
     async fn automatch_simple_items_from_result(
         &mut self,
         result: &(usize, String, String, String),
-    ) -> Option<(usize, Vec<String>)> {
+        rules: &AliasSearchRules,
+    ) -> Option<(usize, Vec<String>, Option<HashMap<String, usize>>)> {
         let entry_id = result.0;
-        let label = &result.1;
         let type_q = &result.2;
-        let aliases: Vec<&str> = result.3.split('|').collect();
-        let mut items = match self.app.wikidata().search_db_with_type(label, type_q).await {
-            Ok(items) => items,
-            _ => return None, // Ignore error
-        };
-        for alias in &aliases {
-            let mut tmp = match self.app.wikidata().search_db_with_type(alias, type_q).await {
-                Ok(tmp) => tmp,
-                _ => continue, // Ignore error
-            };
-            items.append(&mut tmp);
+        let names = rules.names_to_search(&result.1, &result.3);
+        let mut results = vec![];
+        for (num, name) in names.iter().enumerate() {
+            match self
+                .app
+                .wikidata()
+                .search_db_terms(name, type_q, rules.match_item_aliases)
+                .await
+            {
+                Ok(matches) => {
+                    for (item, term) in &matches {
+                        debug!("automatch_simple: entry {entry_id} '{name}' matches {item} via {term:?}");
+                    }
+                    results.push(matches.into_iter().map(|(item, _)| item).collect());
+                }
+                _ if num == 0 => return None, // Ignore error
+                _ => continue,                // Ignore error
+            }
         }
-        items.sort();
-        items.dedup();
+        let ranked = rules.rank_items_with_hits(results);
+        let mut items: Vec<String> = ranked.iter().map(|(item, _)| item.to_owned()).collect();
         if self
             .app
             .wikidata()
@@ -489,7 +911,11 @@ impl AutoMatch {
         {
             return None; // Ignore error
         }
-        Some((entry_id, items))
+        // Hits only count towards the match if they are meant to
+        let hits = rules
+            .prefer_multiple_hits
+            .then(|| ranked.into_iter().collect());
+        Some((entry_id, items, hits))
     }
 
     //TODO test
@@ -530,6 +956,10 @@ impl AutoMatch {
             offset += results_in_original_catalog.len();
         }
         let _ = self.clear_offset().await;
+        self.app
+            .storage()
+            .finalize_catalog_overview(catalog_id)
+            .await?;
         Ok(())
     }
 
@@ -546,12 +976,101 @@ impl AutoMatch {
         if let Some(v) = name_type2id.get(&key) {
             for entry_id in v {
                 if let Ok(mut entry) = Entry::from_id(*entry_id, &self.app).await {
-                    let _ = entry.set_match(&q, USER_AUTO).await;
+                    if entry
+                        .set_match_without_overview(&q, USER_AUTO)
+                        .await
+                        .is_ok()
+                    {
+                        let _ = self
+                            .overview_rebuild
+                            .match_set(&self.app, entry.catalog)
+                            .await;
+                    }
                 };
             }
         }
     }
 
+    /// Matches unmatched entries to the item of fully matched entries in other active catalogs with the same `ext_url`,
+    /// after normalization (see `normalize_ext_url`). URLs that point to several items are ignored.
+    pub async fn automatch_by_ext_url(&mut self, catalog_id: usize) -> Result<()> {
+        let batch_size = 500;
+        let mut last_entry_id = self.get_last_job_offset().await;
+        loop {
+            let query = EntryQuery::default()
+                .with_catalog_id(catalog_id)
+                .with_match_state(MatchState::unmatched())
+                .with_min_id_exclusive(Some(last_entry_id))
+                .with_order_by(EntryQueryOrder::Id)
+                .with_limit(batch_size);
+            let entries = self.app.storage().get_entry_query(&query).await?;
+            let urls: Vec<String> = entries
+                .iter()
+                .map(|entry| normalize_ext_url(&entry.ext_url))
+                .filter(|url| !url.is_empty())
+                .unique()
+                .collect();
+            let variants: Vec<String> = urls.iter().flat_map(|url| ext_url_variants(url)).collect();
+            let found = self
+                .app
+                .storage()
+                .find_matched_entries_by_ext_url(&variants)
+                .await?;
+            let url2q = Self::ext_url_to_unique_item(&found, catalog_id);
+            for entry in &entries {
+                let q = match url2q.get(&normalize_ext_url(&entry.ext_url)) {
+                    Some(q) => format!("Q{q}"),
+                    None => continue,
+                };
+                let mut entry = entry.to_owned();
+                entry.set_app(&self.app);
+                if entry
+                    .set_match_without_overview(&q, USER_AUTO)
+                    .await
+                    .is_ok()
+                {
+                    let _ = self
+                        .overview_rebuild
+                        .match_set(&self.app, entry.catalog)
+                        .await;
+                }
+            }
+            last_entry_id = match entries.last() {
+                Some(entry) => entry.id,
+                None => break,
+            };
+            if entries.len() < batch_size {
+                break;
+            }
+            let _ = self.remember_offset(last_entry_id).await;
+        }
+        let _ = self.clear_offset().await;
+        self.app
+            .storage()
+            .finalize_catalog_overview(catalog_id)
+            .await?;
+        Ok(())
+    }
+
+    /// Normalized URL => item, for URLs that entries in other catalogs than `catalog_id` match to a single item
+    fn ext_url_to_unique_item(
+        found: &[(String, usize, isize)],
+        catalog_id: usize,
+    ) -> HashMap<String, isize> {
+        let mut url2qs: HashMap<String, HashSet<isize>> = HashMap::new();
+        for (url, _catalog, q) in found
+            .iter()
+            .filter(|(_, catalog, _)| *catalog != catalog_id)
+        {
+            url2qs.entry(normalize_ext_url(url)).or_default().insert(*q);
+        }
+        url2qs
+            .into_iter()
+            .filter(|(_url, qs)| qs.len() == 1)
+            .filter_map(|(url, qs)| Some((url, qs.into_iter().next()?)))
+            .collect()
+    }
+
     pub async fn purge_automatches(&self, catalog_id: usize) -> Result<()> {
         self.app.storage().purge_automatches(catalog_id).await
     }
@@ -560,6 +1079,7 @@ impl AutoMatch {
         &self,
         result: &(usize, String, String, String),
         mw_api: &Api,
+        occupations: &OccupationDisambiguation,
     ) -> Result<()> {
         let entry_id = result.0;
         let candidate_items = match self
@@ -573,26 +1093,150 @@ impl AutoMatch {
             0 => {} // No results
             1 => {
                 let q = &candidate_items[0];
-                let _ = Entry::from_id(entry_id, &self.app)
+                if Entry::from_id(entry_id, &self.app)
                     .await?
                     .set_match(q, USER_DATE_MATCH)
-                    .await;
+                    .await
+                    .is_ok()
+                    && Self::has_day_precision_dates(result)
+                {
+                    let items = wikimisc::wikibase::entity_container::EntityContainer::new();
+                    let _ = items.load_entities(mw_api, &candidate_items).await;
+                    self.check_date_precision(result, q, &items).await;
+                }
             }
             _ => {
-                Issue::new(
-                    entry_id,
-                    IssueType::WdDuplicate,
-                    json!(candidate_items),
-                    &self.app,
-                )
-                .await?
-                .insert()
-                .await?;
+                let mut entry = Entry::from_id(entry_id, &self.app).await?;
+                let items = wikimisc::wikibase::entity_container::EntityContainer::new();
+                let _ = items.load_entities(mw_api, &candidate_items).await;
+                match Self::disambiguate_by_occupation(
+                    occupations,
+                    &entry,
+                    &candidate_items,
+                    &items,
+                ) {
+                    Some(q) => {
+                        if entry.set_match(&q, USER_DATE_MATCH).await.is_ok() {
+                            self.check_date_precision(result, &q, &items).await;
+                        }
+                    }
+                    None => {
+                        Issue::new(
+                            entry_id,
+                            IssuePayload::WdDuplicate(candidate_items),
+                            &self.app,
+                        )
+                        .await?
+                        .insert()
+                        .await?;
+                    }
+                }
             }
         }
         Ok(())
     }
 
+    /// `true` if the entry has a birth or death date with day precision, so a `DATE_PRECISION_MISMATCH` is possible
+    fn has_day_precision_dates(result: &(usize, String, String, String)) -> bool {
+        result.2.len() == 10 || result.3.len() == 10
+    }
+
+    /// Adds a `DATE_PRECISION_MISMATCH` issue if the entry has day-precision person dates,
+    /// and the matched item only has them at a coarser precision, or for a different day.
+    /// A failure to add the issue is logged, as the match itself has been set.
+    async fn check_date_precision(
+        &self,
+        result: &(usize, String, String, String),
+        q: &str,
+        items: &wikimisc::wikibase::entity_container::EntityContainer,
+    ) {
+        let (entry_id, _, born, died) = result;
+        let item = match items.get_entity(q.to_owned()) {
+            Some(item) => item,
+            None => return,
+        };
+        let item_born = Self::item_dates(&item, "P569");
+        let item_died = Self::item_dates(&item, "P570");
+        if !Self::dates_disagree_at_day_precision(born, &item_born)
+            && !Self::dates_disagree_at_day_precision(died, &item_died)
+        {
+            return;
+        }
+        let payload = DatePrecisionMismatchPayload {
+            q: q.to_string(),
+            entry_born: born.to_owned(),
+            entry_died: died.to_owned(),
+            item_born,
+            item_died,
+        };
+        let issue = Issue::new(
+            *entry_id,
+            IssuePayload::DatePrecisionMismatch(payload),
+            &self.app,
+        )
+        .await;
+        let inserted = match issue {
+            Ok(issue) => issue.insert().await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = inserted {
+            warn!("Could not add DATE_PRECISION_MISMATCH issue for entry {entry_id}: {e}");
+        }
+    }
+
+    /// `true` if the entry date has day precision, and the item has dates, but none of them is that day
+    fn dates_disagree_at_day_precision(entry_date: &str, item_dates: &[String]) -> bool {
+        entry_date.len() == 10
+            && !item_dates.is_empty()
+            && !item_dates.iter().any(|date| date == entry_date)
+    }
+
+    /// Returns the dates of a time property of an item, as `YYYY-MM-DD`, `YYYY-MM`, or `YYYY`, depending on their precision
+    fn item_dates(item: &wikimisc::wikibase::Entity, property: &str) -> Vec<String> {
+        item.claims_with_property(property)
+            .iter()
+            .filter_map(|statement| {
+                let data_value = statement.main_snak().data_value().as_ref()?;
+                match data_value.value() {
+                    wikimisc::wikibase::value::Value::Time(tv) => {
+                        Self::date_at_precision(tv.time(), tv.precision().to_owned())
+                    }
+                    _ => None,
+                }
+            })
+            .unique()
+            .collect()
+    }
+
+    /// Formats a Wikibase time (eg `+1952-03-11T00:00:00Z`) according to its precision (9=year, 10=month, 11=day).
+    /// Returns `None` for BCE dates, and for precisions coarser than a year.
+    fn date_at_precision(time: &str, precision: u64) -> Option<String> {
+        let date = time.strip_prefix('+')?.split('T').next()?;
+        let len = match precision {
+            9 => 4,
+            10 => 7,
+            11.. => 10,
+            _ => return None,
+        };
+        date.get(0..len).map(|s| s.to_string())
+    }
+
+    /// Final tie-breaker for the person matchers: the only candidate with an occupation (P106) named in the entry description
+    fn disambiguate_by_occupation(
+        occupations: &OccupationDisambiguation,
+        entry: &Entry,
+        candidates: &[String],
+        items: &wikimisc::wikibase::entity_container::EntityContainer,
+    ) -> Option<String> {
+        let candidates: Vec<String> = candidates.iter().unique().cloned().collect();
+        occupations.pick_candidate(&entry.ext_desc, &candidates, |q| {
+            items
+                .get_entity(q.to_owned())
+                .map(|item| OccupationDisambiguation::item_occupations(&item))
+                .unwrap_or_default()
+        })
+    }
+
     async fn match_person_by_dates_process_result_get_candidate_items(
         &self,
         result: &(usize, String, String, String),
@@ -626,6 +1270,7 @@ impl AutoMatch {
 
     pub async fn match_person_by_dates(&mut self, catalog_id: usize) -> Result<()> {
         let mw_api = self.app.wikidata().get_mw_api().await?;
+        let occupations = OccupationDisambiguation::from_app(&self.app).await?;
         let mut offset = self.get_last_job_offset().await;
         let batch_size = 5000;
         loop {
@@ -637,7 +1282,7 @@ impl AutoMatch {
             for result in &results {
                 // Ignore error
                 let _ = self
-                    .match_person_by_dates_process_result(result, &mw_api)
+                    .match_person_by_dates_process_result(result, &mw_api, &occupations)
                     .await;
             }
             if results.len() < batch_size {
@@ -658,6 +1303,7 @@ impl AutoMatch {
     ) -> Result<()> {
         // let (match_field, match_prop) = match_field.get_field_and_prop();
         let mw_api = self.app.wikidata().get_mw_api().await?;
+        let occupations = OccupationDisambiguation::from_app(&self.app).await?;
         // CAUTION: Do NOT use views in the SQL statement, it will/might throw an "Prepared statement needs to be re-prepared" error
         let mut offset = self.get_last_job_offset().await;
         let batch_size = 100;
@@ -678,6 +1324,7 @@ impl AutoMatch {
                     &items,
                     precision.as_i32(),
                     &match_field,
+                    &occupations,
                 )
                 .await?;
             }
@@ -727,6 +1374,7 @@ impl AutoMatch {
         items: &wikimisc::wikibase::entity_container::EntityContainer,
         precision: i32,
         match_field: &DateMatchField,
+        occupations: &OccupationDisambiguation,
     ) -> Result<()> {
         let mut candidates = vec![];
         for q in &result.matches {
@@ -743,21 +1391,102 @@ impl AutoMatch {
                     result,
                     &mut candidates,
                     q,
-                );
+                )?;
             }
         }
-        if candidates.len() == 1 {
-            // TODO >1
-            if let Some(q) = candidates.first() {
-                let _ = Entry::from_id(result.entry_id, &self.app)
-                    .await?
-                    .set_match(q, USER_DATE_MATCH)
-                    .await;
+        let mut q = if candidates.len() == 1 {
+            candidates.first().cloned()
+        } else {
+            let rules = DatePromotionRules::from_app(&self.app);
+            Self::promote_full_date_candidate(result, &candidates, &rules, |q| {
+                items.get_entity(q.to_owned()).is_some_and(|item| {
+                    Self::item_has_date(&item, "P569", &result.born)
+                        && Self::item_has_date(&item, "P570", &result.died)
+                })
+            })
+        };
+        let mut entry = match q {
+            Some(_) => Entry::from_id(result.entry_id, &self.app).await?,
+            None if candidates.len() > 1 => {
+                let entry = Entry::from_id(result.entry_id, &self.app).await?;
+                q = Self::disambiguate_by_occupation(occupations, &entry, &candidates, items);
+                entry
             }
+            None => return Ok(()),
         };
+        if let Some(q) = q {
+            let _ = entry.set_match(&q, USER_DATE_MATCH).await;
+        }
         Ok(())
     }
 
+    /// Of several candidates that match one date, returns the only one that matches both birth and death date, if the rules allow it.
+    fn promote_full_date_candidate(
+        result: &CandidateDates,
+        candidates: &[String],
+        rules: &DatePromotionRules,
+        both_dates_match: impl Fn(&str) -> bool,
+    ) -> Option<String> {
+        if !rules.enabled {
+            return None;
+        }
+        if rules.require_day_precision && (result.born.len() != 10 || result.died.len() != 10) {
+            return None;
+        }
+        let candidates: Vec<&String> = candidates.iter().unique().collect();
+        if candidates.len() < 2 || candidates.len() > rules.max_candidates {
+            return None;
+        }
+        let full: Vec<&String> = candidates
+            .into_iter()
+            .filter(|q| both_dates_match(q))
+            .collect();
+        if full.len() == 1 {
+            full.first().map(|q| q.to_string())
+        } else {
+            None
+        }
+    }
+
+    /// Checks if an item has a date statement that matches a date, at the precision of the date (year or day)
+    fn item_has_date(item: &wikimisc::wikibase::Entity, property: &str, date: &str) -> bool {
+        let precision = match date.len() {
+            4 => 4,
+            10 => 10,
+            _ => return false,
+        };
+        item.claims_with_property(property)
+            .iter()
+            .filter_map(|statement| Self::statement_date(statement, precision).ok())
+            .any(|statement_date| statement_date.as_deref() == Some(date))
+    }
+
+    /// Returns the date of a time statement, as `YYYY` (precision 4) or `YYYY-MM-DD` (precision 10),
+    /// or `None` if the statement has no parsable time. Fails on any other precision.
+    fn statement_date(
+        statement: &wikimisc::wikibase::Statement,
+        precision: i32,
+    ) -> Result<Option<String>> {
+        let format = match precision {
+            4 => "%Y",
+            10 => "%Y-%m-%d",
+            other => return Err(anyhow!("Bad date precision {other}")),
+        };
+        let data_value = match statement.main_snak().data_value() {
+            Some(data_value) => data_value,
+            None => return Ok(None),
+        };
+        let time = match data_value.value() {
+            wikimisc::wikibase::value::Value::Time(tv) => tv,
+            _ => return Ok(None),
+        };
+        Ok(
+            NaiveDateTime::parse_from_str(time.time(), "+%Y-%m-%dT%H:%M:%SZ")
+                .ok() // Could not parse date
+                .map(|dt| dt.format(format).to_string()),
+        )
+    }
+
     //TODO test
     async fn search_person(&self, name: &str) -> Result<Vec<String>> {
         let name = Person::sanitize_simplify_name(name);
@@ -919,30 +1648,17 @@ impl AutoMatch {
         result: &CandidateDates,
         candidates: &mut Vec<String>,
         q: &str,
-    ) {
-        let main_snak = statement.main_snak();
-        let data_value = match main_snak.data_value() {
-            Some(dv) => dv,
-            None => return,
-        };
-        let time = match data_value.value() {
-            wikimisc::wikibase::value::Value::Time(tv) => tv,
-            _ => return,
-        };
-        let dt = match NaiveDateTime::parse_from_str(time.time(), "+%Y-%m-%dT%H:%M:%SZ") {
-            Ok(dt) => dt,
-            _ => return, // Could not parse date
-        };
-        let date = match precision {
-            4 => format!("{}", dt.format("%Y")),
-            10 => format!("{}", dt.format("%Y-%m-%d")),
-            other => panic!("Bad precision {}", other), // Should never happen
+    ) -> Result<()> {
+        let date = match Self::statement_date(statement, precision)? {
+            Some(date) => date,
+            None => return Ok(()),
         };
         if (match_field == "born" && date == result.born)
             || (match_field == "died" && date == result.died)
         {
             candidates.push(q.to_string());
         }
+        Ok(())
     }
 
     async fn automatch_complex_batch_process_row(
@@ -982,6 +1698,33 @@ impl AutoMatch {
         name2entries
     }
 
+    /// Searches for the normalized name variants of the entries that had no search results for their name
+    /// or aliases, concurrently for all entries. Per entry, the first variant with results wins,
+    /// so the least altered form is used.
+    async fn automatch_by_search_normalized_fallback(
+        &self,
+        result_batch: &[(usize, String, String, String)],
+        search_results: &[(usize, String)],
+    ) -> Vec<(usize, String)> {
+        let entries_with_results: HashSet<usize> =
+            search_results.iter().map(|(id, _)| *id).collect();
+        let futures = result_batch
+            .iter()
+            .filter(|(entry_id, ..)| !entries_with_results.contains(entry_id))
+            .map(|(entry_id, label, type_q, _aliases)| async move {
+                for variant in name_normalize::search_variants(label) {
+                    if let Some((entry_id, items)) = self
+                        .search_with_type_and_entity_id(*entry_id, &variant, type_q)
+                        .await
+                    {
+                        return items.into_iter().map(|q| (entry_id, q)).collect_vec();
+                    }
+                }
+                vec![]
+            });
+        join_all(futures).await.into_iter().flatten().collect()
+    }
+
     async fn automatch_by_search_process_results_batch_process_futures(
         &self,
         result_batch: &[(usize, String, String, String)],
@@ -1148,6 +1891,187 @@ mod tests {
         assert!(entry.is_unmatched());
     }
 
+    #[test]
+    fn test_automatch_entry_query() {
+        let query = AutoMatch::automatch_entry_query(123, Some(true), 5000, Some(456));
+        assert_eq!(query.catalog_id, Some(123));
+        assert_eq!(query.has_signal, Some(true));
+        assert_eq!(query.min_id_exclusive, Some(456));
+        assert_eq!(query.order_by, Some(EntryQueryOrder::Id));
+        assert_eq!(query.limit, Some(5000));
+        assert_eq!(query.offset, 0);
+        let query = AutoMatch::automatch_entry_query(123, None, 5000, None);
+        assert_eq!(query.min_id_exclusive, None);
+    }
+
+    #[test]
+    fn test_ext_url_to_unique_item() {
+        let found = vec![
+            ("https://example.org/a".to_string(), 2, 42),
+            ("http://www.example.org/a/".to_string(), 3, 42),
+            ("https://example.org/b".to_string(), 2, 1),
+            ("https://example.org/b".to_string(), 3, 2),
+            ("https://example.org/c".to_string(), 1, 7),
+        ];
+        let url2q = AutoMatch::ext_url_to_unique_item(&found, 1);
+        assert_eq!(url2q.get("example.org/a"), Some(&42));
+        assert_eq!(url2q.get("example.org/b"), None); // Ambiguous
+        assert_eq!(url2q.get("example.org/c"), None); // Same catalog
+    }
+
+    #[test]
+    fn test_date_at_precision() {
+        let time = "+1952-03-11T00:00:00Z";
+        assert_eq!(
+            AutoMatch::date_at_precision(time, 11),
+            Some("1952-03-11".to_string())
+        );
+        assert_eq!(
+            AutoMatch::date_at_precision(time, 10),
+            Some("1952-03".to_string())
+        );
+        assert_eq!(
+            AutoMatch::date_at_precision(time, 9),
+            Some("1952".to_string())
+        );
+        assert_eq!(AutoMatch::date_at_precision(time, 8), None);
+        assert_eq!(
+            AutoMatch::date_at_precision("-0500-00-00T00:00:00Z", 9),
+            None
+        );
+    }
+
+    #[test]
+    fn test_dates_disagree_at_day_precision() {
+        let year_only = vec!["1952".to_string()];
+        let both = vec!["1952".to_string(), "1952-03-11".to_string()];
+        assert!(AutoMatch::dates_disagree_at_day_precision(
+            "1952-03-11",
+            &year_only
+        ));
+        assert!(AutoMatch::dates_disagree_at_day_precision(
+            "1952-03-12",
+            &["1952-03-11".to_string()]
+        ));
+        assert!(!AutoMatch::dates_disagree_at_day_precision(
+            "1952-03-11",
+            &both
+        ));
+        assert!(!AutoMatch::dates_disagree_at_day_precision(
+            "1952", &year_only
+        ));
+        assert!(!AutoMatch::dates_disagree_at_day_precision(
+            "1952-03-11",
+            &[]
+        ));
+    }
+
+    #[test]
+    fn test_alias_search_rules_names_to_search() {
+        let rules = AliasSearchRules::default();
+        assert_eq!(
+            rules.names_to_search("Foo", "Bar| Foo |Baz||Bar"),
+            vec!["Foo", "Bar", "Baz"]
+        );
+        assert_eq!(rules.names_to_search("Foo", ""), vec!["Foo"]);
+        let rules = AliasSearchRules {
+            max_searches: 2,
+            ..Default::default()
+        };
+        assert_eq!(rules.names_to_search("Foo", "Bar|Baz"), vec!["Foo", "Bar"]);
+        let rules = AliasSearchRules {
+            max_searches: 0,
+            ..Default::default()
+        };
+        assert_eq!(rules.names_to_search("Foo", "Bar|Baz"), vec!["Foo"]);
+    }
+
+    #[test]
+    fn test_alias_search_rules_rank_items() {
+        let results = vec![
+            vec!["Q3".to_string(), "Q1".to_string()],
+            vec!["Q2".to_string(), "Q2".to_string()],
+            vec!["Q2".to_string(), "Q3".to_string()],
+        ];
+        let rules = AliasSearchRules::default();
+        assert_eq!(rules.rank_items(results.clone()), vec!["Q1", "Q2", "Q3"]);
+        let rules = AliasSearchRules {
+            prefer_multiple_hits: true,
+            ..Default::default()
+        };
+        assert_eq!(rules.rank_items(results), vec!["Q2", "Q3", "Q1"]);
+    }
+
+    #[test]
+    fn test_promote_full_date_candidate() {
+        let result = CandidateDates {
+            entry_id: 1,
+            born: "1900-01-02".to_string(),
+            died: "1950-03-04".to_string(),
+            matches: vec![],
+        };
+        let candidates: Vec<String> = ["Q1", "Q2", "Q2", "Q3"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let q2_full = |q: &str| q == "Q2";
+        let rules = DatePromotionRules {
+            enabled: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            AutoMatch::promote_full_date_candidate(&result, &candidates, &rules, q2_full),
+            Some("Q2".to_string())
+        );
+
+        // Off by default
+        let default_rules = DatePromotionRules::default();
+        assert_eq!(
+            AutoMatch::promote_full_date_candidate(&result, &candidates, &default_rules, q2_full),
+            None
+        );
+
+        // Not if two candidates match both dates
+        assert_eq!(
+            AutoMatch::promote_full_date_candidate(&result, &candidates, &rules, |q| q != "Q1"),
+            None
+        );
+
+        // Too many candidates
+        let strict = DatePromotionRules {
+            max_candidates: 2,
+            ..rules
+        };
+        assert_eq!(
+            AutoMatch::promote_full_date_candidate(&result, &candidates, &strict, q2_full),
+            None
+        );
+
+        // Year precision only with any_precision
+        let year_result = CandidateDates {
+            born: "1900".to_string(),
+            died: "1950".to_string(),
+            ..result
+        };
+        assert_eq!(
+            AutoMatch::promote_full_date_candidate(&year_result, &candidates, &rules, q2_full),
+            None
+        );
+        let any_precision = DatePromotionRules {
+            require_day_precision: false,
+            ..rules
+        };
+        assert_eq!(
+            AutoMatch::promote_full_date_candidate(
+                &year_result,
+                &candidates,
+                &any_precision,
+                q2_full
+            ),
+            Some("Q2".to_string())
+        );
+    }
+
     #[tokio::test]
     async fn test_match_person_by_single_date() {
         let _test_lock = TEST_MUTEX.lock();
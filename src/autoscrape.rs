@@ -1,15 +1,21 @@
-use crate::app_state::AppState;
+use crate::app_state::{AppState, HostLimits};
+use crate::autoscrape_config::AutoscrapeConfig;
 use crate::autoscrape_levels::*;
 use crate::autoscrape_resolve::RE_SIMPLE_SPACE;
 use crate::autoscrape_scraper::AutoscrapeScraper;
 use crate::catalog::Catalog;
+use crate::datasource::KV_STRIP_HTML_DESCRIPTIONS;
 use crate::extended_entry::ExtendedEntry;
 use crate::job::*;
+use crate::permissions::Permissions;
 use anyhow::Result;
 use serde_json::{json, Value};
 use std::collections::HashMap;
 use std::error::Error;
 use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{info, info_span, warn, Instrument};
 
 //pub type AutoscrapeRegex = fancy_regex::Regex;
 //pub type AutoscrapeRegexBuilder = fancy_regex::RegexBuilder;
@@ -21,6 +27,8 @@ const AUTOSCRAPER_USER_AGENT: &str =
     "Mozilla/5.0 (Macintosh; Intel Mac OS X 10.12; rv:56.0) Gecko/20100101 Firefox/56.0";
 const AUTOSCRAPE_ENTRY_BATCH_SIZE: usize = 100;
 const AUTOSCRAPE_URL_LOAD_TIMEOUT_SEC: u64 = 60;
+/// How long a loaded robots.txt is used before it is loaded again
+const ROBOTS_TXT_TTL_SEC: u64 = 3600;
 
 #[derive(Debug, Clone)]
 pub enum AutoscrapeError {
@@ -28,6 +36,8 @@ pub enum AutoscrapeError {
     UnknownLevelType(String),
     BadType(Value),
     MediawikiFailure(String),
+    InvalidConfig(String),
+    DisallowedByRobots(String),
 }
 
 impl Error for AutoscrapeError {}
@@ -39,6 +49,10 @@ impl fmt::Display for AutoscrapeError {
             AutoscrapeError::UnknownLevelType(s) => write!(f, "{s}"), // user-facing output
             AutoscrapeError::BadType(v) => write!(f, "{v}"),
             AutoscrapeError::MediawikiFailure(v) => write!(f, "{v}"),
+            AutoscrapeError::InvalidConfig(s) => write!(f, "Invalid autoscrape config: {s}"), // user-facing output
+            AutoscrapeError::DisallowedByRobots(url) => {
+                write!(f, "Disallowed by robots.txt: {url}") // user-facing output
+            }
             AutoscrapeError::NoAutoscrapeForCatalog(catalog_id) => {
                 write!(f, "No Autoscraper for catalog {catalog_id}")
             }
@@ -85,6 +99,239 @@ pub trait JsonStuff {
     }
 }
 
+/// Loads external URLs for an autoscraper, through the shared `HostThrottle` with the limits of its catalog,
+/// unless robots.txt disallows them. Cloned into levels that load pages themselves; clones share the robots.txt cache and the log.
+#[derive(Debug, Clone)]
+pub struct ScrapeFetcher {
+    app: AppState,
+    client: reqwest::Client,
+    limits: HostLimits,
+    robots: Arc<tokio::sync::Mutex<RobotsCache>>,
+    /// URL and text of the last page loaded by `get_text`, so a level that parses the page the scraper
+    /// has just loaded (eg for a "next page" link) does not download it again
+    last_page: Arc<std::sync::Mutex<Option<(String, String)>>>,
+    /// Problems of this run that did not stop it, eg URLs disallowed by robots.txt
+    log: Arc<std::sync::Mutex<Vec<String>>>,
+}
+
+impl ScrapeFetcher {
+    pub fn new(app: &AppState, limits: HostLimits) -> Result<Self> {
+        Ok(Self {
+            app: app.clone(),
+            client: Autoscrape::reqwest_client_external()?,
+            limits,
+            robots: Arc::new(tokio::sync::Mutex::new(RobotsCache::default())),
+            last_page: Arc::new(std::sync::Mutex::new(None)),
+            log: Arc::new(std::sync::Mutex::new(vec![])),
+        })
+    }
+
+    /// Loads a URL, or fails with `AutoscrapeError::DisallowedByRobots`, which is added to the log
+    pub async fn get(&self, url: &str) -> Result<reqwest::Response> {
+        if !self.robots.lock().await.is_allowed(self, url).await {
+            let e = AutoscrapeError::DisallowedByRobots(url.to_string());
+            info!("Autoscrape: {e}");
+            if let Ok(mut log) = self.log.lock() {
+                log.push(e.to_string());
+            }
+            return Err(e.into());
+        }
+        self.get_unchecked(url).await
+    }
+
+    pub fn log(&self) -> Vec<String> {
+        self.log.lock().map(|log| log.clone()).unwrap_or_default()
+    }
+
+    pub async fn get_text(&self, url: &str) -> Result<String> {
+        if let Some(text) = self.last_page_text(url) {
+            return Ok(text);
+        }
+        let text = self.get(url).await?.error_for_status()?.text().await?;
+        if let Ok(mut last_page) = self.last_page.lock() {
+            *last_page = Some((url.to_string(), text.to_owned()));
+        }
+        Ok(text)
+    }
+
+    fn last_page_text(&self, url: &str) -> Option<String> {
+        let last_page = self.last_page.lock().ok()?;
+        match last_page.as_ref() {
+            Some((last_url, text)) if last_url == url => Some(text.to_owned()),
+            _ => None,
+        }
+    }
+
+    /// Loads a URL without checking robots.txt, eg for robots.txt itself
+    async fn get_unchecked(&self, url: &str) -> Result<reqwest::Response> {
+        self.app
+            .host_throttle()
+            .get(&self.client, url, self.limits)
+            .await
+    }
+}
+
+/// The `Allow`/`Disallow` rules of a robots.txt that apply to a user agent
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RobotsRules {
+    /// (allow, path pattern)
+    rules: Vec<(bool, String)>,
+}
+
+impl RobotsRules {
+    /// Parses a robots.txt. Uses the groups whose `User-agent` is the product token of `user_agent`
+    /// (eg `Mozilla` for `Mozilla/5.0 (...)`, case-insensitive), or the `*` groups if there are none.
+    pub fn parse(text: &str, user_agent: &str) -> Self {
+        let token = Self::product_token(user_agent);
+        let mut groups: Vec<(Vec<String>, Vec<(bool, String)>)> = vec![];
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let (key, value) = match line.split_once(':') {
+                Some((key, value)) => (key.trim().to_lowercase(), value.trim()),
+                None => continue,
+            };
+            match key.as_str() {
+                "user-agent" => match groups.last_mut() {
+                    Some((agents, rules)) if rules.is_empty() => agents.push(value.to_lowercase()),
+                    _ => groups.push((vec![value.to_lowercase()], vec![])),
+                },
+                "allow" | "disallow" if !value.is_empty() => {
+                    if let Some((_, rules)) = groups.last_mut() {
+                        rules.push((key == "allow", value.to_string()));
+                    }
+                }
+                _ => {}
+            }
+        }
+        let matches_agent = |agents: &[String], specific: bool| {
+            agents.iter().any(|agent| match specific {
+                true => agent != "*" && Self::product_token(agent) == token,
+                false => agent == "*",
+            })
+        };
+        let specific = groups.iter().any(|(agents, _)| matches_agent(agents, true));
+        let rules = groups
+            .into_iter()
+            .filter(|(agents, _)| matches_agent(agents, specific))
+            .flat_map(|(_, rules)| rules)
+            .collect();
+        Self { rules }
+    }
+
+    /// The name of a user agent without version or comment, lowercased
+    fn product_token(user_agent: &str) -> String {
+        user_agent
+            .split(['/', ' '])
+            .next()
+            .unwrap_or_default()
+            .to_lowercase()
+    }
+
+    /// Checks a path (with query string). The longest matching rule wins; `Allow` wins a tie.
+    pub fn is_allowed(&self, path: &str) -> bool {
+        self.rules
+            .iter()
+            .filter(|(_, pattern)| Self::pattern_matches(pattern, path))
+            .max_by_key(|(allow, pattern)| (pattern.len(), *allow))
+            .map(|(allow, _)| *allow)
+            .unwrap_or(true)
+    }
+
+    /// Matches a robots.txt path pattern, with `*` wildcards and an optional `$` end anchor
+    fn pattern_matches(pattern: &str, path: &str) -> bool {
+        let (pattern, anchored) = match pattern.strip_suffix('$') {
+            Some(pattern) => (pattern, true),
+            None => (pattern, false),
+        };
+        let regex = pattern
+            .split('*')
+            .map(regex::escape)
+            .collect::<Vec<String>>()
+            .join(".*");
+        let regex = format!("^{regex}{}", if anchored { "$" } else { "" });
+        regex::Regex::new(&regex)
+            .map(|re| re.is_match(path))
+            .unwrap_or(false)
+    }
+}
+
+/// robots.txt rules per host, loaded on first use, and again after `ROBOTS_TXT_TTL_SEC`
+#[derive(Debug, Default)]
+pub struct RobotsCache {
+    hosts: HashMap<String, (Instant, RobotsRules)>,
+}
+
+impl RobotsCache {
+    /// Checks if our user agent may load a URL. An unavailable robots.txt allows everything.
+    pub async fn is_allowed(&mut self, fetcher: &ScrapeFetcher, url: &str) -> bool {
+        let url = match reqwest::Url::parse(url) {
+            Ok(url) => url,
+            Err(_) => return true,
+        };
+        let origin = url.origin().ascii_serialization();
+        let is_fresh = |(loaded, _): &(Instant, RobotsRules)| {
+            loaded.elapsed() < Duration::from_secs(ROBOTS_TXT_TTL_SEC)
+        };
+        if !self.hosts.get(&origin).is_some_and(is_fresh) {
+            let rules = Self::load_rules(fetcher, &origin).await;
+            self.hosts
+                .insert(origin.to_owned(), (Instant::now(), rules));
+        }
+        let mut path = url.path().to_string();
+        if let Some(query) = url.query() {
+            path = format!("{path}?{query}");
+        }
+        match self.hosts.get(&origin) {
+            Some((_, rules)) => rules.is_allowed(&path),
+            None => true,
+        }
+    }
+
+    async fn load_rules(fetcher: &ScrapeFetcher, origin: &str) -> RobotsRules {
+        let response = match fetcher.get_unchecked(&format!("{origin}/robots.txt")).await {
+            Ok(response) if response.status().is_success() => response,
+            _ => return RobotsRules::default(),
+        };
+        match response.text().await {
+            Ok(text) => RobotsRules::parse(&text, AUTOSCRAPER_USER_AGENT),
+            Err(_) => RobotsRules::default(),
+        }
+    }
+}
+
+/// A page loaded by `Autoscrape::scrape_test`, with the entries parsed from it
+#[derive(Debug, Clone)]
+pub struct ScrapeTestPage {
+    pub url: String,
+    pub entries: Vec<ExtendedEntry>,
+}
+
+impl ScrapeTestPage {
+    pub fn to_json(&self) -> Value {
+        let entries: Vec<Value> = self
+            .entries
+            .iter()
+            .map(|ee| {
+                let mut aux: Vec<(usize, String)> = ee.aux.iter().cloned().collect();
+                aux.sort();
+                let aux: Vec<Value> = aux
+                    .into_iter()
+                    .map(|(prop, value)| json!({"property": format!("P{prop}"), "value": value}))
+                    .collect();
+                json!({
+                    "ext_id": ee.entry.ext_id,
+                    "ext_name": ee.entry.ext_name,
+                    "ext_desc": ee.entry.ext_desc,
+                    "ext_url": ee.entry.ext_url,
+                    "type": ee.entry.type_name,
+                    "aux": aux,
+                })
+            })
+            .collect();
+        json!({"url": self.url, "entries": entries})
+    }
+}
+
 #[derive(Debug)]
 pub struct Autoscrape {
     autoscrape_id: usize,
@@ -97,7 +344,13 @@ pub struct Autoscrape {
     app: AppState,
     job: Option<Job>,
     urls_loaded: usize,
+    entries_found: usize,
     entry_batch: Vec<ExtendedEntry>,
+    fetcher: ScrapeFetcher,
+    /// MD5 of the scraper JSON, to invalidate saved progress when the scraper changes
+    config_md5: String,
+    /// If the catalog has `strip_html_descriptions` set
+    strip_html_descriptions: bool,
 }
 
 impl Jobbable for Autoscrape {
@@ -119,15 +372,51 @@ impl Autoscrape {
     //TODO test
     pub async fn new(catalog_id: usize, app: &AppState) -> Result<Self> {
         let results = app.storage().autoscrape_get_for_catalog(catalog_id).await?;
-        let (id, json) = results
+        let (id, json_string) = results
             .first()
             .ok_or(AutoscrapeError::NoAutoscrapeForCatalog(catalog_id))?;
-        let json: Value = serde_json::from_str(json)?;
+        let json: Value = serde_json::from_str(json_string)?;
+        // Configs are validated when they are saved; older ones may be lenient, but still run
+        if let Err(e) = AutoscrapeConfig::from_json(&json) {
+            warn!("Autoscrape config of catalog {catalog_id} is not valid, running it anyway: {e}");
+        }
         let mut ret = Self::new_basic(id, catalog_id, app, &json)?;
+        let kv = app
+            .storage()
+            .get_catalog_key_value_pairs(catalog_id)
+            .await?;
+        ret.fetcher = ScrapeFetcher::new(app, HostLimits::from_key_value_pairs(&kv))?;
+        ret.strip_html_descriptions = kv
+            .get(KV_STRIP_HTML_DESCRIPTIONS)
+            .map(|v| v.trim() == "1")
+            .unwrap_or(false);
+        ret.config_md5 = format!("{:x}", md5::compute(json_string));
         Self::initialize_with_options(json, &mut ret)?;
         Ok(ret)
     }
 
+    /// Saves a new or changed autoscrape config for a catalog, and queues it to run.
+    /// The user needs to own the existing scraper, or be allowed to edit the catalog;
+    /// the user then becomes the owner of the scraper.
+    pub async fn save_config(
+        app: &AppState,
+        catalog_id: usize,
+        config: &AutoscrapeConfig,
+        user_id: usize,
+    ) -> Result<()> {
+        let owns_scraper =
+            user_id > 0 && app.storage().autoscrape_get_owner(catalog_id).await? == Some(user_id);
+        if !owns_scraper {
+            let catalog = Catalog::from_id(catalog_id, app).await?;
+            Permissions::new(app)
+                .check_can_edit_catalog(user_id, &catalog)
+                .await?;
+        }
+        app.storage()
+            .save_autoscrape_config(catalog_id, config, user_id)
+            .await
+    }
+
     pub fn catalog_id(&self) -> usize {
         self.catalog_id
     }
@@ -136,10 +425,59 @@ impl Autoscrape {
         &self.app
     }
 
+    pub fn fetcher(&self) -> &ScrapeFetcher {
+        &self.fetcher
+    }
+
+    /// Problems of this run that did not stop it, eg URLs disallowed by robots.txt
+    pub fn log(&self) -> Vec<String> {
+        self.fetcher.log()
+    }
+
     pub fn levels(&self) -> &Vec<AutoscrapeLevel> {
         &self.levels
     }
 
+    pub fn autoscrape_id(&self) -> usize {
+        self.autoscrape_id
+    }
+
+    pub fn strip_html_descriptions(&self) -> bool {
+        self.strip_html_descriptions
+    }
+
+    /// Loads and parses the first page of the scraper, without adding any entries.
+    /// Returns the number of entries found on that page.
+    pub async fn test_fetch(&mut self) -> usize {
+        self.scrape_test(1)
+            .await
+            .iter()
+            .map(|page| page.entries.len())
+            .sum()
+    }
+
+    /// Runs the scraper for up to `max_urls` URLs, with the usual rate limits and robots.txt rules,
+    /// but without writing anything to the database.
+    pub async fn scrape_test(&mut self, max_urls: usize) -> Vec<ScrapeTestPage> {
+        self.init().await;
+        let mut ret = vec![];
+        while ret.len() < max_urls {
+            let url = self.get_current_url().await;
+            let entries = match self.fetch_url(&url).await {
+                Some(html) => {
+                    let html = self.patch_html(html);
+                    self.scraper.process_html_page(&html, self)
+                }
+                None => vec![],
+            };
+            ret.push(ScrapeTestPage { url, entries });
+            if self.tick().await {
+                break;
+            }
+        }
+        ret
+    }
+
     //TODO test
     fn options_from_json(&mut self, json: &Value) {
         self.simple_space = json
@@ -199,37 +537,42 @@ impl Autoscrape {
         if crosses_threshold {
             let _ = self.remember_state().await;
         }
+        self.fetch_url(url).await
+    }
+
+    /// Loads a URL, unless robots.txt disallows it
+    async fn fetch_url(&self, url: &str) -> Option<String> {
         // TODO POST
-        Self::reqwest_client_external()
-            .ok()?
-            .get(url)
-            .send()
-            .await
-            .ok()?
-            .text()
-            .await
-            .ok()
+        self.fetcher.get_text(url).await.ok()
     }
 
     async fn get_current_url(&self) -> String {
-        let current = self.current();
-        let mut url = self.scraper.url().to_string();
-        current
-            .iter()
-            .enumerate()
-            .for_each(|(l0, s)| url = url.replace(&format!("${}", l0 + 1), s));
+        Self::url_with_level_values(self.scraper.url(), &self.current())
+    }
+
+    /// Replaces `$1`, `$2` etc. in a URL with the current values of the levels.
+    /// Higher levels go first, so `$10` is not taken for `$1` followed by "0".
+    pub fn url_with_level_values(url: &str, values: &[String]) -> String {
+        let mut url = url.to_string();
+        for (l0, value) in values.iter().enumerate().rev() {
+            url = url.replace(&format!("${}", l0 + 1), value);
+        }
         url
     }
 
     async fn get_patched_html(&mut self, url: String) -> Option<String> {
-        let mut html = self.load_url(&url).await?;
+        let html = self.load_url(&url).await?;
+        Some(self.patch_html(html))
+    }
+
+    fn patch_html(&self, mut html: String) -> String {
         if self.simple_space {
             html = RE_SIMPLE_SPACE.replace_all(&html, " ").to_string();
         }
         if self.utf8_encode {
             // TODO
         }
-        Some(html)
+        html
     }
 
     //TODO test
@@ -238,6 +581,7 @@ impl Autoscrape {
         let url = self.get_current_url().await;
         if let Some(html) = self.get_patched_html(url).await {
             let mut extended_entries = self.scraper.process_html_page(&html, self);
+            self.entries_found += extended_entries.len();
             self.entry_batch.append(&mut extended_entries);
             let entry_batch_len = self.entry_batch.len();
             if entry_batch_len >= AUTOSCRAPE_ENTRY_BATCH_SIZE {
@@ -312,27 +656,112 @@ impl Autoscrape {
             .collect();
         let json = json!(json);
         self.remember_job_data(&json).await?;
+        let progress = self.progress_json().to_string();
+        // A missing checkpoint only means an interrupted run starts over, so it does not stop the scrape
+        if let Err(e) = self
+            .app
+            .storage()
+            .autoscrape_save_progress(self.autoscrape_id, &progress)
+            .await
+        {
+            warn!(
+                "Autoscrape {}: could not save progress: {e}",
+                self.autoscrape_id
+            );
+        }
+        Catalog::invalidate_cache(self.catalog_id, &self.app); // Progress is a catalog key-value pair
         Ok(())
     }
 
+    /// The current position of all levels, for `autoscrape_save_progress`
+    fn progress_json(&self) -> Value {
+        let levels: Vec<Value> = self
+            .levels
+            .iter()
+            .map(|level| level.level_type().get_state())
+            .collect();
+        json!({"config_md5": self.config_md5, "levels": levels, "urls_loaded": self.urls_loaded})
+    }
+
+    /// Restores the position from `progress_json` output.
+    /// Returns `false`, changing nothing, if the progress is for another scraper configuration, or does not fit the levels.
+    fn restore_progress(&mut self, progress: &Value) -> bool {
+        if progress.get("config_md5").and_then(|v| v.as_str()) != Some(self.config_md5.as_str()) {
+            return false;
+        }
+        let levels = match progress.get("levels").and_then(|v| v.as_array()) {
+            Some(levels) if levels.len() == self.levels.len() => levels,
+            _ => return false,
+        };
+        levels
+            .iter()
+            .enumerate()
+            .for_each(|(num, j)| self.levels[num].level_type_mut().set_state(j));
+        self.urls_loaded = progress
+            .get("urls_loaded")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(0) as usize;
+        true
+    }
+
+    /// Resumes from the progress saved by an interrupted run, if there is any, and it matches the current scraper.
+    /// Returns `true` if there was saved progress, whether it was used or discarded.
+    async fn resume_saved_progress(&mut self) -> Result<bool> {
+        let progress = match self
+            .app
+            .storage()
+            .autoscrape_get_progress(self.autoscrape_id)
+            .await?
+        {
+            Some(progress) => progress,
+            None => return Ok(false),
+        };
+        let progress: Value = serde_json::from_str(&progress).unwrap_or_default();
+        if self.restore_progress(&progress) {
+            info!("Autoscrape {} resuming saved progress", self.autoscrape_id);
+        } else {
+            info!(
+                "Autoscrape {}: discarding saved progress, the scraper has changed",
+                self.autoscrape_id
+            );
+        }
+        Ok(true)
+    }
+
     //TODO test
     pub async fn run(&mut self) -> Result<()> {
-        self.init().await;
-        let _ = self.start().await;
-        loop {
-            self.iterate_one().await;
-            if self.tick().await {
-                break;
+        let span = info_span!(
+            "autoscrape",
+            autoscrape_id = self.autoscrape_id,
+            catalog_id = self.catalog_id
+        );
+        async {
+            self.init().await;
+            if let Err(e) = self.start().await {
+                warn!("Autoscrape start failed: {e}");
             }
+            loop {
+                self.iterate_one().await;
+                if self.tick().await {
+                    break;
+                }
+            }
+            if let Err(e) = self.finish().await {
+                warn!("Autoscrape finish failed: {e}");
+            }
+            Ok(())
         }
-        let _ = self.finish().await;
-        Ok(())
+        .instrument(span)
+        .await
     }
 
     //TODO test
     pub async fn start(&mut self) -> Result<()> {
         let autoscrape_id = self.autoscrape_id;
         self.app.storage().autoscrape_start(autoscrape_id).await?;
+        if self.resume_saved_progress().await? {
+            return Ok(());
+        }
         if let Some(json) = self.get_last_job_data().await {
             if let Some(arr) = json.as_array() {
                 if arr.len() == self.levels.len() {
@@ -354,12 +783,25 @@ impl Autoscrape {
             .storage()
             .autoscrape_finish(autoscrape_id, last_run_urls)
             .await?;
+        Catalog::invalidate_cache(self.catalog_id, &self.app); // Progress was removed
+        let _ = self
+            .app
+            .storage()
+            .autoscrape_record_run_result(autoscrape_id, self.entries_found, false)
+            .await;
         let catalog = Catalog::from_id(self.catalog_id, &self.app).await?;
         let _ = catalog.refresh_overview_table().await;
         let _ = self.clear_offset().await;
         let _ =
             Job::queue_simple_job(&self.app, self.catalog_id, "automatch_by_search", None).await;
         let _ = Job::queue_simple_job(&self.app, self.catalog_id, "microsync", None).await;
+        let log = self.log();
+        if !log.is_empty() {
+            let note = format!("{} URLs disallowed by robots.txt", log.len());
+            if let Some(job) = self.get_current_job_mut() {
+                let _ = job.set_note(Some(note)).await;
+            }
+        }
         Ok(())
     }
 
@@ -396,7 +838,11 @@ impl Autoscrape {
             )?,
             job: None,
             urls_loaded: 0,
+            entries_found: 0,
             entry_batch: vec![],
+            fetcher: ScrapeFetcher::new(app, HostLimits::default())?,
+            config_md5: String::new(),
+            strip_html_descriptions: false,
         };
         Ok(ret)
     }
@@ -429,6 +875,22 @@ mod tests {
     const _TEST_ENTRY_ID: usize = 143962196;
     const _TEST_ITEM_ID: usize = 13520818; // Q13520818
 
+    #[test]
+    fn test_scrape_test_page_to_json() {
+        let mut ee = ExtendedEntry::default();
+        ee.entry.ext_id = "abc".to_string();
+        ee.entry.ext_name = "Foo".to_string();
+        ee.aux.insert((214, "123".to_string()));
+        let page = ScrapeTestPage {
+            url: "https://example.org/1".to_string(),
+            entries: vec![ee],
+        };
+        let j = page.to_json();
+        assert_eq!(j["url"], json!("https://example.org/1"));
+        assert_eq!(j["entries"][0]["ext_name"], json!("Foo"));
+        assert_eq!(j["entries"][0]["aux"][0]["property"], json!("P214"));
+    }
+
     #[test]
     fn test_fix_regex() {
         let s = r#"<input type=\"checkbox\" name=\"genre\" id=\"(|sub)genreid\\:D[+]+([\\d]+)\" aria-label=\"Filter by (genre|style): (.+?)\" value=\"(.+?)\">"#;
@@ -436,6 +898,33 @@ mod tests {
         let _r = AutoscrapeRegex::new(&s).expect("fix regex fail");
     }
 
+    #[test]
+    fn test_robots_rules() {
+        let robots_txt = "User-agent: *\nDisallow: /search\nAllow: /search/about\nDisallow: /*.pdf$ # documents\n\nUser-agent: BadBot\nDisallow: /";
+        let rules = RobotsRules::parse(robots_txt, AUTOSCRAPER_USER_AGENT);
+        assert!(rules.is_allowed("/person/123"));
+        assert!(!rules.is_allowed("/search?q=foo"));
+        assert!(rules.is_allowed("/search/about"));
+        assert!(!rules.is_allowed("/files/list.pdf"));
+        assert!(rules.is_allowed("/files/list.pdf?page=2"));
+
+        let rules = RobotsRules::parse(robots_txt, "BadBot/1.0");
+        assert!(!rules.is_allowed("/person/123"));
+        let rules = RobotsRules::parse(robots_txt, "NotABadBot/1.0");
+        assert!(rules.is_allowed("/person/123"));
+        assert!(!rules.is_allowed("/search?q=foo"));
+        let rules = RobotsRules::parse("User-agent: Bot\nDisallow: /", "BadBot/1.0");
+        assert!(rules.is_allowed("/person/123"));
+        let rules = RobotsRules::parse(
+            "User-agent: mozilla/5.0\nDisallow: /",
+            AUTOSCRAPER_USER_AGENT,
+        );
+        assert!(!rules.is_allowed("/person/123"));
+
+        let rules = RobotsRules::parse("User-agent: *\nDisallow:", AUTOSCRAPER_USER_AGENT);
+        assert!(rules.is_allowed("/"));
+    }
+
     #[tokio::test]
     async fn test_autoscrape() {
         let mnm = get_test_app();
@@ -447,4 +936,30 @@ mod tests {
         }
         assert_eq!(cnt, 319);
     }
+
+    #[tokio::test]
+    async fn test_restore_progress() {
+        let mnm = get_test_app();
+        let mut autoscrape = Autoscrape::new(TEST_CATALOG_ID, &mnm).await.unwrap();
+        autoscrape.init().await;
+        for _ in 0..5 {
+            autoscrape.tick().await;
+        }
+        autoscrape.urls_loaded = 5;
+        let progress = autoscrape.progress_json();
+
+        let mut resumed = Autoscrape::new(TEST_CATALOG_ID, &mnm).await.unwrap();
+        resumed.init().await;
+        assert!(resumed.restore_progress(&progress));
+        assert_eq!(resumed.current(), autoscrape.current());
+        assert_eq!(resumed.urls_loaded, 5);
+
+        // Progress for a changed scraper is discarded
+        let mut changed = Autoscrape::new(TEST_CATALOG_ID, &mnm).await.unwrap();
+        changed.init().await;
+        changed.config_md5 = "something else".to_string();
+        let before = changed.current();
+        assert!(!changed.restore_progress(&progress));
+        assert_eq!(changed.current(), before);
+    }
 }
@@ -0,0 +1,477 @@
+use crate::autoscrape::{AutoscrapeError, AutoscrapeRegex, AutoscrapeRegexBuilder, JsonStuff};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// A number that can be given as a JSON number or a numeric string, eg `5` or `"5"`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum AutoscrapeNumber {
+    Number(u64),
+    Text(String),
+}
+
+impl AutoscrapeNumber {
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            AutoscrapeNumber::Number(n) => Some(*n),
+            AutoscrapeNumber::Text(s) => s.trim().parse::<u64>().ok(),
+        }
+    }
+}
+
+/// A single regular expression, or a list of them
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum AutoscrapeRegexList {
+    Single(String),
+    Multiple(Vec<String>),
+}
+
+impl AutoscrapeRegexList {
+    pub fn as_vec(&self) -> Vec<&str> {
+        match self {
+            AutoscrapeRegexList::Single(s) => vec![s.as_str()],
+            AutoscrapeRegexList::Multiple(v) => v.iter().map(|s| s.as_str()).collect(),
+        }
+    }
+}
+
+/// A level of an autoscrape config; `extra` holds the keys not known here, so they survive saving
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "lowercase")]
+pub enum AutoscrapeLevelConfig {
+    Keys {
+        keys: Vec<String>,
+        #[serde(flatten)]
+        extra: Map<String, Value>,
+    },
+    Range {
+        start: AutoscrapeNumber,
+        end: AutoscrapeNumber,
+        step: AutoscrapeNumber,
+        #[serde(flatten)]
+        extra: Map<String, Value>,
+    },
+    Follow {
+        url: String,
+        rx: String,
+        #[serde(flatten)]
+        extra: Map<String, Value>,
+    },
+    #[serde(rename = "mediawiki")]
+    MediaWiki {
+        url: String,
+        #[serde(flatten)]
+        extra: Map<String, Value>,
+    },
+    Paginate {
+        url: String,
+        rx: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        max_pages: Option<AutoscrapeNumber>,
+        #[serde(flatten)]
+        extra: Map<String, Value>,
+    },
+}
+
+impl AutoscrapeLevelConfig {
+    pub fn mode(&self) -> &str {
+        match self {
+            AutoscrapeLevelConfig::Keys { .. } => "keys",
+            AutoscrapeLevelConfig::Range { .. } => "range",
+            AutoscrapeLevelConfig::Follow { .. } => "follow",
+            AutoscrapeLevelConfig::MediaWiki { .. } => "mediawiki",
+            AutoscrapeLevelConfig::Paginate { .. } => "paginate",
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        match self {
+            AutoscrapeLevelConfig::Keys { .. } | AutoscrapeLevelConfig::MediaWiki { .. } => Ok(()),
+            AutoscrapeLevelConfig::Range {
+                start, end, step, ..
+            } => {
+                let start = AutoscrapeConfig::number("start", start)?;
+                let end = AutoscrapeConfig::number("end", end)?;
+                let step = AutoscrapeConfig::number("step", step)?;
+                if step == 0 {
+                    return Err("step: must be larger than 0".to_string());
+                }
+                if start > end {
+                    return Err(format!("start ({start}) is larger than end ({end})"));
+                }
+                Ok(())
+            }
+            AutoscrapeLevelConfig::Follow { rx, .. } => AutoscrapeConfig::regex("rx", rx),
+            AutoscrapeLevelConfig::Paginate { rx, max_pages, .. } => {
+                if let Some(max_pages) = max_pages {
+                    AutoscrapeConfig::number("max_pages", max_pages)?;
+                }
+                AutoscrapeConfig::regex("rx", rx)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AutoscrapeResolveConfig {
+    #[serde(rename = "use")]
+    pub use_pattern: String,
+    #[serde(default)]
+    pub rx: Vec<(String, String)>,
+    /// Keys not known here, kept so they are not lost when the config is saved
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AutoscrapeResolvesConfig {
+    pub id: AutoscrapeResolveConfig,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<AutoscrapeResolveConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub desc: Option<AutoscrapeResolveConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<AutoscrapeResolveConfig>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub entry_type: Option<AutoscrapeResolveConfig>,
+    /// Keys not known here, kept so they are not lost when the config is saved
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl AutoscrapeResolvesConfig {
+    fn validate(&self) -> Result<(), String> {
+        let resolves = [
+            ("id", Some(&self.id)),
+            ("name", self.name.as_ref()),
+            ("desc", self.desc.as_ref()),
+            ("url", self.url.as_ref()),
+            ("type", self.entry_type.as_ref()),
+        ];
+        for (key, resolve) in resolves {
+            let resolve = match resolve {
+                Some(resolve) => resolve,
+                None => continue,
+            };
+            for (num, (pattern, _replacement)) in resolve.rx.iter().enumerate() {
+                AutoscrapeConfig::regex(&format!("{key}.rx[{num}]"), pattern)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AutoscrapeAuxConfig {
+    pub prop: String,
+    pub id: String,
+    /// Keys not known here, kept so they are not lost when the config is saved
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AutoscrapeOptionsConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub simple_space: Option<AutoscrapeNumber>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skip_failed: Option<AutoscrapeNumber>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub utf8_encode: Option<AutoscrapeNumber>,
+    /// Keys not known here, kept so they are not lost when the config is saved
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl AutoscrapeOptionsConfig {
+    fn validate(&self) -> Result<(), String> {
+        let options = [
+            ("simple_space", &self.simple_space),
+            ("skip_failed", &self.skip_failed),
+            ("utf8_encode", &self.utf8_encode),
+        ];
+        for (key, value) in options {
+            let value = match value {
+                Some(value) => value,
+                None => continue,
+            };
+            AutoscrapeConfig::number(key, value)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AutoscrapeScraperConfig {
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rx_block: Option<String>,
+    pub rx_entry: AutoscrapeRegexList,
+    pub resolve: AutoscrapeResolvesConfig,
+    #[serde(default)]
+    pub aux: Vec<AutoscrapeAuxConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<AutoscrapeOptionsConfig>,
+    /// Keys not known here, kept so they are not lost when the config is saved
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl AutoscrapeScraperConfig {
+    fn validate(&self) -> Result<(), String> {
+        if let Some(rx_block) = &self.rx_block {
+            if !rx_block.is_empty() {
+                AutoscrapeConfig::multi_line_regex("rx_block", rx_block)?;
+            }
+        }
+        let rx_entry = self.rx_entry.as_vec();
+        if rx_entry.is_empty() {
+            return Err("rx_entry: no regular expression given".to_string());
+        }
+        for (num, rx) in rx_entry.iter().enumerate() {
+            AutoscrapeConfig::multi_line_regex(&format!("rx_entry[{num}]"), rx)?;
+        }
+        self.resolve
+            .validate()
+            .map_err(|e| format!("resolve.{e}"))?;
+        for (num, aux) in self.aux.iter().enumerate() {
+            if aux.prop.replace('P', "").parse::<usize>().is_err() {
+                return Err(format!(
+                    "aux[{num}].prop: expected a property like 'P123', got '{}'",
+                    aux.prop
+                ));
+            }
+        }
+        if let Some(options) = &self.options {
+            options.validate().map_err(|e| format!("options.{e}"))?;
+        }
+        Ok(())
+    }
+}
+
+/// The typed form of the autoscrape JSON, as stored in the `autoscrape` table.
+/// Used to validate a config and report precisely what is wrong with it, and to save it,
+/// see `Storage::save_autoscrape_config`. Keys it does not know are kept as they are.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AutoscrapeConfig {
+    pub scraper: AutoscrapeScraperConfig,
+    pub levels: Vec<AutoscrapeLevelConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<AutoscrapeOptionsConfig>,
+    /// Keys not known here, kept so they are not lost when the config is saved
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl JsonStuff for AutoscrapeConfig {}
+
+impl AutoscrapeConfig {
+    pub fn from_json_str(json: &str) -> Result<Self, AutoscrapeError> {
+        let json: Value = serde_json::from_str(json)
+            .map_err(|e| AutoscrapeError::InvalidConfig(format!("not valid JSON: {e}")))?;
+        Self::from_json(&json)
+    }
+
+    /// Deserializes and validates the config section by section, so errors can name the
+    /// section, level, and field that is wrong.
+    pub fn from_json(json: &Value) -> Result<Self, AutoscrapeError> {
+        let scraper = json
+            .get("scraper")
+            .ok_or_else(|| AutoscrapeError::InvalidConfig("missing field `scraper`".to_string()))?;
+        let scraper: AutoscrapeScraperConfig = serde_json::from_value(scraper.to_owned())
+            .map_err(|e| AutoscrapeError::InvalidConfig(format!("scraper: {e}")))?;
+        scraper
+            .validate()
+            .map_err(|e| AutoscrapeError::InvalidConfig(format!("scraper.{e}")))?;
+
+        let no_levels = vec![];
+        let levels = match json.get("levels") {
+            Some(levels) => levels.as_array().ok_or_else(|| {
+                AutoscrapeError::InvalidConfig("`levels` needs to be an array".to_string())
+            })?,
+            None => &no_levels, // Single page scraper
+        };
+        let levels = levels
+            .iter()
+            .enumerate()
+            .map(|(num, level)| Self::level_from_json(num, level))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let options: Option<AutoscrapeOptionsConfig> = match json.get("options") {
+            Some(options) => Some(
+                serde_json::from_value(options.to_owned())
+                    .map_err(|e| AutoscrapeError::InvalidConfig(format!("options: {e}")))?,
+            ),
+            None => None,
+        };
+        if let Some(options) = &options {
+            options
+                .validate()
+                .map_err(|e| AutoscrapeError::InvalidConfig(format!("options.{e}")))?;
+        }
+
+        let extra = json
+            .as_object()
+            .into_iter()
+            .flatten()
+            .filter(|(key, _)| !["scraper", "levels", "options"].contains(&key.as_str()))
+            .map(|(key, value)| (key.to_owned(), value.to_owned()))
+            .collect();
+
+        Ok(Self {
+            scraper,
+            levels,
+            options,
+            extra,
+        })
+    }
+
+    /// Levels are numbered from 1 in error messages, as they are in the scraper URL (`$1` etc.)
+    fn level_from_json(num: usize, json: &Value) -> Result<AutoscrapeLevelConfig, AutoscrapeError> {
+        let level: AutoscrapeLevelConfig = serde_json::from_value(json.to_owned())
+            .map_err(|e| AutoscrapeError::InvalidConfig(format!("level {}: {e}", num + 1)))?;
+        level.validate().map_err(|e| {
+            AutoscrapeError::InvalidConfig(format!("level {} ({}): {e}", num + 1, level.mode()))
+        })?;
+        Ok(level)
+    }
+
+    fn number(key: &str, value: &AutoscrapeNumber) -> Result<u64, String> {
+        value
+            .as_u64()
+            .ok_or_else(|| format!("{key}: expected a non-negative integer, got {value:?}"))
+    }
+
+    fn regex(key: &str, pattern: &str) -> Result<(), String> {
+        AutoscrapeRegex::new(&Self::fix_regex(pattern))
+            .map(|_| ())
+            .map_err(|e| format!("{key}: invalid regular expression: {e}"))
+    }
+
+    fn multi_line_regex(key: &str, pattern: &str) -> Result<(), String> {
+        AutoscrapeRegexBuilder::new(&Self::fix_regex(pattern))
+            .multi_line(true)
+            .build()
+            .map(|_| ())
+            .map_err(|e| format!("{key}: invalid regular expression: {e}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn test_json() -> Value {
+        json!({
+            "levels": [
+                {"mode": "keys", "keys": ["a", "b"]},
+                {"mode": "range", "start": 0, "end": "100", "step": 10}
+            ],
+            "options": {"simple_space": 1},
+            "scraper": {
+                "url": "https://example.org/$1/$2",
+                "rx_entry": "<a href=\"/(\\d+)\">(.+?)</a>",
+                "resolve": {
+                    "id": {"use": "$1"},
+                    "name": {"use": "$2", "rx": [["^The ", ""]]}
+                },
+                "aux": [{"prop": "P123", "id": "$1"}]
+            }
+        })
+    }
+
+    fn error_for(json: &Value) -> String {
+        AutoscrapeConfig::from_json(json).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_autoscrape_config_round_trip() {
+        let config = AutoscrapeConfig::from_json(&test_json()).unwrap();
+        let json = serde_json::to_value(&config).unwrap();
+        assert!(!json.to_string().contains("null"));
+        assert_eq!(json["levels"][1]["mode"], json!("range"));
+        assert_eq!(AutoscrapeConfig::from_json(&json).unwrap(), config);
+    }
+
+    #[test]
+    fn test_autoscrape_config_keeps_unknown_keys() {
+        let mut json = test_json();
+        json["note"] = json!("hand-written");
+        json["levels"][0]["comment"] = json!("first level");
+        json["scraper"]["charset"] = json!("latin1");
+        json["scraper"]["resolve"]["id"]["comment"] = json!("numeric");
+        json["scraper"]["aux"][0]["qualifier"] = json!("P580");
+        json["options"]["something_new"] = json!(1);
+        let config = AutoscrapeConfig::from_json(&json).unwrap();
+        let saved = serde_json::to_value(&config).unwrap();
+        assert_eq!(saved["note"], json!("hand-written"));
+        assert_eq!(saved["levels"][0]["comment"], json!("first level"));
+        assert_eq!(saved["levels"][0]["mode"], json!("keys"));
+        assert_eq!(saved["scraper"]["charset"], json!("latin1"));
+        assert_eq!(
+            saved["scraper"]["resolve"]["id"]["comment"],
+            json!("numeric")
+        );
+        assert_eq!(saved["scraper"]["aux"][0]["qualifier"], json!("P580"));
+        assert_eq!(saved["options"]["something_new"], json!(1));
+        assert_eq!(AutoscrapeConfig::from_json(&saved).unwrap(), config);
+    }
+
+    #[test]
+    fn test_autoscrape_config_from_json() {
+        let config = AutoscrapeConfig::from_json(&test_json()).unwrap();
+        assert_eq!(config.levels.len(), 2);
+        assert_eq!(config.levels[1].mode(), "range");
+        assert_eq!(config.scraper.resolve.id.use_pattern, "$1");
+        assert_eq!(config.scraper.aux[0].prop, "P123");
+        assert_eq!(
+            config.options.unwrap().simple_space,
+            Some(AutoscrapeNumber::Number(1))
+        );
+    }
+
+    #[test]
+    fn test_autoscrape_config_errors() {
+        assert!(AutoscrapeConfig::from_json_str("{")
+            .unwrap_err()
+            .to_string()
+            .contains("not valid JSON"));
+
+        let mut json = test_json();
+        json["levels"][1]["mode"] = json!("foobar");
+        assert!(error_for(&json).contains("level 2: unknown variant `foobar`"));
+
+        let mut json = test_json();
+        json["levels"][0] = json!({"mode": "follow", "url": "https://example.org"});
+        assert!(error_for(&json).contains("level 1: missing field `rx`"));
+
+        let mut json = test_json();
+        json["levels"][1]["step"] = json!("ten");
+        assert!(error_for(&json).contains("level 2 (range): step: expected"));
+
+        let mut json = test_json();
+        json["scraper"]["rx_entry"] = json!(["(unclosed"]);
+        assert!(error_for(&json).contains("scraper.rx_entry[0]: invalid regular expression"));
+
+        let mut json = test_json();
+        json["scraper"]["resolve"]["name"]["rx"][0][0] = json!("(unclosed");
+        assert!(error_for(&json).contains("scraper.resolve.name.rx[0]: invalid"));
+
+        let mut json = test_json();
+        json["scraper"]["aux"][0]["prop"] = json!("foo");
+        assert!(error_for(&json).contains("scraper.aux[0].prop"));
+
+        let mut json = test_json();
+        json["scraper"]["resolve"]
+            .as_object_mut()
+            .unwrap()
+            .remove("id");
+        assert!(error_for(&json).contains("scraper: missing field `id`"));
+
+        let mut json = test_json();
+        json["levels"] = json!({"mode": "keys"});
+        assert!(error_for(&json).contains("`levels` needs to be an array"));
+    }
+}
@@ -1,8 +1,24 @@
-use crate::autoscrape::{Autoscrape, AutoscrapeError, AutoscrapeRegex, JsonStuff};
+use crate::autoscrape::{Autoscrape, AutoscrapeError, AutoscrapeRegex, JsonStuff, ScrapeFetcher};
 use anyhow::Result;
 use async_trait::async_trait;
 use serde_json::{json, Value};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+
+const DEFAULT_PAGINATE_MAX_PAGES: u64 = 100;
+
+/// Loads a URL through the fetcher of the autoscraper, or directly if the level was not initialized by one
+/// Errors of the fetcher, eg `AutoscrapeError::DisallowedByRobots`, are passed on as they are.
+async fn fetch_text(fetcher: Option<&ScrapeFetcher>, url: &str) -> Result<String> {
+    if let Some(fetcher) = fetcher {
+        return fetcher.get_text(url).await;
+    }
+    let text = match Autoscrape::reqwest_client_external()?.get(url).send().await {
+        Ok(x) => x.text().await.ok(),
+        _ => None,
+    }
+    .ok_or_else(|| AutoscrapeError::MediawikiFailure(url.to_string()))?;
+    Ok(text)
+}
 
 #[async_trait]
 trait Level {
@@ -198,13 +214,7 @@ impl AutoscrapeFollow {
 
     async fn refill_cache_get_text(&mut self, autoscrape: &Autoscrape) -> Result<String> {
         let url = self.refill_cache_get_url(autoscrape);
-        let client = Autoscrape::reqwest_client_external()?;
-        let text = match client.get(url.to_owned()).send().await {
-            Ok(x) => x.text().await.ok(),
-            _ => None,
-        }
-        .ok_or_else(|| AutoscrapeError::MediawikiFailure(url.clone()))?;
-        Ok(text)
+        fetch_text(Some(autoscrape.fetcher()), &url).await
     }
 
     fn refill_cache_get_url(&mut self, autoscrape: &Autoscrape) -> String {
@@ -223,19 +233,126 @@ impl AutoscrapeFollow {
     }
 }
 
+/// Follows "next page" links, starting at `url`, until there is no next page,
+/// a page was already visited, or `max_pages` pages have been returned.
+/// `rx` needs to capture the next page URL, which can be relative to the current page.
+#[derive(Debug, Clone)]
+pub struct AutoscrapePaginate {
+    url: String,
+    regex: String,
+    max_pages: u64,
+    current_url: String,
+    pages: u64,
+    visited: HashSet<String>,
+    fetcher: Option<ScrapeFetcher>,
+}
+
+impl JsonStuff for AutoscrapePaginate {}
+
+#[async_trait]
+impl Level for AutoscrapePaginate {
+    async fn init(&mut self, autoscrape: &Autoscrape) {
+        self.current_url = Autoscrape::url_with_level_values(&self.url, &autoscrape.current());
+        self.fetcher = Some(autoscrape.fetcher().to_owned());
+        self.pages = 1;
+        self.visited.clear();
+        self.visited.insert(self.current_url.to_owned());
+    }
+
+    async fn tick(&mut self) -> bool {
+        if self.pages >= self.max_pages {
+            return true;
+        }
+        let text = match self.load_current_page().await {
+            Ok(text) => text,
+            Err(_) => return true,
+        };
+        let next_url = match self.next_page_url(&text) {
+            Some(url) => url,
+            None => return true,
+        };
+        if !self.visited.insert(next_url.to_owned()) {
+            return true; // Cycle
+        }
+        self.current_url = next_url;
+        self.pages += 1;
+        false
+    }
+
+    fn current(&self) -> String {
+        self.current_url.to_owned()
+    }
+
+    fn get_state(&self) -> Value {
+        json!({"current_url":self.current_url.to_owned(),"pages":self.pages})
+    }
+
+    fn set_state(&mut self, json: &Value) {
+        if let Some(current_url) = json.get("current_url") {
+            if let Some(current_url) = current_url.as_str() {
+                self.current_url = current_url.to_string();
+                self.visited.insert(self.current_url.to_owned());
+            }
+        }
+        if let Some(pages) = json.get("pages") {
+            if let Some(pages) = pages.as_u64() {
+                self.pages = pages
+            }
+        }
+    }
+}
+
+impl AutoscrapePaginate {
+    fn from_json(json: &Value) -> Result<Self, AutoscrapeError> {
+        let max_pages = match json.get("max_pages") {
+            Some(_) => Self::json_as_u64(json, "max_pages")?,
+            None => DEFAULT_PAGINATE_MAX_PAGES,
+        };
+        let url = Self::json_as_str(json, "url")?;
+        Ok(Self {
+            current_url: url.to_owned(),
+            url,
+            regex: Self::fix_regex(&Self::json_as_str(json, "rx")?),
+            max_pages,
+            pages: 1,
+            visited: HashSet::new(),
+            fetcher: None,
+        })
+    }
+
+    /// Loads the current page; the fetcher returns the page the scraper has just loaded without downloading it again
+    async fn load_current_page(&self) -> Result<String> {
+        fetch_text(self.fetcher.as_ref(), &self.current_url).await
+    }
+
+    /// Finds the next page link in the page text, as an absolute URL
+    fn next_page_url(&self, text: &str) -> Option<String> {
+        let regex = AutoscrapeRegex::new(&self.regex).ok()?;
+        let next = regex
+            .captures_iter(text)
+            .filter_map(|cap| cap.get(1))
+            .map(|url| url.as_str().replace("&amp;", "&"))
+            .next()?;
+        let base = reqwest::Url::parse(&self.current_url).ok()?;
+        Some(base.join(&next).ok()?.to_string())
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct AutoscrapeMediaWiki {
     url: String,
     apfrom: String,
     title_cache: Vec<String>,
     last_url: Option<String>,
+    fetcher: Option<ScrapeFetcher>,
 }
 
 impl JsonStuff for AutoscrapeMediaWiki {}
 
 #[async_trait]
 impl Level for AutoscrapeMediaWiki {
-    async fn init(&mut self, _autoscrape: &Autoscrape) {
+    async fn init(&mut self, autoscrape: &Autoscrape) {
+        self.fetcher = Some(autoscrape.fetcher().to_owned());
         self.title_cache.clear();
     }
 
@@ -282,6 +399,7 @@ impl AutoscrapeMediaWiki {
             apfrom: String::new(),
             title_cache: vec![],
             last_url: None,
+            fetcher: None,
         })
     }
 
@@ -293,7 +411,7 @@ impl AutoscrapeMediaWiki {
         }
         self.last_url = Some(url.to_owned());
 
-        let json = Self::refill_cache_load_json(&url).await?;
+        let json = Self::refill_cache_load_json(self.fetcher.as_ref(), &url).await?;
         self.refill_cache_set_from_json(json, url)?;
         Ok(())
     }
@@ -315,13 +433,8 @@ impl AutoscrapeMediaWiki {
         Ok(())
     }
 
-    async fn refill_cache_load_json(url: &String) -> Result<Value> {
-        let client = Autoscrape::reqwest_client_external()?;
-        let text = match client.get(url.to_owned()).send().await {
-            Ok(x) => x.text().await.ok(),
-            _ => None,
-        }
-        .ok_or_else(|| AutoscrapeError::MediawikiFailure(url.clone()))?;
+    async fn refill_cache_load_json(fetcher: Option<&ScrapeFetcher>, url: &str) -> Result<Value> {
+        let text = fetch_text(fetcher, url).await?;
         let json: Value = serde_json::from_str(&text)?;
         Ok(json)
     }
@@ -333,6 +446,7 @@ pub enum AutoscrapeLevelType {
     Range(AutoscrapeRange),
     Follow(AutoscrapeFollow),
     MediaWiki(AutoscrapeMediaWiki),
+    Paginate(AutoscrapePaginate),
 }
 
 impl AutoscrapeLevelType {
@@ -342,6 +456,7 @@ impl AutoscrapeLevelType {
             AutoscrapeLevelType::Range(x) => x.init(autoscrape).await,
             AutoscrapeLevelType::Follow(x) => x.init(autoscrape).await,
             AutoscrapeLevelType::MediaWiki(x) => x.init(autoscrape).await,
+            AutoscrapeLevelType::Paginate(x) => x.init(autoscrape).await,
         }
     }
 
@@ -351,6 +466,7 @@ impl AutoscrapeLevelType {
             AutoscrapeLevelType::Range(x) => x.tick().await,
             AutoscrapeLevelType::Follow(x) => x.tick().await,
             AutoscrapeLevelType::MediaWiki(x) => x.tick().await,
+            AutoscrapeLevelType::Paginate(x) => x.tick().await,
         }
     }
 
@@ -360,6 +476,7 @@ impl AutoscrapeLevelType {
             AutoscrapeLevelType::Range(x) => x.current(),
             AutoscrapeLevelType::Follow(x) => x.current(),
             AutoscrapeLevelType::MediaWiki(x) => x.current(),
+            AutoscrapeLevelType::Paginate(x) => x.current(),
         }
     }
 
@@ -369,6 +486,7 @@ impl AutoscrapeLevelType {
             AutoscrapeLevelType::Range(x) => x.get_state(),
             AutoscrapeLevelType::Follow(x) => x.get_state(),
             AutoscrapeLevelType::MediaWiki(x) => x.get_state(),
+            AutoscrapeLevelType::Paginate(x) => x.get_state(),
         }
     }
 
@@ -378,6 +496,7 @@ impl AutoscrapeLevelType {
             AutoscrapeLevelType::Range(x) => x.set_state(json),
             AutoscrapeLevelType::Follow(x) => x.set_state(json),
             AutoscrapeLevelType::MediaWiki(x) => x.set_state(json),
+            AutoscrapeLevelType::Paginate(x) => x.set_state(json),
         }
     }
 }
@@ -399,6 +518,7 @@ impl AutoscrapeLevel {
             "range" => AutoscrapeLevelType::Range(AutoscrapeRange::from_json(json)?),
             "follow" => AutoscrapeLevelType::Follow(AutoscrapeFollow::from_json(json)?),
             "mediawiki" => AutoscrapeLevelType::MediaWiki(AutoscrapeMediaWiki::from_json(json)?),
+            "paginate" => AutoscrapeLevelType::Paginate(AutoscrapePaginate::from_json(json)?),
             _ => return Err(AutoscrapeError::UnknownLevelType(json.to_string())),
         };
         Ok(Self { level_type })
@@ -462,4 +582,50 @@ mod tests {
         assert!(level.tick().await);
         assert_eq!(level.current(), "4");
     }
+
+    #[test]
+    fn test_autoscrape_level_paginate_next_page_url() {
+        let json = json!({
+            "mode": "paginate",
+            "url": "https://example.org/list?page=1",
+            "rx": "<a href=\"([^\"]+)\" class=\"next\">"
+        });
+        let level = AutoscrapePaginate::from_json(&json).unwrap();
+        assert_eq!(level.max_pages, DEFAULT_PAGINATE_MAX_PAGES);
+        assert_eq!(
+            level.next_page_url(r#"<a href="/list?page=2&amp;sort=name" class="next">"#),
+            Some("https://example.org/list?page=2&sort=name".to_string())
+        );
+        assert_eq!(level.next_page_url("<p>Last page</p>"), None);
+    }
+
+    #[test]
+    fn test_autoscrape_level_paginate_url_with_level_values() {
+        let values = vec!["A".to_string(), "20".to_string()];
+        assert_eq!(
+            Autoscrape::url_with_level_values(
+                "https://example.org:8080/list/$1?year=$2&page=10",
+                &values
+            ),
+            "https://example.org:8080/list/A?year=20&page=10"
+        );
+        let values: Vec<String> = (1..=10).map(|level| format!("v{level}")).collect();
+        assert_eq!(
+            Autoscrape::url_with_level_values("https://example.org/$1/$10", &values),
+            "https://example.org/v1/v10"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_autoscrape_level_paginate_max_pages() {
+        let json = json!({
+            "mode": "paginate",
+            "url": "https://example.org/list?page=1",
+            "rx": "href=\"([^\"]+)\"",
+            "max_pages": 1
+        });
+        let mut level = AutoscrapeLevel::from_json(&json).unwrap();
+        assert_eq!(level.current(), "https://example.org/list?page=1");
+        assert!(level.tick().await); // Does not load the page
+    }
 }
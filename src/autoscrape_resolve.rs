@@ -12,6 +12,8 @@ lazy_static! {
         .build()
         .expect("Regex error");
     static ref RE_HTML: Regex = Regex::new(r"(<.*?>)").expect("Regex error");
+    static ref RE_HTML_SCRIPT_STYLE: Regex =
+        Regex::new(r"(?is)<(script|style)\b.*?</(script|style)\s*>").expect("Regex error");
 }
 
 #[derive(Debug, Clone, Default)]
@@ -66,6 +68,13 @@ impl AutoscrapeResolve {
         RE_SIMPLE_SPACE.replace_all(&ret, " ").trim().into()
     }
 
+    /// Removes scripts/styles and HTML tags, decodes entities, and collapses whitespace.
+    /// Used for descriptions of catalogs with `strip_html_descriptions` set.
+    pub fn strip_html(s: &str) -> String {
+        let ret = RE_HTML_SCRIPT_STYLE.replace_all(s, " ");
+        Self::fix_html(&ret)
+    }
+
     // #lizard forgives
     fn from_json_regex(
         regex: Value,
@@ -201,6 +210,21 @@ mod tests {
         assert_eq!(ret, "replace");
     }
 
+    #[test]
+    fn test_strip_html() {
+        assert_eq!(
+            AutoscrapeResolve::strip_html("<p>Painter &amp; <b>sculptor</b></p>\n<br/>  born 1850"),
+            "Painter & sculptor born 1850"
+        );
+        assert_eq!(
+            AutoscrapeResolve::strip_html(
+                "A<script type=\"text/javascript\">var x = '<b>';</script>B<STYLE>p{}</STYLE>"
+            ),
+            "A B"
+        );
+        assert_eq!(AutoscrapeResolve::strip_html("No markup"), "No markup");
+    }
+
     #[test]
     fn test_autoscrape_resolve_aux_from_json() {
         let json = json!({"prop": "P123", "id": "id"});
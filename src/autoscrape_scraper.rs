@@ -137,7 +137,7 @@ impl AutoscrapeScraper {
         } else {
             Some(type_name)
         };
-        let entry_ex = ExtendedEntry {
+        let mut entry_ex = ExtendedEntry {
             entry: Entry {
                 id: ENTRY_NEW_ID,
                 catalog: autoscrape.catalog_id(),
@@ -162,7 +162,11 @@ impl AutoscrapeScraper {
             aliases: vec![],
             descriptions: HashMap::new(),
             location: None,
+            replace_automatic_match: false,
         };
+        if autoscrape.strip_html_descriptions() {
+            entry_ex.strip_html_from_descriptions();
+        }
         entry_ex
     }
 
@@ -4,15 +4,16 @@ use crate::catalog::*;
 use crate::entry::*;
 use crate::issue::*;
 use crate::job::*;
+use crate::overview_rebuild::OverviewRebuild;
 use crate::wikidata::META_ITEMS;
 use crate::wikidata_commands::*;
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use futures::future::join_all;
+use itertools::Itertools;
 use lazy_static::lazy_static;
 use mediawiki::Api;
 use regex::Regex;
-use serde_json::json;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fmt;
 use wikimisc::wikibase::entity_container::EntityContainer;
@@ -28,6 +29,34 @@ pub const AUX_BLACKLISTED_PROPERTIES: &[usize] = &[
 pub const AUX_DO_NOT_SYNC_CATALOG_TO_WIKIDATA: &[usize] = &[655];
 pub const AUX_PROPERTIES_ALSO_USING_LOWERCASE: &[usize] = &[2002];
 
+/// `kv_catalog` key for the only auxiliary properties that may be added to Wikidata from a catalog,
+/// as a comma-separated list, eg `P214,P227`. The global blacklists apply on top of this.
+pub const KV_AUX2WD_ALLOWED_PROPERTIES: &str = "aux2wd_allowed_properties";
+
+/// `kv_catalog` key for the auxiliary properties used by `match_via_multiple_aux`, as a comma-separated list, eg `P214,P213`
+pub const KV_JOINT_AUX_PROPERTIES: &str = "joint_aux_properties";
+
+/// `kv` key for properties the auxiliary matcher must not use, in addition to `AUX_BLACKLISTED_PROPERTIES`,
+/// as a comma-separated list, eg `P233,P235`; a leading `=` replaces the built-in list instead
+pub const KV_AUX_BLACKLISTED_PROPERTIES: &str = "aux_blacklisted_properties";
+/// `kv` key for catalogs the auxiliary matcher must not use, in addition to `AUX_BLACKLISTED_CATALOGS`,
+/// as a comma-separated list of IDs; a leading `=` replaces the built-in list instead
+pub const KV_AUX_BLACKLISTED_CATALOGS: &str = "aux_blacklisted_catalogs";
+
+/// Parses a comma-separated list of IDs like `P233, 235` from the value of the key-value pair `key`.
+/// An optional `P` prefix is ignored; invalid parts are an error.
+pub fn parse_id_list(s: &str, key: &str) -> Result<Vec<usize>> {
+    s.split(',')
+        .map(|part| part.trim())
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            part.trim_start_matches(['P', 'p'])
+                .parse::<usize>()
+                .map_err(|_| anyhow!("Invalid ID '{part}' in {key}"))
+        })
+        .collect()
+}
+
 lazy_static! {
     static ref RE_COORDINATE_PATTERN: Regex =
         Regex::new(r"^\@{0,1}([0-9\.\-]+)[,/]([0-9\.\-]+)$").expect("Regex error");
@@ -138,7 +167,14 @@ pub struct AuxiliaryMatcher {
     catalogs: HashMap<usize, Option<Catalog>>,
     properties: EntityContainer,
     aux2wd_skip_existing_property: bool,
+    /// Per-catalog allowlist for `add_auxiliary_to_wikidata`; `None` allows all (non-blacklisted) properties
+    aux2wd_allowed_properties: Option<Vec<usize>>,
+    /// `AUX_BLACKLISTED_PROPERTIES`, plus those set in the database, see `load_blacklists`
+    blacklisted_properties: Vec<usize>,
+    /// `AUX_BLACKLISTED_CATALOGS`, plus those set in the database, see `load_blacklists`
+    blacklisted_catalogs: Vec<usize>,
     job: Option<Job>,
+    overview_rebuild: OverviewRebuild,
 }
 
 impl Jobbable for AuxiliaryMatcher {
@@ -167,7 +203,11 @@ impl AuxiliaryMatcher {
             catalogs: HashMap::new(),
             properties: EntityContainer::new(),
             aux2wd_skip_existing_property: true,
+            aux2wd_allowed_properties: None,
+            blacklisted_properties: AUX_BLACKLISTED_PROPERTIES.to_vec(),
+            blacklisted_catalogs: AUX_BLACKLISTED_CATALOGS.to_vec(),
             job: None,
+            overview_rebuild: OverviewRebuild::new(app),
         }
     }
 
@@ -200,7 +240,8 @@ impl AuxiliaryMatcher {
 
     //TODO test
     pub async fn match_via_auxiliary(&mut self, catalog_id: usize) -> Result<()> {
-        let blacklisted_catalogs = Self::get_blacklisted_catalogs();
+        self.load_blacklists().await?;
+        let blacklisted_catalogs = self.get_blacklisted_catalogs();
         let extid_props = self.get_extid_props().await?;
         let mut offset = self.get_last_job_offset().await;
         let batch_size = self.get_batch_size();
@@ -231,10 +272,180 @@ impl AuxiliaryMatcher {
             let _ = self.remember_offset(offset).await;
         }
         let _ = self.clear_offset().await;
+        self.app
+            .storage()
+            .finalize_catalog_overview(catalog_id)
+            .await?;
         let _ = Job::queue_simple_job(&self.app, catalog_id, "aux2wd", None).await;
         Ok(())
     }
 
+    /// Matches entries only if at least two of their auxiliary values for `properties` find the same single item on Wikidata,
+    /// and that item has all of these values. This avoids false positives from identifiers that are
+    /// occasionally wrong or shared on their own, like VIAF.
+    pub async fn match_via_multiple_aux(
+        &mut self,
+        catalog_id: usize,
+        properties: &[usize],
+    ) -> Result<()> {
+        self.load_blacklists().await?;
+        if self.blacklisted_catalogs.contains(&catalog_id) {
+            return Err(AuxiliaryMatcherError::BlacklistedCatalog.into());
+        }
+        let properties: Vec<usize> = properties
+            .iter()
+            .copied()
+            .filter(|p| !self.blacklisted_properties.contains(p))
+            .filter(|p| !self.is_catalog_property_combination_suspect(catalog_id, *p))
+            .unique()
+            .collect();
+        if properties.len() < 2 {
+            return Err(anyhow!(
+                "match_via_multiple_aux requires at least two usable properties"
+            ));
+        }
+        let batch_size = self.get_batch_size();
+        let search_batch_size = self.get_search_batch_size();
+        let mw_api = self.app.wikidata().get_mw_api().await?;
+        let mut after_entry_id = self.get_last_job_offset().await;
+        loop {
+            let results = self
+                .app
+                .storage()
+                .auxiliary_matcher_match_via_multiple_aux(
+                    catalog_id,
+                    &properties,
+                    after_entry_id,
+                    batch_size,
+                )
+                .await?;
+            let last_entry_id = match results.iter().map(|aux| aux.entry_id).max() {
+                Some(entry_id) => entry_id,
+                None => break,
+            };
+            let number_of_entries = results.iter().map(|aux| aux.entry_id).unique().count();
+            let mut searches: HashMap<usize, Vec<(AuxiliaryResults, Vec<String>)>> = HashMap::new();
+            for results_chunk in results.chunks(search_batch_size) {
+                let futures = results_chunk
+                    .iter()
+                    .map(|aux| self.search_property_value(aux.to_owned()));
+                for (aux, items) in join_all(futures).await.into_iter().flatten() {
+                    searches.entry(aux.entry_id).or_default().push((aux, items));
+                }
+            }
+            let matches: Vec<(String, Vec<AuxiliaryResults>)> = searches
+                .into_values()
+                .filter_map(|entry_searches| {
+                    let property_items: Vec<(usize, Vec<String>)> = entry_searches
+                        .iter()
+                        .map(|(aux, items)| (aux.property, items.to_owned()))
+                        .collect();
+                    let q = Self::joint_candidate(&property_items)?;
+                    let auxs = entry_searches
+                        .into_iter()
+                        .filter(|(_aux, items)| items.contains(&q))
+                        .map(|(aux, _items)| aux)
+                        .collect();
+                    Some((q, auxs))
+                })
+                .collect();
+            self.match_via_multiple_aux_check_items(matches, &mw_api)
+                .await;
+            if number_of_entries < batch_size {
+                break;
+            }
+            after_entry_id = last_entry_id;
+            let _ = self.remember_offset(after_entry_id).await;
+        }
+        let _ = self.clear_offset().await;
+        self.app
+            .storage()
+            .finalize_catalog_overview(catalog_id)
+            .await?;
+        Ok(())
+    }
+
+    /// The properties for `match_via_multiple_aux`, from the `KV_JOINT_AUX_PROPERTIES` catalog key-value pair
+    pub async fn joint_aux_properties(&self, catalog_id: usize) -> Result<Vec<usize>> {
+        match Catalog::from_id(catalog_id, &self.app)
+            .await?
+            .get_key_value_pairs()
+            .await?
+            .get(KV_JOINT_AUX_PROPERTIES)
+        {
+            Some(s) => parse_id_list(s, KV_JOINT_AUX_PROPERTIES),
+            None => Err(anyhow!(
+                "Catalog {catalog_id} has no {KV_JOINT_AUX_PROPERTIES}"
+            )),
+        }
+    }
+
+    /// The single item that all properties with search results point to, if there are at least two such properties.
+    /// `searches` are (property, found items) for each auxiliary value of an entry; the items for several values
+    /// of the same property are combined.
+    fn joint_candidate(searches: &[(usize, Vec<String>)]) -> Option<String> {
+        let mut by_property: HashMap<usize, HashSet<String>> = HashMap::new();
+        for (property, items) in searches.iter().filter(|(_, items)| !items.is_empty()) {
+            by_property
+                .entry(*property)
+                .or_default()
+                .extend(items.iter().cloned());
+        }
+        if by_property.len() < 2 {
+            return None;
+        }
+        let mut item_sets = by_property.into_values();
+        let first = item_sets.next()?;
+        let common: HashSet<String> = item_sets.fold(first, |acc, items| {
+            acc.intersection(&items).cloned().collect()
+        });
+        if common.len() == 1 {
+            common.into_iter().next()
+        } else {
+            None
+        }
+    }
+
+    async fn match_via_multiple_aux_check_items(
+        &mut self,
+        matches: Vec<(String, Vec<AuxiliaryResults>)>,
+        mw_api: &mediawiki::api::Api,
+    ) {
+        // Load the actual entities, don't trust the search results
+        let items_to_load: Vec<String> = matches
+            .iter()
+            .map(|(q, _auxs)| q.to_owned())
+            .unique()
+            .collect();
+        let entities = EntityContainer::new();
+        let _ = entities.load_entities(mw_api, &items_to_load).await;
+        for (q, auxs) in &matches {
+            let entity = match entities.get_entity(q.to_owned()) {
+                Some(entity) => entity,
+                None => continue,
+            };
+            if !auxs.iter().all(|aux| aux.entity_has_statement(&entity)) {
+                continue;
+            }
+            let entry_id = match auxs.first() {
+                Some(aux) => aux.entry_id,
+                None => continue,
+            };
+            if let Ok(mut entry) = Entry::from_id(entry_id, &self.app).await {
+                if entry
+                    .set_match_without_overview(q, USER_AUX_MATCH)
+                    .await
+                    .is_ok()
+                {
+                    let _ = self
+                        .overview_rebuild
+                        .match_set(&self.app, entry.catalog)
+                        .await;
+                }
+            }
+        }
+    }
+
     async fn match_via_auxiliary_check_items(
         &mut self,
         items_to_check: Vec<(String, AuxiliaryResults)>,
@@ -251,7 +462,16 @@ impl AuxiliaryMatcher {
             if let Some(entity) = &entities.get_entity(q.to_owned()) {
                 if aux.entity_has_statement(entity) {
                     if let Ok(mut entry) = Entry::from_id(aux.entry_id, &self.app).await {
-                        let _ = entry.set_match(q, USER_AUX_MATCH).await;
+                        if entry
+                            .set_match_without_overview(q, USER_AUX_MATCH)
+                            .await
+                            .is_ok()
+                        {
+                            let _ = self
+                                .overview_rebuild
+                                .match_set(&self.app, entry.catalog)
+                                .await;
+                        }
                     }
                 }
             }
@@ -284,8 +504,7 @@ impl AuxiliaryMatcher {
                 std::cmp::Ordering::Greater => {
                     Issue::new(
                         aux.entry_id,
-                        IssueType::WdDuplicate,
-                        json!(search_results),
+                        IssuePayload::WdDuplicate(search_results),
                         &self.app,
                     )
                     .await?
@@ -307,10 +526,20 @@ impl AuxiliaryMatcher {
         for results_chunk in results.chunks(search_batch_size) {
             let mut futures = vec![];
             for aux in results_chunk {
-                if !self.is_catalog_property_combination_suspect(catalog_id, aux.property) {
-                    let future = self.search_property_value(aux.to_owned());
-                    futures.push(future);
+                if self.is_catalog_property_combination_suspect(catalog_id, aux.property) {
+                    continue;
+                }
+                // The catalog's own property; the external ID already identifies the entry there
+                if self
+                    .app
+                    .catalogs_for_property(aux.property)
+                    .await?
+                    .contains(&catalog_id)
+                {
+                    continue;
                 }
+                let future = self.search_property_value(aux.to_owned());
+                futures.push(future);
             }
             let futures_results = join_all(futures).await.into_iter().flatten();
             for (aux, items) in futures_results {
@@ -318,15 +547,10 @@ impl AuxiliaryMatcher {
                     std::cmp::Ordering::Less => {}
                     std::cmp::Ordering::Equal => items_to_check.push((items[0].to_owned(), aux)),
                     std::cmp::Ordering::Greater => {
-                        Issue::new(
-                            aux.entry_id,
-                            IssueType::WdDuplicate,
-                            json!(items),
-                            &self.app,
-                        )
-                        .await?
-                        .insert()
-                        .await?;
+                        Issue::new(aux.entry_id, IssuePayload::WdDuplicate(items), &self.app)
+                            .await?
+                            .insert()
+                            .await?;
                     }
                 }
             }
@@ -359,7 +583,7 @@ impl AuxiliaryMatcher {
             .properties_that_have_external_ids
             .iter()
             .filter_map(|s| s.replace('P', "").parse::<usize>().ok())
-            .filter(|i| !AUX_BLACKLISTED_PROPERTIES.contains(i))
+            .filter(|i| !self.blacklisted_properties.contains(i))
             .map(|i| format!("{}", i))
             .collect();
         Ok(extid_props)
@@ -373,7 +597,18 @@ impl AuxiliaryMatcher {
         self.properties_using_items = Self::get_properties_using_items(&self.app).await?;
         self.properties_that_have_external_ids =
             Self::get_properties_that_have_external_ids(&self.app).await?;
-        let blacklisted_properties: Vec<String> = AUX_BLACKLISTED_PROPERTIES
+        self.aux2wd_allowed_properties = match Catalog::from_id(catalog_id, &self.app)
+            .await?
+            .get_key_value_pairs()
+            .await?
+            .get(KV_AUX2WD_ALLOWED_PROPERTIES)
+        {
+            Some(s) => Some(Self::parse_allowed_properties(s)?),
+            None => None,
+        };
+        self.load_blacklists().await?;
+        let blacklisted_properties: Vec<String> = self
+            .blacklisted_properties
             .iter()
             .map(|u| format!("{}", u))
             .collect();
@@ -517,10 +752,13 @@ impl AuxiliaryMatcher {
         aux: &AuxiliaryResults,
         entities: &EntityContainer,
     ) -> bool {
-        if AUX_BLACKLISTED_PROPERTIES.contains(&aux.property) {
+        if self.blacklisted_properties.contains(&aux.property) {
             // No blacklisted properties
             return false;
         }
+        if !self.is_allowed_aux2wd_property(aux.property) {
+            return false;
+        }
         if let Some(entity) = entities.get_entity(aux.q()) {
             if META_ITEMS
                 .iter()
@@ -591,8 +829,7 @@ impl AuxiliaryMatcher {
                     }
                 } else if let Ok(issue) = Issue::new(
                     aux.entry_id,
-                    IssueType::Mismatch,
-                    json!([search_results[0], aux.q()]),
+                    IssuePayload::mismatch(search_results[0].to_owned(), aux.q()),
                     &self.app,
                 )
                 .await
@@ -603,8 +840,7 @@ impl AuxiliaryMatcher {
             std::cmp::Ordering::Greater => {
                 if let Ok(issue) = Issue::new(
                     aux.entry_id,
-                    IssueType::Multiple,
-                    json!({"wd": search_results,"app": aux.value,}),
+                    IssuePayload::multiple(search_results, &aux.value),
                     &self.app,
                 )
                 .await
@@ -691,9 +927,13 @@ impl AuxiliaryMatcher {
         (&Catalog, Vec<WikidataCommandPropertyValue>),
         Option<Vec<WikidataCommandPropertyValue>>,
     > {
-        self.catalogs
-            .entry(entry.catalog)
-            .or_insert(Catalog::from_id(entry.catalog, &self.app).await.ok());
+        if !self.catalogs.contains_key(&entry.catalog) {
+            // Only cache a missing catalog, not a database error
+            let catalog = Catalog::from_id_if_exists(entry.catalog, &self.app)
+                .await
+                .map_err(|_| None)?;
+            self.catalogs.insert(entry.catalog, catalog);
+        }
         let catalog = match self.catalogs.get(&entry.catalog) {
             Some(catalog) => catalog,
             None => return Err(None), // No catalog, no source
@@ -747,13 +987,63 @@ impl AuxiliaryMatcher {
         Some(())
     }
 
+    /// Parses a `KV_AUX2WD_ALLOWED_PROPERTIES` value, eg `P214, P227` or `214,227`.
+    fn parse_allowed_properties(s: &str) -> Result<Vec<usize>> {
+        parse_id_list(s, KV_AUX2WD_ALLOWED_PROPERTIES)
+    }
+
+    fn is_allowed_aux2wd_property(&self, prop: usize) -> bool {
+        match &self.aux2wd_allowed_properties {
+            Some(allowed) => allowed.contains(&prop),
+            None => true,
+        }
+    }
+
     //TODO test
     fn is_catalog_property_combination_suspect(&self, catalog_id: usize, prop: usize) -> bool {
         AUX_BLACKLISTED_CATALOGS_PROPERTIES.contains(&(catalog_id, prop))
     }
 
-    pub fn get_blacklisted_catalogs() -> Vec<String> {
-        let blacklisted_catalogs: Vec<String> = AUX_BLACKLISTED_CATALOGS
+    /// Applies the blacklists in the `kv` table, where set, to the built-in ones,
+    /// so they can be changed without a new release. Fails if a blacklist can not be parsed.
+    pub async fn load_blacklists(&mut self) -> Result<()> {
+        let storage = self.app.storage();
+        if let Some(s) = storage.get_kv_value(KV_AUX_BLACKLISTED_PROPERTIES).await? {
+            Self::apply_kv_blacklist(
+                &mut self.blacklisted_properties,
+                &s,
+                KV_AUX_BLACKLISTED_PROPERTIES,
+            )?;
+        }
+        if let Some(s) = storage.get_kv_value(KV_AUX_BLACKLISTED_CATALOGS).await? {
+            Self::apply_kv_blacklist(
+                &mut self.blacklisted_catalogs,
+                &s,
+                KV_AUX_BLACKLISTED_CATALOGS,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Adds the IDs in the `kv` value `s` to `list`, or replaces `list` with them if `s` starts with `=`
+    fn apply_kv_blacklist(list: &mut Vec<usize>, s: &str, key: &str) -> Result<()> {
+        let (replace, s) = match s.trim_start().strip_prefix('=') {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+        let ids = parse_id_list(s, key)?;
+        if replace {
+            list.clear();
+        }
+        list.extend(ids);
+        list.sort();
+        list.dedup();
+        Ok(())
+    }
+
+    pub fn get_blacklisted_catalogs(&self) -> Vec<String> {
+        let blacklisted_catalogs: Vec<String> = self
+            .blacklisted_catalogs
             .iter()
             .map(|u| format!("{}", u))
             .collect();
@@ -818,6 +1108,89 @@ mod tests {
         assert!(!am.entity_already_has_property(&aux, &entity).await);
     }
 
+    #[test]
+    fn test_parse_allowed_properties() {
+        assert_eq!(
+            AuxiliaryMatcher::parse_allowed_properties("P214, p227,,1234").unwrap(),
+            vec![214, 227, 1234]
+        );
+        assert!(AuxiliaryMatcher::parse_allowed_properties("")
+            .unwrap()
+            .is_empty());
+        assert!(AuxiliaryMatcher::parse_allowed_properties("P214,Q5").is_err());
+    }
+
+    #[test]
+    fn test_parse_id_list() {
+        assert_eq!(
+            parse_id_list("P233, 235,,p846", "test").unwrap(),
+            vec![233, 235, 846]
+        );
+        assert!(parse_id_list("506,foo", "test").is_err());
+        assert!(parse_id_list("P2x33", "test").is_err());
+        assert!(parse_id_list("", "test").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_apply_kv_blacklist() {
+        let mut list = vec![506, 7];
+        AuxiliaryMatcher::apply_kv_blacklist(&mut list, "P12, 7", "test").unwrap();
+        assert_eq!(list, vec![7, 12, 506]);
+        AuxiliaryMatcher::apply_kv_blacklist(&mut list, "=P233,235", "test").unwrap();
+        assert_eq!(list, vec![233, 235]);
+        AuxiliaryMatcher::apply_kv_blacklist(&mut list, "=", "test").unwrap();
+        assert!(list.is_empty());
+        let mut list = vec![506];
+        assert!(AuxiliaryMatcher::apply_kv_blacklist(&mut list, "=foo", "test").is_err());
+        assert_eq!(list, vec![506]);
+    }
+
+    #[test]
+    fn test_joint_candidate() {
+        let searches = |v: &[(usize, &[&str])]| -> Vec<(usize, Vec<String>)> {
+            v.iter()
+                .map(|(p, items)| (*p, items.iter().map(|q| q.to_string()).collect()))
+                .collect()
+        };
+        // Two properties agree
+        assert_eq!(
+            AuxiliaryMatcher::joint_candidate(&searches(&[
+                (214, &["Q1", "Q2"]),
+                (213, &["Q1"]),
+                (227, &[])
+            ])),
+            Some("Q1".to_string())
+        );
+        // Only one property has results
+        assert_eq!(
+            AuxiliaryMatcher::joint_candidate(&searches(&[(214, &["Q1"]), (214, &["Q1"])])),
+            None
+        );
+        // Properties disagree
+        assert_eq!(
+            AuxiliaryMatcher::joint_candidate(&searches(&[(214, &["Q1"]), (213, &["Q2"])])),
+            None
+        );
+        // Ambiguous
+        assert_eq!(
+            AuxiliaryMatcher::joint_candidate(&searches(&[
+                (214, &["Q1", "Q2"]),
+                (213, &["Q1", "Q2"])
+            ])),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_is_allowed_aux2wd_property() {
+        let app = get_test_app();
+        let mut am = AuxiliaryMatcher::new(&app);
+        assert!(am.is_allowed_aux2wd_property(17));
+        am.aux2wd_allowed_properties = Some(vec![214]);
+        assert!(am.is_allowed_aux2wd_property(214));
+        assert!(!am.is_allowed_aux2wd_property(17));
+    }
+
     #[tokio::test]
     async fn test_add_auxiliary_to_wikidata() {
         let _test_lock = TEST_MUTEX.lock();
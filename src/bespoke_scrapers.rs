@@ -1,7 +1,8 @@
 use crate::{
-    app_state::{AppState, USER_AUX_MATCH},
+    app_state::{AppState, HostLimits, USER_AUX_MATCH},
     entry::{CoordinateLocation, Entry, ENTRY_NEW_ID},
     extended_entry::ExtendedEntry,
+    job::Job,
 };
 use anyhow::Result;
 use async_trait::async_trait;
@@ -32,6 +33,18 @@ pub trait BespokeScraper {
         }
     }
 
+    /// Loads a URL as text, within the per-host limits of the catalog
+    async fn load_text(&self, url: &str) -> Result<String> {
+        let kv = self
+            .app()
+            .storage()
+            .get_catalog_key_value_pairs(self.catalog_id())
+            .await?;
+        let limits = HostLimits::from_key_value_pairs(&kv);
+        let throttle = self.app().host_throttle();
+        throttle.get_text(throttle.client(), url, limits).await
+    }
+
     async fn process_cache(&self, entry_cache: &mut Vec<ExtendedEntry>) -> Result<()> {
         if entry_cache.is_empty() {
             return Ok(());
@@ -46,6 +59,7 @@ pub trait BespokeScraper {
             .collect();
         let entry_ids: Vec<usize> = ext_id2id.values().copied().collect();
         let existing_entries = Entry::multiple_from_ids(&entry_ids, self.app()).await?;
+        let mut automatch_removed = false;
         for ext_entry in entry_cache {
             let ext_id = &ext_entry.entry.ext_id;
             let existing_entry = match ext_id2id.get(ext_id) {
@@ -60,7 +74,8 @@ pub trait BespokeScraper {
                     if self.testing() {
                         println!("EXISTS: {:?}", ext_entry);
                     } else {
-                        ext_entry.update_existing(&mut entry, self.app()).await?;
+                        automatch_removed |=
+                            ext_entry.update_existing(&mut entry, self.app()).await?;
                     }
                 }
                 None => {
@@ -72,6 +87,10 @@ pub trait BespokeScraper {
                 }
             };
         }
+        if automatch_removed {
+            Job::queue_simple_job(self.app(), self.catalog_id(), "automatch_by_search", None)
+                .await?;
+        }
         Ok(())
     }
 }
@@ -101,8 +120,7 @@ impl BespokeScraper for BespokeScraper121 {
         // let filename = "/Users/mm6/Downloads/Sikart_PersonenDaten.csv";
         // let file = File::open(filename)?;
         let url = "https://www.sikart.ch/personen_export.aspx";
-        let client = reqwest::Client::new();
-        let text = client.get(url).send().await?.text().await?;
+        let text = self.load_text(url).await?;
         let file = std::io::Cursor::new(text);
         let mut reader = csv::ReaderBuilder::new()
             .has_headers(true)
@@ -221,8 +239,7 @@ impl BespokeScraper for BespokeScraper6479 {
         // let filename = "/Users/mm6/Downloads/muenzkabinett.csv";
         // let file = File::open(filename)?;
         let url = "https://www.sikart.ch/personen_export.aspx";
-        let client = reqwest::Client::new();
-        let text = client.get(url).send().await?.text().await?;
+        let text = self.load_text(url).await?;
         let file = std::io::Cursor::new(text);
         let mut reader = csv::ReaderBuilder::new()
             .has_headers(true)
@@ -1,10 +1,84 @@
 use crate::app_state::AppState;
 use crate::entry::AuxiliaryRow;
+use crate::permissions::Permissions;
+use crate::storage::StorageError;
 use anyhow::{anyhow, Result};
 use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use wikimisc::wikibase::Reference;
 use wikimisc::wikibase::Snak;
 
+/// How long a catalog loaded via `Catalog::from_id` is reused before it is loaded again
+const CATALOG_CACHE_TTL: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CatalogError {
+    NameExists(usize),
+    NoName,
+}
+
+impl Error for CatalogError {}
+
+impl fmt::Display for CatalogError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CatalogError::NameExists(id) => write!(f, "CatalogError::NameExists({id})"),
+            CatalogError::NoName => write!(f, "CatalogError::NoName"),
+        }
+    }
+}
+
+/// A statement that is added to every item created from a catalog, as stored in `catalog_default_statement`.
+/// With `as_reference`, it is added to the reference of every statement instead, eg a fixed "stated in".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CatalogDefaultStatement {
+    pub id: usize,
+    pub catalog_id: usize,
+    pub property: usize,
+    /// An item (`Q123`) or a string
+    pub value: String,
+    pub as_reference: bool,
+}
+
+impl CatalogDefaultStatement {
+    pub fn new(catalog_id: usize, property: usize, value: &str, as_reference: bool) -> Self {
+        Self {
+            id: 0,
+            catalog_id,
+            property,
+            value: value.trim().to_string(),
+            as_reference,
+        }
+    }
+
+    pub fn from_row(row: (usize, usize, usize, String, u8)) -> Self {
+        Self {
+            id: row.0,
+            catalog_id: row.1,
+            property: row.2,
+            value: row.3,
+            as_reference: row.4 == 1,
+        }
+    }
+
+    /// Returns the snak for this statement; item values become item snaks, everything else string snaks.
+    pub fn snak(&self) -> Snak {
+        let prop = format!("P{}", self.property);
+        let is_item = self
+            .value
+            .strip_prefix('Q')
+            .is_some_and(|num| !num.is_empty() && num.chars().all(|c| c.is_ascii_digit()));
+        if is_item {
+            Snak::new_item(&prop, &self.value)
+        } else {
+            Snak::new_string(&prop, &self.value)
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Catalog {
     pub id: usize,
@@ -22,22 +96,117 @@ pub struct Catalog {
     pub has_person_date: String,
     pub taxon_run: bool,
     pub app: Option<AppState>,
+    pub kv_pairs: Arc<Mutex<Option<HashMap<String, String>>>>,
 }
 
 impl Catalog {
     /// Returns a Catalog object for a given entry ID.
+    /// Catalogs (including their key-value pairs, once loaded) are cached on the `AppState` for a few minutes;
+    /// use `invalidate_cache()` after changing the catalog configuration.
     pub async fn from_id(catalog_id: usize, app: &AppState) -> Result<Self> {
+        if let Some(cached) = app.catalog_cache().get(&catalog_id) {
+            let (loaded, catalog) = cached.value();
+            if loaded.elapsed() < CATALOG_CACHE_TTL {
+                let mut ret = catalog.to_owned();
+                ret.set_mnm(app);
+                return Ok(ret);
+            }
+        }
         let mut ret = app.storage().get_catalog_from_id(catalog_id).await?;
+        // The cached copy has no app, to avoid a reference cycle
+        app.catalog_cache()
+            .insert(catalog_id, (Instant::now(), ret.to_owned()));
         ret.set_mnm(app);
         Ok(ret)
     }
 
+    /// Returns a Catalog object for a given catalog ID, or `None` if there is no such catalog.
+    /// Other errors, eg database failures, are returned as such.
+    pub async fn from_id_if_exists(catalog_id: usize, app: &AppState) -> Result<Option<Self>> {
+        match Self::from_id(catalog_id, app).await {
+            Ok(catalog) => Ok(Some(catalog)),
+            Err(e) if StorageError::is_not_found(&e) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Returns the catalog for a source item, eg the Wikidata item of the database it was imported from.
+    /// Accepts `Q123` or `123`; returns `None` if there is no such catalog.
+    pub async fn from_source_item(source_item: &str, app: &AppState) -> Result<Option<Self>> {
+        let source_item = source_item.trim();
+        let numeric = source_item
+            .strip_prefix(['Q', 'q'])
+            .unwrap_or(source_item)
+            .parse::<usize>()
+            .map_err(|_| anyhow!("Invalid source item '{source_item}'"))?;
+        let mut ret = app.storage().get_catalog_from_source_item(numeric).await?;
+        if let Some(catalog) = ret.as_mut() {
+            catalog.set_mnm(app);
+        }
+        Ok(ret)
+    }
+
+    /// Removes a catalog from the `from_id()` cache, so it will be reloaded from the database.
+    pub fn invalidate_cache(catalog_id: usize, app: &AppState) {
+        app.catalog_cache().remove(&catalog_id);
+    }
+
+    /// Creates a new catalog in the database, and sets the new ID.
+    /// Returns `CatalogError::NameExists` with the existing catalog ID if a catalog with that name already exists.
+    pub async fn create(&mut self, app: &AppState) -> Result<usize> {
+        let catalog_id = app.storage().create_catalog(self).await?;
+        self.id = catalog_id;
+        self.set_mnm(app);
+        Ok(catalog_id)
+    }
+
     /// Returns a `HashMap` of key-value pairs for the catalog.
+    /// These are cached on the object after the first call; use `refresh_key_value_pairs()` to reload.
     pub async fn get_key_value_pairs(&self) -> Result<HashMap<String, String>> {
-        self.app()?
+        if let Some(kv_pairs) = self.kv_pairs_cache()?.as_ref() {
+            return Ok(kv_pairs.to_owned());
+        }
+        self.refresh_key_value_pairs().await
+    }
+
+    /// Reloads the key-value pairs for the catalog from the database, and updates the cache.
+    pub async fn refresh_key_value_pairs(&self) -> Result<HashMap<String, String>> {
+        let kv_pairs = self
+            .app()?
             .storage()
             .get_catalog_key_value_pairs(self.id)
-            .await
+            .await?;
+        *self.kv_pairs_cache()? = Some(kv_pairs.to_owned());
+        Ok(kv_pairs)
+    }
+
+    /// Sets a key-value pair of a catalog, and removes the catalog from the `from_id()` cache.
+    pub async fn set_key_value_pair(
+        catalog_id: usize,
+        key: &str,
+        value: &str,
+        app: &AppState,
+    ) -> Result<()> {
+        app.storage()
+            .set_catalog_key_value_pair(catalog_id, key, value)
+            .await?;
+        Self::invalidate_cache(catalog_id, app);
+        Ok(())
+    }
+
+    /// Deletes a key-value pair of a catalog, and removes the catalog from the `from_id()` cache.
+    pub async fn delete_key_value_pair(catalog_id: usize, key: &str, app: &AppState) -> Result<()> {
+        app.storage()
+            .delete_catalog_key_value_pair(catalog_id, key)
+            .await?;
+        Self::invalidate_cache(catalog_id, app);
+        Ok(())
+    }
+
+    fn kv_pairs_cache(&self) -> Result<std::sync::MutexGuard<Option<HashMap<String, String>>>> {
+        self.kv_pairs
+            .lock()
+            .map_err(|e| anyhow!("Catalog {}: kv cache lock poisoned: {e}", self.id))
     }
 
     /// Sets the `MixNMatch` object. Automatically done when created via `from_id()`.
@@ -54,10 +223,12 @@ impl Catalog {
 
     //TODO test
     pub async fn refresh_overview_table(&self) -> Result<()> {
-        self.app()?
-            .storage()
+        let app = self.app()?;
+        app.storage()
             .catalog_refresh_overview_table(self.id)
-            .await
+            .await?;
+        Self::invalidate_cache(self.id, app);
+        Ok(())
     }
 
     pub async fn references(&self, entry: &crate::entry::Entry) -> Vec<Reference> {
@@ -89,6 +260,12 @@ impl Catalog {
                 snaks.push(snak);
             }
         }
+        for ds in self.default_statements().await.unwrap_or_default() {
+            let snak = ds.snak();
+            if ds.as_reference && !snaks.contains(&snak) {
+                snaks.push(snak);
+            }
+        }
         if snaks.is_empty() {
             return vec![];
         }
@@ -103,10 +280,77 @@ impl Catalog {
                 .set_catalog_taxon_run(self.id, new_taxon_run)
                 .await?;
             self.taxon_run = new_taxon_run;
+            Self::invalidate_cache(self.id, app);
         }
         Ok(())
     }
 
+    /// Activates or deactivates the catalog, if the user is allowed to.
+    pub async fn set_active(&mut self, active: bool, user_id: usize) -> Result<()> {
+        let app = self.app()?.clone();
+        Permissions::new(&app)
+            .check_can_edit_catalog(user_id, self)
+            .await?;
+        if self.active != active {
+            app.storage().catalog_set_active(self.id, active).await?;
+            self.active = active;
+            Self::invalidate_cache(self.id, &app);
+        }
+        Ok(())
+    }
+
+    /// Deletes the catalog and all its entries, if the user is allowed to.
+    pub async fn delete(self, user_id: usize) -> Result<()> {
+        let app = self.app()?.clone();
+        Permissions::new(&app)
+            .check_can_edit_catalog(user_id, &self)
+            .await?;
+        app.storage().catalog_delete(self.id).await?;
+        Self::invalidate_cache(self.id, &app);
+        Ok(())
+    }
+
+    /// Returns the default statements of the catalog, both for items and references.
+    pub async fn default_statements(&self) -> Result<Vec<CatalogDefaultStatement>> {
+        self.app()?
+            .storage()
+            .get_catalog_default_statements(self.id)
+            .await
+    }
+
+    /// Adds a default statement to the catalog, if the user is allowed to. Returns the new ID.
+    pub async fn add_default_statement(
+        &self,
+        property: usize,
+        value: &str,
+        as_reference: bool,
+        user_id: usize,
+    ) -> Result<usize> {
+        let app = self.app()?;
+        Permissions::new(app)
+            .check_can_edit_catalog(user_id, self)
+            .await?;
+        if property == 0 || value.trim().is_empty() {
+            return Err(anyhow!(
+                "Catalog {}: default statement needs a property and a value",
+                self.id
+            ));
+        }
+        let ds = CatalogDefaultStatement::new(self.id, property, value, as_reference);
+        app.storage().add_catalog_default_statement(&ds).await
+    }
+
+    /// Removes a default statement from the catalog, if the user is allowed to.
+    pub async fn remove_default_statement(&self, id: usize, user_id: usize) -> Result<()> {
+        let app = self.app()?;
+        Permissions::new(app)
+            .check_can_edit_catalog(user_id, self)
+            .await?;
+        app.storage()
+            .delete_catalog_default_statement(self.id, id)
+            .await
+    }
+
     pub async fn number_of_entries(&self) -> Result<usize> {
         let ret = self
             .app()?
@@ -125,10 +369,74 @@ mod tests {
     const TEST_CATALOG_ID: usize = 5526;
     const _TEST_ENTRY_ID: usize = 143962196;
 
+    #[test]
+    fn test_catalog_default_statement_snak() {
+        let ds = CatalogDefaultStatement::new(TEST_CATALOG_ID, 31, " Q5 ", false);
+        assert_eq!(ds.value, "Q5");
+        assert_eq!(ds.snak(), Snak::new_item("P31", "Q5"));
+        let ds = CatalogDefaultStatement::new(TEST_CATALOG_ID, 1810, "Q5 and more", true);
+        assert_eq!(ds.snak(), Snak::new_string("P1810", "Q5 and more"));
+        let ds = CatalogDefaultStatement::new(TEST_CATALOG_ID, 1810, "Q", true);
+        assert_eq!(ds.snak(), Snak::new_string("P1810", "Q"));
+    }
+
     #[tokio::test]
     async fn test_catalog_from_id() {
         let app = get_test_app();
         let catalog = Catalog::from_id(TEST_CATALOG_ID, &app).await.unwrap();
         assert_eq!(catalog.name.unwrap(), "TEST CATALOG");
     }
+
+    #[tokio::test]
+    async fn test_get_catalogs_by_property() {
+        let app = get_test_app();
+        let catalogs = app
+            .storage()
+            .get_catalogs_by_property(214, true)
+            .await
+            .unwrap();
+        assert!(!catalogs.is_empty());
+        assert!(catalogs
+            .iter()
+            .all(|c| c.wd_prop == Some(214) && c.wd_qual.is_none() && c.active));
+    }
+
+    #[tokio::test]
+    async fn test_catalog_from_source_item() {
+        let app = get_test_app();
+        assert!(Catalog::from_source_item("foo", &app).await.is_err());
+        let catalog = Catalog::from_id(TEST_CATALOG_ID, &app).await.unwrap();
+        if let Some(source_item) = catalog.source_item {
+            let found = Catalog::from_source_item(&format!("Q{source_item}"), &app)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(found.source_item, Some(source_item));
+            assert!(found.app.is_some());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_catalog_from_id_cached() {
+        let app = get_test_app();
+        Catalog::invalidate_cache(TEST_CATALOG_ID, &app);
+        let catalog1 = Catalog::from_id(TEST_CATALOG_ID, &app).await.unwrap();
+        assert!(app.catalog_cache().contains_key(&TEST_CATALOG_ID));
+        let catalog2 = Catalog::from_id(TEST_CATALOG_ID, &app).await.unwrap();
+        assert!(Arc::ptr_eq(&catalog1.kv_pairs, &catalog2.kv_pairs));
+        assert!(catalog2.app.is_some());
+        Catalog::invalidate_cache(TEST_CATALOG_ID, &app);
+        assert!(!app.catalog_cache().contains_key(&TEST_CATALOG_ID));
+    }
+
+    #[tokio::test]
+    async fn test_get_key_value_pairs_cached() {
+        let app = get_test_app();
+        let catalog = Catalog::from_id(TEST_CATALOG_ID, &app).await.unwrap();
+        assert!(catalog.kv_pairs.lock().unwrap().is_none());
+        let kv1 = catalog.get_key_value_pairs().await.unwrap();
+        assert!(catalog.kv_pairs.lock().unwrap().is_some());
+        let kv2 = catalog.get_key_value_pairs().await.unwrap();
+        assert_eq!(kv1, kv2);
+    }
 }
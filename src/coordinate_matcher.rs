@@ -1,27 +1,41 @@
 use crate::app_state::AppState;
 use crate::app_state::USER_LOCATION_MATCH;
-use crate::entry::Entry;
+use crate::entry::{CoordinateLocation, Entry, DEFAULT_COORDINATE_DECIMALS, KV_LOCATION_PRECISION};
 use crate::job::{Job, Jobbable};
+use crate::quick_compare::QuickCompare;
 use anyhow::{anyhow, Result};
 use lazy_static::lazy_static;
 use log::error;
 use mediawiki::api::Api;
 use regex::{Regex, RegexBuilder};
 use std::collections::HashMap;
+use wikimisc::wikibase::entity_container::EntityContainer;
 
-const DEFAULT_MAX_DISTANCE: &str = "500m";
-const MAX_AUTOMATCH_DISTANCE: f64 = 0.1; // km
+/// `kv_catalog` key for the maximum distance of a matching item, eg "500m" or "2km"
+pub const KV_LOCATION_DISTANCE: &str = "location_distance";
+const DEFAULT_MAX_DISTANCE_KM: f64 = 0.5;
+/// Mean earth radius
+const EARTH_RADIUS_METERS: f64 = 6_371_000.0;
 const MAX_RESULTS_FOR_RANDOM_CATALOG: usize = 5000;
 
 lazy_static! {
-    static ref RE_METERS: Regex = RegexBuilder::new(r"^([0-9.]+)m$")
-        .build()
-        .expect("Regex error");
-    static ref RE_KILOMETERS: Regex = RegexBuilder::new(r"^([0-9.]+)km$")
+    static ref RE_DISTANCE: Regex = RegexBuilder::new(r"^\s*([0-9]*\.?[0-9]+)\s*(m|km)?\s*$")
+        .case_insensitive(true)
         .build()
         .expect("Regex error");
 }
 
+/// Great-circle distance between two coordinates in degrees, in meters.
+/// Uses the haversine formula, which unlike the spherical law of cosines is stable for short distances.
+pub fn distance_meters(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let d_lat = lat2 - lat1;
+    let d_lon = (lon2 - lon1).to_radians();
+    let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+    // Rounding errors can push `a` slightly above 1 for antipodal points
+    2.0 * EARTH_RADIUS_METERS * a.sqrt().min(1.0).asin()
+}
+
 #[derive(Debug, Clone)]
 pub struct LocationRow {
     pub lat: f64,
@@ -90,7 +104,9 @@ impl CoordinateMatcher {
     }
 
     async fn process_row(&self, row: &LocationRow) -> Result<()> {
-        let (max_distance, max_distance_sparql) = self.get_max_distance_sparql_for_entry(row);
+        let row = &self.round_row_location(row);
+        let max_distance_km = self.get_max_distance_km(row);
+        let max_distance = format!("{}m", (max_distance_km * 1000.0).round());
         let ext_name = row
             .ext_name
             .split('(')
@@ -116,7 +132,7 @@ impl CoordinateMatcher {
             }
         }
 
-        self.process_row_process_matches(matches, row, max_distance_sparql)
+        self.process_row_process_matches(matches, row, max_distance_km)
             .await;
         Ok(())
     }
@@ -125,22 +141,20 @@ impl CoordinateMatcher {
         &self,
         matches: Vec<String>,
         row: &LocationRow,
-        max_distance_sparql: f64,
+        max_distance_km: f64,
     ) {
         if matches.is_empty() {
             if self.is_permission("allow_location_create", row.catalog_id, "yes")
-                && self
-                    .try_match_via_sparql_query(row, max_distance_sparql)
-                    .await
+                && self.try_match_via_sparql_query(row, max_distance_km).await
             {
                 error!("CoordinateMatcher: TODO create item");
             }
         } else if self.is_permission("allow_location_match", row.catalog_id, "yes")
-            && !self.try_match_via_wikidata_search(row, &matches).await
+            && !self
+                .try_match_via_wikidata_search(row, &matches, max_distance_km)
+                .await
         {
-            let _ = self
-                .try_match_via_sparql_query(row, max_distance_sparql)
-                .await;
+            let _ = self.try_match_via_sparql_query(row, max_distance_km).await;
         }
     }
 
@@ -172,7 +186,16 @@ impl CoordinateMatcher {
     }
 
     // Returns true if there is a match
-    async fn try_match_via_wikidata_search(&self, row: &LocationRow, items: &[String]) -> bool {
+    async fn try_match_via_wikidata_search(
+        &self,
+        row: &LocationRow,
+        items: &[String],
+        max_distance_km: f64,
+    ) -> bool {
+        let items = self
+            .items_within_distance(row, items, max_distance_km)
+            .await;
+        let items = items.as_slice();
         if items.is_empty() {
             return false;
         }
@@ -196,6 +219,38 @@ impl CoordinateMatcher {
         true // Entry is fully or partially matched
     }
 
+    /// Returns the items whose coordinates are within `max_distance_km` of the row, checked against the actual items
+    /// rather than the search index. If the items can not be loaded, they are all returned.
+    async fn items_within_distance(
+        &self,
+        row: &LocationRow,
+        items: &[String],
+        max_distance_km: f64,
+    ) -> Vec<String> {
+        let entities = EntityContainer::new();
+        if entities
+            .load_entities(&self.mw_api, &items.to_vec())
+            .await
+            .is_err()
+        {
+            return items.to_vec();
+        }
+        let max_distance_meters = max_distance_km * 1000.0;
+        items
+            .iter()
+            .filter(|q| {
+                entities
+                    .get_entity(q.to_string())
+                    .and_then(|entity| QuickCompare::get_coordinates(&entity))
+                    .is_some_and(|location| {
+                        distance_meters(row.lat, row.lon, location.lat, location.lon)
+                            <= max_distance_meters
+                    })
+            })
+            .cloned()
+            .collect()
+    }
+
     // Returns true if no results were found
     async fn try_match_via_sparql_query(&self, row: &LocationRow, max_distance: f64) -> bool {
         let type_query = self
@@ -222,7 +277,12 @@ impl CoordinateMatcher {
         let mut candidates = vec![];
         if let Some(bindings) = sparql_result["results"]["bindings"].as_array() {
             for b in bindings {
-                if b["distance"]["value"].as_f64().unwrap_or(0.0) > max_distance {
+                // SPARQL JSON results have literal values as strings
+                let distance = match &b["distance"]["value"] {
+                    serde_json::Value::String(s) => s.parse::<f64>().ok(),
+                    value => value.as_f64(),
+                };
+                if !distance.is_some_and(|distance| distance <= max_distance) {
                     continue;
                 }
                 if let Some(place) = b["place"]["value"].as_str() {
@@ -248,22 +308,45 @@ impl CoordinateMatcher {
         Ok(())
     }
 
-    // (max_distance,max_distance_sparql)
-    fn get_max_distance_sparql_for_entry(&self, row: &LocationRow) -> (String, f64) {
-        let max_distance = self
-            .get_permission_value("location_distance", row.catalog_id)
-            .map_or_else(|| DEFAULT_MAX_DISTANCE.to_string(), |s| s.to_owned());
-        let mut max_distance_sparql = MAX_AUTOMATCH_DISTANCE; // Default
-        if let Some(captures) = RE_KILOMETERS.captures(&max_distance) {
-            if let Ok(value) = captures[1].parse::<f64>() {
-                max_distance_sparql = value;
-            }
-        } else if let Some(captures) = RE_METERS.captures(&max_distance) {
-            if let Ok(value) = captures[1].parse::<f64>() {
-                max_distance_sparql = value / 1000.0;
-            }
+    /// The `location_distance` of the catalog in km, or the default if it is not set or can not be parsed
+    fn get_max_distance_km(&self, row: &LocationRow) -> f64 {
+        self.get_permission_value(KV_LOCATION_DISTANCE, row.catalog_id)
+            .and_then(|value| Self::parse_distance_km(value))
+            .unwrap_or(DEFAULT_MAX_DISTANCE_KM)
+    }
+
+    /// Parses a distance like "500m", "2km", or "1.5 km" into km. A number without unit is in meters.
+    /// Returns `None` for invalid or zero distances.
+    pub fn parse_distance_km(value: &str) -> Option<f64> {
+        let captures = RE_DISTANCE.captures(value)?;
+        let number = captures[1].parse::<f64>().ok()?;
+        let km = match captures.get(2).map(|unit| unit.as_str().to_lowercase()) {
+            Some(unit) if unit == "km" => number,
+            _ => number / 1000.0,
+        };
+        if km > 0.0 {
+            Some(km)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the row with its coordinates rounded to the catalog `location_precision`, or the default precision,
+    /// so stored coordinates with spurious precision are compared consistently with Wikidata.
+    fn round_row_location(&self, row: &LocationRow) -> LocationRow {
+        let decimals = self
+            .get_permission_value(KV_LOCATION_PRECISION, row.catalog_id)
+            .and_then(|value| CoordinateLocation::parse_precision(value))
+            .unwrap_or(DEFAULT_COORDINATE_DECIMALS);
+        let location = CoordinateLocation {
+            lat: row.lat,
+            lon: row.lon,
         }
-        (max_distance, max_distance_sparql)
+        .rounded(decimals);
+        let mut ret = row.to_owned();
+        ret.lat = location.lat;
+        ret.lon = location.lon;
+        ret
     }
 
     fn get_entry_type(&self, row: &LocationRow) -> Option<String> {
@@ -316,6 +399,42 @@ mod tests {
     const TEST_CATALOG_ID: usize = 5526;
     const TEST_ENTRY_ID: usize = 157175552;
 
+    #[test]
+    fn test_distance_meters() {
+        assert_eq!(distance_meters(12.3, 45.6, 12.3, 45.6), 0.0);
+        // Paris - London
+        let d = distance_meters(48.8566, 2.3522, 51.5074, -0.1278);
+        assert!((d - 343_560.0).abs() < 1000.0, "{d}");
+        // One degree of longitude at the equator
+        let d = distance_meters(0.0, 0.0, 0.0, 1.0);
+        assert!((d - 111_195.0).abs() < 10.0, "{d}");
+        // Short distances
+        let d = distance_meters(52.0, 13.0, 52.0001, 13.0);
+        assert!((d - 11.12).abs() < 0.01, "{d}");
+        // Symmetric
+        assert_eq!(
+            distance_meters(48.8566, 2.3522, 51.5074, -0.1278),
+            distance_meters(51.5074, -0.1278, 48.8566, 2.3522)
+        );
+        // Antipodal points
+        let d = distance_meters(0.0, 0.0, 0.0, 180.0);
+        assert!(
+            (d - std::f64::consts::PI * EARTH_RADIUS_METERS).abs() < 1.0,
+            "{d}"
+        );
+    }
+
+    #[test]
+    fn test_parse_distance_km() {
+        assert_eq!(CoordinateMatcher::parse_distance_km("500m"), Some(0.5));
+        assert_eq!(CoordinateMatcher::parse_distance_km("2km"), Some(2.0));
+        assert_eq!(CoordinateMatcher::parse_distance_km(" 1.5 KM "), Some(1.5));
+        assert_eq!(CoordinateMatcher::parse_distance_km("250"), Some(0.25));
+        assert_eq!(CoordinateMatcher::parse_distance_km("0m"), None);
+        assert_eq!(CoordinateMatcher::parse_distance_km("far"), None);
+        assert_eq!(CoordinateMatcher::parse_distance_km("2 miles"), None);
+    }
+
     #[tokio::test]
     async fn test_match_by_coordinates() {
         let app = get_test_app();
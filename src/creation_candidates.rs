@@ -0,0 +1,129 @@
+use crate::app_state::AppState;
+use crate::extended_entry::ExtendedEntry;
+use anyhow::Result;
+
+/// Default number of creation candidates per query
+pub const DEFAULT_CREATION_CANDIDATES_LIMIT: usize = 50;
+
+/// Selects entries of a catalog that are candidates for creating a new item:
+/// unmatched entries, and those that a user marked as "not on Wikidata".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CreationCandidateQuery {
+    pub catalog_id: usize,
+    pub type_name: Option<String>,
+    /// Only entries with a birth or death date
+    pub with_dates: bool,
+    /// Minimum number of unmatched or "not on Wikidata" entries with the same name, across all catalogs, including the entry itself
+    pub min_shared_names: usize,
+    pub limit: usize,
+    pub offset: usize,
+}
+
+impl CreationCandidateQuery {
+    pub fn new(catalog_id: usize) -> Self {
+        Self {
+            catalog_id,
+            type_name: None,
+            with_dates: false,
+            min_shared_names: 1,
+            limit: DEFAULT_CREATION_CANDIDATES_LIMIT,
+            offset: 0,
+        }
+    }
+
+    pub fn with_type(mut self, type_name: &str) -> Self {
+        self.type_name = Some(type_name.to_string());
+        self
+    }
+
+    pub fn with_dates(mut self, with_dates: bool) -> Self {
+        self.with_dates = with_dates;
+        self
+    }
+
+    pub fn with_min_shared_names(mut self, min_shared_names: usize) -> Self {
+        self.min_shared_names = min_shared_names;
+        self
+    }
+
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    pub fn with_offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+}
+
+/// An entry suitable for item creation, with the data needed to create the item
+#[derive(Debug, Clone)]
+pub struct CreationCandidate {
+    pub entry: ExtendedEntry,
+    pub shared_names: usize,
+}
+
+impl CreationCandidate {
+    /// Returns the candidates for a query, most shared names first.
+    pub async fn get(query: &CreationCandidateQuery, app: &AppState) -> Result<Vec<Self>> {
+        let (entries, shared_names): (Vec<_>, Vec<_>) = app
+            .storage()
+            .get_entries_for_creation(query)
+            .await?
+            .into_iter()
+            .map(|(mut entry, shared_names)| {
+                entry.set_app(app);
+                (entry, shared_names)
+            })
+            .unzip();
+        let ret = ExtendedEntry::multiple_from_entries(entries, app)
+            .await?
+            .into_iter()
+            .zip(shared_names)
+            .map(|(entry, shared_names)| Self {
+                entry,
+                shared_names,
+            })
+            .collect();
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app_state::get_test_app;
+
+    const TEST_CATALOG_ID: usize = 5526;
+
+    #[test]
+    fn test_creation_candidate_query() {
+        let query = CreationCandidateQuery::new(123)
+            .with_type("Q5")
+            .with_dates(true)
+            .with_min_shared_names(2)
+            .with_limit(10)
+            .with_offset(20);
+        assert_eq!(query.catalog_id, 123);
+        assert_eq!(query.type_name, Some("Q5".to_string()));
+        assert!(query.with_dates);
+        assert_eq!(query.min_shared_names, 2);
+        assert_eq!(query.limit, 10);
+        assert_eq!(query.offset, 20);
+    }
+
+    #[tokio::test]
+    async fn test_creation_candidates() {
+        let app = get_test_app();
+        let query = CreationCandidateQuery::new(TEST_CATALOG_ID).with_limit(5);
+        let candidates = CreationCandidate::get(&query, &app).await.unwrap();
+        assert!(candidates.len() <= 5);
+        for candidate in &candidates {
+            let entry = &candidate.entry.entry;
+            assert_eq!(entry.catalog, TEST_CATALOG_ID);
+            assert!(entry.is_unmatched() || (entry.q == Some(-1) && entry.is_fully_matched()));
+            assert!(candidate.shared_names >= 1);
+        }
+    }
+}
@@ -1,7 +1,10 @@
-use crate::app_state::AppState;
+use crate::app_state::{AppState, USER_AUX_MATCH};
 use crate::autoscrape::Autoscrape;
-use crate::update_catalog::UpdateCatalogError;
+use crate::catalog::Catalog;
+use crate::extended_entry::ExtendedEntry;
+use crate::update_catalog::{UpdateCatalogError, UpdateInfo};
 use anyhow::Result;
+use csv::StringRecord;
 use lazy_static::lazy_static;
 use regex::Regex;
 use serde_json::json;
@@ -12,8 +15,22 @@ use std::fs;
 use std::fs::File;
 use std::io::Cursor;
 use std::path::Path;
+use std::sync::Arc;
 use uuid::Uuid;
 
+/// Column label prefixes for language-tagged names and descriptions, eg `name:de` or `desc:fr`
+pub const LANGUAGE_NAME_COLUMN_PREFIX: &str = "name:";
+pub const LANGUAGE_DESC_COLUMN_PREFIX: &str = "desc:";
+
+/// `kv_catalog` key; if "1", HTML is stripped from imported and scraped descriptions, see `AutoscrapeResolve::strip_html`
+pub const KV_STRIP_HTML_DESCRIPTIONS: &str = "strip_html_descriptions";
+
+/// Columns of a SPARQL source without a `columns` key, filled from the query variables of the same name
+const SPARQL_DEFAULT_COLUMNS: &[&str] = &["id", "name", "desc", "url"];
+/// Columns whose SPARQL values are item URIs, which are shortened to the item ID
+const SPARQL_ITEM_COLUMNS: &[&str] = &["id", "q", "autoq"];
+const WIKIDATA_ENTITY_URI_PREFIX: &str = "http://www.wikidata.org/entity/";
+
 lazy_static! {
     static ref RE_PATTERN_WRAP_REMOVAL: Regex =
         Regex::new(r"^\|(.+)\|$").expect("Regexp construction");
@@ -27,11 +44,77 @@ pub struct LineCounter {
     pub offset: usize,
 }
 
+/// The result of a dry run of an import: the detected headers, and the first rows as they would be imported.
+#[derive(Debug, Clone, Default)]
+pub struct ImportPreview {
+    pub headers: Vec<String>,
+    pub rows: Vec<ExtendedEntry>,
+    pub errors: Vec<String>,
+}
+
+/// Reads the first `max_rows` data rows of an import source, and parses them into entries, without writing anything.
+/// Headers are taken from the first header row of the file, or from the configured columns if there is none.
+pub async fn preview(
+    app: &AppState,
+    update_info: &UpdateInfo,
+    max_rows: usize,
+) -> Result<ImportPreview> {
+    let json = update_info.json()?;
+    let mut datasource = DataSource::new(update_info.catalog, &json)?;
+    let catalog = Catalog::from_id(update_info.catalog, app).await?;
+    datasource.load_catalog_options(&catalog).await?;
+    let mut reader = datasource.get_reader(app).await?;
+    let mut ret = ImportPreview {
+        headers: datasource.columns.clone(),
+        ..Default::default()
+    };
+    let mut header_rows = datasource.num_header_rows;
+    let mut skip_rows = datasource.skip_first_rows;
+    for record in reader.records() {
+        if ret.rows.len() + ret.errors.len() >= max_rows {
+            break;
+        }
+        let record = match record {
+            Ok(record) => datasource.normalize_record(record),
+            Err(e) => {
+                ret.errors.push(e.to_string());
+                continue;
+            }
+        };
+        if record.is_empty() {
+            continue;
+        }
+        if header_rows > 0 {
+            if header_rows == datasource.num_header_rows {
+                ret.headers = record.iter().map(|s| s.to_string()).collect();
+            }
+            header_rows -= 1;
+            continue;
+        }
+        if skip_rows > 0 {
+            skip_rows -= 1;
+            continue;
+        }
+        if record.len() < datasource.min_cols {
+            ret.errors
+                .push(UpdateCatalogError::NotEnoughColumns(record.len()).to_string());
+            continue;
+        }
+        match ExtendedEntry::from_row(&record, &mut datasource) {
+            Ok(extended_entry) => ret.rows.push(extended_entry),
+            Err(e) => ret.errors.push(e.to_string()),
+        }
+    }
+    datasource.clear_tmp_file();
+    Ok(ret)
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 enum DataSourceType {
     Unknown,
     Csv,
     Tsv,
+    FixedWidth,
 }
 
 impl DataSourceType {
@@ -40,6 +123,7 @@ impl DataSourceType {
         match s.to_string().trim().to_uppercase().as_str() {
             "CSV" => Self::Csv,
             "TSV" => Self::Tsv,
+            "FIXED" | "FIXED_WIDTH" => Self::FixedWidth,
             _ => Self::Unknown,
         }
     }
@@ -49,6 +133,72 @@ impl DataSourceType {
 pub enum DataSourceLocation {
     Url(String),
     FilePath(String),
+    /// A Wikidata SPARQL query, from the `sparql` key.
+    /// `column_map` (`sparql_column_map`) maps column labels to query variables where they differ, eg `{"id":"item"}`.
+    Sparql {
+        query: String,
+        column_map: HashMap<String, String>,
+    },
+}
+
+/// Maps the result columns of a SPARQL query to the columns of a `DataSource`, by variable name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SparqlColumns {
+    /// The query variables, to recognize the header row
+    headers: Vec<String>,
+    /// For each `DataSource` column, the position of its variable in the result, if any
+    indices: Vec<Option<usize>>,
+    item_columns: Vec<bool>,
+}
+
+impl SparqlColumns {
+    pub fn new(
+        columns: &[String],
+        column_map: &HashMap<String, String>,
+        headers: &[String],
+    ) -> Self {
+        let indices = columns
+            .iter()
+            .map(|column| {
+                let column = column.trim();
+                let variable = column_map.get(column).map_or(column, |v| v.as_str());
+                let variable = variable.trim_start_matches('?');
+                headers.iter().position(|header| header == variable)
+            })
+            .collect();
+        let item_columns = columns
+            .iter()
+            .map(|column| SPARQL_ITEM_COLUMNS.contains(&column.trim()))
+            .collect();
+        Self {
+            headers: headers.to_vec(),
+            indices,
+            item_columns,
+        }
+    }
+
+    /// Puts the values of a result row in `DataSource` column order; the header row becomes an empty record
+    pub fn map_record(&self, record: &StringRecord) -> StringRecord {
+        if record.iter().eq(self.headers.iter().map(|s| s.as_str())) {
+            return StringRecord::new();
+        }
+        let values: Vec<String> = self
+            .indices
+            .iter()
+            .zip(self.item_columns.iter())
+            .map(|(index, is_item)| {
+                let value = index.and_then(|index| record.get(index)).unwrap_or("");
+                if *is_item {
+                    value
+                        .trim_start_matches(WIKIDATA_ENTITY_URI_PREFIX)
+                        .to_string()
+                } else {
+                    value.to_string()
+                }
+            })
+            .collect();
+        StringRecord::from(values)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -81,24 +231,172 @@ impl Pattern {
     }
 }
 
+/// Column positions for fixed-width files, from the `column_ranges` key, eg `[[0,10],[10,40],[40,null]]`.
+/// Each range is `[start,end]`, start inclusive, end exclusive; an end of `null` means "to the end of the line".
+/// Positions are characters, unless `column_range_unit` is `"byte"`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FixedWidthColumns {
+    pub ranges: Vec<(usize, Option<usize>)>,
+    pub use_bytes: bool,
+}
+
+impl FixedWidthColumns {
+    fn from_json(json: &serde_json::Value) -> Result<Option<Self>> {
+        let ranges = match json.get("column_ranges") {
+            Some(ranges) => ranges
+                .as_array()
+                .ok_or(UpdateCatalogError::BadColumnRanges)?,
+            None => return Ok(None),
+        };
+        let ranges = ranges
+            .iter()
+            .map(Self::range_from_json)
+            .collect::<Result<Vec<_>>>()?;
+        let use_bytes = json
+            .get("column_range_unit")
+            .and_then(|v| v.as_str())
+            .map(|s| s.trim().to_lowercase() == "byte")
+            .unwrap_or(false);
+        Ok(Some(Self { ranges, use_bytes }))
+    }
+
+    fn range_from_json(range: &serde_json::Value) -> Result<(usize, Option<usize>)> {
+        let range = range
+            .as_array()
+            .ok_or(UpdateCatalogError::BadColumnRanges)?;
+        let start = range
+            .first()
+            .and_then(|v| v.as_u64())
+            .ok_or(UpdateCatalogError::BadColumnRanges)? as usize;
+        let end = match range.get(1) {
+            Some(serde_json::Value::Null) | None => None,
+            Some(v) => Some(v.as_u64().ok_or(UpdateCatalogError::BadColumnRanges)? as usize),
+        };
+        if let Some(end) = end {
+            if end < start {
+                return Err(UpdateCatalogError::BadColumnRanges.into());
+            }
+        }
+        Ok((start, end))
+    }
+
+    /// Splits a line into trimmed column values. Columns beyond the end of the line are empty.
+    pub fn split_line(&self, line: &str) -> Vec<String> {
+        let chars: Vec<char> = line.chars().collect();
+        let bytes = line.as_bytes();
+        self.ranges
+            .iter()
+            .map(|(start, end)| {
+                if self.use_bytes {
+                    let end = end.unwrap_or(bytes.len()).min(bytes.len());
+                    let start = (*start).min(end);
+                    String::from_utf8_lossy(&bytes[start..end])
+                        .trim()
+                        .to_string()
+                } else {
+                    let end = end.unwrap_or(chars.len()).min(chars.len());
+                    let start = (*start).min(end);
+                    chars[start..end]
+                        .iter()
+                        .collect::<String>()
+                        .trim()
+                        .to_string()
+                }
+            })
+            .collect()
+    }
+}
+
+/// Options for matches that the source provides in a `q` (or `autoq`) column, from the update info JSON
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrematchOptions {
+    /// The user the matches are attributed to (`q_user_id`); 0 makes them preliminary matches
+    pub user_id: usize,
+    /// Only match entries that have no match yet (`q_only_unmatched`, default `true`).
+    /// Otherwise, preliminary matches are replaced as well; manual matches never are.
+    pub only_unmatched: bool,
+    /// Check that the items exist on Wikidata (`q_validate`); redirects are resolved to their target
+    pub validate: bool,
+    /// Numeric item => target item for redirects, or `None` for deleted items; set at runtime when validating
+    pub replacements: HashMap<isize, Option<isize>>,
+}
+
+impl Default for PrematchOptions {
+    fn default() -> Self {
+        Self {
+            user_id: USER_AUX_MATCH,
+            only_unmatched: true,
+            validate: false,
+            replacements: HashMap::new(),
+        }
+    }
+}
+
+impl PrematchOptions {
+    pub fn from_json(json: &serde_json::Value) -> Self {
+        let default = Self::default();
+        Self {
+            user_id: json
+                .get("q_user_id")
+                .and_then(|v| v.as_u64())
+                .map(|v| v as usize)
+                .unwrap_or(default.user_id),
+            only_unmatched: json
+                .get("q_only_unmatched")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(default.only_unmatched),
+            validate: json
+                .get("q_validate")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(default.validate),
+            replacements: default.replacements,
+        }
+    }
+
+    /// Returns the item to use for a source item; `None` if it was found to be deleted
+    pub fn resolve(&self, q: isize) -> Option<isize> {
+        match self.replacements.get(&q) {
+            Some(replacement) => *replacement,
+            None => Some(q),
+        }
+    }
+}
+
+/// A downloaded source file, removed when the last clone of its `DataSource` is dropped,
+/// so an import that fails early does not leave it behind
+#[derive(Debug)]
+pub struct TmpFile(OsString);
+
+impl Drop for TmpFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct DataSource {
     pub catalog_id: usize,
     pub json: serde_json::Value,
-    _columns: Vec<String>,
+    pub columns: Vec<String>,
     pub just_add: bool,
     pub min_cols: usize,
     pub num_header_rows: u64,
     pub skip_first_rows: u64,
     pub ext_id_column: usize,
     pub patterns: Vec<Pattern>,
-    pub tmp_file: Option<OsString>,
+    pub tmp_file: Option<Arc<TmpFile>>,
     pub colmap: HashMap<String, usize>,
     pub default_type: Option<String>,
     pub url_pattern: Option<String>,
     _update_existing_description: Option<bool>,
     _update_all_descriptions: Option<bool>,
     pub fail_on_error: bool,
+    pub fixed_width: Option<FixedWidthColumns>,
+    pub sparql_columns: Option<SparqlColumns>, // Set at runtime
+    pub name_language: Option<String>,
+    pub desc_language: Option<String>,
+    pub strip_html_descriptions: bool,
+    pub prematch: PrematchOptions,
     pub line_counter: LineCounter,
     pub rows_to_skip: u64, // Modified at runtime
     pub offset: usize,     // Set at runtime
@@ -112,10 +410,12 @@ impl DataSource {
         let colmap = Self::get_colmap(&columns);
         let ext_id_column = Self::get_ext_id_column(&colmap)?;
         let min_cols = Self::extract_min_cols(json, &columns);
+        let name_language = Self::get_primary_language(json, &columns, LANGUAGE_NAME_COLUMN_PREFIX);
+        let desc_language = Self::get_primary_language(json, &columns, LANGUAGE_DESC_COLUMN_PREFIX);
         let mut ret = Self {
             catalog_id,
             json: json.clone(),
-            _columns: columns,
+            columns,
             just_add: Self::extract_bool("just_add", json),
             min_cols: min_cols as usize,
             num_header_rows: Self::extract_u64("num_header_rows", json),
@@ -138,6 +438,12 @@ impl DataSource {
                 .unwrap_or(None),
             line_counter: LineCounter::default(),
             fail_on_error: false, // TODO?
+            fixed_width: FixedWidthColumns::from_json(json)?,
+            sparql_columns: None,
+            name_language,
+            desc_language,
+            strip_html_descriptions: false,
+            prematch: PrematchOptions::from_json(json),
             tmp_file: None,
             rows_to_skip: 0,
             offset: 0,
@@ -146,6 +452,16 @@ impl DataSource {
         Ok(ret)
     }
 
+    /// Sets options that are configured per catalog, rather than per import
+    pub async fn load_catalog_options(&mut self, catalog: &Catalog) -> Result<()> {
+        let kv_pairs = catalog.get_key_value_pairs().await?;
+        self.strip_html_descriptions = kv_pairs
+            .get(KV_STRIP_HTML_DESCRIPTIONS)
+            .map(|v| v.trim() == "1")
+            .unwrap_or(false);
+        Ok(())
+    }
+
     //TODO test
     async fn fetch_url(&self, url: &String, file_name: &Path) -> Result<()> {
         let response = Autoscrape::reqwest_client_external()?
@@ -158,38 +474,103 @@ impl DataSource {
         Ok(())
     }
 
-    //TODO test
-    pub fn clear_tmp_file(&self) {
-        if let Some(path) = &self.tmp_file {
-            let _ = fs::remove_file(path);
-        };
+    /// Returns the numeric items in the `q`/`autoq` column of the rows
+    pub fn get_prematch_items(&self, rows: &[StringRecord]) -> Vec<isize> {
+        let columns: Vec<usize> = ["q", "autoq"]
+            .iter()
+            .filter_map(|label| self.colmap.get(*label))
+            .copied()
+            .collect();
+        let mut ret: Vec<isize> = rows
+            .iter()
+            .flat_map(|row| columns.iter().filter_map(|col| row.get(*col)))
+            .filter_map(|cell| cell.trim().replace('Q', "").parse::<isize>().ok())
+            .filter(|q| *q > 0)
+            .collect();
+        ret.sort();
+        ret.dedup();
+        ret
+    }
+
+    /// Removes the downloaded source file now, instead of when the data source is dropped
+    pub fn clear_tmp_file(&mut self) {
+        self.tmp_file = None;
     }
 
-    //TODO test
     pub async fn get_reader(&mut self, app: &AppState) -> Result<csv::Reader<File>> {
-        let mut builder = csv::ReaderBuilder::new();
-        let builder = builder.flexible(true).has_headers(false);
-        let builder = match self.get_source_type(app).await? {
-            DataSourceType::Csv => builder.delimiter(b','),
-            DataSourceType::Tsv => builder.delimiter(b'\t'),
-            DataSourceType::Unknown => return Err(UpdateCatalogError::MissingDataSourceType.into()),
-        };
         match self.get_source_location(app)? {
             DataSourceLocation::Url(url) => {
+                let builder = self.get_reader_builder(app).await?;
                 let mut full_path = temp_dir();
                 let file_name = format!("{}.tmp", Uuid::new_v4());
                 full_path.push(file_name);
                 let full_path = full_path.as_path();
-                let full_path_string = OsString::from(full_path);
-                self.tmp_file = Some(full_path_string);
+                self.tmp_file = Some(Arc::new(TmpFile(OsString::from(full_path))));
                 self.fetch_url(&url, full_path).await?;
-                let builder = builder.from_path(full_path)?;
-                Ok(builder)
+                Ok(builder.from_path(full_path)?)
+            }
+            DataSourceLocation::FilePath(path) => {
+                Ok(self.get_reader_builder(app).await?.from_path(path)?)
+            }
+            DataSourceLocation::Sparql { query, column_map } => {
+                let mut reader = app.wikidata().load_sparql_csv(&query).await?;
+                self.set_sparql_columns(&mut reader, &column_map)?;
+                Ok(reader)
             }
-            DataSourceLocation::FilePath(path) => Ok(builder.from_path(path)?),
         }
     }
 
+    /// Maps the columns of a SPARQL result to the columns of this data source, by the variables in its header row
+    fn set_sparql_columns<R: std::io::Read>(
+        &mut self,
+        reader: &mut csv::Reader<R>,
+        column_map: &HashMap<String, String>,
+    ) -> Result<()> {
+        let headers: Vec<String> = reader.headers()?.iter().map(|s| s.to_string()).collect();
+        self.sparql_columns = Some(SparqlColumns::new(&self.columns, column_map, &headers));
+        Ok(())
+    }
+
+    /// Returns a CSV reader builder for the file type of this data source
+    async fn get_reader_builder(&self, app: &AppState) -> Result<csv::ReaderBuilder> {
+        let mut builder = csv::ReaderBuilder::new();
+        builder.flexible(true).has_headers(false);
+        match self.get_source_type(app).await? {
+            DataSourceType::Csv => {
+                builder.delimiter(b',');
+            }
+            DataSourceType::Tsv => {
+                builder.delimiter(b'\t');
+            }
+            DataSourceType::FixedWidth => {
+                if self.fixed_width.is_none() {
+                    return Err(UpdateCatalogError::BadColumnRanges.into());
+                }
+                // One field per line, split in normalize_record
+                builder.delimiter(0x1F).quoting(false);
+            }
+            DataSourceType::Unknown => return Err(UpdateCatalogError::MissingDataSourceType.into()),
+        }
+        Ok(builder)
+    }
+
+    /// Splits fixed-width lines into columns, and puts SPARQL results in column order; other records are returned unchanged.
+    /// Blank fixed-width lines become empty records, so they are skipped like blank CSV/TSV lines.
+    pub fn normalize_record(&self, record: StringRecord) -> StringRecord {
+        if let Some(sparql_columns) = &self.sparql_columns {
+            return sparql_columns.map_record(&record);
+        }
+        let fixed_width = match &self.fixed_width {
+            Some(fixed_width) => fixed_width,
+            None => return record,
+        };
+        let line = record.iter().collect::<Vec<&str>>().join("\x1F");
+        if line.trim().is_empty() {
+            return StringRecord::new();
+        }
+        StringRecord::from(fixed_width.split_line(&line))
+    }
+
     pub fn get_source_location(&self, app: &AppState) -> Result<DataSourceLocation> {
         if let Some(url) = self.json.get("source_url") {
             if let Some(url) = url.as_str() {
@@ -202,6 +583,22 @@ impl DataSource {
                 return Ok(DataSourceLocation::FilePath(path));
             }
         };
+        if let Some(query) = self.json.get("sparql").and_then(|v| v.as_str()) {
+            let column_map = self
+                .json
+                .get("sparql_column_map")
+                .and_then(|v| v.as_object())
+                .map(|o| {
+                    o.iter()
+                        .filter_map(|(k, v)| Some((k.to_owned(), v.as_str()?.to_string())))
+                        .collect()
+                })
+                .unwrap_or_default();
+            return Ok(DataSourceLocation::Sparql {
+                query: query.to_string(),
+                column_map,
+            });
+        }
         Err(UpdateCatalogError::MissingDataSourceLocation.into())
     }
 
@@ -243,12 +640,32 @@ impl DataSource {
         let ext_id_column = colmap
             .get("id")
             .ok_or(Box::new(UpdateCatalogError::MissingColumn))?;
-        let _ = colmap
-            .get("name")
-            .ok_or(Box::new(UpdateCatalogError::MissingColumn))?;
+        let has_name_column = colmap
+            .keys()
+            .any(|label| label == "name" || label.starts_with(LANGUAGE_NAME_COLUMN_PREFIX));
+        if !has_name_column {
+            return Err(UpdateCatalogError::MissingColumn.into());
+        }
         Ok(*ext_id_column)
     }
 
+    /// The language whose tagged column (eg `name:de`) is used for `ext_name`/`ext_desc`, if there is no plain value.
+    /// This is the `language` key if set, otherwise the language of the first tagged column.
+    fn get_primary_language(
+        json: &serde_json::Value,
+        columns: &[String],
+        prefix: &str,
+    ) -> Option<String> {
+        if let Some(language) = json.get("language").and_then(|v| v.as_str()) {
+            return Some(language.trim().to_string());
+        }
+        columns
+            .iter()
+            .filter_map(|col| col.trim().strip_prefix(prefix))
+            .map(|language| language.to_string())
+            .next()
+    }
+
     fn get_colmap(columns: &[String]) -> HashMap<String, usize> {
         let colmap: HashMap<String, usize> = columns
             .iter()
@@ -288,6 +705,138 @@ impl DataSource {
             .filter_map(|v| v.as_str())
             .map(|s| s.to_string())
             .collect();
+        if columns.is_empty() && json.get("sparql").is_some() {
+            return SPARQL_DEFAULT_COLUMNS
+                .iter()
+                .map(|s| s.to_string())
+                .collect();
+        }
         columns
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_width_columns() {
+        let json = json!({"column_ranges": [[0, 4], [4, 12], [12, null]]});
+        let fwc = FixedWidthColumns::from_json(&json).unwrap().unwrap();
+        assert!(!fwc.use_bytes);
+        assert_eq!(
+            fwc.split_line("0001Müller  Herr Dr."),
+            vec!["0001", "Müller", "Herr Dr."]
+        );
+        assert_eq!(fwc.split_line("0002"), vec!["0002", "", ""]);
+
+        let json = json!({"column_ranges": [[0, 4], [4, 11]], "column_range_unit": "byte"});
+        let fwc = FixedWidthColumns::from_json(&json).unwrap().unwrap();
+        assert!(fwc.use_bytes);
+        assert_eq!(fwc.split_line("0001Müller  "), vec!["0001", "Müller"]);
+
+        assert_eq!(FixedWidthColumns::from_json(&json!({})).unwrap(), None);
+        assert!(FixedWidthColumns::from_json(&json!({"column_ranges": [[5, 2]]})).is_err());
+        assert!(FixedWidthColumns::from_json(&json!({"column_ranges": [["a"]]})).is_err());
+    }
+
+    #[test]
+    fn test_sparql_columns() {
+        let columns: Vec<String> = ["id", "name", "desc", "q"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let column_map: HashMap<String, String> = [("id", "?item"), ("q", "item")]
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let headers: Vec<String> = ["item", "name"].iter().map(|s| s.to_string()).collect();
+        let sparql_columns = SparqlColumns::new(&columns, &column_map, &headers);
+        let record =
+            StringRecord::from(vec!["http://www.wikidata.org/entity/Q42", "Douglas Adams"]);
+        assert_eq!(
+            sparql_columns
+                .map_record(&record)
+                .iter()
+                .collect::<Vec<_>>(),
+            vec!["Q42", "Douglas Adams", "", "Q42"]
+        );
+        assert!(sparql_columns
+            .map_record(&StringRecord::from(vec!["item", "name"]))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_sparql_default_columns() {
+        let json = json!({"sparql": "SELECT ?id ?name {}"});
+        let datasource = DataSource::new(1, &json).unwrap();
+        assert_eq!(datasource.columns, vec!["id", "name", "desc", "url"]);
+    }
+
+    #[test]
+    fn test_sparql_csv_reader() {
+        let json = json!({
+            "sparql": "SELECT ?item ?itemLabel ?born {}",
+            "columns": ["id", "name", "desc", "q"]
+        });
+        let mut datasource = DataSource::new(1, &json).unwrap();
+        let column_map: HashMap<String, String> = [
+            ("id", "item"),
+            ("name", "itemLabel"),
+            ("desc", "?born"),
+            ("q", "item"),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        // As returned by the query service
+        let csv = "item,itemLabel,born\r\n\
+            http://www.wikidata.org/entity/Q42,Douglas Adams,1952-03-11T00:00:00Z\r\n\
+            http://www.wikidata.org/entity/Q1,\"Universe, the\",\r\n";
+        for has_headers in [true, false] {
+            let mut reader = csv::ReaderBuilder::new()
+                .has_headers(has_headers)
+                .from_reader(csv.as_bytes());
+            datasource
+                .set_sparql_columns(&mut reader, &column_map)
+                .unwrap();
+            let rows: Vec<Vec<String>> = reader
+                .records()
+                .map(|record| datasource.normalize_record(record.unwrap()))
+                .filter(|record| !record.is_empty())
+                .map(|record| record.iter().map(|s| s.to_string()).collect())
+                .collect();
+            assert_eq!(
+                rows,
+                vec![
+                    vec!["Q42", "Douglas Adams", "1952-03-11T00:00:00Z", "Q42"],
+                    vec!["Q1", "Universe, the", "", "Q1"],
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn test_tmp_file() {
+        let path = temp_dir().join(format!("{}.tmp", Uuid::new_v4()));
+        fs::write(&path, "test").unwrap();
+        let mut datasource = DataSource::new(1, &json!({"columns": ["id", "name"]})).unwrap();
+        datasource.tmp_file = Some(Arc::new(TmpFile(OsString::from(&path))));
+        let clone = datasource.clone();
+        drop(datasource);
+        assert!(path.exists());
+        drop(clone);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_normalize_record() {
+        let json = json!({"columns": ["id", "name"], "column_ranges": [[0, 4], [4, null]]});
+        let datasource = DataSource::new(1, &json).unwrap();
+        let record = datasource.normalize_record(StringRecord::from(vec!["0001 John Doe"]));
+        assert_eq!(record.iter().collect::<Vec<_>>(), vec!["0001", "John Doe"]);
+        assert!(datasource
+            .normalize_record(StringRecord::from(vec!["   "]))
+            .is_empty());
+    }
+}
@@ -1,10 +1,14 @@
 use crate::app_state::{AppState, USER_AUTO};
 use crate::catalog::Catalog;
+use crate::issue::{Issue, IssuePayload};
 use crate::person::Person;
-use anyhow::{anyhow, Result};
+use crate::storage::StorageError;
+use anyhow::{anyhow, Context, Result};
+use log::info;
 use mysql_async::{Row, Value};
 use rand::prelude::*;
-use std::collections::HashMap;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fmt;
 use wikimisc::timestamp::TimeStamp;
@@ -15,7 +19,189 @@ use wikimisc::wikibase::{
 };
 
 pub const ENTRY_NEW_ID: usize = 0;
+/// Default maximum number of multi-match candidates stored per entry;
+/// can be set via `task_specific_usize.multi_match_max_candidates` in the config
+pub const DEFAULT_MULTI_MATCH_MAX_CANDIDATES: usize = 50;
 pub const WESTERN_LANGUAGES: &[&str] = &["en", "de", "fr", "es", "nl", "it", "pt"];
+/// Default lead the best candidate needs over the runner-up to be auto-matched, see `set_auto_and_multi_match`;
+/// can be set via `task_specific_usize.automatch_score_margin` in the config. Tied candidates are never auto-matched.
+pub const DEFAULT_AUTOMATCH_SCORE_MARGIN: usize = 1;
+/// `kv_entry` key for the JSON list of scored candidates, for display
+pub const KV_MATCH_CANDIDATE_SCORES: &str = "match_candidate_scores";
+/// `kv_entry` key; if "1", the entry is never matched automatically, eg for a known-ambiguous name
+pub const KV_NO_AUTOMATCH: &str = "no_automatch";
+/// `kv_catalog` key for the number of decimal places coordinates of a catalog are rounded to when stored
+pub const KV_LOCATION_PRECISION: &str = "location_precision";
+/// Decimal places used when comparing coordinates, unless the catalog sets `location_precision` (about 0.1m)
+pub const DEFAULT_COORDINATE_DECIMALS: u32 = 6;
+/// f64 does not carry more significant decimal places than this for coordinates
+const MAX_COORDINATE_DECIMALS: u32 = 12;
+
+/// The multi-match candidates of an entry, as stored, capped at a maximum number
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MultiMatchCandidates {
+    /// Comma-separated numeric item IDs
+    pub candidates: String,
+    /// The number of candidates before truncation
+    pub candidate_count: usize,
+    /// Whether candidates were dropped to stay within the maximum
+    pub truncated: bool,
+}
+
+impl MultiMatchCandidates {
+    /// Keeps the first `max_candidates` numeric item IDs, which are expected in order of preference
+    pub fn new(qs_numeric: &[String], max_candidates: usize) -> Self {
+        let candidates = qs_numeric
+            .iter()
+            .take(max_candidates)
+            .cloned()
+            .collect::<Vec<String>>()
+            .join(",");
+        Self {
+            candidates,
+            candidate_count: qs_numeric.len(),
+            truncated: qs_numeric.len() > max_candidates,
+        }
+    }
+}
+
+/// A candidate item for an entry, with its match score; higher is better
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MatchCandidate {
+    pub q: String,
+    pub score: usize,
+}
+
+/// The candidate items, aliases and descriptions for scoring the candidates of several entries,
+/// loaded with one `wbgetentities` call and one query per table instead of per entry.
+/// See `Entry::set_auto_and_multi_match_with_batch`.
+pub struct MatchCandidateBatch {
+    entities: EntityContainer,
+    aliases: HashMap<usize, Vec<LocaleString>>,
+    descriptions: HashMap<usize, HashMap<String, String>>,
+}
+
+impl MatchCandidateBatch {
+    /// Loads the data for the entries that have several candidate items
+    pub async fn load(
+        entry_id2items: &HashMap<usize, Vec<String>>,
+        app: &AppState,
+    ) -> Result<Self> {
+        let multi: Vec<(&usize, &Vec<String>)> = entry_id2items
+            .iter()
+            .filter(|(_entry_id, items)| items.len() > 1)
+            .collect();
+        let entry_ids: Vec<usize> = multi.iter().map(|(entry_id, _)| **entry_id).collect();
+        let mut qs: Vec<String> = multi
+            .iter()
+            .flat_map(|(_, items)| items.iter())
+            .filter_map(|q| AppState::item2numeric(q))
+            .map(|q| format!("Q{q}"))
+            .collect();
+        qs.sort();
+        qs.dedup();
+        let entities = EntityContainer::new();
+        if !qs.is_empty() {
+            let mw_api = app.wikidata().get_mw_api().await?;
+            entities
+                .load_entities(&mw_api, &qs)
+                .await
+                .map_err(|e| anyhow!("{e}"))?;
+        }
+        Ok(Self {
+            entities,
+            aliases: app.storage().entries_get_aliases(&entry_ids).await?,
+            descriptions: app
+                .storage()
+                .entries_get_language_descriptions(&entry_ids)
+                .await?,
+        })
+    }
+}
+
+/// The parts of a candidate item that are relevant for scoring
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MatchCandidateData {
+    pub q: String,
+    pub labels: Vec<String>,
+    pub aliases: Vec<String>,
+    pub descriptions: Vec<String>,
+    pub instance_of: Vec<String>,
+}
+
+impl MatchCandidateData {
+    pub fn from_entity(q: &str, entity: &Entity) -> Self {
+        let instance_of = entity
+            .claims_with_property("P31")
+            .iter()
+            .filter_map(|claim| match claim.main_snak().data_value() {
+                Some(datavalue) => match datavalue.value() {
+                    wikimisc::wikibase::Value::Entity(e) => Some(e.id().to_string()),
+                    _ => None,
+                },
+                None => None,
+            })
+            .collect();
+        Self {
+            q: q.to_string(),
+            labels: entity
+                .labels()
+                .iter()
+                .map(|l| l.value().to_string())
+                .collect(),
+            aliases: entity
+                .aliases()
+                .iter()
+                .map(|l| l.value().to_string())
+                .collect(),
+            descriptions: entity
+                .descriptions()
+                .iter()
+                .map(|l| l.value().to_string())
+                .collect(),
+            instance_of,
+        }
+    }
+
+    /// Scores the candidate against the (lowercase) entry names and description words:
+    /// 4 for an exact label match, 3 for an exact alias match, 1 for a partial match,
+    /// plus 2 if the item has the entry type as P31, plus 1 per shared description word (up to 2).
+    pub fn score(
+        &self,
+        entry_names: &[String],
+        type_name: Option<&str>,
+        description_words: &HashSet<String>,
+    ) -> usize {
+        let labels: Vec<String> = self.labels.iter().map(|s| s.to_lowercase()).collect();
+        let aliases: Vec<String> = self.aliases.iter().map(|s| s.to_lowercase()).collect();
+        let mut score = if labels.iter().any(|l| entry_names.contains(l)) {
+            4
+        } else if aliases.iter().any(|a| entry_names.contains(a)) {
+            3
+        } else if labels.iter().chain(aliases.iter()).any(|l| {
+            entry_names
+                .iter()
+                .any(|name| !name.is_empty() && (l.contains(name) || name.contains(l.as_str())))
+        }) {
+            1
+        } else {
+            0
+        };
+        if let Some(type_name) = type_name {
+            if self.instance_of.iter().any(|q| q == type_name) {
+                score += 2;
+            }
+        }
+        let shared_words = self
+            .descriptions
+            .iter()
+            .flat_map(|d| Entry::description_words(d))
+            .collect::<HashSet<String>>()
+            .intersection(description_words)
+            .count();
+        score + shared_words.min(2)
+    }
+}
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct CoordinateLocation {
@@ -23,6 +209,26 @@ pub struct CoordinateLocation {
     pub lon: f64,
 }
 
+impl CoordinateLocation {
+    /// Returns the location with latitude and longitude rounded to `decimals` decimal places.
+    pub fn rounded(&self, decimals: u32) -> Self {
+        let factor = 10_f64.powi(decimals.min(MAX_COORDINATE_DECIMALS) as i32);
+        Self {
+            lat: (self.lat * factor).round() / factor,
+            lon: (self.lon * factor).round() / factor,
+        }
+    }
+
+    /// Parses a `location_precision` value; returns `None` if it is not a number of decimal places.
+    pub fn parse_precision(value: &str) -> Option<u32> {
+        value
+            .trim()
+            .parse::<u32>()
+            .ok()
+            .map(|decimals| decimals.min(MAX_COORDINATE_DECIMALS))
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 pub struct AuxiliaryRow {
     pub row_id: usize,
@@ -32,6 +238,103 @@ pub struct AuxiliaryRow {
     pub entry_is_matched: bool,
 }
 
+/// A match that was removed by a user, as recorded in the `log` table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemovedMatch {
+    pub log_id: usize,
+    pub entry_id: usize,
+    pub q: Option<isize>,
+    pub user_id: Option<usize>,
+    pub timestamp: Option<String>,
+}
+
+impl RemovedMatch {
+    pub fn from_row(row: (usize, usize, Option<isize>, Option<usize>, Option<String>)) -> Self {
+        Self {
+            log_id: row.0,
+            entry_id: row.1,
+            q: row.2,
+            user_id: row.3,
+            timestamp: row.4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchEventType {
+    /// A match set by a user
+    Match,
+    /// A match removed by a user
+    RemoveQ,
+}
+
+/// A manual match or match removal, for recent-changes style feeds, see `Storage::get_recent_match_events`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MatchEvent {
+    pub event_type: MatchEventType,
+    pub entry_id: usize,
+    pub catalog_id: usize,
+    pub ext_id: String,
+    pub ext_name: String,
+    pub q: Option<isize>,
+    pub user_id: Option<usize>,
+    pub timestamp: String,
+}
+
+/// Where an entry came from, see `Storage::get_entry_provenance`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EntryProvenance {
+    pub entry_id: usize,
+    /// The `update_info` ID of the import that created the entry; `None` for entries from scrapers and other sources
+    pub import_id: Option<usize>,
+    /// Creation time of the entry
+    pub timestamp: Option<String>,
+    /// The user who set up the import
+    pub user_id: Option<usize>,
+}
+
+impl EntryProvenance {
+    pub fn from_row(entry_id: usize, row: (Option<usize>, Option<String>, Option<usize>)) -> Self {
+        Self {
+            entry_id,
+            import_id: row.0,
+            timestamp: row.1,
+            user_id: row.2,
+        }
+    }
+}
+
+impl MatchEvent {
+    #[allow(clippy::type_complexity)]
+    pub fn from_row(
+        row: (
+            String,
+            usize,
+            usize,
+            String,
+            String,
+            Option<isize>,
+            Option<usize>,
+            String,
+        ),
+    ) -> Self {
+        Self {
+            event_type: match row.0.as_str() {
+                "remove_q" => MatchEventType::RemoveQ,
+                _ => MatchEventType::Match,
+            },
+            entry_id: row.1,
+            catalog_id: row.2,
+            ext_id: row.3,
+            ext_name: row.4,
+            q: row.5,
+            user_id: row.6,
+            timestamp: row.7,
+        }
+    }
+}
+
 impl AuxiliaryRow {
     //TODO test
     pub fn from_row(row: &Row) -> Option<Self> {
@@ -132,6 +435,16 @@ impl Entry {
         Ok(ret)
     }
 
+    /// Returns an Entry object for a given entry ID, or `None` if there is no such entry.
+    /// Other errors, eg database failures, are returned as such.
+    pub async fn from_id_if_exists(entry_id: usize, app: &AppState) -> Result<Option<Self>> {
+        match Self::from_id(entry_id, app).await {
+            Ok(entry) => Ok(Some(entry)),
+            Err(e) if StorageError::is_not_found(&e) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
     pub fn new_from_catalog_and_ext_id(catalog_id: usize, ext_id: &str) -> Self {
         Self {
             id: ENTRY_NEW_ID,
@@ -168,13 +481,51 @@ impl Entry {
         Ok(ret)
     }
 
+    /// Returns the entries in active catalogs that have `value` for a Wikidata property,
+    /// either as the external ID of a catalog using that property, or as an auxiliary value.
+    pub async fn from_property_value(
+        prop_numeric: usize,
+        value: &str,
+        app: &AppState,
+    ) -> Result<Vec<Self>> {
+        let value = Self::fix_external_id(&format!("P{prop_numeric}"), value);
+        let catalog_ids = app.catalogs_for_property(prop_numeric).await?;
+        let mut entry_ids = app
+            .storage()
+            .get_entry_ids_by_ext_id(&catalog_ids, &value)
+            .await?;
+        entry_ids.append(
+            &mut app
+                .storage()
+                .get_entry_ids_by_aux(prop_numeric, &value, true)
+                .await?,
+        );
+        entry_ids.sort();
+        entry_ids.dedup();
+        let mut ret: Vec<Self> = Self::multiple_from_ids(&entry_ids, app)
+            .await?
+            .into_values()
+            .collect();
+        ret.sort_by_key(|entry| entry.id);
+        Ok(ret)
+    }
+
     /// Inserts the current entry into the database. id must be ENTRY_NEW_ID.
     //TODO test
     pub async fn insert_as_new(&mut self) -> Result<()> {
+        self.insert_as_new_from_import(None).await
+    }
+
+    /// Inserts the current entry into the database, and records the `update_info` ID of the import that created it.
+    pub async fn insert_as_new_from_import(&mut self, import_id: Option<usize>) -> Result<()> {
         if self.id != ENTRY_NEW_ID {
             return Err(EntryError::TryingToInsertExistingEntry.into());
         }
-        self.id = self.app()?.storage().entry_insert_as_new(self).await?;
+        self.id = self
+            .app()?
+            .storage()
+            .entry_insert_as_new(self, import_id)
+            .await?;
         Ok(())
     }
 
@@ -299,10 +650,28 @@ impl Entry {
         self.add_to_item_descriptions(language, item).await?;
         self.add_to_item_coordinates(&references, item).await?;
         self.add_to_item_person_dates(&references, item).await?;
+        self.add_to_item_default_statements(&catalog, &references, item)
+            .await?;
         self.add_to_item_auxiliary(references, item).await?;
         Ok(())
     }
 
+    /// Adds the non-reference default statements of the catalog
+    async fn add_to_item_default_statements(
+        &self,
+        catalog: &Catalog,
+        references: &Vec<Reference>,
+        item: &mut ItemEntity,
+    ) -> Result<()> {
+        for ds in catalog.default_statements().await? {
+            if !ds.as_reference {
+                let claim = Statement::new_normal(ds.snak(), vec![], references.to_owned());
+                self.add_claim_or_references(item, claim);
+            }
+        }
+        Ok(())
+    }
+
     async fn add_to_item_auxiliary(
         &self,
         references: Vec<Reference>,
@@ -518,19 +887,59 @@ impl Entry {
         Ok(())
     }
 
-    /// Updates type_name locally and in the database
-    //TODO test
-    pub async fn set_type_name(&mut self, type_name: Option<String>) -> Result<()> {
-        if self.type_name != type_name {
-            self.check_valid_id()?;
-            let entry_id = self.id;
-            self.type_name.clone_from(&type_name);
-            self.app()?
-                .storage()
-                .entry_set_type_name(type_name, entry_id)
+    /// Updates type_name locally and in the database.
+    /// Full matches are left untouched; see `set_type_name_with_cascade`.
+    pub async fn set_type_name(&mut self, type_name: Option<String>) -> Result<bool> {
+        self.set_type_name_with_cascade(type_name, true).await
+    }
+
+    /// Updates type_name locally and in the database.
+    /// If the entry had a type before, an automatic match was made under the old type, so it is removed.
+    /// Full matches are kept if `keep_full_matches` is set; otherwise, they get a `MISMATCH` issue for review.
+    /// Returns `true` if an automatic match was removed; the caller should then queue a catalog re-match,
+    /// once for all changed entries.
+    pub async fn set_type_name_with_cascade(
+        &mut self,
+        type_name: Option<String>,
+        keep_full_matches: bool,
+    ) -> Result<bool> {
+        if self.type_name == type_name {
+            return Ok(false);
+        }
+        self.check_valid_id()?;
+        let entry_id = self.id;
+        let old_type_name = std::mem::replace(&mut self.type_name, type_name.clone());
+        self.app()?
+            .storage()
+            .entry_set_type_name(type_name, entry_id)
+            .await?;
+        self.type_change_cascade(old_type_name, keep_full_matches)
+            .await
+    }
+
+    async fn type_change_cascade(
+        &mut self,
+        old_type_name: Option<String>,
+        keep_full_matches: bool,
+    ) -> Result<bool> {
+        if old_type_name.is_none() {
+            return Ok(false); // A type was added, not changed
+        }
+        if self.is_partially_matched() {
+            self.unmatch().await?;
+            return Ok(true);
+        } else if self.is_fully_matched() && !keep_full_matches {
+            let payload = IssuePayload::type_changed(
+                old_type_name,
+                self.type_name.to_owned(),
+                self.q.map(|q| format!("Q{q}")),
+            );
+            Issue::new(self.id, payload, self.app()?)
+                .await?
+                .insert()
                 .await?;
         }
-        Ok(())
+        Ok(false)
     }
 
     /// Update person dates in the database, where necessary
@@ -643,10 +1052,18 @@ impl Entry {
         Ok(())
     }
 
-    /// Update coordinate location in the database, where necessary
+    /// Update coordinate location in the database, where necessary.
+    /// The location is rounded first if the catalog sets `location_precision`.
     pub async fn set_coordinate_location(&self, cl: &Option<CoordinateLocation>) -> Result<()> {
+        let cl = match cl {
+            Some(cl) => match self.get_location_precision().await? {
+                Some(decimals) => Some(cl.rounded(decimals)),
+                None => Some(cl.to_owned()),
+            },
+            None => None,
+        };
         let existing_cl = self.get_coordinate_location().await?;
-        if existing_cl != *cl {
+        if existing_cl != cl {
             let entry_id = self.id;
             match cl {
                 Some(cl) => {
@@ -666,6 +1083,17 @@ impl Entry {
         Ok(())
     }
 
+    /// Returns the number of decimal places the catalog of this entry rounds coordinates to, if set
+    async fn get_location_precision(&self) -> Result<Option<u32>> {
+        let kv_pairs = Catalog::from_id(self.catalog, self.app()?)
+            .await?
+            .get_key_value_pairs()
+            .await?;
+        Ok(kv_pairs
+            .get(KV_LOCATION_PRECISION)
+            .and_then(|value| CoordinateLocation::parse_precision(value)))
+    }
+
     /// Returns the coordinate locationm or None
     pub async fn get_coordinate_location(&self) -> Result<Option<CoordinateLocation>> {
         self.check_valid_id()?;
@@ -683,25 +1111,52 @@ impl Entry {
     }
 
     /// Before q query or an update to the entry in the database, checks if this is a valid entry ID (eg not a new entry)
+    /// Returns an error if the entry is not in the database yet.
+    /// The error carries the catalog and external ID, so the offending source row can be identified.
     pub fn check_valid_id(&self) -> Result<()> {
         match self.id {
-            ENTRY_NEW_ID => Err(EntryError::TryingToUpdateNewEntry.into()),
+            ENTRY_NEW_ID => Err(EntryError::TryingToUpdateNewEntry).with_context(|| {
+                format!(
+                    "Entry without ID in catalog {}, ext_id '{}'",
+                    self.catalog, self.ext_id
+                )
+            }),
             _ => Ok(()),
         }
     }
 
     /// Sets a match for the entry, and marks the entry as matched in other tables.
     pub async fn set_match(&mut self, q: &str, user_id: usize) -> Result<bool> {
+        self.set_match_internal(q, user_id, true).await
+    }
+
+    /// Like `set_match`, but does not update the overview table.
+    /// Batch matchers use this, and call `Storage::finalize_catalog_overview` once when done.
+    pub async fn set_match_without_overview(&mut self, q: &str, user_id: usize) -> Result<bool> {
+        self.set_match_internal(q, user_id, false).await
+    }
+
+    async fn set_match_internal(
+        &mut self,
+        q: &str,
+        user_id: usize,
+        update_overview: bool,
+    ) -> Result<bool> {
         self.check_valid_id()?;
         let q_numeric = AppState::item2numeric(q).ok_or(anyhow!("'{}' is not a valid item", &q))?;
 
         let timestamp = TimeStamp::now();
-        if self
-            .app()?
-            .storage()
-            .entry_set_match(self, user_id, q_numeric, &timestamp)
-            .await?
-        {
+        let storage = self.app()?.storage();
+        let changed = if update_overview {
+            storage
+                .entry_set_match(self, user_id, q_numeric, &timestamp)
+                .await?
+        } else {
+            storage
+                .entry_set_match_without_overview(self, user_id, q_numeric, &timestamp)
+                .await?
+        };
+        if changed {
             self.user = Some(user_id);
             self.timestamp = Some(timestamp);
             self.q = Some(q_numeric);
@@ -751,8 +1206,21 @@ impl Entry {
         }
     }
 
-    /// Sets auto-match and multi-match for an entry
+    /// Sets auto-match and multi-match for an entry.
+    /// With several candidates, these are scored (see `score_match_candidates`), and the best one is auto-matched,
+    /// unless it does not lead the runner-up by `automatch_score_margin`; then, only the multi-match is set.
+    /// If the candidates can not be scored, the lowest item ID is auto-matched.
     pub async fn set_auto_and_multi_match(&mut self, items: &[String]) -> Result<()> {
+        self.set_auto_and_multi_match_with_batch(items, None).await
+    }
+
+    /// As `set_auto_and_multi_match`, but scores the candidates with the data in `batch`, if given,
+    /// instead of loading it for this entry alone.
+    pub async fn set_auto_and_multi_match_with_batch(
+        &mut self,
+        items: &[String],
+        batch: Option<&MatchCandidateBatch>,
+    ) -> Result<()> {
         let mut qs_numeric: Vec<isize> = items
             .iter()
             .filter_map(|q| AppState::item2numeric(q))
@@ -762,38 +1230,226 @@ impl Entry {
         }
         qs_numeric.sort();
         qs_numeric.dedup();
-        if self.q == Some(qs_numeric[0]) {
-            return Ok(()); // Automatch exists, skipping multimatch
+        if qs_numeric.len() == 1 {
+            if self.q != Some(qs_numeric[0]) {
+                self.set_match(&format!("Q{}", qs_numeric[0]), USER_AUTO)
+                    .await?;
+            }
+            return Ok(());
         }
-        self.set_match(&format!("Q{}", qs_numeric[0]), USER_AUTO)
-            .await?;
-        if qs_numeric.len() > 1 {
-            self.set_multi_match(items).await?;
+        let (best, scored) = self.best_auto_match(&qs_numeric, batch, None).await?;
+        if let Some(q) = best {
+            if self.q == AppState::item2numeric(&q) {
+                return Ok(()); // Automatch exists, skipping multimatch
+            }
+            self.set_match(&q, USER_AUTO).await?;
         }
-        Ok(())
+        self.set_multi_match_scored(items, &scored).await
+    }
+
+    /// Returns the candidate to auto-match from several unique numeric item IDs, see `set_auto_and_multi_match`.
+    /// With `hits` (item => number of names that found it), each candidate's hits are added to its score.
+    /// Also returns the scored candidates, by descending score (empty if they could not be scored).
+    /// The candidate scores are stored for display.
+    pub async fn best_auto_match(
+        &self,
+        qs_numeric: &[isize],
+        batch: Option<&MatchCandidateBatch>,
+        hits: Option<&HashMap<String, usize>>,
+    ) -> Result<(Option<String>, Vec<MatchCandidate>)> {
+        let qs: Vec<String> = qs_numeric.iter().map(|q| format!("Q{q}")).collect();
+        let scored = match batch {
+            Some(batch) => Ok(self.score_loaded_match_candidates(
+                &qs,
+                &batch.entities,
+                batch
+                    .aliases
+                    .get(&self.id)
+                    .map(|v| v.as_slice())
+                    .unwrap_or_default(),
+                batch.descriptions.get(&self.id),
+            )),
+            None => self.score_match_candidates(&qs).await,
+        };
+        match scored {
+            Ok(mut candidates) => {
+                if let Some(hits) = hits {
+                    Self::add_hits_to_scores(&mut candidates, hits);
+                }
+                self.set_match_candidate_scores(&candidates).await?;
+                let best = Self::best_match_candidate(&candidates, self.automatch_score_margin()?);
+                Ok((best, candidates))
+            }
+            Err(_) => Ok((qs.first().cloned(), vec![])),
+        }
+    }
+
+    fn automatch_score_margin(&self) -> Result<usize> {
+        Ok(*self
+            .app()?
+            .task_specific_usize()
+            .get("automatch_score_margin")
+            .unwrap_or(&DEFAULT_AUTOMATCH_SCORE_MARGIN))
+    }
+
+    /// Adds the number of hits to the score of each candidate, and sorts them by descending score again
+    fn add_hits_to_scores(candidates: &mut [MatchCandidate], hits: &HashMap<String, usize>) {
+        for candidate in candidates.iter_mut() {
+            candidate.score += hits.get(&candidate.q).copied().unwrap_or(0);
+        }
+        candidates.sort_by(|a, b| b.score.cmp(&a.score));
+    }
+
+    /// Returns the best candidate, if it leads the runner-up by at least `margin`.
+    /// Candidates are expected to be sorted by descending score.
+    fn best_match_candidate(candidates: &[MatchCandidate], margin: usize) -> Option<String> {
+        let best = candidates.first()?;
+        match candidates.get(1) {
+            Some(runner_up) if best.score < runner_up.score + margin => None,
+            _ => Some(best.q.to_owned()),
+        }
+    }
+
+    /// Scores candidate items for this entry, by name/alias match, type, and description overlap.
+    /// Returns the candidates sorted by descending score; ties keep the given order.
+    pub async fn score_match_candidates(&self, qs: &[String]) -> Result<Vec<MatchCandidate>> {
+        let app = self.app()?;
+        let mw_api = app.wikidata().get_mw_api().await?;
+        let entities = EntityContainer::new();
+        entities
+            .load_entities(&mw_api, &qs.to_vec())
+            .await
+            .map_err(|e| anyhow!("{e}"))?;
+        let aliases = self.get_aliases().await?;
+        let descriptions = self.get_language_descriptions().await?;
+        Ok(self.score_loaded_match_candidates(qs, &entities, &aliases, Some(&descriptions)))
+    }
+
+    /// Scores candidate items with already loaded entities and entry aliases/descriptions, see `score_match_candidates`
+    fn score_loaded_match_candidates(
+        &self,
+        qs: &[String],
+        entities: &EntityContainer,
+        aliases: &[LocaleString],
+        descriptions: Option<&HashMap<String, String>>,
+    ) -> Vec<MatchCandidate> {
+        let mut entry_names = vec![self.ext_name.to_lowercase()];
+        entry_names.extend(aliases.iter().map(|alias| alias.value().to_lowercase()));
+        let mut description_words = Self::description_words(&self.ext_desc);
+        for desc in descriptions.into_iter().flat_map(|d| d.values()) {
+            description_words.extend(Self::description_words(desc));
+        }
+
+        let mut candidates: Vec<MatchCandidate> = qs
+            .iter()
+            .map(|q| {
+                let score = match entities.get_entity(q.to_owned()) {
+                    Some(entity) => MatchCandidateData::from_entity(q, &entity).score(
+                        &entry_names,
+                        self.type_name.as_deref(),
+                        &description_words,
+                    ),
+                    None => 0,
+                };
+                MatchCandidate {
+                    q: q.to_owned(),
+                    score,
+                }
+            })
+            .collect();
+        candidates.sort_by(|a, b| b.score.cmp(&a.score));
+        candidates
+    }
+
+    /// Flags the entry so that `USER_AUTO` never matches it, or removes that flag.
+    /// Existing matches are not changed.
+    pub async fn set_no_automatch(&self, no_automatch: bool) -> Result<()> {
+        self.check_valid_id()?;
+        self.app()?
+            .storage()
+            .entry_set_no_automatch(self.id, no_automatch)
+            .await
+    }
+
+    /// Returns `true` if the entry must not be matched automatically, see `set_no_automatch`
+    pub async fn is_no_automatch(&self) -> Result<bool> {
+        self.check_valid_id()?;
+        self.app()?.storage().entry_get_no_automatch(self.id).await
+    }
+
+    /// Stores the candidate scores for the entry, so they can be displayed alongside the multi-match.
+    async fn set_match_candidate_scores(&self, candidates: &[MatchCandidate]) -> Result<()> {
+        let json = serde_json::to_string(candidates)?;
+        self.app()?
+            .storage()
+            .entry_set_kv(self.id, KV_MATCH_CANDIDATE_SCORES, &json)
+            .await
+    }
+
+    /// Lowercase words of a description, ignoring short words like "of" or "the"
+    fn description_words(s: &str) -> HashSet<String> {
+        s.split(|c: char| !c.is_alphanumeric())
+            .filter(|w| w.chars().count() > 3)
+            .map(|w| w.to_lowercase())
+            .collect()
     }
 
-    /// Sets multi-matches for an entry
+    /// Sets multi-matches for an entry.
+    /// Only the first `multi_match_max_candidates` items are stored; `candidate_count` is always the full count.
     pub async fn set_multi_match(&self, items: &[String]) -> Result<()> {
+        self.set_multi_match_scored(items, &[]).await
+    }
+
+    /// As `set_multi_match`, but with more than `multi_match_max_candidates` items,
+    /// the best `scored` candidates (see `best_auto_match`) are kept.
+    pub async fn set_multi_match_scored(
+        &self,
+        items: &[String],
+        scored: &[MatchCandidate],
+    ) -> Result<()> {
         let entry_id = self.id;
         let app = self.app()?;
-        let qs_numeric: Vec<String> = items
+        let qs_numeric: Vec<String> = Self::order_by_score(items, scored)
             .iter()
             .filter_map(|q| AppState::item2numeric(q))
             .map(|q| q.to_string())
             .collect();
-        if qs_numeric.is_empty() || qs_numeric.len() > 10 {
+        if qs_numeric.is_empty() {
             return self.remove_multi_match().await;
         }
-        let candidates = qs_numeric.join(",");
-        let candidates_count = qs_numeric.len();
+        let max_candidates = *app
+            .task_specific_usize()
+            .get("multi_match_max_candidates")
+            .unwrap_or(&DEFAULT_MULTI_MATCH_MAX_CANDIDATES);
+        let mm = MultiMatchCandidates::new(&qs_numeric, max_candidates);
+        if mm.truncated {
+            info!(
+                "Entry {entry_id}: storing {max_candidates} of {} multi-match candidates",
+                mm.candidate_count
+            );
+        }
 
         app.storage()
-            .entry_set_multi_match(entry_id, candidates, candidates_count)
+            .entry_set_multi_match(entry_id, mm.candidates, mm.candidate_count)
             .await?;
         Ok(())
     }
 
+    /// Returns the distinct items, the `scored` ones first (by descending score), then the others in their original order
+    fn order_by_score(items: &[String], scored: &[MatchCandidate]) -> Vec<String> {
+        let mut ret: Vec<String> = scored
+            .iter()
+            .filter(|candidate| items.contains(&candidate.q))
+            .map(|candidate| candidate.q.to_owned())
+            .collect();
+        for item in items {
+            if !ret.contains(item) {
+                ret.push(item.to_owned());
+            }
+        }
+        ret
+    }
+
     /// Removes multi-matches for an entry, eg when the entry has been fully matched.
     pub async fn remove_multi_match(&self) -> Result<()> {
         self.app()?
@@ -867,6 +1523,32 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_coordinate_location_rounded() {
+        let cl = CoordinateLocation {
+            lat: 51.123456789012,
+            lon: -0.987654321098,
+        };
+        assert_eq!(
+            cl.rounded(4),
+            CoordinateLocation {
+                lat: 51.1235,
+                lon: -0.9877
+            }
+        );
+        assert_eq!(
+            cl.rounded(0),
+            CoordinateLocation {
+                lat: 51.0,
+                lon: -1.0
+            }
+        );
+        assert_eq!(CoordinateLocation::parse_precision(" 5"), Some(5));
+        assert_eq!(CoordinateLocation::parse_precision("99"), Some(12));
+        assert_eq!(CoordinateLocation::parse_precision("-1"), None);
+        assert_eq!(CoordinateLocation::parse_precision("abc"), None);
+    }
+
     #[tokio::test]
     async fn test_coordinate_location() {
         let _test_lock = TEST_MUTEX.lock();
@@ -948,6 +1630,167 @@ mod tests {
         assert!(entry.timestamp.is_none());
     }
 
+    #[tokio::test]
+    async fn test_set_type_name_cascade() {
+        let _test_lock = TEST_MUTEX.lock();
+        let app = get_test_app();
+        let mut entry = Entry::from_id(TEST_ENTRY_ID, &app).await.unwrap();
+        let original_type = entry.type_name.clone();
+
+        // Full matches are kept
+        entry.set_match("Q1", 4).await.unwrap();
+        entry.set_type_name(Some("Q5".to_string())).await.unwrap();
+        entry
+            .set_type_name(Some("Q16521".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(entry.q, Some(1));
+
+        // Automatic matches are removed
+        entry.unmatch().await.unwrap();
+        entry.set_match("Q1", USER_AUTO).await.unwrap();
+        assert!(entry.is_partially_matched());
+        assert!(entry.set_type_name(Some("Q5".to_string())).await.unwrap());
+        assert!(entry.is_unmatched());
+        let entry = Entry::from_id(TEST_ENTRY_ID, &app).await.unwrap();
+        assert!(entry.is_unmatched());
+        assert_eq!(entry.type_name, Some("Q5".to_string()));
+
+        // Cleanup
+        let mut entry = entry;
+        entry.set_type_name(original_type).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_entry_set_match_batch() {
+        let _test_lock = TEST_MUTEX.lock();
+        let app = get_test_app();
+        let mut entry = Entry::from_id(TEST_ENTRY_ID, &app).await.unwrap();
+        entry.unmatch().await.unwrap();
+
+        let matches = vec![(entry.clone(), 1)];
+        let changed = app
+            .storage()
+            .entry_set_match_batch(&matches, 4)
+            .await
+            .unwrap();
+        assert_eq!(changed, 1);
+        let mut entry = Entry::from_id(TEST_ENTRY_ID, &app).await.unwrap();
+        assert_eq!(entry.q, Some(1));
+        assert_eq!(entry.user, Some(4));
+
+        // Unchanged matches, and automatches on fully matched entries, are skipped
+        let matches = vec![(entry.clone(), 1)];
+        assert_eq!(
+            app.storage()
+                .entry_set_match_batch(&matches, 4)
+                .await
+                .unwrap(),
+            0
+        );
+        let matches = vec![(entry.clone(), 2)];
+        assert_eq!(
+            app.storage()
+                .entry_set_match_batch(&matches, USER_AUTO)
+                .await
+                .unwrap(),
+            0
+        );
+
+        // Cleanup
+        entry.unmatch().await.unwrap();
+    }
+
+    #[test]
+    fn test_match_candidate_score() {
+        let candidate = MatchCandidateData {
+            q: "Q1".to_string(),
+            labels: vec!["John Doe".to_string()],
+            aliases: vec!["J. Doe".to_string()],
+            descriptions: vec!["British painter and sculptor".to_string()],
+            instance_of: vec!["Q5".to_string()],
+        };
+        let no_words = HashSet::new();
+        let names = vec!["john doe".to_string()];
+        assert_eq!(candidate.score(&names, None, &no_words), 4);
+        assert_eq!(candidate.score(&names, Some("Q5"), &no_words), 6);
+        assert_eq!(candidate.score(&names, Some("Q16521"), &no_words), 4);
+        let names = vec!["j. doe".to_string()];
+        assert_eq!(candidate.score(&names, None, &no_words), 3);
+        let names = vec!["doe".to_string()];
+        assert_eq!(candidate.score(&names, None, &no_words), 1);
+        let names = vec!["jane roe".to_string()];
+        assert_eq!(candidate.score(&names, None, &no_words), 0);
+        let words = Entry::description_words("Painter, born in London; sculptor");
+        assert_eq!(candidate.score(&names, None, &words), 2);
+    }
+
+    #[test]
+    fn test_best_match_candidate() {
+        let candidates = vec![
+            MatchCandidate {
+                q: "Q1".to_string(),
+                score: 6,
+            },
+            MatchCandidate {
+                q: "Q2".to_string(),
+                score: 4,
+            },
+        ];
+        assert_eq!(
+            Entry::best_match_candidate(&candidates, 0),
+            Some("Q1".to_string())
+        );
+        assert_eq!(
+            Entry::best_match_candidate(&candidates, 2),
+            Some("Q1".to_string())
+        );
+        assert_eq!(Entry::best_match_candidate(&candidates, 3), None);
+        assert_eq!(
+            Entry::best_match_candidate(&candidates[1..], 3),
+            Some("Q2".to_string())
+        );
+        assert_eq!(Entry::best_match_candidate(&[], 0), None);
+        let tied = vec![
+            MatchCandidate {
+                q: "Q1".to_string(),
+                score: 4,
+            },
+            MatchCandidate {
+                q: "Q2".to_string(),
+                score: 4,
+            },
+        ];
+        assert_eq!(
+            Entry::best_match_candidate(&tied, DEFAULT_AUTOMATCH_SCORE_MARGIN),
+            None
+        );
+    }
+
+    #[test]
+    fn test_add_hits_to_scores() {
+        let mut candidates = vec![
+            MatchCandidate {
+                q: "Q1".to_string(),
+                score: 4,
+            },
+            MatchCandidate {
+                q: "Q2".to_string(),
+                score: 4,
+            },
+        ];
+        let hits: HashMap<String, usize> = [("Q1".to_string(), 1), ("Q2".to_string(), 3)]
+            .into_iter()
+            .collect();
+        Entry::add_hits_to_scores(&mut candidates, &hits);
+        assert_eq!(candidates[0].q, "Q2");
+        assert_eq!(candidates[0].score, 7);
+        assert_eq!(
+            Entry::best_match_candidate(&candidates, DEFAULT_AUTOMATCH_SCORE_MARGIN),
+            Some("Q2".to_string())
+        );
+    }
+
     #[tokio::test]
     async fn test_utf8() {
         let app = get_test_app();
@@ -1028,6 +1871,74 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_from_property_value() {
+        let _test_lock = TEST_MUTEX.lock();
+        let app = get_test_app();
+        let entry = Entry::from_id(TEST_ENTRY_ID, &app).await.unwrap();
+        entry
+            .set_auxiliary(214, Some("12345".to_string()))
+            .await
+            .unwrap();
+        let entries = Entry::from_property_value(214, "12345", &app)
+            .await
+            .unwrap();
+        assert!(entries.iter().any(|e| e.id == TEST_ENTRY_ID));
+        entry.set_auxiliary(214, None).await.unwrap();
+    }
+
+    #[test]
+    fn test_order_by_score() {
+        let items: Vec<String> = ["Q1", "Q2", "Q3", "Q2"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(Entry::order_by_score(&items, &[]), ["Q1", "Q2", "Q3"]);
+        let scored = vec![
+            MatchCandidate {
+                q: "Q3".to_string(),
+                score: 5,
+            },
+            MatchCandidate {
+                q: "Q4".to_string(),
+                score: 4,
+            },
+            MatchCandidate {
+                q: "Q2".to_string(),
+                score: 1,
+            },
+        ];
+        assert_eq!(Entry::order_by_score(&items, &scored), ["Q3", "Q2", "Q1"]);
+    }
+
+    #[test]
+    fn test_multi_match_candidates() {
+        let qs: Vec<String> = (1..=5).map(|q| q.to_string()).collect();
+        let mm = MultiMatchCandidates::new(&qs, 10);
+        assert_eq!(mm.candidates, "1,2,3,4,5");
+        assert_eq!(mm.candidate_count, 5);
+        assert!(!mm.truncated);
+        let mm = MultiMatchCandidates::new(&qs, 5);
+        assert!(!mm.truncated);
+        let mm = MultiMatchCandidates::new(&qs, 3);
+        assert_eq!(mm.candidates, "1,2,3");
+        assert_eq!(mm.candidate_count, 5);
+        assert!(mm.truncated);
+    }
+
+    #[test]
+    fn test_check_valid_id_error_context() {
+        let entry = Entry::new_from_catalog_and_ext_id(1234, "foo123");
+        let error = entry.check_valid_id().unwrap_err();
+        let message = format!("{error}");
+        assert!(message.contains("1234"));
+        assert!(message.contains("foo123"));
+        assert!(matches!(
+            error.downcast_ref::<EntryError>(),
+            Some(EntryError::TryingToUpdateNewEntry)
+        ));
+    }
+
     #[tokio::test]
     async fn test_is_unmatched() {
         let _test_lock = TEST_MUTEX.lock();
@@ -1071,6 +1982,21 @@ mod tests {
         assert!(entry.check_valid_id().is_err());
     }
 
+    #[tokio::test]
+    async fn test_no_automatch() {
+        let _test_lock = TEST_MUTEX.lock();
+        let app = get_test_app();
+        let mut entry = Entry::from_id(TEST_ENTRY_ID, &app).await.unwrap();
+        entry.unmatch().await.unwrap();
+        entry.set_no_automatch(true).await.unwrap();
+        assert!(entry.is_no_automatch().await.unwrap());
+        assert!(!entry.set_match("Q12345", USER_AUTO).await.unwrap());
+        let entry = Entry::from_id(TEST_ENTRY_ID, &app).await.unwrap();
+        assert!(entry.is_unmatched());
+        entry.set_no_automatch(false).await.unwrap();
+        assert!(!entry.is_no_automatch().await.unwrap());
+    }
+
     #[tokio::test]
     async fn test_add_alias() {
         let _test_lock = TEST_MUTEX.lock();
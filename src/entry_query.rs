@@ -0,0 +1,122 @@
+use crate::match_state::MatchState;
+
+/// Sort order for `EntryQuery` results
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryQueryOrder {
+    Id,
+    ExtName,
+    /// Uses the indexed `random` column, not `RAND()`
+    Random,
+    Timestamp,
+}
+
+impl EntryQueryOrder {
+    /// Columns are qualified, so `random` is the indexed column rather than the `entry_sql_select` alias.
+    /// Orders that are not unique fall back to the ID, so paging with `offset` is stable.
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            EntryQueryOrder::Id => "`entry`.`id`",
+            EntryQueryOrder::ExtName => "`entry`.`ext_name`,`entry`.`id`",
+            EntryQueryOrder::Random => "`entry`.`random`",
+            EntryQueryOrder::Timestamp => "`entry`.`timestamp`,`entry`.`id`",
+        }
+    }
+}
+
+/// Describes a set of entries, for use with `Storage::get_entry_query` and the automatchers.
+/// All filters are optional; `limit` and `offset` page through the result.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EntryQuery {
+    pub catalog_id: Option<usize>,
+    pub match_state: Option<MatchState>,
+    pub type_name: Option<String>,
+    pub name_regexp: Option<String>,
+    /// Entries whose name does not match this regular expression
+    pub name_not_regexp: Option<String>,
+    /// Entries with (`true`) or without (`false`) data beyond the name: person dates, auxiliary values, a location, or a description
+    pub has_signal: Option<bool>,
+    /// Only entries with an ID greater than this, eg those added since the last run of a matcher
+    pub min_id_exclusive: Option<usize>,
+    pub order_by: Option<EntryQueryOrder>,
+    pub limit: Option<usize>,
+    pub offset: usize,
+}
+
+impl EntryQuery {
+    pub fn with_catalog_id(mut self, catalog_id: usize) -> Self {
+        self.catalog_id = Some(catalog_id);
+        self
+    }
+
+    pub fn with_match_state(mut self, match_state: MatchState) -> Self {
+        self.match_state = Some(match_state);
+        self
+    }
+
+    pub fn with_type(mut self, type_name: &str) -> Self {
+        self.type_name = Some(type_name.to_string());
+        self
+    }
+
+    pub fn with_name_regexp(mut self, name_regexp: &str) -> Self {
+        self.name_regexp = Some(name_regexp.to_string());
+        self
+    }
+
+    pub fn with_name_not_regexp(mut self, name_not_regexp: &str) -> Self {
+        self.name_not_regexp = Some(name_not_regexp.to_string());
+        self
+    }
+
+    pub fn with_has_signal(mut self, has_signal: Option<bool>) -> Self {
+        self.has_signal = has_signal;
+        self
+    }
+
+    pub fn with_min_id_exclusive(mut self, min_id_exclusive: Option<usize>) -> Self {
+        self.min_id_exclusive = min_id_exclusive;
+        self
+    }
+
+    pub fn with_order_by(mut self, order_by: EntryQueryOrder) -> Self {
+        self.order_by = Some(order_by);
+        self
+    }
+
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn with_offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_query_builder() {
+        let query = EntryQuery::default()
+            .with_catalog_id(123)
+            .with_match_state(MatchState::unmatched())
+            .with_type("Q5")
+            .with_name_not_regexp("^\\S+$")
+            .with_has_signal(Some(true))
+            .with_order_by(EntryQueryOrder::Random)
+            .with_limit(10)
+            .with_offset(20);
+        assert_eq!(query.catalog_id, Some(123));
+        assert_eq!(query.match_state, Some(MatchState::unmatched()));
+        assert_eq!(query.type_name, Some("Q5".to_string()));
+        assert_eq!(query.name_regexp, None);
+        assert_eq!(query.name_not_regexp, Some("^\\S+$".to_string()));
+        assert_eq!(query.has_signal, Some(true));
+        assert_eq!(query.order_by, Some(EntryQueryOrder::Random));
+        assert_eq!(query.limit, Some(10));
+        assert_eq!(query.offset, 20);
+    }
+}
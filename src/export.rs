@@ -0,0 +1,441 @@
+use crate::app_state::AppState;
+use crate::entry::{AuxiliaryRow, CoordinateLocation, Entry};
+use crate::entry_query::{EntryQuery, EntryQueryOrder};
+use crate::match_state::MatchState;
+use anyhow::{anyhow, Result};
+use futures::stream::{self, BoxStream, StreamExt};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::io::Write;
+use std::ops::BitOr;
+use std::str::FromStr;
+
+/// Entries loaded from the database per batch
+const EXPORT_BATCH_SIZE: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Tab-separated, with a header row
+    Tab,
+    /// One JSON object per line
+    Jsonl,
+}
+
+impl FromStr for ExportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "tab" | "tsv" => Ok(Self::Tab),
+            "jsonl" | "json" => Ok(Self::Jsonl),
+            other => Err(anyhow!("Unknown export format '{other}'")),
+        }
+    }
+}
+
+/// The columns to export, combined with `|`, eg `ExportColumns::EXT_ID | ExportColumns::Q`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExportColumns(u32);
+
+impl ExportColumns {
+    pub const ENTRY_ID: Self = Self(1 << 0);
+    pub const EXT_ID: Self = Self(1 << 1);
+    pub const EXT_NAME: Self = Self(1 << 2);
+    pub const EXT_DESC: Self = Self(1 << 3);
+    pub const EXT_URL: Self = Self(1 << 4);
+    pub const TYPE: Self = Self(1 << 5);
+    pub const Q: Self = Self(1 << 6);
+    pub const USER: Self = Self(1 << 7);
+    pub const TIMESTAMP: Self = Self(1 << 8);
+    /// Born and died
+    pub const PERSON_DATES: Self = Self(1 << 9);
+    pub const AUX: Self = Self(1 << 10);
+    /// Latitude and longitude
+    pub const LOCATION: Self = Self(1 << 11);
+    pub const ALL: Self = Self((1 << 12) - 1);
+    /// The columns of a worklist, see `export_worklist`
+    pub const WORKLIST: Self =
+        Self(Self::EXT_ID.0 | Self::EXT_NAME.0 | Self::EXT_DESC.0 | Self::EXT_URL.0);
+
+    const NAMES: &'static [(&'static str, Self)] = &[
+        ("entry_id", Self::ENTRY_ID),
+        ("ext_id", Self::EXT_ID),
+        ("ext_name", Self::EXT_NAME),
+        ("ext_desc", Self::EXT_DESC),
+        ("ext_url", Self::EXT_URL),
+        ("type", Self::TYPE),
+        ("q", Self::Q),
+        ("user", Self::USER),
+        ("timestamp", Self::TIMESTAMP),
+        ("person_dates", Self::PERSON_DATES),
+        ("aux", Self::AUX),
+        ("location", Self::LOCATION),
+    ];
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    pub const fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Parses a comma-separated list of column names, eg `ext_id,ext_name,q`
+    pub fn from_names(names: &str) -> Result<Self> {
+        names
+            .split(',')
+            .map(|name| name.trim())
+            .filter(|name| !name.is_empty())
+            .try_fold(Self::empty(), |columns, name| {
+                Self::NAMES
+                    .iter()
+                    .find(|(n, _)| *n == name)
+                    .map(|(_, column)| columns | *column)
+                    .ok_or_else(|| anyhow!("Unknown export column '{name}'"))
+            })
+    }
+
+    /// Returns the field names for these columns, in export order
+    pub fn field_names(&self) -> Vec<&'static str> {
+        let mut ret = vec![];
+        for (name, column) in Self::NAMES {
+            if !self.contains(*column) {
+                continue;
+            }
+            match *column {
+                Self::PERSON_DATES => ret.extend(["born", "died"]),
+                Self::LOCATION => ret.extend(["lat", "lon"]),
+                _ => ret.push(name),
+            }
+        }
+        ret
+    }
+}
+
+impl Default for ExportColumns {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+impl BitOr for ExportColumns {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// Returns all entries of a catalog as rows in the given format, with extended data (person dates, auxiliary values, location)
+/// loaded as the columns require. The `Tab` format starts with a header row. Rows have no trailing newline.
+pub fn export_catalog(
+    app: &AppState,
+    catalog_id: usize,
+    format: ExportFormat,
+    columns: ExportColumns,
+) -> BoxStream<'static, Result<String>> {
+    let query = EntryQuery::default().with_catalog_id(catalog_id);
+    export_entries(app, query, format, columns)
+}
+
+/// Returns the unmatched entries of a catalog as tab-separated rows, to hand out to volunteers.
+/// Person dates and auxiliary values are added as columns on request.
+pub fn export_worklist(
+    app: &AppState,
+    catalog_id: usize,
+    with_dates: bool,
+    with_aux: bool,
+) -> BoxStream<'static, Result<String>> {
+    let mut columns = ExportColumns::WORKLIST;
+    if with_dates {
+        columns = columns | ExportColumns::PERSON_DATES;
+    }
+    if with_aux {
+        columns = columns | ExportColumns::AUX;
+    }
+    let query = EntryQuery::default()
+        .with_catalog_id(catalog_id)
+        .with_match_state(MatchState::unmatched());
+    export_entries(app, query, ExportFormat::Tab, columns)
+}
+
+/// Returns the entries of a query as rows, in batches ordered by ID; `limit`, `offset` and `min_id_exclusive` of the query are ignored.
+fn export_entries(
+    app: &AppState,
+    base_query: EntryQuery,
+    format: ExportFormat,
+    columns: ExportColumns,
+) -> BoxStream<'static, Result<String>> {
+    let header = match format {
+        ExportFormat::Tab => Some(Ok(columns.field_names().join("\t"))),
+        ExportFormat::Jsonl => None,
+    };
+    let app = app.clone();
+    let rows = stream::unfold(Some(0), move |after_id| {
+        let app = app.clone();
+        let query = base_query.clone();
+        async move {
+            let after_id = after_id?;
+            let query = query
+                .with_min_id_exclusive(Some(after_id))
+                .with_order_by(EntryQueryOrder::Id)
+                .with_limit(EXPORT_BATCH_SIZE)
+                .with_offset(0);
+            match export_batch(&app, &query, format, columns).await {
+                Ok((rows, last_id)) => {
+                    let next = if rows.len() < EXPORT_BATCH_SIZE {
+                        None
+                    } else {
+                        last_id
+                    };
+                    Some((rows.into_iter().map(Ok).collect::<Vec<_>>(), next))
+                }
+                Err(e) => Some((vec![Err(e)], None)),
+            }
+        }
+    })
+    .flat_map(stream::iter);
+    stream::iter(header).chain(rows).boxed()
+}
+
+/// Writes a catalog export, one row per line.
+pub async fn write_catalog_export(
+    app: &AppState,
+    catalog_id: usize,
+    format: ExportFormat,
+    columns: ExportColumns,
+    out: &mut impl Write,
+) -> Result<()> {
+    write_rows(export_catalog(app, catalog_id, format, columns), out).await
+}
+
+/// Writes the worklist of a catalog, one row per line.
+pub async fn write_worklist(
+    app: &AppState,
+    catalog_id: usize,
+    with_dates: bool,
+    with_aux: bool,
+    out: &mut impl Write,
+) -> Result<()> {
+    write_rows(export_worklist(app, catalog_id, with_dates, with_aux), out).await
+}
+
+async fn write_rows(
+    mut rows: BoxStream<'static, Result<String>>,
+    out: &mut impl Write,
+) -> Result<()> {
+    while let Some(row) = rows.next().await {
+        writeln!(out, "{}", row?)?;
+    }
+    Ok(())
+}
+
+/// Extended data of a batch of entries, loaded with one query per table, as the columns require
+#[derive(Debug, Default)]
+struct ExportBatchData {
+    person_dates: HashMap<usize, (Option<String>, Option<String>)>,
+    aux: HashMap<usize, Vec<AuxiliaryRow>>,
+    locations: HashMap<usize, CoordinateLocation>,
+}
+
+impl ExportBatchData {
+    async fn load(app: &AppState, entry_ids: &[usize], columns: ExportColumns) -> Result<Self> {
+        let storage = app.storage();
+        let mut ret = Self::default();
+        if columns.contains(ExportColumns::PERSON_DATES) {
+            ret.person_dates = storage.entries_get_person_dates(entry_ids).await?;
+        }
+        if columns.contains(ExportColumns::AUX) {
+            ret.aux = storage.entries_get_aux(entry_ids).await?;
+        }
+        if columns.contains(ExportColumns::LOCATION) {
+            ret.locations = storage.entries_get_coordinate_locations(entry_ids).await?;
+        }
+        Ok(ret)
+    }
+}
+
+/// Returns the rows of a batch, and the ID of the last entry in it
+async fn export_batch(
+    app: &AppState,
+    query: &EntryQuery,
+    format: ExportFormat,
+    columns: ExportColumns,
+) -> Result<(Vec<String>, Option<usize>)> {
+    let entries = app.storage().get_entry_query(query).await?;
+    let entry_ids: Vec<usize> = entries.iter().map(|entry| entry.id).collect();
+    let data = ExportBatchData::load(app, &entry_ids, columns).await?;
+    let rows = entries
+        .iter()
+        .map(|entry| format_row(export_fields(entry, &data, columns), format))
+        .collect();
+    Ok((rows, entry_ids.last().copied()))
+}
+
+/// Returns (field name, value) for an entry, in export order
+fn export_fields(
+    entry: &Entry,
+    data: &ExportBatchData,
+    columns: ExportColumns,
+) -> Vec<(&'static str, Value)> {
+    let mut ret = vec![];
+    if columns.contains(ExportColumns::ENTRY_ID) {
+        ret.push(("entry_id", json!(entry.id)));
+    }
+    if columns.contains(ExportColumns::EXT_ID) {
+        ret.push(("ext_id", json!(entry.ext_id)));
+    }
+    if columns.contains(ExportColumns::EXT_NAME) {
+        ret.push(("ext_name", json!(entry.ext_name)));
+    }
+    if columns.contains(ExportColumns::EXT_DESC) {
+        ret.push(("ext_desc", json!(entry.ext_desc)));
+    }
+    if columns.contains(ExportColumns::EXT_URL) {
+        ret.push(("ext_url", json!(entry.ext_url)));
+    }
+    if columns.contains(ExportColumns::TYPE) {
+        ret.push(("type", json!(entry.type_name)));
+    }
+    if columns.contains(ExportColumns::Q) {
+        ret.push(("q", json!(entry.q)));
+    }
+    if columns.contains(ExportColumns::USER) {
+        ret.push(("user", json!(entry.user)));
+    }
+    if columns.contains(ExportColumns::TIMESTAMP) {
+        ret.push(("timestamp", json!(entry.timestamp)));
+    }
+    if columns.contains(ExportColumns::PERSON_DATES) {
+        let (born, died) = data
+            .person_dates
+            .get(&entry.id)
+            .cloned()
+            .unwrap_or_default();
+        ret.push(("born", json!(born)));
+        ret.push(("died", json!(died)));
+    }
+    if columns.contains(ExportColumns::AUX) {
+        let aux: Vec<Value> = data
+            .aux
+            .get(&entry.id)
+            .into_iter()
+            .flatten()
+            .map(|row| json!({"property": format!("P{}", row.prop_numeric), "value": row.value}))
+            .collect();
+        ret.push(("aux", json!(aux)));
+    }
+    if columns.contains(ExportColumns::LOCATION) {
+        let location = data.locations.get(&entry.id);
+        ret.push(("lat", json!(location.map(|l| l.lat))));
+        ret.push(("lon", json!(location.map(|l| l.lon))));
+    }
+    ret
+}
+
+fn format_row(fields: Vec<(&'static str, Value)>, format: ExportFormat) -> String {
+    match format {
+        ExportFormat::Jsonl => {
+            let map: Map<String, Value> = fields
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect();
+            Value::Object(map).to_string()
+        }
+        ExportFormat::Tab => fields
+            .iter()
+            .map(|(_, value)| format_tab_value(value))
+            .collect::<Vec<String>>()
+            .join("\t"),
+    }
+}
+
+/// Auxiliary values become `P123=value|P456=value`; tabs and newlines are replaced by spaces.
+fn format_tab_value(value: &Value) -> String {
+    let s = match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.to_owned(),
+        Value::Array(values) => values
+            .iter()
+            .map(|v| match (v["property"].as_str(), v["value"].as_str()) {
+                (Some(property), Some(value)) => format!("{property}={value}"),
+                _ => v.to_string(),
+            })
+            .collect::<Vec<String>>()
+            .join("|"),
+        other => other.to_string(),
+    };
+    s.replace(['\t', '\n', '\r'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app_state::get_test_app;
+
+    const TEST_CATALOG_ID: usize = 5526;
+
+    #[test]
+    fn test_export_columns() {
+        let columns = ExportColumns::EXT_ID | ExportColumns::Q;
+        assert!(columns.contains(ExportColumns::EXT_ID));
+        assert!(!columns.contains(ExportColumns::AUX));
+        assert_eq!(columns.field_names(), vec!["ext_id", "q"]);
+        assert_eq!(ExportColumns::from_names("ext_id, q").unwrap(), columns);
+        assert!(ExportColumns::from_names("ext_id,foobar").is_err());
+        assert_eq!(
+            (ExportColumns::PERSON_DATES | ExportColumns::LOCATION).field_names(),
+            vec!["born", "died", "lat", "lon"]
+        );
+        assert_eq!(ExportColumns::ALL.field_names().len(), 14);
+        assert_eq!(
+            ExportColumns::WORKLIST.field_names(),
+            vec!["ext_id", "ext_name", "ext_desc", "ext_url"]
+        );
+    }
+
+    #[test]
+    fn test_export_format() {
+        assert_eq!(
+            "jsonl".parse::<ExportFormat>().unwrap(),
+            ExportFormat::Jsonl
+        );
+        assert_eq!(" TSV".parse::<ExportFormat>().unwrap(), ExportFormat::Tab);
+        assert!("xml".parse::<ExportFormat>().is_err());
+    }
+
+    #[test]
+    fn test_format_row() {
+        let fields = vec![
+            ("ext_id", json!("abc")),
+            ("ext_name", json!("Foo\tBar")),
+            ("q", json!(null)),
+            (
+                "aux",
+                json!([{"property":"P214","value":"123"},{"property":"P227","value":"456"}]),
+            ),
+        ];
+        assert_eq!(
+            format_row(fields.to_owned(), ExportFormat::Tab),
+            "abc\tFoo Bar\t\tP214=123|P227=456"
+        );
+        let jsonl = format_row(fields, ExportFormat::Jsonl);
+        let v: Value = serde_json::from_str(&jsonl).unwrap();
+        assert_eq!(v["ext_name"], json!("Foo\tBar"));
+        assert_eq!(v["aux"][1]["property"], json!("P227"));
+    }
+
+    #[tokio::test]
+    async fn test_export_catalog() {
+        let app = get_test_app();
+        let columns = ExportColumns::ENTRY_ID | ExportColumns::EXT_ID;
+        let rows: Vec<String> = export_catalog(&app, TEST_CATALOG_ID, ExportFormat::Tab, columns)
+            .take(3)
+            .map(|row| row.unwrap())
+            .collect()
+            .await;
+        assert_eq!(rows[0], "entry_id\text_id");
+        assert!(rows.len() > 1);
+        assert_eq!(rows[1].split('\t').count(), 2);
+    }
+}
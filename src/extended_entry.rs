@@ -1,5 +1,6 @@
-use crate::app_state::AppState;
-use crate::datasource::DataSource;
+use crate::app_state::{AppState, USER_AUTO, USER_AUX_MATCH};
+use crate::autoscrape_resolve::AutoscrapeResolve;
+use crate::datasource::{DataSource, LANGUAGE_DESC_COLUMN_PREFIX, LANGUAGE_NAME_COLUMN_PREFIX};
 use crate::entry::*;
 use crate::update_catalog::UpdateCatalogError;
 use anyhow::{anyhow, Result};
@@ -30,9 +31,68 @@ pub struct ExtendedEntry {
     pub aliases: Vec<LocaleString>,
     pub descriptions: HashMap<String, String>,
     pub location: Option<CoordinateLocation>,
+    /// Replace a preliminary match of an existing entry with `entry.q`, not just set it on unmatched entries
+    pub replace_automatic_match: bool,
 }
 
 impl ExtendedEntry {
+    /// Loads the extended data of an existing entry from the database.
+    pub async fn from_entry(entry: Entry) -> Result<Self> {
+        let (born, died) = entry.get_person_dates().await?;
+        let aux = entry
+            .get_aux()
+            .await?
+            .into_iter()
+            .map(|row| (row.prop_numeric, row.value))
+            .collect();
+        Ok(Self {
+            aux,
+            born,
+            died,
+            aliases: entry.get_aliases().await?,
+            descriptions: entry.get_language_descriptions().await?,
+            location: entry.get_coordinate_location().await?,
+            entry,
+            replace_automatic_match: false,
+        })
+    }
+
+    /// As `from_entry` for several entries, with one query per table
+    pub async fn multiple_from_entries(entries: Vec<Entry>, app: &AppState) -> Result<Vec<Self>> {
+        let storage = app.storage();
+        let entry_ids: Vec<usize> = entries.iter().map(|entry| entry.id).collect();
+        let mut person_dates = storage.entries_get_person_dates(&entry_ids).await?;
+        let mut aux = storage.entries_get_aux(&entry_ids).await?;
+        let mut aliases = storage.entries_get_aliases(&entry_ids).await?;
+        let mut descriptions = storage
+            .entries_get_language_descriptions(&entry_ids)
+            .await?;
+        let mut locations = storage.entries_get_coordinate_locations(&entry_ids).await?;
+        let ret = entries
+            .into_iter()
+            .map(|entry| {
+                let id = entry.id;
+                let (born, died) = person_dates.remove(&id).unwrap_or_default();
+                Self {
+                    aux: aux
+                        .remove(&id)
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|row| (row.prop_numeric, row.value))
+                        .collect(),
+                    born,
+                    died,
+                    aliases: aliases.remove(&id).unwrap_or_default(),
+                    descriptions: descriptions.remove(&id).unwrap_or_default(),
+                    location: locations.remove(&id),
+                    entry,
+                    replace_automatic_match: false,
+                }
+            })
+            .collect();
+        Ok(ret)
+    }
+
     //TODO test
     pub fn from_row(row: &csv::StringRecord, datasource: &mut DataSource) -> Result<Self> {
         let ext_id = row
@@ -45,6 +105,10 @@ impl ExtendedEntry {
 
         Self::from_row_colmap(datasource, row, &mut ret)?;
         Self::from_row_patterns(datasource, row, &mut ret)?;
+        ret.set_primary_from_language_columns(datasource);
+        if datasource.strip_html_descriptions {
+            ret.strip_html_from_descriptions();
+        }
 
         if ret.entry.type_name.is_none() {
             ret.entry.type_name.clone_from(&datasource.default_type);
@@ -56,9 +120,26 @@ impl ExtendedEntry {
             }
         }
 
+        ret.apply_prematch_options(datasource);
         Ok(ret)
     }
 
+    /// Attributes a match from the source to the configured user, and drops or replaces invalid items
+    fn apply_prematch_options(&mut self, datasource: &DataSource) {
+        let q = match self.entry.q {
+            Some(q) => q,
+            None => return,
+        };
+        self.entry.q = datasource.prematch.resolve(q);
+        if self.entry.q.is_some() {
+            self.entry.user = Some(datasource.prematch.user_id);
+        } else {
+            self.entry.user = None;
+            self.entry.timestamp = None;
+        }
+        self.replace_automatic_match = !datasource.prematch.only_unmatched;
+    }
+
     fn from_row_patterns(
         datasource: &mut DataSource,
         row: &csv::StringRecord,
@@ -91,10 +172,46 @@ impl ExtendedEntry {
         Ok(())
     }
 
+    /// Uses the primary language name/description columns for `ext_name`/`ext_desc`, if those are not set otherwise.
+    /// The alias that is identical to the name, in the same language, is then redundant.
+    fn set_primary_from_language_columns(&mut self, datasource: &DataSource) {
+        if let Some(language) = &datasource.name_language {
+            if self.entry.ext_name.is_empty() {
+                if let Some(alias) = self
+                    .aliases
+                    .iter()
+                    .find(|a| a.language() == language.as_str())
+                {
+                    self.entry.ext_name = alias.value().to_string();
+                }
+            }
+            let ext_name = self.entry.ext_name.to_owned();
+            self.aliases
+                .retain(|a| a.language() != language.as_str() || a.value() != ext_name.as_str());
+        }
+        if let Some(language) = &datasource.desc_language {
+            if self.entry.ext_desc.is_empty() {
+                if let Some(desc) = self.descriptions.get(language) {
+                    self.entry.ext_desc = desc.to_owned();
+                }
+            }
+        }
+    }
+
+    /// Strips HTML from the descriptions, for catalogs with `strip_html_descriptions` set
+    pub fn strip_html_from_descriptions(&mut self) {
+        self.entry.ext_desc = AutoscrapeResolve::strip_html(&self.entry.ext_desc);
+        self.descriptions
+            .values_mut()
+            .for_each(|desc| *desc = AutoscrapeResolve::strip_html(desc));
+    }
+
+    /// Updates an existing entry with the values of this one.
+    /// Returns `true` if an automatic match was removed because the type changed; see `Entry::set_type_name`.
     //TODO test
-    pub async fn update_existing(&mut self, entry: &mut Entry, app: &AppState) -> Result<()> {
+    pub async fn update_existing(&mut self, entry: &mut Entry, app: &AppState) -> Result<bool> {
         entry.set_app(app);
-        self.update_existing_basic_values(entry).await?;
+        let automatch_removed = self.update_existing_basic_values(entry).await?;
         if self.born.is_some() || self.died.is_some() {
             entry.set_person_dates(&self.born, &self.died).await?;
         }
@@ -104,29 +221,33 @@ impl ExtendedEntry {
         self.sync_aliases(entry).await?;
         self.sync_descriptions(entry).await?;
         self.sync_auxiliary(entry).await?;
-        Ok(())
+        Ok(automatch_removed)
     }
 
-    async fn update_existing_basic_values(&mut self, entry: &mut Entry) -> Result<()> {
+    async fn update_existing_basic_values(&mut self, entry: &mut Entry) -> Result<bool> {
         if !self.entry.ext_name.is_empty() {
             entry.set_ext_name(&self.entry.ext_name).await?;
         }
         if !self.entry.ext_desc.is_empty() {
             entry.set_ext_desc(&self.entry.ext_desc).await?;
         }
+        let mut automatch_removed = false;
         if self.entry.type_name.is_some() {
-            entry.set_type_name(self.entry.type_name.clone()).await?;
+            automatch_removed = entry.set_type_name(self.entry.type_name.clone()).await?;
         }
         if !self.entry.ext_url.is_empty() {
             entry.set_ext_url(&self.entry.ext_url).await?;
         }
-        if entry.q.is_none() {
+        let can_match =
+            entry.q.is_none() || (self.replace_automatic_match && entry.user == Some(USER_AUTO));
+        if can_match {
             if let Some(q) = self.entry.q {
                 // println!("UPDATING Q{q} for {}", entry.id);
-                entry.set_match(&format!("Q{q}"), 4).await?;
+                let user_id = self.entry.user.unwrap_or(USER_AUX_MATCH);
+                entry.set_match(&format!("Q{q}"), user_id).await?;
             }
         }
-        Ok(())
+        Ok(automatch_removed)
     }
 
     // Adds new aliases.
@@ -181,8 +302,17 @@ impl ExtendedEntry {
     // #lizard forgives
     //TODO test
     pub async fn insert_new(&mut self, app: &AppState) -> Result<()> {
+        self.insert_new_from_import(app, None).await
+    }
+
+    /// Inserts a new entry and its associated data, recording the `update_info` ID of the import that created it
+    pub async fn insert_new_from_import(
+        &mut self,
+        app: &AppState,
+        import_id: Option<usize>,
+    ) -> Result<()> {
         self.entry.set_app(app);
-        self.entry.insert_as_new().await?;
+        self.entry.insert_as_new_from_import(import_id).await?;
 
         // TODO use update_existing_description
         // TODO use update_all_descriptions
@@ -216,6 +346,7 @@ impl ExtendedEntry {
     fn process_cell(&mut self, label: &str, cell: &str) -> Result<()> {
         if !self.parse_alias(label, cell)
             && !self.parse_description(label, cell)
+            && !self.parse_language_column(label, cell)
             && !self.parse_property(label, cell)?
         {
             match label {
@@ -233,7 +364,7 @@ impl ExtendedEntry {
                     }
                     if self.entry.q.is_some() {
                         // q is set, also set user and timestamp
-                        self.entry.user = Some(4); // Auxiliary data matcher
+                        self.entry.user = Some(USER_AUX_MATCH); // Overridden by the data source options
                         self.entry.timestamp = Some(TimeStamp::now());
                     }
                 }
@@ -282,6 +413,26 @@ impl ExtendedEntry {
         }
     }
 
+    /// Language-tagged names (`name:de`) become aliases, descriptions (`desc:fr`) language descriptions.
+    /// Empty cells are ignored.
+    fn parse_language_column(&mut self, label: &str, cell: &str) -> bool {
+        let cell = cell.trim();
+        if let Some(language) = label.strip_prefix(LANGUAGE_NAME_COLUMN_PREFIX) {
+            if !cell.is_empty() {
+                self.aliases.push(LocaleString::new(language, cell));
+            }
+            true
+        } else if let Some(language) = label.strip_prefix(LANGUAGE_DESC_COLUMN_PREFIX) {
+            if !cell.is_empty() {
+                self.descriptions
+                    .insert(language.to_string(), cell.to_string());
+            }
+            true
+        } else {
+            false
+        }
+    }
+
     //TODO test
     fn parse_property(&mut self, label: &str, cell: &str) -> Result<bool> {
         let property_num = match Self::get_capture(&RE_PROPERTY, label) {
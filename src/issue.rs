@@ -1,5 +1,7 @@
-use anyhow::Result;
-use serde_json::Value;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
 use std::error::Error;
 use std::fmt;
 
@@ -24,13 +26,17 @@ impl fmt::Display for IssueError {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum IssueType {
     WdDuplicate,
     Mismatch,
     ItemDeleted,
     MismatchDates,
     Multiple,
+    /// The ext_id is no longer in the source of an incremental catalog update
+    RemovedFromSource,
+    /// The entry has day-precision person dates that the matched item does not have
+    DatePrecisionMismatch,
 }
 
 impl IssueType {
@@ -41,6 +47,8 @@ impl IssueType {
             "ITEM_DELETED" => Ok(IssueType::ItemDeleted),
             "MISMATCH_DATES" => Ok(IssueType::MismatchDates),
             "MULTIPLE" => Ok(IssueType::Multiple),
+            "REMOVED_FROM_SOURCE" => Ok(IssueType::RemovedFromSource),
+            "DATE_PRECISION_MISMATCH" => Ok(IssueType::DatePrecisionMismatch),
             _ => Err(IssueError::UnregognizedType),
         }
     }
@@ -52,6 +60,8 @@ impl IssueType {
             IssueType::ItemDeleted => "ITEM_DELETED",
             IssueType::MismatchDates => "MISMATCH_DATES",
             IssueType::Multiple => "MULTIPLE",
+            IssueType::RemovedFromSource => "REMOVED_FROM_SOURCE",
+            IssueType::DatePrecisionMismatch => "DATE_PRECISION_MISMATCH",
         }
     }
 }
@@ -88,8 +98,207 @@ impl IssueStatus {
     }
 }
 
+/// Old and new type of an entry, see `TypeChangedPayload`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TypeChange {
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+/// `MISMATCH` for a fully matched entry whose type has changed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TypeChangedPayload {
+    pub type_changed: TypeChange,
+    /// The item the entry is matched to
+    pub q: Option<String>,
+}
+
+/// `MULTIPLE`: several items have the auxiliary value of an entry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MultiplePayload {
+    pub wd: Vec<String>,
+    #[serde(rename = "app")]
+    pub value: String,
+}
+
+/// `MISMATCH`: the item found via an auxiliary value, and the item the entry is matched to, as `["Q1","Q2"]`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "(String, String)", into = "(String, String)")]
+pub struct MismatchPayload {
+    pub found: String,
+    pub matched: String,
+}
+
+impl From<(String, String)> for MismatchPayload {
+    fn from((found, matched): (String, String)) -> Self {
+        Self { found, matched }
+    }
+}
+
+impl From<MismatchPayload> for (String, String) {
+    fn from(payload: MismatchPayload) -> Self {
+        (payload.found, payload.matched)
+    }
+}
+
+/// `REMOVED_FROM_SOURCE`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RemovedFromSourcePayload {
+    pub ext_id: String,
+}
+
+/// `DATE_PRECISION_MISMATCH`: the person dates of the entry, and the P569/P570 dates of the matched item,
+/// as `YYYY-MM-DD`, `YYYY-MM`, or `YYYY` depending on their precision
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DatePrecisionMismatchPayload {
+    pub q: String,
+    pub entry_born: String,
+    pub entry_died: String,
+    pub item_born: Vec<String>,
+    pub item_died: Vec<String>,
+}
+
+/// The `json` of an issue, with the shape that belongs to its type, which is the JSON the issues UI expects.
+/// Deserializing without the issue type picks the first variant of that shape (a pair of items is a `Mismatch`);
+/// use `from_json` where the type is known.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum IssuePayload {
+    Mismatch(MismatchPayload),
+    /// `WD_DUPLICATE`: the items that all match the entry, as `["Q1","Q2"]`
+    WdDuplicate(Vec<String>),
+    /// `MISMATCH` for a type change
+    TypeChanged(TypeChangedPayload),
+    Multiple(MultiplePayload),
+    RemovedFromSource(RemovedFromSourcePayload),
+    DatePrecisionMismatch(DatePrecisionMismatchPayload),
+    /// Payloads of other types (eg from the PHP code), or that do not have the expected shape.
+    /// Only read from existing issues; `Issue::new` rejects it.
+    #[serde(skip_deserializing, serialize_with = "IssuePayload::serialize_other")]
+    Other(IssueType, Value),
+}
+
+impl IssuePayload {
+    pub fn mismatch(found: String, matched: String) -> Self {
+        Self::Mismatch(MismatchPayload { found, matched })
+    }
+
+    pub fn type_changed(from: Option<String>, to: Option<String>, q: Option<String>) -> Self {
+        Self::TypeChanged(TypeChangedPayload {
+            type_changed: TypeChange { from, to },
+            q,
+        })
+    }
+
+    pub fn multiple(wd: Vec<String>, value: &str) -> Self {
+        Self::Multiple(MultiplePayload {
+            wd,
+            value: value.to_string(),
+        })
+    }
+
+    pub fn removed_from_source(ext_id: &str) -> Self {
+        Self::RemovedFromSource(RemovedFromSourcePayload {
+            ext_id: ext_id.to_string(),
+        })
+    }
+
+    pub const fn issue_type(&self) -> IssueType {
+        match self {
+            IssuePayload::WdDuplicate(_) => IssueType::WdDuplicate,
+            IssuePayload::Mismatch(_) => IssueType::Mismatch,
+            IssuePayload::TypeChanged(_) => IssueType::Mismatch,
+            IssuePayload::Multiple(_) => IssueType::Multiple,
+            IssuePayload::RemovedFromSource(_) => IssueType::RemovedFromSource,
+            IssuePayload::DatePrecisionMismatch(_) => IssueType::DatePrecisionMismatch,
+            IssuePayload::Other(issue_type, _) => *issue_type,
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!(self)
+    }
+
+    /// `Other` is serialized as its JSON, without the issue type
+    fn serialize_other<S: serde::Serializer>(
+        _issue_type: &IssueType,
+        json: &Value,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        json.serialize(serializer)
+    }
+
+    /// Parses the `json` of an issue of a type; falls back to `Other` for unexpected shapes
+    pub fn from_json(issue_type: IssueType, json: &Value) -> Self {
+        let parsed = match issue_type {
+            IssueType::WdDuplicate => serde_json::from_value(json.to_owned())
+                .ok()
+                .map(IssuePayload::WdDuplicate),
+            IssueType::Mismatch => match serde_json::from_value(json.to_owned()) {
+                Ok(payload) => Some(IssuePayload::Mismatch(payload)),
+                Err(_) => serde_json::from_value(json.to_owned())
+                    .ok()
+                    .map(IssuePayload::TypeChanged),
+            },
+            IssueType::Multiple => serde_json::from_value(json.to_owned())
+                .ok()
+                .map(IssuePayload::Multiple),
+            IssueType::RemovedFromSource => serde_json::from_value(json.to_owned())
+                .ok()
+                .map(IssuePayload::RemovedFromSource),
+            IssueType::DatePrecisionMismatch => serde_json::from_value(json.to_owned())
+                .ok()
+                .map(IssuePayload::DatePrecisionMismatch),
+            IssueType::ItemDeleted | IssueType::MismatchDates => None,
+        };
+        parsed.unwrap_or_else(|| IssuePayload::Other(issue_type, json.to_owned()))
+    }
+}
+
+/// Open issue counts for a catalog, by type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CatalogIssueSummary {
+    pub catalog_id: usize,
+    pub counts: HashMap<IssueType, usize>,
+    pub total: usize,
+}
+
+impl CatalogIssueSummary {
+    /// Converts the result of `Storage::get_issue_counts_by_catalog` into summaries,
+    /// catalogs with most open issues first
+    pub fn from_counts(counts: HashMap<usize, HashMap<IssueType, usize>>) -> Vec<Self> {
+        let mut ret: Vec<Self> = counts
+            .into_iter()
+            .map(|(catalog_id, counts)| Self {
+                catalog_id,
+                total: counts.values().sum(),
+                counts,
+            })
+            .collect();
+        ret.sort_by(|a, b| b.total.cmp(&a.total).then(a.catalog_id.cmp(&b.catalog_id)));
+        ret
+    }
+
+    pub fn count(&self, issue_type: IssueType) -> usize {
+        *self.counts.get(&issue_type).unwrap_or(&0)
+    }
+}
+
+/// `id`, `entry_id`, `type`, `json`, `status`, `user_id`, `resolved_ts`, `catalog` from the `issues` table
+pub type IssueRow = (
+    usize,
+    usize,
+    String,
+    String,
+    String,
+    Option<usize>,
+    Option<String>,
+    usize,
+);
+
 #[derive(Debug)]
 pub struct Issue {
+    pub id: usize,
     pub entry_id: usize,
     pub issue_type: IssueType,
     pub json: Value,
@@ -97,21 +306,24 @@ pub struct Issue {
     pub user_id: Option<usize>,
     pub resolved_ts: Option<String>,
     pub catalog_id: usize,
-    app: AppState,
+    app: Option<AppState>,
 }
 
 impl Issue {
-    pub async fn new(
-        entry_id: usize,
-        issue_type: IssueType,
-        json: Value,
-        app: &AppState,
-    ) -> Result<Self> {
+    /// A new issue; fails for `IssuePayload::Other`, which is only for reading existing issues
+    pub async fn new(entry_id: usize, payload: IssuePayload, app: &AppState) -> Result<Self> {
+        if let IssuePayload::Other(issue_type, _) = &payload {
+            return Err(anyhow!(
+                "Can not create a {} issue without a typed payload",
+                issue_type.to_str()
+            ));
+        }
         Ok(Self {
-            app: app.clone(),
+            app: Some(app.clone()),
+            id: 0,
             entry_id,
-            issue_type,
-            json,
+            issue_type: payload.issue_type(),
+            json: payload.to_json(),
             status: IssueStatus::Open,
             user_id: None,
             resolved_ts: None,
@@ -119,8 +331,142 @@ impl Issue {
         })
     }
 
+    /// Creates an issue from a database row; use `set_app` before `insert`.
+    pub fn from_row(row: IssueRow) -> Result<Self> {
+        Ok(Self {
+            id: row.0,
+            entry_id: row.1,
+            issue_type: IssueType::new(&row.2)?,
+            json: serde_json::from_str(&row.3).unwrap_or(Value::Null),
+            status: IssueStatus::new(&row.4)?,
+            user_id: row.5,
+            resolved_ts: row.6,
+            catalog_id: row.7,
+            app: None,
+        })
+    }
+
+    pub fn payload(&self) -> IssuePayload {
+        IssuePayload::from_json(self.issue_type, &self.json)
+    }
+
+    pub fn set_app(&mut self, app: &AppState) {
+        self.app = Some(app.clone());
+    }
+
+    fn app(&self) -> Result<&AppState> {
+        self.app.as_ref().ok_or(anyhow!("Issue: No app set"))
+    }
+
     pub async fn insert(&self) -> Result<()> {
-        self.app.storage().issue_insert(self).await?;
+        self.app()?.storage().issue_insert(self).await?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catalog_issue_summary() {
+        let mut counts = HashMap::new();
+        counts.insert(
+            1,
+            HashMap::from([(IssueType::Mismatch, 2), (IssueType::WdDuplicate, 3)]),
+        );
+        counts.insert(2, HashMap::from([(IssueType::Multiple, 42)]));
+        let summary = CatalogIssueSummary::from_counts(counts);
+        assert_eq!(summary.len(), 2);
+        assert_eq!(summary[0].catalog_id, 2);
+        assert_eq!(summary[0].total, 42);
+        assert_eq!(summary[1].total, 5);
+        assert_eq!(summary[1].count(IssueType::WdDuplicate), 3);
+        assert_eq!(summary[1].count(IssueType::ItemDeleted), 0);
+    }
+
+    #[test]
+    fn test_issue_from_row() {
+        let issue = Issue::from_row((
+            1,
+            2,
+            "MISMATCH".to_string(),
+            "[\"Q1\",\"Q2\"]".to_string(),
+            "OPEN".to_string(),
+            None,
+            None,
+            3,
+        ))
+        .unwrap();
+        assert_eq!(issue.entry_id, 2);
+        assert_eq!(issue.issue_type.to_str(), "MISMATCH");
+        assert_eq!(issue.json, serde_json::json!(["Q1", "Q2"]));
+        assert_eq!(issue.catalog_id, 3);
+        assert_eq!(
+            issue.payload(),
+            IssuePayload::mismatch("Q1".to_string(), "Q2".to_string())
+        );
+        assert!(issue.app().is_err());
+
+        let row = (
+            1,
+            2,
+            "FOO".to_string(),
+            "".to_string(),
+            "OPEN".to_string(),
+            None,
+            None,
+            3,
+        );
+        assert!(Issue::from_row(row).is_err());
+    }
+
+    #[test]
+    fn test_issue_payload_round_trip() {
+        let payloads = [
+            IssuePayload::WdDuplicate(vec!["Q1".to_string(), "Q2".to_string(), "Q3".to_string()]),
+            IssuePayload::mismatch("Q1".to_string(), "Q2".to_string()),
+            IssuePayload::type_changed(Some("Q5".to_string()), None, Some("Q42".to_string())),
+            IssuePayload::multiple(vec!["Q1".to_string(), "Q2".to_string()], "abc"),
+            IssuePayload::removed_from_source("abc"),
+            IssuePayload::DatePrecisionMismatch(DatePrecisionMismatchPayload {
+                q: "Q1".to_string(),
+                entry_born: "1900-01-02".to_string(),
+                entry_died: "1950-03-04".to_string(),
+                item_born: vec!["1900".to_string()],
+                item_died: vec!["1950-03-05".to_string()],
+            }),
+            IssuePayload::Other(IssueType::MismatchDates, json!({"foo": "bar"})),
+        ];
+        for payload in payloads {
+            let json = payload.to_json();
+            assert_eq!(
+                IssuePayload::from_json(payload.issue_type(), &json),
+                payload
+            );
+            if !matches!(payload, IssuePayload::Other(..)) {
+                assert_eq!(
+                    serde_json::from_value::<IssuePayload>(json).unwrap(),
+                    payload
+                );
+            }
+        }
+        assert!(serde_json::from_value::<IssuePayload>(json!({"foo": "bar"})).is_err());
+    }
+
+    #[test]
+    fn test_issue_payload_json() {
+        assert_eq!(
+            IssuePayload::multiple(vec!["Q1".to_string()], "abc").to_json(),
+            json!({"wd": ["Q1"], "app": "abc"})
+        );
+        assert_eq!(
+            IssuePayload::type_changed(Some("Q5".to_string()), None, None).to_json(),
+            json!({"type_changed": {"from": "Q5", "to": null}, "q": null})
+        );
+        assert_eq!(
+            IssuePayload::from_json(IssueType::WdDuplicate, &json!("!")),
+            IssuePayload::Other(IssueType::WdDuplicate, json!("!"))
+        );
+    }
+}
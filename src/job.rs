@@ -2,6 +2,7 @@ use crate::app_state::AppState;
 use crate::automatch::*;
 use crate::autoscrape::*;
 use crate::auxiliary_matcher::*;
+use crate::catalog::Catalog;
 use crate::coordinate_matcher::CoordinateMatcher;
 use crate::job_row::JobRow;
 use crate::job_status::JobStatus;
@@ -9,17 +10,23 @@ use crate::maintenance::*;
 use crate::match_state::MatchState;
 use crate::microsync::*;
 use crate::php_wrapper::*;
+use crate::reference_fixer::ReferenceFixer;
+use crate::statement_text::StatementTextMatcher;
+use crate::storage::StorageError;
 use crate::taxon_matcher::*;
 use crate::update_catalog::*;
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
 use chrono::Duration;
-use chrono::Local;
 use serde_json::json;
 use std::error::Error;
 use std::fmt;
+use tracing::{error, info, info_span, Instrument};
 use wikimisc::timestamp::TimeStamp;
 
+const DEFAULT_JOB_RETRY_BASE_SEC: usize = 300;
+const DEFAULT_JOB_RETRY_MAX_SEC: usize = 86400;
+
 /// A trait that allows to manage temporary job data (eg offset)
 #[async_trait]
 pub trait Jobbable {
@@ -83,6 +90,8 @@ pub trait Jobbable {
 pub enum JobError {
     S(String),
     TimeError,
+    InactiveCatalog(usize),
+    Running(usize),
 }
 
 impl Error for JobError {}
@@ -93,6 +102,67 @@ impl fmt::Display for JobError {
         match self {
             JobError::S(s) => write!(f, "JobError::S: {s}"),
             JobError::TimeError => write!(f, "JobError::TimeError"),
+            JobError::InactiveCatalog(catalog_id) => {
+                write!(f, "Catalog {catalog_id} is inactive")
+            }
+            JobError::Running(job_id) => write!(f, "Job {job_id} is RUNNING"),
+        }
+    }
+}
+
+/// Rough classification of a job failure, stored alongside the full error text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobErrorCategory {
+    Database,
+    Network,
+    Parse,
+    Io,
+    Job,
+    Other,
+}
+
+impl JobErrorCategory {
+    /// Finds the first error in the chain that can be classified
+    pub fn from_error(error: &anyhow::Error) -> Self {
+        for cause in error.chain() {
+            if cause.is::<mysql_async::Error>() {
+                return Self::Database;
+            }
+            if let Some(storage_error) = cause.downcast_ref::<StorageError>() {
+                return match storage_error {
+                    StorageError::Db(_) | StorageError::Constraint(_) => Self::Database,
+                    StorageError::NotFound(_)
+                    | StorageError::Invalid(_)
+                    | StorageError::Entry(_) => Self::Other,
+                };
+            }
+            if cause.is::<reqwest::Error>() {
+                return Self::Network;
+            }
+            if cause.is::<serde_json::Error>()
+                || cause.is::<csv::Error>()
+                || cause.is::<std::num::ParseIntError>()
+            {
+                return Self::Parse;
+            }
+            if cause.is::<std::io::Error>() {
+                return Self::Io;
+            }
+            if cause.is::<JobError>() {
+                return Self::Job;
+            }
+        }
+        Self::Other
+    }
+
+    pub const fn as_str(&self) -> &str {
+        match self {
+            Self::Database => "DATABASE",
+            Self::Network => "NETWORK",
+            Self::Parse => "PARSE",
+            Self::Io => "IO",
+            Self::Job => "JOB",
+            Self::Other => "OTHER",
         }
     }
 }
@@ -133,41 +203,100 @@ impl Job {
 
     //TODO test
     pub async fn run(&mut self) -> Result<()> {
+        let job_id = self.get_id().await?;
         let catalog_id = self.get_catalog().await?;
         let action = self.get_action().await?;
-        let res = self.run_this_job().await;
-        match res {
-            Ok(_) => self.run_ok(catalog_id, action).await?,
-            Err(e) => self.run_error(catalog_id, &action, &e).await?,
+        let span = info_span!("job", job_id, catalog_id, action = %action);
+        async {
+            let res = self.run_this_job().await;
+            match res {
+                Ok(_) => {
+                    self.run_ok().await?;
+                    self.update_next_ts().await
+                }
+                // `run_error` schedules the retry or the next run, if any
+                Err(e) => self.run_error(catalog_id, &e).await,
+            }
         }
-        self.update_next_ts().await
+        .instrument(span)
+        .await
     }
 
-    async fn run_error(
-        &mut self,
-        catalog_id: usize,
-        action: &str,
-        error: &anyhow::Error,
-    ) -> Result<()> {
+    async fn run_error(&mut self, catalog_id: usize, error: &anyhow::Error) -> Result<()> {
         match catalog_id {
-            0 => self.set_status(JobStatus::Done).await?, // Don't fail
-            _ => self.set_status(JobStatus::Failed).await?,
+            0 => {
+                self.set_status(JobStatus::Done).await?; // Don't fail
+
+                // Otherwise, a failed repeating job would be due again right away
+                self.update_next_ts().await?;
+            }
+            _ => {
+                let job_id = self.get_id().await?;
+                let failures = self.app.storage().jobs_increment_failures(job_id).await?;
+                self.data.failures = failures;
+                if failures >= self.app.max_job_failures() {
+                    // Dead letter: do not retry automatically, keep the last error in the note
+                    self.set_status(JobStatus::Blocked).await?;
+                } else {
+                    self.set_status(JobStatus::Failed).await?;
+                    self.schedule_retry(failures).await?;
+                }
+            }
         }
         let note = Some(format!("{error}"));
         self.set_note(note).await?;
         let job_id = self.get_id().await?;
-        println!("Job {job_id} catalog {catalog_id}:{action} FAILED: {error}");
+        let category = JobErrorCategory::from_error(error);
+        let details = format!("{error:?}"); // Includes the cause chain
+        self.app
+            .storage()
+            .jobs_set_error(job_id, category.as_str(), details)
+            .await?;
+        error!(failures = self.data.failures, "Job failed: {error}");
         Ok(())
     }
 
-    async fn run_ok(&mut self, catalog_id: usize, action: String) -> Result<(), anyhow::Error> {
+    /// Schedules a FAILED job to be retried, with exponential backoff.
+    /// The delays are configured via `task_specific_usize` in the config:
+    /// `job_retry_base_sec` for the first retry, doubling with every failure, up to `job_retry_max_sec`.
+    async fn schedule_retry(&mut self, failures: usize) -> Result<()> {
+        let config = self.app.task_specific_usize();
+        let base_sec = *config
+            .get("job_retry_base_sec")
+            .unwrap_or(&DEFAULT_JOB_RETRY_BASE_SEC);
+        let max_sec = *config
+            .get("job_retry_max_sec")
+            .unwrap_or(&DEFAULT_JOB_RETRY_MAX_SEC);
+        let delay = Self::retry_delay_sec(failures, base_sec, max_sec);
+        let delay = Duration::try_seconds(delay as i64).ok_or(JobError::TimeError)?;
+        let next_ts = TimeStamp::str2utc(&TimeStamp::now())
+            .ok_or(JobError::TimeError)?
+            .checked_add_signed(delay)
+            .ok_or(JobError::TimeError)?
+            .format("%Y%m%d%H%M%S")
+            .to_string();
+        let job_id = self.get_id().await?;
+        self.put_next_ts(&next_ts).await?;
+        self.app
+            .storage()
+            .jobs_update_next_ts(job_id, next_ts)
+            .await
+    }
+
+    /// Seconds to wait before retrying a job that has failed `failures` times in a row
+    fn retry_delay_sec(failures: usize, base_sec: usize, max_sec: usize) -> usize {
+        let exponent = failures.saturating_sub(1).min(usize::BITS as usize - 1) as u32;
+        base_sec.saturating_mul(1 << exponent).min(max_sec)
+    }
+
+    async fn run_ok(&mut self) -> Result<(), anyhow::Error> {
         self.set_status(JobStatus::Done).await?;
-        println!(
-            "Job {} catalog {}:{} completed.",
-            self.get_id().await?,
-            catalog_id,
-            action
-        );
+        if self.data.failures > 0 {
+            let job_id = self.get_id().await?;
+            self.app.storage().jobs_reset_failures(job_id).await?;
+            self.data.failures = 0;
+        }
+        info!("Job completed");
         Ok(())
     }
 
@@ -217,6 +346,9 @@ impl Job {
         if let Some(job_id) = self.get_next_low_priority_job().await {
             return Some(job_id);
         }
+        if let Some(job_id) = self.get_next_retry_job().await {
+            return Some(job_id);
+        }
         if let Some(job_id) = self.get_next_scheduled_job().await {
             return Some(job_id);
         }
@@ -241,6 +373,56 @@ impl Job {
             .await
     }
 
+    /// Makes sure a repeating job exists, running every `repeat_after_sec` seconds.
+    /// A new job runs right away; an existing one keeps its status and schedule.
+    pub async fn queue_repeating_job(
+        app: &AppState,
+        catalog_id: usize,
+        action: &str,
+        repeat_after_sec: usize,
+    ) -> Result<()> {
+        app.storage()
+            .jobs_queue_repeating_job(catalog_id, action, repeat_after_sec, TimeStamp::now())
+            .await
+    }
+
+    /// Returns the IDs of jobs that were BLOCKED after failing too many times in a row
+    pub async fn get_dead_letter_jobs(app: &AppState) -> Result<Vec<usize>> {
+        app.storage()
+            .jobs_get_dead_letter(app.max_job_failures())
+            .await
+    }
+
+    /// Puts a BLOCKED (dead letter) job back into the queue, see `requeue`
+    pub async fn requeue_dead_letter_job(app: &AppState, job_id: usize) -> Result<()> {
+        let row = app.storage().jobs_row_from_id(job_id).await?;
+        if row.status != JobStatus::Blocked {
+            return Err(anyhow!("Job {job_id} is not BLOCKED"));
+        }
+        Self::requeue(app, job_id, false).await
+    }
+
+    /// Puts a job back into the queue, with TODO or HIGH_PRIORITY status, clearing its failure counter and error notes.
+    /// `next_ts` is cleared, so the job runs right away; repeating jobs get their next run scheduled when they finish.
+    /// RUNNING jobs and jobs of inactive catalogs are refused.
+    pub async fn requeue(app: &AppState, job_id: usize, high_priority: bool) -> Result<()> {
+        let row = app.storage().jobs_row_from_id(job_id).await?;
+        if row.status == JobStatus::Running {
+            return Err(JobError::Running(job_id).into());
+        }
+        if row.catalog > 0 && !Catalog::from_id(row.catalog, app).await?.active {
+            return Err(JobError::InactiveCatalog(row.catalog).into());
+        }
+        let status = if high_priority {
+            JobStatus::HighPriority
+        } else {
+            JobStatus::Todo
+        };
+        app.storage()
+            .jobs_requeue(job_id, &status, TimeStamp::now())
+            .await
+    }
+
     /// Sets the value for `json` locally and in database, from a serde_json::Value
     //TODO test
     pub async fn set_json(&mut self, json: Option<serde_json::Value>) -> Result<()> {
@@ -276,8 +458,7 @@ impl Job {
         if self.data.status == JobStatus::Blocked {
             return Err(anyhow!("Job::run_this_job: Blocked"));
         }
-        let current_time_str = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
-        println!("{current_time_str}: Starting job {:?}", self.get_id().await);
+        info!("Starting job");
         let catalog_id = self.get_catalog().await?;
         match self.get_action().await?.as_str() {
             "automatch" => {
@@ -290,6 +471,11 @@ impl Job {
                 am.set_current_job(self);
                 am.automatch_by_search(catalog_id).await
             }
+            "automatch_by_ext_url" => {
+                let mut am = AutoMatch::new(&self.app);
+                am.set_current_job(self);
+                am.automatch_by_ext_url(catalog_id).await
+            }
             "automatch_from_other_catalogs" => {
                 let mut am = AutoMatch::new(&self.app);
                 am.set_current_job(self);
@@ -347,26 +533,50 @@ impl Job {
                 am.set_current_job(self);
                 am.match_via_auxiliary(catalog_id).await
             }
+            "match_via_multiple_aux" => {
+                let mut am = AuxiliaryMatcher::new(&self.app);
+                am.set_current_job(self);
+                let properties = am.joint_aux_properties(catalog_id).await?;
+                am.match_via_multiple_aux(catalog_id, &properties).await
+            }
             "taxon_matcher" => {
                 let mut tm = TaxonMatcher::new(&self.app);
                 tm.set_current_job(self);
                 tm.match_taxa(catalog_id).await
             }
+            "statement_text_populate" => {
+                let mut stm = StatementTextMatcher::new(&self.app);
+                stm.set_current_job(self);
+                stm.populate_catalog(catalog_id).await.map(|_| ())
+            }
+            "statement_text_matcher" => {
+                let mut stm = StatementTextMatcher::new(&self.app);
+                stm.set_current_job(self);
+                stm.match_catalog(catalog_id).await.map(|_| ())
+            }
+            "reference_fixer" => {
+                let mut rf = ReferenceFixer::new(&self.app);
+                rf.set_current_job(self);
+                rf.run().await.map(|_| ())
+            }
             "update_from_tabbed_file" => {
                 let mut uc = UpdateCatalog::new(&self.app);
                 uc.set_current_job(self);
-                uc.update_from_tabbed_file(catalog_id).await
+                uc.update_from_tabbed_file(catalog_id).await.map(|_| ())
+            }
+            "refresh_from_source" => {
+                let mut uc = UpdateCatalog::new(&self.app);
+                uc.set_current_job(self);
+                uc.refresh_from_source(catalog_id).await.map(|_| ())
             }
             "microsync" => {
                 let mut ms = Microsync::new(&self.app);
                 ms.set_current_job(self);
                 let catalog_id = match catalog_id {
                     0 => {
-                        match self
-                            .app
-                            .storage()
-                            .get_random_active_catalog_id_with_property()
-                            .await
+                        match Maintenance::new(&self.app)
+                            .next_catalog_from_cursor(KV_MICROSYNC_CATALOG_CURSOR)
+                            .await?
                         {
                             Some(id) => id,
                             None => return Ok(()), // Ignore, very unlikely
@@ -374,18 +584,75 @@ impl Job {
                     }
                     other => other,
                 };
-                ms.check_catalog(catalog_id).await
+                ms.check_catalog(catalog_id, false).await.map(|_| ())
+            }
+            "microsync_dry_run" => {
+                let mut ms = Microsync::new(&self.app);
+                ms.set_current_job(self);
+                let report = ms.check_catalog(catalog_id, true).await?;
+                self.set_json(Some(json!(report))).await
             }
 
             "maintenance_name_and_full_dates" => {
+                let batch_size = *self
+                    .app
+                    .task_specific_usize()
+                    .get("maintenance_name_and_full_dates_batch_size")
+                    .unwrap_or(&DEFAULT_NAME_AND_FULL_DATES_BATCH_SIZE);
                 Maintenance::new(&self.app)
-                    .match_by_name_and_full_dates()
+                    .match_by_name_and_full_dates(batch_size)
                     .await
+                    .map(|_| ())
             }
-            "maintenance_automatch" => Maintenance::new(&self.app).automatch().await,
+            "maintenance_automatch" => {
+                let limit = *self
+                    .app
+                    .task_specific_usize()
+                    .get("maintenance_automatch_limit")
+                    .unwrap_or(&DEFAULT_MAINTENANCE_AUTOMATCH_LIMIT);
+                Maintenance::new(&self.app)
+                    .automatch(limit)
+                    .await
+                    .map(|_| ())
+            }
+            "flag_stale_autoscrapers" => {
+                let runs = *self
+                    .app
+                    .task_specific_usize()
+                    .get("autoscrape_stale_runs")
+                    .unwrap_or(&DEFAULT_AUTOSCRAPE_STALE_RUNS);
+                Maintenance::new(&self.app)
+                    .flag_stale_autoscrapers(runs)
+                    .await
+                    .map(|_| ())
+            }
+            "requeue_automatch_for_new_entries" => Maintenance::new(&self.app)
+                .requeue_automatch_for_new_entries()
+                .await
+                .map(|_| ()),
             "update_props_todo" => Maintenance::new(&self.app).update_props_todo().await,
             "remove_p17_for_humans" => Maintenance::new(&self.app).remove_p17_for_humans().await,
             "cleanup_mnm_relations" => Maintenance::new(&self.app).cleanup_mnm_relations().await,
+            "fill_missing_random" => Maintenance::new(&self.app)
+                .fill_missing_random()
+                .await
+                .map(|_| ()),
+            "check_catalog_properties" => Maintenance::new(&self.app)
+                .find_catalogs_with_deprecated_properties()
+                .await
+                .map(|_| ()),
+            "find_mojibake" => Maintenance::new(&self.app)
+                .fix_mojibake(catalog_id, false)
+                .await
+                .map(|_| ()),
+            "fix_mojibake" => Maintenance::new(&self.app)
+                .fix_mojibake(catalog_id, true)
+                .await
+                .map(|_| ()),
+            "strip_html_descriptions" => Maintenance::new(&self.app)
+                .strip_html_from_descriptions(catalog_id)
+                .await
+                .map(|_| ()),
 
             "create_match_person_dates" => {
                 Maintenance::new(&self.app)
@@ -405,6 +672,27 @@ impl Job {
                     .await
             }
 
+            "dedupe_ext_ids" => Maintenance::new(&self.app)
+                .dedupe_ext_ids(catalog_id)
+                .await
+                .map(|_| ()),
+            "recompute_overview" => self
+                .app
+                .storage()
+                .refresh_all_overview_tables()
+                .await
+                .map(|_| ()),
+            "sync_matched_items" => {
+                let catalog_id = match catalog_id {
+                    0 => None, // All catalogs
+                    id => Some(id),
+                };
+                Maintenance::new(&self.app)
+                    .sync_matched_items(catalog_id)
+                    .await
+                    .map(|_| ())
+            }
+
             "maintenance_inventory_match" => {
                 Maintenance::new(&self.app)
                     .fully_match_via_collection_inventory_number()
@@ -556,6 +844,15 @@ impl Job {
             .await
     }
 
+    /// Returns a FAILED job whose retry is due
+    async fn get_next_retry_job(&self) -> Option<usize> {
+        let timestamp = TimeStamp::now();
+        self.app
+            .storage()
+            .jobs_get_next_job(JobStatus::Failed, None, &self.skip_actions, Some(timestamp))
+            .await
+    }
+
     //TODO test
     async fn get_next_scheduled_job(&self) -> Option<usize> {
         let timestamp = TimeStamp::now();
@@ -584,6 +881,44 @@ mod tests {
         assert_eq!(job.get_action().await.unwrap(), "automatch_by_search");
     }
 
+    #[test]
+    fn test_job_error_category_from_error() {
+        let e = anyhow!(JobError::TimeError);
+        assert_eq!(JobErrorCategory::from_error(&e), JobErrorCategory::Job);
+        let e = anyhow::Error::from("x".parse::<usize>().unwrap_err()).context("parsing");
+        assert_eq!(JobErrorCategory::from_error(&e), JobErrorCategory::Parse);
+        let e = anyhow!("something else");
+        assert_eq!(JobErrorCategory::from_error(&e), JobErrorCategory::Other);
+        let e = anyhow!(StorageError::Constraint("Duplicate entry".to_string()));
+        assert_eq!(JobErrorCategory::from_error(&e), JobErrorCategory::Database);
+        let e = anyhow!(StorageError::NotFound("No entry #123".to_string()));
+        assert_eq!(JobErrorCategory::from_error(&e), JobErrorCategory::Other);
+    }
+
+    #[test]
+    fn test_retry_delay_sec() {
+        assert_eq!(Job::retry_delay_sec(1, 300, 86400), 300);
+        assert_eq!(Job::retry_delay_sec(2, 300, 86400), 600);
+        assert_eq!(Job::retry_delay_sec(4, 300, 86400), 2400);
+        assert_eq!(Job::retry_delay_sec(10, 300, 86400), 86400);
+        assert_eq!(Job::retry_delay_sec(1000, 300, 86400), 86400);
+        assert_eq!(Job::retry_delay_sec(0, 300, 86400), 300);
+    }
+
+    #[test]
+    fn test_job_error_inactive_catalog() {
+        let e = anyhow!(JobError::InactiveCatalog(123));
+        assert_eq!(e.to_string(), "Catalog 123 is inactive");
+        assert_eq!(JobErrorCategory::from_error(&e), JobErrorCategory::Job);
+    }
+
+    #[test]
+    fn test_job_error_running() {
+        let e = anyhow!(JobError::Running(42));
+        assert_eq!(e.to_string(), "Job 42 is RUNNING");
+        assert_eq!(JobErrorCategory::from_error(&e), JobErrorCategory::Job);
+    }
+
     #[tokio::test]
     async fn test_get_next_ts() {
         let app = get_test_app();
@@ -13,6 +13,7 @@ type JobRowMySql = (
     Option<usize>,
     String,
     usize,
+    usize,
 );
 
 #[derive(Debug, Clone, Default)]
@@ -28,6 +29,8 @@ pub struct JobRow {
     pub repeat_after_sec: Option<usize>,
     pub next_ts: String,
     pub user_id: usize,
+    /// Consecutive failures of the job, reset when it succeeds
+    pub failures: usize,
 }
 
 impl JobRow {
@@ -44,6 +47,7 @@ impl JobRow {
             repeat_after_sec: x.8,
             next_ts: x.9,
             user_id: x.10,
+            failures: x.11,
         }
     }
 
@@ -60,6 +64,12 @@ impl JobRow {
             repeat_after_sec: None,
             next_ts: "".to_string(),
             user_id: 0,
+            failures: 0,
         }
     }
+
+    /// The number of the current attempt to run the job: 1 for the first run, 2 for the first retry, etc.
+    pub const fn attempt(&self) -> usize {
+        self.failures + 1
+    }
 }
@@ -40,8 +40,10 @@
 
 // pub mod api;
 pub mod app_state;
+pub mod atom;
 pub mod automatch;
 pub mod autoscrape;
+pub mod autoscrape_config;
 pub mod autoscrape_levels;
 pub mod autoscrape_resolve;
 pub mod autoscrape_scraper;
@@ -49,8 +51,11 @@ pub mod auxiliary_matcher;
 pub mod bespoke_scrapers;
 pub mod catalog;
 pub mod coordinate_matcher;
+pub mod creation_candidates;
 pub mod datasource;
 pub mod entry;
+pub mod entry_query;
+pub mod export;
 pub mod extended_entry;
 pub mod issue;
 pub mod job;
@@ -60,14 +65,23 @@ pub mod maintenance;
 pub mod match_state;
 pub mod microsync;
 pub mod mysql_misc;
+pub mod name_normalize;
+pub mod name_rewrite;
+pub mod overview_rebuild;
+pub mod permissions;
 pub mod person;
 pub mod php_wrapper;
+pub mod quick_compare;
+pub mod reference_fixer;
+pub mod statement_text;
 pub mod storage;
 pub mod storage_mysql;
 pub mod task_size;
 // pub mod storage_wikibase;
 pub mod taxon_matcher;
 pub mod update_catalog;
+pub mod url_normalize;
+pub mod user;
 pub mod wdrc;
 pub mod wikidata;
 pub mod wikidata_commands;
@@ -131,6 +145,244 @@ async fn run() -> Result<()> {
             .await
         }
         Some("hpjob") => app.run_single_hp_job().await,
+        Some("dead_letter") => {
+            for job_id in job::Job::get_dead_letter_jobs(&app).await? {
+                println!("{job_id}");
+            }
+            Ok(())
+        }
+        Some("job_error") => {
+            let job_id = argv
+                .get(3)
+                .expect("Job ID as third parameter required")
+                .parse::<usize>()?;
+            match app.storage().jobs_get_error(job_id).await? {
+                Some((category, details)) => println!("{category}\n{details}"),
+                None => println!("Job {job_id} has no stored error"),
+            }
+            Ok(())
+        }
+        Some("import-preview") => {
+            let catalog_id = argv
+                .get(3)
+                .expect("Catalog ID as third parameter required")
+                .parse::<usize>()?;
+            let max_rows = argv
+                .get(4)
+                .and_then(|s| s.parse::<usize>().ok())
+                .unwrap_or(10);
+            let update_info = app
+                .storage()
+                .update_catalog_get_update_info(catalog_id)
+                .await?
+                .pop()
+                .ok_or(update_catalog::UpdateCatalogError::NoUpdateInfoForCatalog)?;
+            let preview = datasource::preview(&app, &update_info, max_rows).await?;
+            println!("Headers: {}", preview.headers.join(" | "));
+            for row in &preview.rows {
+                let entry = &row.entry;
+                println!(
+                    "{}\t{}\t{}\t{}",
+                    entry.ext_id,
+                    entry.ext_name,
+                    entry.ext_url,
+                    entry.type_name.to_owned().unwrap_or_default()
+                );
+            }
+            for error in &preview.errors {
+                println!("ERROR: {error}");
+            }
+            Ok(())
+        }
+        Some("export") => {
+            let catalog_id = argv
+                .get(3)
+                .expect("Catalog ID as third parameter required")
+                .parse::<usize>()?;
+            let option = |name: &str| {
+                argv.iter()
+                    .position(|arg| arg == name)
+                    .and_then(|pos| argv.get(pos + 1))
+            };
+            let format = match option("--format") {
+                Some(format) => format.parse()?,
+                None => export::ExportFormat::Tab,
+            };
+            let columns = match option("--columns") {
+                Some(columns) => export::ExportColumns::from_names(columns)?,
+                None => export::ExportColumns::ALL,
+            };
+            let mut out = std::io::stdout().lock();
+            export::write_catalog_export(&app, catalog_id, format, columns, &mut out).await
+        }
+        Some("worklist") => {
+            let catalog_id = argv
+                .get(3)
+                .expect("Catalog ID as third parameter required")
+                .parse::<usize>()?;
+            let with_dates = argv.iter().any(|arg| arg == "--with-dates");
+            let with_aux = argv.iter().any(|arg| arg == "--with-aux");
+            let mut out = std::io::stdout().lock();
+            export::write_worklist(&app, catalog_id, with_dates, with_aux, &mut out).await
+        }
+        Some("revert") => {
+            let catalog_id = argv
+                .get(3)
+                .expect("Catalog ID as third parameter required")
+                .parse::<usize>()?;
+            let user_id = argv
+                .get(4)
+                .expect("User ID as fourth parameter required")
+                .parse::<usize>()?;
+            let operator_user_id = argv
+                .get(5)
+                .expect("Operator user ID as fifth parameter required")
+                .parse::<usize>()?;
+            if !argv.iter().any(|arg| arg == "--confirm") {
+                println!("This will remove all matches by user {user_id} in catalog {catalog_id}; add --confirm to proceed");
+                return Ok(());
+            }
+            let unmatched = app
+                .storage()
+                .unmatch_by_user_in_catalog(catalog_id, user_id, operator_user_id)
+                .await?;
+            println!("{unmatched} entries unmatched");
+            Ok(())
+        }
+        Some("provenance") => {
+            let entry_id = argv
+                .get(3)
+                .expect("Entry ID as third parameter required")
+                .parse::<usize>()?;
+            let provenance = app.storage().get_entry_provenance(entry_id).await?;
+            println!("{}", serde_json::to_string_pretty(&provenance)?);
+            Ok(())
+        }
+        Some("no_automatch") => {
+            let entry_id = argv
+                .get(3)
+                .expect("Entry ID as third parameter required")
+                .parse::<usize>()?;
+            let entry = entry::Entry::from_id(entry_id, &app).await?;
+            match argv.get(4).map(|s| s.as_str()) {
+                Some("on") => entry.set_no_automatch(true).await?,
+                Some("off") => entry.set_no_automatch(false).await?,
+                Some(other) => panic!("Expected on or off, not '{other}'"),
+                None => {}
+            }
+            println!(
+                "Entry {entry_id}: no_automatch={}",
+                entry.is_no_automatch().await?
+            );
+            Ok(())
+        }
+        Some("status") => {
+            let summary = app.status_summary().await?;
+            println!("{}", serde_json::to_string_pretty(&summary)?);
+            Ok(())
+        }
+        Some("maintenance") => {
+            let routine = argv.get(3).expect(
+                "Maintenance routine as third parameter required: automatch | name_and_full_dates",
+            );
+            let limit = argv
+                .iter()
+                .position(|arg| arg == "--limit")
+                .and_then(|pos| argv.get(pos + 1))
+                .map(|limit| limit.parse::<usize>())
+                .transpose()?;
+            let maintenance = maintenance::Maintenance::new(&app);
+            let matched = match routine.as_str() {
+                "automatch" => {
+                    maintenance
+                        .automatch(
+                            limit.unwrap_or(maintenance::DEFAULT_MAINTENANCE_AUTOMATCH_LIMIT),
+                        )
+                        .await?
+                }
+                "name_and_full_dates" => {
+                    maintenance
+                        .match_by_name_and_full_dates(
+                            limit.unwrap_or(maintenance::DEFAULT_NAME_AND_FULL_DATES_BATCH_SIZE),
+                        )
+                        .await?
+                }
+                other => panic!("Unknown maintenance routine '{other}'"),
+            };
+            println!("{matched} entries matched");
+            Ok(())
+        }
+        Some("rc-atom") => {
+            let catalog_id = argv.get(3).map(|s| s.parse::<usize>()).transpose()?;
+            let feed = atom::AtomFeed::load(&app, catalog_id).await?;
+            println!("{}", feed.render()?);
+            Ok(())
+        }
+        Some("scrape-test") => {
+            let option = |name: &str| {
+                argv.iter()
+                    .position(|arg| arg == name)
+                    .and_then(|pos| argv.get(pos + 1))
+            };
+            let catalog_id = option("--catalog")
+                .expect("--catalog ID required")
+                .parse::<usize>()?;
+            let max_urls = option("--max-urls")
+                .map(|s| s.parse::<usize>())
+                .transpose()?
+                .unwrap_or(1);
+            let as_json = argv.iter().any(|arg| arg == "--json");
+            let mut autoscrape = autoscrape::Autoscrape::new(catalog_id, &app).await?;
+            let pages = autoscrape.scrape_test(max_urls).await;
+            if as_json {
+                let pages: Vec<serde_json::Value> =
+                    pages.iter().map(|page| page.to_json()).collect();
+                println!("{}", serde_json::to_string_pretty(&pages)?);
+            } else {
+                for page in &pages {
+                    println!("{} ({} entries)", page.url, page.entries.len());
+                    for ee in &page.entries {
+                        let entry = &ee.entry;
+                        println!(
+                            "\t{}\t{}\t{}\t{}",
+                            entry.ext_id, entry.ext_name, entry.ext_desc, entry.ext_url
+                        );
+                    }
+                }
+            }
+            for line in autoscrape.log() {
+                println!("NOTE: {line}");
+            }
+            Ok(())
+        }
+        Some("requeue_dead_letter") => {
+            let job_id = argv
+                .get(3)
+                .expect("Job ID as third parameter required")
+                .parse::<usize>()?;
+            job::Job::requeue_dead_letter_job(&app, job_id).await
+        }
+        Some("schedule_refresh") => {
+            let catalog_id = argv
+                .get(3)
+                .expect("Catalog ID as third parameter required")
+                .parse::<usize>()?;
+            let repeat_after_sec = argv
+                .get(4)
+                .expect("Interval in seconds as fourth parameter required")
+                .parse::<usize>()?;
+            update_catalog::UpdateCatalog::new(&app)
+                .schedule_refresh_from_source(catalog_id, repeat_after_sec)
+                .await
+        }
+        Some("requeue") => {
+            let job_id = argv
+                .get(3)
+                .expect("Job ID as third parameter required")
+                .parse::<usize>()?;
+            let high_priority = argv.iter().any(|arg| arg == "--high");
+            job::Job::requeue(&app, job_id, high_priority).await
+        }
         // Some("from_props") => {
         //     let props: Vec<u32> = argv
         //         .get(3)
@@ -156,9 +408,10 @@ async fn run() -> Result<()> {
 
 #[tokio::main(flavor = "multi_thread", worker_threads = 3)]
 async fn main() -> Result<()> {
+    app_state::AppState::init_tracing();
     match run().await {
         Ok(_) => {}
-        Err(e) => println!("CATASTROPHIC FAILURE: {e}"),
+        Err(e) => tracing::error!("CATASTROPHIC FAILURE: {e}"),
     }
     Ok(())
 }
@@ -1,12 +1,94 @@
 use crate::app_state::{AppState, USER_AUX_MATCH, USER_DATE_MATCH};
+use crate::autoscrape::Autoscrape;
+use crate::autoscrape_resolve::AutoscrapeResolve;
 use crate::auxiliary_matcher::AuxiliaryMatcher;
 use crate::catalog::Catalog;
+use crate::datasource::KV_STRIP_HTML_DESCRIPTIONS;
 use crate::entry::Entry;
 use crate::match_state::MatchState;
+use crate::wikidata::PropertyStatus;
 use crate::PropTodo;
 use anyhow::{anyhow, Result};
 use futures::future::join_all;
+use lazy_static::lazy_static;
+use log::{info, warn};
+use regex::Regex;
 use std::collections::{HashMap, HashSet};
+use wikimisc::timestamp::TimeStamp;
+
+/// `kv` key for a comma-separated list of catalog IDs to exclude from site-wide candidate generation
+pub const KV_MAINTENANCE_EXCLUDED_CATALOGS: &str = "maintenance_excluded_catalogs";
+
+/// `kv_catalog` key set by `find_catalogs_with_deprecated_properties` for catalogs whose property
+/// was deprecated ("deprecated") or deleted ("missing"); removed once the property is fine again
+pub const KV_PROPERTY_STATUS: &str = "property_status";
+
+/// `kv` key for the last catalog that a catalog-less `microsync` job checked
+pub const KV_MICROSYNC_CATALOG_CURSOR: &str = "microsync_catalog_cursor";
+
+/// Default maximum number of entries `Maintenance::match_by_name_and_full_dates` matches per run
+pub const DEFAULT_NAME_AND_FULL_DATES_BATCH_SIZE: usize = 100;
+/// Default maximum number of entries `Maintenance::automatch` matches per run
+pub const DEFAULT_MAINTENANCE_AUTOMATCH_LIMIT: usize = 500;
+/// Consecutive runs without entries after which an autoscraper is considered broken
+pub const DEFAULT_AUTOSCRAPE_STALE_RUNS: usize = 3;
+/// `autoscrape.status` of scrapers flagged by `flag_stale_autoscrapers`
+pub const AUTOSCRAPE_STATUS_BROKEN: &str = "BROKEN";
+
+/// Items per Wikidata lookup in `sync_matched_items`
+const MATCHED_ITEMS_BATCH_SIZE: usize = 5000;
+
+/// Pause between SPARQL queries when refreshing `props_todo` counts
+const PROPS_TODO_SPARQL_DELAY_MS: u64 = 500;
+
+/// Windows-1252 characters in the 0x80-0x9F range, which `utf8_encode`-style double encoding produces,
+/// alongside the byte they stand for
+const CP1252_HIGH_CHARS: &[(char, u8)] = &[
+    ('€', 0x80),
+    ('‚', 0x82),
+    ('ƒ', 0x83),
+    ('„', 0x84),
+    ('…', 0x85),
+    ('†', 0x86),
+    ('‡', 0x87),
+    ('ˆ', 0x88),
+    ('‰', 0x89),
+    ('Š', 0x8A),
+    ('‹', 0x8B),
+    ('Œ', 0x8C),
+    ('Ž', 0x8E),
+    ('‘', 0x91),
+    ('’', 0x92),
+    ('“', 0x93),
+    ('”', 0x94),
+    ('•', 0x95),
+    ('–', 0x96),
+    ('—', 0x97),
+    ('˜', 0x98),
+    ('™', 0x99),
+    ('š', 0x9A),
+    ('›', 0x9B),
+    ('œ', 0x9C),
+    ('ž', 0x9E),
+    ('Ÿ', 0x9F),
+];
+
+lazy_static! {
+    /// A UTF-8 lead byte followed by a continuation byte, both decoded as Latin-1/Windows-1252, eg `Ã©`
+    static ref RE_MOJIBAKE: Regex = Regex::new(
+        r"[\x{C2}-\x{F4}][\x{80}-\x{BF}€‚ƒ„…†‡ˆ‰Š‹ŒŽ‘’“”•–—˜™š›œžŸ]"
+    )
+    .expect("Regexp construction");
+}
+
+/// Result of a mojibake scan of a catalog, see `Maintenance::fix_mojibake`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MojibakeReport {
+    pub entries_checked: usize,
+    pub names_affected: usize,
+    pub descriptions_affected: usize,
+    pub applied: bool,
+}
 
 pub struct Maintenance {
     app: AppState,
@@ -17,6 +99,56 @@ impl Maintenance {
         Self { app: app.clone() }
     }
 
+    /// Returns the catalogs to exclude from site-wide candidate generation,
+    /// from both the config file and the `kv` table.
+    pub async fn get_excluded_catalogs(&self) -> Result<Vec<usize>> {
+        let mut ret = self.app.maintenance_excluded_catalogs().to_vec();
+        if let Some(s) = self
+            .app
+            .storage()
+            .get_kv_value(KV_MAINTENANCE_EXCLUDED_CATALOGS)
+            .await?
+        {
+            ret.append(&mut Self::parse_catalog_id_list(&s));
+        }
+        ret.sort();
+        ret.dedup();
+        Ok(ret)
+    }
+
+    /// Returns the next active catalog with a property, in ID order, after the one stored in `kv` under `cursor_key`,
+    /// and stores it as the new cursor. Wraps around after the last catalog,
+    /// so successive runs cover all catalogs, rather than the same random ones over and over.
+    pub async fn next_catalog_from_cursor(&self, cursor_key: &str) -> Result<Option<usize>> {
+        let after_catalog_id = self
+            .app
+            .storage()
+            .get_kv_value(cursor_key)
+            .await?
+            .and_then(|s| s.trim().parse::<usize>().ok())
+            .unwrap_or(0);
+        let catalog_id = self
+            .app
+            .storage()
+            .get_next_active_catalog_id_with_property(after_catalog_id)
+            .await?;
+        if let Some(catalog_id) = catalog_id {
+            self.app
+                .storage()
+                .set_kv_value(cursor_key, &catalog_id.to_string())
+                .await?;
+        }
+        Ok(catalog_id)
+    }
+
+    /// Parses a comma- or whitespace-separated list of catalog IDs, ignoring invalid ones
+    pub fn parse_catalog_id_list(s: &str) -> Vec<usize> {
+        s.split(|c: char| c == ',' || c.is_whitespace())
+            .filter_map(|id| id.trim().parse::<usize>().ok())
+            .filter(|id| *id > 0)
+            .collect()
+    }
+
     /// Iterates over blocks of (fully or partially) matched Wikidata items, and replaces redirects with their targets.
     pub async fn fix_redirects(&self, catalog_id: usize, state: &MatchState) -> Result<()> {
         let mut offset = 0;
@@ -36,23 +168,30 @@ impl Maintenance {
 
     /// For unmatched entries with day-precision birth and death dates,
     /// finds other, matched entries with the same name and full dates,
-    /// then matches them.
-    pub async fn match_by_name_and_full_dates(&self) -> Result<()> {
-        const BATCH_SIZE: usize = 100;
+    /// then matches them. Looks at up to `batch_size` candidates; returns the number of entries matched.
+    pub async fn match_by_name_and_full_dates(&self, batch_size: usize) -> Result<usize> {
+        let excluded_catalogs = self.get_excluded_catalogs().await?;
         let mut results = self
             .app
             .storage()
-            .maintenance_match_people_via_name_and_full_dates(BATCH_SIZE)
+            .maintenance_match_people_via_name_and_full_dates(batch_size, &excluded_catalogs)
             .await?;
         results.sort();
         results.dedup();
+        let mut matched = 0;
         for (entry_id, q) in results {
-            if let Ok(mut entry) = Entry::from_id(entry_id, &self.app).await {
+            if let Some(mut entry) = Entry::from_id_if_exists(entry_id, &self.app).await? {
                 // Ignore error
-                let _ = entry.set_match(&format!("Q{q}"), USER_DATE_MATCH).await;
+                if entry
+                    .set_match(&format!("Q{q}"), USER_DATE_MATCH)
+                    .await
+                    .is_ok()
+                {
+                    matched += 1;
+                }
             };
         }
-        Ok(())
+        Ok(matched)
     }
 
     pub async fn create_match_person_dates_jobs_for_catalogs(&self) -> Result<()> {
@@ -67,86 +206,72 @@ impl Maintenance {
         // We don't really care if one of these fails occasionally
         let _ = self.update_props_todo_add_new_properties().await;
         let _ = self.update_props_todo_update_items_using().await;
+        let _ = self
+            .app
+            .storage()
+            .set_props_todo_number_of_records_floor()
+            .await;
         let _ = self.app.storage().mark_props_todo_as_has_catalog().await;
         // TODO add default_type?
         Ok(())
     }
 
-    async fn update_props_todo_update_items_using_get_props_todo(&self) -> Result<HashSet<u64>> {
-        let props_todo: HashSet<u64> = self
+    /// Properties in `props_todo` that still have no catalog, the ones without any count first
+    async fn update_props_todo_update_items_using_get_props_todo(&self) -> Result<Vec<u64>> {
+        let mut props_todo: Vec<PropTodo> = self
             .app
             .storage()
             .get_props_todo()
             .await?
             .into_iter()
-            .filter(|p| p.items_using.is_none()) // Only those without number; comment out to update everything
-            .map(|p| p.prop_num)
+            .filter(|p| p.status == "NO_CATALOG")
             .collect();
-        Ok(props_todo)
-    }
-
-    async fn update_props_todo_update_items_using_get_bindings(
-        &self,
-    ) -> Result<Vec<serde_json::Value>> {
-        let mw_api = self.app.wikidata().get_mw_api().await?;
-        let sparql = r#"select ?p (count(?q) AS ?cnt) { ?q ?p [] } group by ?p"#;
-        let results = match mw_api.sparql_query(sparql).await {
-            Ok(results) => results,
-            Err(_) => return Ok(vec![]),
-        };
-        let bindings = match results["results"]["bindings"].as_array() {
-            Some(bindings) => bindings,
-            None => return Ok(vec![]),
-        };
-        Ok(bindings.to_owned())
+        props_todo.sort_by_key(|p| p.items_using.is_some());
+        Ok(props_todo.into_iter().map(|p| p.prop_num).collect())
     }
 
+    /// Refreshes the `items_using` count for each `props_todo` property, one SPARQL COUNT per property
     async fn update_props_todo_update_items_using(&self) -> Result<()> {
         let props_todo = self
             .update_props_todo_update_items_using_get_props_todo()
             .await?;
-        let bindings = self
-            .update_props_todo_update_items_using_get_bindings()
-            .await?;
-        for b in bindings {
-            self.update_props_todo_update_items_using_process_binding(b, &props_todo)
-                .await;
+        let mw_api = self.app.wikidata().get_mw_api().await?;
+        for prop_num in props_todo {
+            if let Some(cnt) = self
+                .update_props_todo_update_items_using_get_count(&mw_api, prop_num)
+                .await
+            {
+                let _ = self
+                    .app
+                    .storage()
+                    .set_props_todo_items_using(prop_num, cnt)
+                    .await;
+            }
+            // Don't hammer the SPARQL service
+            tokio::time::sleep(tokio::time::Duration::from_millis(
+                PROPS_TODO_SPARQL_DELAY_MS,
+            ))
+            .await;
         }
         Ok(())
     }
 
-    async fn update_props_todo_update_items_using_process_binding(
+    async fn update_props_todo_update_items_using_get_count(
         &self,
-        b: serde_json::Value,
-        props_todo: &HashSet<u64>,
-    ) {
-        let (prop_url, cnt) = match (b["p"]["value"].as_str(), b["cnt"]["value"].as_str()) {
-            (Some(prop_url), Some(cnt)) => (prop_url, cnt),
-            _ => return,
-        };
-        let (url, prop) = match prop_url.rsplit_once('/') {
-            Some((url, prop)) => (url, prop),
-            _ => return,
-        };
-        if prop.chars().nth(0) != Some('P') || url != "http://www.wikidata.org/prop/direct" {
-            return;
-        }
-        let prop_num = match prop[1..].parse::<u64>() {
-            Ok(prop_num) => prop_num,
-            Err(_) => return,
-        };
-        if !props_todo.contains(&prop_num) {
-            return;
-        }
-        let cnt = match cnt.parse::<u64>() {
-            Ok(cnt) => cnt,
-            Err(_) => return,
-        };
-        let _ = self
-            .app
-            .storage()
-            .set_props_todo_items_using(prop_num, cnt)
-            .await;
+        mw_api: &mediawiki::Api,
+        prop_num: u64,
+    ) -> Option<u64> {
+        let sparql = format!("SELECT (count(?q) AS ?cnt) {{ ?q wdt:P{prop_num} [] }}");
+        let results = mw_api.sparql_query(&sparql).await.ok()?;
+        Self::parse_sparql_count(&results)
+    }
+
+    /// Parses the `?cnt` value of the first binding in a SPARQL JSON result
+    fn parse_sparql_count(results: &serde_json::Value) -> Option<u64> {
+        results["results"]["bindings"].as_array()?.first()?["cnt"]["value"]
+            .as_str()?
+            .parse::<u64>()
+            .ok()
     }
 
     async fn update_props_todo_add_new_properties_get_bindings(
@@ -170,12 +295,15 @@ impl Maintenance {
         Ok(bindings.to_owned())
     }
 
+    /// Adds Wikidata authority control properties that are neither in `props_todo`
+    /// nor used by an active catalog. Their `items_using` is filled in by `update_props_todo_update_items_using`.
     async fn update_props_todo_add_new_properties(&self) -> Result<()> {
         let (properties, prop_names) = self
             .update_props_todo_add_new_properties_get_props()
             .await?;
         let extisting_props = self.app.storage().get_props_todo().await?;
-        let existing_hash: HashSet<u64> = extisting_props.iter().map(|p| p.prop_num).collect();
+        let mut existing_hash: HashSet<u64> = extisting_props.iter().map(|p| p.prop_num).collect();
+        existing_hash.extend(self.app.storage().get_active_catalog_properties().await?);
         let new_props: Vec<PropTodo> = properties
             .iter()
             .filter(|prop_num| !existing_hash.contains(prop_num))
@@ -187,6 +315,7 @@ impl Maintenance {
                 PropTodo::new(*prop_num, name)
             })
             .collect();
+        info!("props_todo: adding {} new properties", new_props.len());
         self.app.storage().add_props_todo(new_props).await?;
         Ok(())
     }
@@ -217,9 +346,10 @@ impl Maintenance {
     }
 
     pub async fn automatch_people_via_year_born(&self) -> Result<()> {
+        let excluded_catalogs = self.get_excluded_catalogs().await?;
         self.app
             .storage()
-            .maintenance_automatch_people_via_year_born()
+            .maintenance_automatch_people_via_year_born(&excluded_catalogs)
             .await
     }
 
@@ -318,6 +448,8 @@ impl Maintenance {
         &self,
         catalog_id: usize,
     ) -> Result<HashMap<String, usize>> {
+        let mut am = AuxiliaryMatcher::new(&self.app);
+        am.load_blacklists().await?;
         let inventory_number2entry_id: HashMap<String, usize> = self
             .app
             .storage()
@@ -326,7 +458,7 @@ impl Maintenance {
                 0,
                 usize::MAX,
                 &["217".to_string()],
-                &AuxiliaryMatcher::get_blacklisted_catalogs(),
+                &am.get_blacklisted_catalogs(),
             )
             .await?
             .iter()
@@ -389,6 +521,223 @@ impl Maintenance {
         }
     }
 
+    /// Checks all items that entries are matched to by users, optionally in one catalog, against Wikidata.
+    /// Matches to redirects are changed to their targets, and matches to deleted items are removed.
+    /// Returns the number of (redirected, deleted) items.
+    pub async fn sync_matched_items(&self, catalog_id: Option<usize>) -> Result<(usize, usize)> {
+        let (mut redirected, mut deleted) = (0, 0);
+        let mut after_q = 0;
+        loop {
+            let batch = self
+                .app
+                .storage()
+                .get_distinct_matched_items(catalog_id, after_q, MATCHED_ITEMS_BATCH_SIZE)
+                .await?;
+            after_q = match batch.last() {
+                Some(q) => *q,
+                None => break,
+            };
+            let unique_qs: Vec<String> = batch.iter().map(|q| format!("Q{q}")).collect();
+            let redirects: HashMap<isize, isize> = self
+                .app
+                .wikidata()
+                .get_redirected_items(&unique_qs)
+                .await?
+                .iter()
+                .filter_map(|(from, to)| {
+                    Some((AppState::item2numeric(from)?, AppState::item2numeric(to)?))
+                })
+                .filter(|(from, to)| *from > 0 && *to > 0 && from != to)
+                .collect();
+            let redirected_items: HashSet<isize> = redirects.keys().copied().collect();
+            redirected += redirects.len();
+            self.app
+                .storage()
+                .maintenance_sync_redirects(redirects)
+                .await?;
+
+            // Redirect pages exist, so they are not reported as deleted
+            let deletions: Vec<isize> = self
+                .app
+                .wikidata()
+                .get_deleted_items(&unique_qs)
+                .await?
+                .iter()
+                .filter_map(|q| AppState::item2numeric(q))
+                .filter(|q| *q > 0 && !redirected_items.contains(q))
+                .collect();
+            if !deletions.is_empty() {
+                deleted += deletions.len();
+                let catalog_ids = self
+                    .app
+                    .storage()
+                    .maintenance_apply_deletions(deletions)
+                    .await?;
+                for catalog_id in catalog_ids {
+                    let catalog = Catalog::from_id(catalog_id, &self.app).await?;
+                    let _ = catalog.refresh_overview_table().await;
+                }
+            }
+            if batch.len() < MATCHED_ITEMS_BATCH_SIZE {
+                break;
+            }
+        }
+        Ok((redirected, deleted))
+    }
+
+    /// Returns the ext_ids that several entries of a catalog share, eg after a bad import
+    pub async fn find_duplicate_ext_ids(&self, catalog_id: usize) -> Result<Vec<String>> {
+        self.app
+            .storage()
+            .maintenance_get_duplicate_ext_ids(catalog_id)
+            .await
+    }
+
+    /// Resolves duplicate ext_ids in a catalog, see `dedupe_entries`. Returns the number of deleted entries.
+    pub async fn dedupe_ext_ids(&self, catalog_id: usize) -> Result<usize> {
+        let mut deleted = 0;
+        for ext_id in self.find_duplicate_ext_ids(catalog_id).await? {
+            let entry_ids = self
+                .app
+                .storage()
+                .get_entry_ids_by_ext_id(&[catalog_id], &ext_id)
+                .await?;
+            let entries: Vec<Entry> = Entry::multiple_from_ids(&entry_ids, &self.app)
+                .await?
+                .into_values()
+                .collect();
+            deleted += self.dedupe_entries(entries).await?;
+        }
+        if deleted > 0 {
+            let catalog = Catalog::from_id(catalog_id, &self.app).await?;
+            catalog.refresh_overview_table().await?;
+        }
+        Ok(deleted)
+    }
+
+    /// Keeps one of the entries with the same ext_id, and deletes the others after merging their
+    /// auxiliary values, aliases, descriptions, dates, and location into it, where it has none.
+    /// Returns the number of deleted entries.
+    async fn dedupe_entries(&self, entries: Vec<Entry>) -> Result<usize> {
+        let keep_id = match Self::entry_to_keep(&entries) {
+            Some(entry_id) => entry_id,
+            None => return Ok(0), // Conflicting manual matches; leave them for a human
+        };
+        let (keep, duplicates): (Vec<Entry>, Vec<Entry>) =
+            entries.into_iter().partition(|entry| entry.id == keep_id);
+        let keep = match keep.first() {
+            Some(entry) => entry,
+            None => return Ok(0),
+        };
+        let mut deleted = 0;
+        for mut duplicate in duplicates {
+            Self::merge_entry_data(&duplicate, keep).await?;
+            duplicate.delete().await?;
+            deleted += 1;
+        }
+        Ok(deleted)
+    }
+
+    /// The ID of the entry to keep out of a group of duplicates: a manually matched one, else an automatically matched one,
+    /// else the lowest ID. Returns `None` if there are manual matches to different items.
+    fn entry_to_keep(entries: &[Entry]) -> Option<usize> {
+        let manual_matches: HashSet<Option<isize>> = entries
+            .iter()
+            .filter(|entry| entry.is_fully_matched())
+            .map(|entry| entry.q)
+            .collect();
+        if manual_matches.len() > 1 {
+            return None;
+        }
+        entries
+            .iter()
+            .min_by_key(|entry| {
+                let rank = if entry.is_fully_matched() {
+                    0
+                } else if entry.is_partially_matched() {
+                    1
+                } else {
+                    2
+                };
+                (rank, entry.id)
+            })
+            .map(|entry| entry.id)
+    }
+
+    async fn merge_entry_data(from: &Entry, into: &Entry) -> Result<()> {
+        let existing_props: HashSet<usize> = into
+            .get_aux()
+            .await?
+            .iter()
+            .map(|row| row.prop_numeric)
+            .collect();
+        for row in from.get_aux().await? {
+            if !existing_props.contains(&row.prop_numeric) {
+                into.set_auxiliary(row.prop_numeric, Some(row.value))
+                    .await?;
+            }
+        }
+        let existing_aliases = into.get_aliases().await?;
+        for alias in from.get_aliases().await? {
+            let exists = existing_aliases
+                .iter()
+                .any(|a| a.language() == alias.language() && a.value() == alias.value());
+            if !exists {
+                into.add_alias(&alias).await?;
+            }
+        }
+        let existing_descriptions = into.get_language_descriptions().await?;
+        for (language, text) in from.get_language_descriptions().await? {
+            if !existing_descriptions.contains_key(&language) {
+                into.set_language_description(&language, Some(text)).await?;
+            }
+        }
+        if into.get_person_dates().await? == (None, None) {
+            let (born, died) = from.get_person_dates().await?;
+            if born.is_some() || died.is_some() {
+                into.set_person_dates(&born, &died).await?;
+            }
+        }
+        if into.get_coordinate_location().await?.is_none() {
+            let location = from.get_coordinate_location().await?;
+            if location.is_some() {
+                into.set_coordinate_location(&location).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the items matched in a catalog that `fix_matched_items` would unlink,
+    /// because they were deleted or are meta items (disambig etc). Changes nothing.
+    pub async fn get_items_to_unlink(
+        &self,
+        catalog_id: usize,
+        state: &MatchState,
+    ) -> Result<Vec<String>> {
+        let mut ret = vec![];
+        let mut offset = 0;
+        loop {
+            let unique_qs = self
+                .app
+                .storage()
+                .get_items(catalog_id, offset, state)
+                .await?;
+            if unique_qs.is_empty() {
+                break;
+            }
+            offset += unique_qs.len();
+            if let Ok(deleted) = self.app.wikidata().get_deleted_items(&unique_qs).await {
+                ret.extend(deleted);
+            }
+            if let Ok(meta_items) = self.app.wikidata().get_meta_items(&unique_qs).await {
+                ret.extend(meta_items);
+            }
+        }
+        ret.sort();
+        ret.dedup();
+        Ok(ret)
+    }
+
     /// Removes P17 auxiliary values for entryies of type Q5 (human)
     pub async fn remove_p17_for_humans(&self) -> Result<()> {
         self.app.storage().remove_p17_for_humans().await
@@ -447,10 +796,268 @@ impl Maintenance {
         Ok(())
     }
 
+    /// Backfills missing `random` values on entries, in batches,
+    /// so random-order queries can use the index. Returns the number of entries changed.
+    pub async fn fill_missing_random(&self) -> Result<usize> {
+        const BATCH_SIZE: usize = 10000;
+        let mut total = 0;
+        loop {
+            let filled = self
+                .app
+                .storage()
+                .maintenance_fill_missing_random(BATCH_SIZE)
+                .await?;
+            total += filled;
+            if filled < BATCH_SIZE {
+                break;
+            }
+        }
+        info!("fill_missing_random: {total} entries got a random value");
+        Ok(total)
+    }
+
+    /// Checks the property of every active catalog against Wikidata, and returns
+    /// (catalog ID, numeric property, status) for catalogs whose property was deleted or deprecated.
+    /// These catalogs are flagged via `KV_PROPERTY_STATUS`; the flag is removed from the others.
+    /// Each property is only looked up once, as many catalogs share a property.
+    pub async fn find_catalogs_with_deprecated_properties(
+        &self,
+    ) -> Result<Vec<(usize, usize, PropertyStatus)>> {
+        let catalog_props = self
+            .app
+            .storage()
+            .get_active_catalogs_with_property()
+            .await?;
+        let mut props: Vec<usize> = catalog_props
+            .iter()
+            .map(|(_catalog_id, prop)| *prop)
+            .collect();
+        props.sort();
+        props.dedup();
+        let mut prop_status = HashMap::new();
+        for chunk in props.chunks(500) {
+            prop_status.extend(self.app.wikidata().get_property_status(chunk).await?);
+        }
+        let mut ret = vec![];
+        for (catalog_id, prop) in catalog_props {
+            let value = match prop_status.get(&prop) {
+                Some(PropertyStatus::Ok) | None => {
+                    Catalog::delete_key_value_pair(catalog_id, KV_PROPERTY_STATUS, &self.app)
+                        .await?;
+                    continue;
+                }
+                Some(PropertyStatus::Deprecated) => "deprecated",
+                Some(PropertyStatus::Missing) => "missing",
+            };
+            info!("Catalog {catalog_id} uses property P{prop}, which is {value}");
+            Catalog::set_key_value_pair(catalog_id, KV_PROPERTY_STATUS, value, &self.app).await?;
+            ret.push((catalog_id, prop, prop_status[&prop]));
+        }
+        Ok(ret)
+    }
+
+    /// Scans `ext_name`/`ext_desc` of all entries in a catalog for double-encoded UTF-8 (eg `JosÃ©` for `José`).
+    /// Proposed fixes are logged; with `apply`, they are written to the entries as well.
+    pub async fn fix_mojibake(&self, catalog_id: usize, apply: bool) -> Result<MojibakeReport> {
+        const BATCH_SIZE: usize = 5000;
+        let mut report = MojibakeReport {
+            applied: apply,
+            ..Default::default()
+        };
+        let mut after_id = 0;
+        loop {
+            let entries = self
+                .app
+                .storage()
+                .get_entry_batch_after(catalog_id, after_id, BATCH_SIZE)
+                .await?;
+            for mut entry in entries.iter().cloned() {
+                report.entries_checked += 1;
+                entry.set_app(&self.app);
+                if let Some(ext_name) = Self::fix_mojibake_string(&entry.ext_name) {
+                    info!(
+                        "fix_mojibake: entry {} name '{}' => '{ext_name}'",
+                        entry.id, entry.ext_name
+                    );
+                    report.names_affected += 1;
+                    if apply {
+                        entry.set_ext_name(&ext_name).await?;
+                    }
+                }
+                if let Some(ext_desc) = Self::fix_mojibake_string(&entry.ext_desc) {
+                    info!(
+                        "fix_mojibake: entry {} description '{}' => '{ext_desc}'",
+                        entry.id, entry.ext_desc
+                    );
+                    report.descriptions_affected += 1;
+                    if apply {
+                        entry.set_ext_desc(&ext_desc).await?;
+                    }
+                }
+            }
+            if entries.len() < BATCH_SIZE {
+                break;
+            }
+            after_id = match entries.last() {
+                Some(entry) => entry.id,
+                None => break,
+            };
+        }
+        info!("fix_mojibake: catalog {catalog_id}: {report:?}");
+        Ok(report)
+    }
+
+    /// Strips HTML from `ext_desc` of all entries in a catalog, eg after a scraper or import left markup in there.
+    /// Only catalogs with `strip_html_descriptions` set are changed. Returns the number of entries changed.
+    pub async fn strip_html_from_descriptions(&self, catalog_id: usize) -> Result<usize> {
+        const BATCH_SIZE: usize = 5000;
+        let kv_pairs = Catalog::from_id(catalog_id, &self.app)
+            .await?
+            .get_key_value_pairs()
+            .await?;
+        if kv_pairs.get(KV_STRIP_HTML_DESCRIPTIONS).map(|v| v.trim()) != Some("1") {
+            info!("strip_html_from_descriptions: catalog {catalog_id} does not have {KV_STRIP_HTML_DESCRIPTIONS} set");
+            return Ok(0);
+        }
+        let mut changed = 0;
+        let mut after_id = 0;
+        loop {
+            let entries = self
+                .app
+                .storage()
+                .get_entry_batch_after(catalog_id, after_id, BATCH_SIZE)
+                .await?;
+            for mut entry in entries.iter().cloned() {
+                let ext_desc = AutoscrapeResolve::strip_html(&entry.ext_desc);
+                if ext_desc != entry.ext_desc {
+                    entry.set_app(&self.app);
+                    entry.set_ext_desc(&ext_desc).await?;
+                    changed += 1;
+                }
+            }
+            if entries.len() < BATCH_SIZE {
+                break;
+            }
+            after_id = match entries.last() {
+                Some(entry) => entry.id,
+                None => break,
+            };
+        }
+        info!("strip_html_from_descriptions: catalog {catalog_id}: {changed} entries changed");
+        Ok(changed)
+    }
+
+    /// Returns the repaired string, if it looks like (possibly repeatedly) double-encoded UTF-8.
+    /// Strings that can not be reversed cleanly are left alone.
+    pub fn fix_mojibake_string(s: &str) -> Option<String> {
+        let mut ret = s.to_string();
+        for _ in 0..3 {
+            if !RE_MOJIBAKE.is_match(&ret) {
+                break;
+            }
+            match Self::decode_mojibake_once(&ret) {
+                Some(fixed) => ret = fixed,
+                None => break,
+            }
+        }
+        if ret == s {
+            None
+        } else {
+            Some(ret)
+        }
+    }
+
+    fn decode_mojibake_once(s: &str) -> Option<String> {
+        let bytes = s
+            .chars()
+            .map(|c| match c as u32 {
+                code @ 0..=0xFF => Some(code as u8),
+                _ => CP1252_HIGH_CHARS
+                    .iter()
+                    .find(|(ch, _)| *ch == c)
+                    .map(|(_, byte)| *byte),
+            })
+            .collect::<Option<Vec<u8>>>()?;
+        String::from_utf8(bytes).ok()
+    }
+
+    /// Queues incremental automatch jobs for catalogs that got new entries since an automatcher last ran on them,
+    /// eg after an import. The jobs only look at entries beyond the ID the previous run has seen.
+    /// Catalogs with a queued or running job for the action are skipped. Returns the number of jobs queued.
+    pub async fn requeue_automatch_for_new_entries(&self) -> Result<usize> {
+        let excluded_catalogs = self.get_excluded_catalogs().await?;
+        let results = self
+            .app
+            .storage()
+            .automatch_get_catalogs_with_new_entries()
+            .await?;
+        let mut queued = 0;
+        for (catalog_id, action, max_entry_id) in results {
+            if excluded_catalogs.contains(&catalog_id) {
+                continue;
+            }
+            let json = serde_json::json!({"min_entry_id": max_entry_id});
+            self.app
+                .storage()
+                .jobs_queue_job_with_json(catalog_id, &action, json.to_string(), TimeStamp::now())
+                .await?;
+            queued += 1;
+        }
+        info!("requeue_automatch_for_new_entries: {queued} jobs queued");
+        Ok(queued)
+    }
+
+    /// Runs a test fetch for each autoscraper of an active catalog, and records the number of entries found as a test.
+    /// Scrapers that found nothing in the last `runs` real runs, nor in the test fetch, get the status `BROKEN`,
+    /// so their configuration can be looked at. Scrapers that can not be loaded are skipped, as that may be
+    /// a passing database problem. Returns the IDs of the flagged autoscrapers.
+    pub async fn flag_stale_autoscrapers(&self, runs: usize) -> Result<Vec<usize>> {
+        let mut flagged = vec![];
+        for (autoscrape_id, catalog_id) in self.app.storage().autoscrape_get_active().await? {
+            let entries_found = match Autoscrape::new(catalog_id, &self.app).await {
+                Ok(mut autoscrape) => autoscrape.test_fetch().await,
+                Err(e) => {
+                    warn!("flag_stale_autoscrapers: autoscraper {autoscrape_id} for catalog {catalog_id} could not be loaded: {e}");
+                    continue;
+                }
+            };
+            self.app
+                .storage()
+                .autoscrape_record_run_result(autoscrape_id, entries_found, true)
+                .await?;
+            if entries_found > 0 {
+                continue;
+            }
+            let results = self
+                .app
+                .storage()
+                .autoscrape_get_recent_run_results(autoscrape_id, runs)
+                .await?;
+            if Self::is_stale_autoscraper(&results, runs) {
+                info!("flag_stale_autoscrapers: autoscraper {autoscrape_id} for catalog {catalog_id} found no entries in {runs} runs");
+                self.app
+                    .storage()
+                    .autoscrape_set_status(autoscrape_id, AUTOSCRAPE_STATUS_BROKEN)
+                    .await?;
+                flagged.push(autoscrape_id);
+            }
+        }
+        Ok(flagged)
+    }
+
+    /// `true` if the last `runs` results (newest first) all found no entries
+    fn is_stale_autoscraper(results: &[usize], runs: usize) -> bool {
+        runs > 0 && results.len() >= runs && results.iter().take(runs).all(|found| *found == 0)
+    }
+
     /// Finds some unmatched (Q5) entries where there is a (unique) full match for that name,
-    /// and uses it as an auto-match
-    pub async fn automatch(&self) -> Result<()> {
-        self.app.storage().maintenance_automatch().await
+    /// and uses it as an auto-match. Matches up to `limit` entries; returns the number of entries matched.
+    pub async fn automatch(&self, limit: usize) -> Result<usize> {
+        let excluded_catalogs = self.get_excluded_catalogs().await?;
+        self.app
+            .storage()
+            .maintenance_automatch(&excluded_catalogs, limit)
+            .await
     }
 }
 
@@ -465,6 +1072,105 @@ mod tests {
     const TEST_CATALOG_ID: usize = 5526;
     const TEST_ENTRY_ID: usize = 143962196;
 
+    #[test]
+    fn test_entry_to_keep() {
+        let entry = |id: usize, q: Option<isize>, user: Option<usize>| Entry {
+            id,
+            q,
+            user,
+            ..Default::default()
+        };
+        // Lowest ID
+        assert_eq!(
+            Maintenance::entry_to_keep(&[entry(3, None, None), entry(2, None, None)]),
+            Some(2)
+        );
+        // Manual match beats automatic match beats unmatched
+        assert_eq!(
+            Maintenance::entry_to_keep(&[
+                entry(1, None, None),
+                entry(2, Some(42), Some(0)),
+                entry(3, Some(42), Some(2))
+            ]),
+            Some(3)
+        );
+        assert_eq!(
+            Maintenance::entry_to_keep(&[entry(1, None, None), entry(2, Some(42), Some(0))]),
+            Some(2)
+        );
+        // Conflicting manual matches
+        assert_eq!(
+            Maintenance::entry_to_keep(&[entry(1, Some(42), Some(2)), entry(2, Some(43), Some(3))]),
+            None
+        );
+        assert_eq!(Maintenance::entry_to_keep(&[]), None);
+    }
+
+    #[test]
+    fn test_is_stale_autoscraper() {
+        assert!(Maintenance::is_stale_autoscraper(&[0, 0, 0], 3));
+        assert!(!Maintenance::is_stale_autoscraper(&[0, 0, 12], 3));
+        assert!(!Maintenance::is_stale_autoscraper(&[0, 0], 3)); // Not enough runs yet
+        assert!(!Maintenance::is_stale_autoscraper(&[], 0));
+    }
+
+    #[test]
+    fn test_parse_catalog_id_list() {
+        assert_eq!(
+            Maintenance::parse_catalog_id_list("4837,5580, 6094"),
+            vec![4837, 5580, 6094]
+        );
+        assert_eq!(Maintenance::parse_catalog_id_list("819 foo 0"), vec![819]);
+        assert!(Maintenance::parse_catalog_id_list("").is_empty());
+    }
+
+    #[test]
+    fn test_fix_mojibake_string() {
+        assert_eq!(
+            Maintenance::fix_mojibake_string("JosÃ© GarcÃ\u{ad}a"),
+            Some("José García".to_string())
+        );
+        assert_eq!(
+            Maintenance::fix_mojibake_string("Ã¢â‚¬â„¢"), // Mojibake of mojibake of ’
+            Some("’".to_string())
+        );
+        assert_eq!(
+            Maintenance::fix_mojibake_string("KÃ¶ln â€“ Stadt"),
+            Some("Köln – Stadt".to_string())
+        );
+        assert_eq!(Maintenance::fix_mojibake_string("José García"), None);
+        assert_eq!(Maintenance::fix_mojibake_string("Ãx"), None);
+        assert_eq!(Maintenance::fix_mojibake_string("東京 Ã©"), None); // Can not be reversed
+        assert_eq!(Maintenance::fix_mojibake_string(""), None);
+    }
+
+    #[test]
+    fn test_parse_sparql_count() {
+        let results = serde_json::json!({"results":{"bindings":[{"cnt":{"type":"literal","value":"12345"}}]}});
+        assert_eq!(Maintenance::parse_sparql_count(&results), Some(12345));
+        let results = serde_json::json!({"results":{"bindings":[]}});
+        assert_eq!(Maintenance::parse_sparql_count(&results), None);
+    }
+
+    #[tokio::test]
+    async fn test_next_catalog_from_cursor() {
+        let app = get_test_app();
+        let maintenance = Maintenance::new(&app);
+        let cursor_key = "test_catalog_cursor";
+        app.storage()
+            .set_kv_value(cursor_key, &TEST_CATALOG_ID.to_string())
+            .await
+            .unwrap();
+        let next = maintenance
+            .next_catalog_from_cursor(cursor_key)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_ne!(next, TEST_CATALOG_ID);
+        let stored = app.storage().get_kv_value(cursor_key).await.unwrap();
+        assert_eq!(stored, Some(next.to_string()));
+    }
+
     #[tokio::test]
     async fn test_unlink_meta_items() {
         let _test_lock = TEST_MUTEX.lock();
@@ -1,9 +1,10 @@
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct MatchState {
     pub unmatched: bool,
     pub partially_matched: bool,
     pub fully_matched: bool,
-    // TODO N/A ?
+    pub na: bool,
+    pub not_on_wikidata: bool,
 }
 
 impl MatchState {
@@ -12,6 +13,8 @@ impl MatchState {
             unmatched: true,
             partially_matched: false,
             fully_matched: false,
+            na: false,
+            not_on_wikidata: false,
         }
     }
 
@@ -20,6 +23,8 @@ impl MatchState {
             unmatched: false,
             partially_matched: false,
             fully_matched: true,
+            na: false,
+            not_on_wikidata: false,
         }
     }
 
@@ -28,6 +33,8 @@ impl MatchState {
             unmatched: true,
             partially_matched: true,
             fully_matched: false,
+            na: false,
+            not_on_wikidata: false,
         }
     }
 
@@ -36,10 +43,28 @@ impl MatchState {
             unmatched: false,
             partially_matched: true,
             fully_matched: true,
+            na: false,
+            not_on_wikidata: false,
+        }
+    }
+
+    /// Every entry, including those matched to N/A or "not on Wikidata"
+    pub fn all() -> Self {
+        Self {
+            unmatched: true,
+            partially_matched: true,
+            fully_matched: true,
+            na: true,
+            not_on_wikidata: true,
         }
     }
 
     pub fn get_sql(&self) -> String {
+        format!(" AND {} ", self.get_sql_condition())
+    }
+
+    /// Returns the bare SQL condition, without the leading `AND`. If no state is selected, the condition is always false.
+    pub fn get_sql_condition(&self) -> String {
         let mut parts = vec![];
         if self.unmatched {
             parts.push("(`q` IS NULL)")
@@ -50,10 +75,32 @@ impl MatchState {
         if self.fully_matched {
             parts.push("(`q`>0 AND `user`>0)")
         }
+        if self.na {
+            parts.push("(`q`=0)")
+        }
+        if self.not_on_wikidata {
+            parts.push("(`q`=-1)")
+        }
         if parts.is_empty() {
-            return "".to_string();
+            return "0".to_string();
+        }
+        format!("({})", parts.join(" OR "))
+    }
+}
+
+/// Inverts the match state, eg `!MatchState::fully_matched()` is every entry that is not fully matched,
+/// including those matched to N/A or "not on Wikidata". `!MatchState::all()` matches no entry.
+impl std::ops::Not for MatchState {
+    type Output = Self;
+
+    fn not(self) -> Self::Output {
+        Self {
+            unmatched: !self.unmatched,
+            partially_matched: !self.partially_matched,
+            fully_matched: !self.fully_matched,
+            na: !self.na,
+            not_on_wikidata: !self.not_on_wikidata,
         }
-        format!(" AND ({}) ", parts.join(" OR "))
     }
 }
 
@@ -67,8 +114,10 @@ mod tests {
             unmatched: false,
             fully_matched: false,
             partially_matched: false,
+            na: false,
+            not_on_wikidata: false,
         };
-        assert_eq!(ms.get_sql().as_str(), "");
+        assert_eq!(ms.get_sql().as_str(), " AND 0 ");
         assert_eq!(
             MatchState::unmatched().get_sql().as_str(),
             " AND ((`q` IS NULL)) "
@@ -85,5 +134,40 @@ mod tests {
             MatchState::any_matched().get_sql().as_str(),
             " AND ((`q`>0 AND `user`=0) OR (`q`>0 AND `user`>0)) "
         );
+        assert_eq!(
+            MatchState::all().get_sql().as_str(),
+            " AND ((`q` IS NULL) OR (`q`>0 AND `user`=0) OR (`q`>0 AND `user`>0) OR (`q`=0) OR (`q`=-1)) "
+        );
+    }
+
+    #[test]
+    fn test_not() {
+        assert_eq!(
+            !MatchState::fully_matched(),
+            MatchState {
+                unmatched: true,
+                partially_matched: true,
+                fully_matched: false,
+                na: true,
+                not_on_wikidata: true,
+            }
+        );
+        assert_eq!(
+            !MatchState::unmatched(),
+            MatchState {
+                unmatched: false,
+                partially_matched: true,
+                fully_matched: true,
+                na: true,
+                not_on_wikidata: true,
+            }
+        );
+        assert_eq!(!!MatchState::unmatched(), MatchState::unmatched());
+        assert_eq!(
+            (!MatchState::fully_matched()).get_sql().as_str(),
+            " AND ((`q` IS NULL) OR (`q`>0 AND `user`=0) OR (`q`=0) OR (`q`=-1)) "
+        );
+        assert_eq!((!MatchState::all()).get_sql().as_str(), " AND 0 ");
+        assert_eq!(!!MatchState::all(), MatchState::all());
     }
 }
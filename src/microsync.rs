@@ -6,9 +6,10 @@ use crate::job::*;
 use crate::maintenance::*;
 use crate::match_state::MatchState;
 use anyhow::Result;
+use futures::{pin_mut, Stream, StreamExt};
+use serde::Serialize;
 use serde_json::Value;
 use std::collections::HashMap;
-use std::fs::File;
 use wikimisc::timestamp::TimeStamp;
 
 pub const EXT_URL_UNIQUE_SEPARATOR: &str = "!@£$%^&|";
@@ -51,6 +52,73 @@ struct ExtIdNoMnM {
     ext_id: String,
 }
 
+/// An entry that is matched to a different item than the one Wikidata has for its external ID
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MicrosyncIssue {
+    pub entry_id: usize,
+    pub ext_id: String,
+    pub q_wd: isize,
+    pub q_mnm: isize,
+}
+
+/// The changes a microsync run would make for a catalog, as computed by a dry run.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct MicrosyncReport {
+    pub catalog_id: usize,
+    /// The catalog was not checked, as it is blacklisted or has no usable property
+    pub skipped: bool,
+    /// Entries that would be matched to the item Wikidata has for their external ID, as (entry ID, item).
+    /// Empty after a real run, which sets the matches in batches while comparing.
+    pub proposed_matches: Vec<(usize, isize)>,
+    /// Matched items that would be unmatched, because they were deleted or are meta items
+    pub proposed_unmatches: Vec<String>,
+    /// Entries matched to a different item than on Wikidata, which would be reported for review
+    pub proposed_issues: Vec<MicrosyncIssue>,
+    /// Items matched to more than one entry in the catalog, as (item, entry IDs)
+    pub duplicate_q_clusters: Vec<(isize, Vec<usize>)>,
+}
+
+impl MicrosyncReport {
+    fn new(
+        catalog_id: usize,
+        proposed_matches: Vec<(usize, isize)>,
+        proposed_unmatches: Vec<String>,
+        match_differs: &[MatchDiffers],
+        multiple_q_in_mnm: &[ExtIdWithMutipleQ],
+    ) -> Self {
+        let proposed_issues = match_differs
+            .iter()
+            .map(|md| MicrosyncIssue {
+                entry_id: md.entry_id,
+                ext_id: md.ext_id.to_owned(),
+                q_wd: md.q_wd,
+                q_mnm: md.q_mnm,
+            })
+            .collect();
+        let duplicate_q_clusters = multiple_q_in_mnm
+            .iter()
+            .map(|e| (e.q, e.entry2ext_id.iter().map(|x| x.0).collect()))
+            .collect();
+        Self {
+            catalog_id,
+            skipped: false,
+            proposed_matches,
+            proposed_unmatches,
+            proposed_issues,
+            duplicate_q_clusters,
+        }
+    }
+
+    /// The report for a catalog that was not checked
+    fn skipped(catalog_id: usize) -> Self {
+        Self {
+            catalog_id,
+            skipped: true,
+            ..Default::default()
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Microsync {
     app: AppState,
@@ -80,24 +148,48 @@ impl Microsync {
         }
     }
 
-    pub async fn check_catalog(&mut self, catalog_id: usize) -> Result<()> {
+    /// Syncs a catalog with Wikidata, and updates its report page on Wikidata.
+    /// With `dry_run`, nothing is changed; the returned report lists what a real run would do.
+    pub async fn check_catalog(
+        &mut self,
+        catalog_id: usize,
+        dry_run: bool,
+    ) -> Result<MicrosyncReport> {
         if BLACKLISTED_CATALOGS.contains(&catalog_id) {
-            return Ok(()); // TODO error?
+            return Ok(MicrosyncReport::skipped(catalog_id));
         }
         let catalog = Catalog::from_id(catalog_id, &self.app).await?;
         let property = match (catalog.wd_prop, catalog.wd_qual) {
             (Some(prop), None) => prop,
-            _ => return Ok(()), // Don't fail this job, just silently close it
+            _ => return Ok(MicrosyncReport::skipped(catalog_id)), // Don't fail this job, just silently close it
         };
         let maintenance = Maintenance::new(&self.app);
-        maintenance
-            .fix_matched_items(catalog_id, &MatchState::fully_matched())
-            .await?;
+        let proposed_unmatches = if dry_run {
+            maintenance
+                .get_items_to_unlink(catalog_id, &MatchState::fully_matched())
+                .await?
+        } else {
+            maintenance
+                .fix_matched_items(catalog_id, &MatchState::fully_matched())
+                .await?;
+            vec![]
+        };
 
         let multiple_extid_in_wikidata = self.get_multiple_extid_in_wikidata(property).await?;
         let multiple_q_in_mnm = self.get_multiple_q_in_mnm(catalog_id).await?;
-        let (extid_not_in_mnm, match_differs) =
-            self.get_differences_mnm_wd(catalog_id, property).await?;
+        let (extid_not_in_mnm, match_differs, new_matches) = self
+            .get_differences_mnm_wd(catalog_id, property, dry_run)
+            .await?;
+        let report = MicrosyncReport::new(
+            catalog_id,
+            new_matches,
+            proposed_unmatches,
+            &match_differs,
+            &multiple_q_in_mnm,
+        );
+        if dry_run {
+            return Ok(report);
+        }
         let wikitext = self
             .wikitext_from_issues(
                 &catalog,
@@ -108,6 +200,17 @@ impl Microsync {
             )
             .await?;
         self.update_wiki_page(catalog_id, &wikitext).await?;
+        Ok(report)
+    }
+
+    /// Matches entries to the items Wikidata has for their external IDs.
+    async fn set_new_matches(&self, new_matches: &[(usize, isize)]) -> Result<()> {
+        for (entry_id, q) in new_matches {
+            Entry::from_id(*entry_id, &self.app)
+                .await?
+                .set_match(&format!("Q{}", q), 4)
+                .await?;
+        }
         Ok(())
     }
 
@@ -394,75 +497,119 @@ impl Microsync {
     //TODO test
     async fn get_q2ext_id_chunk(
         &self,
-        reader: &mut csv::Reader<File>,
+        rows: &mut (impl Stream<Item = Result<Vec<String>>> + Unpin),
         case_insensitive: bool,
         batch_size: usize,
     ) -> Result<Vec<(isize, String)>> {
         let mw_api = self.app.wikidata().get_mw_api().await?;
-        Ok(reader
-            .records()
-            .filter_map(|r| r.ok())
-            .filter_map(|r| {
-                let q = mw_api.extract_entity_from_uri(r.get(0)?).ok()?;
-                let q_numeric = AppState::item2numeric(&q)?;
-                let value = r.get(1)?;
-                let value = if case_insensitive {
-                    value.to_lowercase().to_string()
-                } else {
-                    value.to_string()
-                };
-                Some((q_numeric, value))
-            })
-            .take(batch_size)
-            .collect())
+        let mut ret = vec![];
+        while ret.len() < batch_size {
+            let row = match rows.next().await {
+                Some(row) => row?,
+                None => break,
+            };
+            let q = match row.first().map(|uri| mw_api.extract_entity_from_uri(uri)) {
+                Some(Ok(q)) => q,
+                _ => continue,
+            };
+            let (q_numeric, value) = match (AppState::item2numeric(&q), row.get(1)) {
+                (Some(q_numeric), Some(value)) => (q_numeric, value),
+                _ => continue,
+            };
+            let value = if case_insensitive {
+                value.to_lowercase().to_string()
+            } else {
+                value.to_string()
+            };
+            ret.push((q_numeric, value));
+        }
+        Ok(ret)
     }
 
+    /// Compares the catalog to the external IDs on Wikidata. Unless `dry_run`, the new matches are set
+    /// after each chunk, and not returned.
     //TODO test
     async fn get_differences_mnm_wd(
         &self,
         catalog_id: usize,
         property: usize,
-    ) -> Result<(Vec<ExtIdNoMnM>, Vec<MatchDiffers>)> {
+        dry_run: bool,
+    ) -> Result<(Vec<ExtIdNoMnM>, Vec<MatchDiffers>, Vec<(usize, isize)>)> {
         let case_insensitive = AUX_PROPERTIES_ALSO_USING_LOWERCASE.contains(&property);
         let sparql = format!("SELECT ?item ?value {{ ?item wdt:P{property} ?value }}"); // "ORDER BY ?item" unnecessary?
-        let mut reader = self.app.wikidata().load_sparql_csv(&sparql).await?;
+        let rows = self.app.wikidata().load_sparql_tsv_stream(&sparql).await?;
+        pin_mut!(rows);
         let mut extid_not_in_mnm: Vec<ExtIdNoMnM> = vec![];
         let mut match_differs = vec![];
+        let mut new_matches = vec![];
         let batch_size: usize = 5000;
         loop {
             let chunk = self
                 .get_differences_mnm_wd_process_chunk(
-                    &mut reader,
+                    &mut rows,
                     case_insensitive,
                     batch_size,
                     catalog_id,
                     property,
                     &mut match_differs,
                     &mut extid_not_in_mnm,
+                    &mut new_matches,
                 )
                 .await?;
+            if !dry_run {
+                self.set_new_matches(&new_matches).await?;
+                new_matches.clear();
+            }
             if chunk.len() < batch_size {
                 break;
             }
         }
+        self.remove_ext_ids_in_other_catalogs(catalog_id, property, &mut extid_not_in_mnm)
+            .await?;
         extid_not_in_mnm.sort();
         match_differs.sort();
-        Ok((extid_not_in_mnm, match_differs))
+        Ok((extid_not_in_mnm, match_differs, new_matches))
+    }
+
+    /// Removes the external IDs that are in another active catalog for the same property,
+    /// as they are in Mix'n'match after all
+    async fn remove_ext_ids_in_other_catalogs(
+        &self,
+        catalog_id: usize,
+        property: usize,
+        extid_not_in_mnm: &mut Vec<ExtIdNoMnM>,
+    ) -> Result<()> {
+        let other_catalogs: Vec<usize> = self
+            .app
+            .catalogs_for_property(property)
+            .await?
+            .into_iter()
+            .filter(|other_catalog_id| *other_catalog_id != catalog_id)
+            .collect();
+        for other_catalog_id in other_catalogs {
+            let ext_ids: Vec<&String> = extid_not_in_mnm.iter().map(|x| &x.ext_id).collect();
+            let found = self
+                .get_entries_for_ext_ids(other_catalog_id, property, &ext_ids)
+                .await?;
+            extid_not_in_mnm.retain(|x| !found.contains_key(&x.ext_id));
+        }
+        Ok(())
     }
 
     #[allow(clippy::too_many_arguments)]
     async fn get_differences_mnm_wd_process_chunk(
         &self,
-        reader: &mut csv::Reader<File>,
+        rows: &mut (impl Stream<Item = Result<Vec<String>>> + Unpin),
         case_insensitive: bool,
         batch_size: usize,
         catalog_id: usize,
         property: usize,
         match_differs: &mut Vec<MatchDiffers>,
         extid_not_in_mnm: &mut Vec<ExtIdNoMnM>,
+        new_matches: &mut Vec<(usize, isize)>,
     ) -> Result<Vec<(isize, String)>> {
         let chunk = self
-            .get_q2ext_id_chunk(reader, case_insensitive, batch_size)
+            .get_q2ext_id_chunk(rows, case_insensitive, batch_size)
             .await?;
         let ext_ids: Vec<&String> = chunk.iter().map(|x| &x.1).collect();
         let ext_id2entry = self
@@ -471,8 +618,13 @@ impl Microsync {
         for (q, ext_id) in &chunk {
             match ext_id2entry.get(ext_id) {
                 Some(entry) => {
-                    self.get_differences_mnm_wd_process_entry(entry, q, ext_id, match_differs)
-                        .await?;
+                    Self::get_differences_mnm_wd_process_entry(
+                        entry,
+                        q,
+                        ext_id,
+                        match_differs,
+                        new_matches,
+                    );
                 }
                 None => {
                     if extid_not_in_mnm.len() <= MAX_WIKI_ROWS {
@@ -487,49 +639,43 @@ impl Microsync {
         Ok(chunk)
     }
 
-    async fn get_differences_mnm_wd_process_entry(
-        &self,
+    /// Records a new match, or a mismatch, for an entry that has an external ID used on Wikidata.
+    fn get_differences_mnm_wd_process_entry(
         entry: &SmallEntry,
         q: &isize,
         ext_id: &String,
         match_differs: &mut Vec<MatchDiffers>,
-    ) -> Result<()> {
+        new_matches: &mut Vec<(usize, isize)>,
+    ) {
         if entry.user.is_none() || entry.user == Some(0) || entry.q.is_none() {
             // Found a match but not in app yet
-            Entry::from_id(entry.id, &self.app)
-                .await?
-                .set_match(&format!("Q{}", q), 4)
-                .await?;
+            new_matches.push((entry.id, *q));
         } else if Some(*q) != entry.q {
             // Fully matched but to different item
             if let Some(entry_q) = entry.q {
                 // Entry has N/A or Not In Wikidata, overwrite
-                self.get_differences_mnm_wd_process_entry_overwrite(
+                Self::get_differences_mnm_wd_process_entry_overwrite(
                     entry_q,
                     entry,
                     q,
                     ext_id,
                     match_differs,
-                )
-                .await?;
+                    new_matches,
+                );
             }
         }
-        Ok(())
     }
 
-    async fn get_differences_mnm_wd_process_entry_overwrite(
-        &self,
+    fn get_differences_mnm_wd_process_entry_overwrite(
         entry_q: isize,
         entry: &SmallEntry,
         q: &isize,
         ext_id: &String,
         match_differs: &mut Vec<MatchDiffers>,
-    ) -> Result<()> {
+        new_matches: &mut Vec<(usize, isize)>,
+    ) {
         if entry_q <= 0 {
-            Entry::from_id(entry.id, &self.app)
-                .await?
-                .set_match(&format!("Q{}", q), 4)
-                .await?;
+            new_matches.push((entry.id, *q));
         } else {
             let md = MatchDiffers {
                 ext_id: ext_id.to_owned(),
@@ -542,7 +688,6 @@ impl Microsync {
                 match_differs.push(md);
             }
         }
-        Ok(())
     }
 
     //TODO test
@@ -709,6 +854,71 @@ mod tests {
     async fn test_check_catalog() {
         let app = get_test_app();
         let mut ms = Microsync::new(&app);
-        ms.check_catalog(22).await.unwrap();
+        ms.check_catalog(22, false).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_check_catalog_dry_run() {
+        let app = get_test_app();
+        let mut ms = Microsync::new(&app);
+        let report = ms.check_catalog(22, true).await.unwrap();
+        assert_eq!(report.catalog_id, 22);
+        assert!(!report.skipped);
+    }
+
+    #[tokio::test]
+    async fn test_check_catalog_blacklisted() {
+        let app = get_test_app();
+        let mut ms = Microsync::new(&app);
+        let catalog_id = BLACKLISTED_CATALOGS[0];
+        let report = ms.check_catalog(catalog_id, true).await.unwrap();
+        assert_eq!(report.catalog_id, catalog_id);
+        assert!(report.skipped);
+    }
+
+    #[test]
+    fn test_get_differences_mnm_wd_process_entry() {
+        let mut match_differs = vec![];
+        let mut new_matches = vec![];
+        let unmatched = SmallEntry {
+            id: 1,
+            q: None,
+            user: None,
+            ext_url: String::new(),
+        };
+        let not_on_wd = SmallEntry {
+            id: 2,
+            q: Some(0),
+            user: Some(2),
+            ext_url: String::new(),
+        };
+        let other_item = SmallEntry {
+            id: 3,
+            q: Some(13520818),
+            user: Some(2),
+            ext_url: String::new(),
+        };
+        let same_item = SmallEntry {
+            id: 4,
+            q: Some(42),
+            user: Some(2),
+            ext_url: String::new(),
+        };
+        for entry in [&unmatched, &not_on_wd, &other_item, &same_item] {
+            Microsync::get_differences_mnm_wd_process_entry(
+                entry,
+                &42,
+                &"abc".to_string(),
+                &mut match_differs,
+                &mut new_matches,
+            );
+        }
+        assert_eq!(new_matches, vec![(1, 42), (2, 42)]);
+        assert_eq!(match_differs.len(), 1);
+        assert_eq!(match_differs[0].entry_id, 3);
+
+        let report = MicrosyncReport::new(22, new_matches, vec![], &match_differs, &[]);
+        assert_eq!(report.proposed_issues[0].q_mnm, 13520818);
+        assert_eq!(report.proposed_issues[0].q_wd, 42);
     }
 }
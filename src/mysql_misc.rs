@@ -1,9 +1,100 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use async_trait::async_trait;
 use core::time::Duration;
-use mysql_async::{futures::GetConn, Opts, OptsBuilder, PoolConstraints, PoolOpts};
+use mysql_async::{futures::GetConn, Conn, Opts, OptsBuilder, PoolConstraints, PoolOpts};
 use serde_json::Value;
 
+/// Maximum number of concurrent connections per user to the Toolforge databases
+pub const TOOLFORGE_MAX_DB_CONNECTIONS: usize = 20;
+
+/// DB pool settings, from a JSON config object:
+/// `url`, `min_connections`, `max_connections`, `keep_sec` (idle timeout),
+/// and optionally `wait_timeout_sec` (the MySQL `wait_timeout` of each connection, after which the server closes it when unused),
+/// and `connect_timeout_sec` (how long to wait for a connection from the pool, see `get_conn_with_timeout`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoolConfig {
+    pub url: String,
+    pub min_connections: usize,
+    pub max_connections: usize,
+    pub idle_timeout: Duration,
+    pub wait_timeout_sec: Option<usize>,
+    pub connect_timeout: Option<Duration>,
+}
+
+impl PoolConfig {
+    pub fn from_json(config: &Value) -> Result<Self> {
+        let min_connections = config["min_connections"]
+            .as_u64()
+            .ok_or_else(|| anyhow!("No min_connections value"))?
+            as usize;
+        let max_connections = config["max_connections"]
+            .as_u64()
+            .ok_or_else(|| anyhow!("No max_connections value"))?
+            as usize;
+        let keep_sec = config["keep_sec"]
+            .as_u64()
+            .ok_or_else(|| anyhow!("No keep_sec value"))?;
+        let url = config["url"]
+            .as_str()
+            .ok_or_else(|| anyhow!("No url value"))?
+            .to_string();
+        let wait_timeout_sec = config["wait_timeout_sec"].as_u64().map(|sec| sec as usize);
+        let connect_timeout_sec = config["connect_timeout_sec"].as_u64();
+        if max_connections == 0 {
+            return Err(anyhow!("max_connections must be at least 1"));
+        }
+        if min_connections > max_connections {
+            return Err(anyhow!(
+                "min_connections ({min_connections}) is larger than max_connections ({max_connections})"
+            ));
+        }
+        if wait_timeout_sec == Some(0) {
+            return Err(anyhow!("wait_timeout_sec must be at least 1"));
+        }
+        if connect_timeout_sec == Some(0) {
+            return Err(anyhow!("connect_timeout_sec must be at least 1"));
+        }
+        Ok(Self {
+            url,
+            min_connections,
+            max_connections,
+            idle_timeout: Duration::from_secs(keep_sec),
+            wait_timeout_sec,
+            connect_timeout: connect_timeout_sec.map(Duration::from_secs),
+        })
+    }
+
+    /// Fails if the pool could open more than `limit` connections
+    pub fn check_max_connections(self, limit: usize) -> Result<Self> {
+        if self.max_connections > limit {
+            return Err(anyhow!(
+                "max_connections ({}) exceeds the limit of {limit}",
+                self.max_connections
+            ));
+        }
+        Ok(self)
+    }
+}
+
+/// Gets a connection from a pool, failing with a `TimedOut` IO error after `connect_timeout`, if set
+pub async fn get_conn_with_timeout(
+    pool: mysql_async::Pool,
+    connect_timeout: Option<Duration>,
+) -> Result<Conn, mysql_async::Error> {
+    let get_conn = pool.get_conn();
+    match connect_timeout {
+        Some(connect_timeout) => tokio::time::timeout(connect_timeout, get_conn)
+            .await
+            .map_err(|_| {
+                std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    "Timed out waiting for a DB connection",
+                )
+            })?,
+        None => get_conn.await,
+    }
+}
+
 #[async_trait]
 pub trait MySQLMisc {
     fn pool(&self) -> &mysql_async::Pool;
@@ -19,23 +110,26 @@ pub trait MySQLMisc {
 
     /// Helper function to create a DB pool from a JSON config object
     fn create_pool(config: &Value) -> mysql_async::Pool {
-        let min_connections = config["min_connections"]
-            .as_u64()
-            .expect("No min_connections value") as usize;
-        let max_connections = config["max_connections"]
-            .as_u64()
-            .expect("No max_connections value") as usize;
-        let keep_sec = config["keep_sec"].as_u64().expect("No keep_sec value");
-        let url = config["url"].as_str().expect("No url value");
+        let pool_config = PoolConfig::from_json(config).expect("Invalid DB pool config");
+        Self::create_pool_from_config(&pool_config)
+    }
+
+    /// Helper function to create a DB pool from a validated `PoolConfig`
+    fn create_pool_from_config(pool_config: &PoolConfig) -> mysql_async::Pool {
         let pool_opts = PoolOpts::default()
             .with_constraints(
-                PoolConstraints::new(min_connections, max_connections).expect("Constraints error"),
+                PoolConstraints::new(pool_config.min_connections, pool_config.max_connections)
+                    .expect("Constraints error"),
             )
-            .with_inactive_connection_ttl(Duration::from_secs(keep_sec));
-        let wd_url = url;
+            .with_inactive_connection_ttl(pool_config.idle_timeout);
+        let wd_url = &pool_config.url;
         let wd_opts = Opts::from_url(wd_url)
             .unwrap_or_else(|_| panic!("Can not build options from db_wd URL {}", wd_url));
-        mysql_async::Pool::new(OptsBuilder::from_opts(wd_opts).pool_opts(pool_opts.clone()))
+        mysql_async::Pool::new(
+            OptsBuilder::from_opts(wd_opts)
+                .wait_timeout(pool_config.wait_timeout_sec)
+                .pool_opts(pool_opts.clone()),
+        )
     }
 
     fn sql_placeholders(num: usize) -> String {
@@ -44,3 +138,53 @@ pub trait MySQLMisc {
         placeholders.join(",")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_pool_config_from_json() {
+        let config = json!({"url":"mysql://localhost/test","min_connections":1,"max_connections":8,"keep_sec":30});
+        let pc = PoolConfig::from_json(&config).unwrap();
+        assert_eq!(pc.max_connections, 8);
+        assert_eq!(pc.idle_timeout, Duration::from_secs(30));
+        assert_eq!(pc.wait_timeout_sec, None);
+        assert_eq!(pc.connect_timeout, None);
+
+        let config = json!({"url":"mysql://localhost/test","min_connections":1,"max_connections":8,"keep_sec":30,"wait_timeout_sec":600});
+        let pc = PoolConfig::from_json(&config).unwrap();
+        assert_eq!(pc.wait_timeout_sec, Some(600));
+
+        let config = json!({"url":"mysql://localhost/test","min_connections":1,"max_connections":8,"keep_sec":30,"connect_timeout_sec":10});
+        let pc = PoolConfig::from_json(&config).unwrap();
+        assert_eq!(pc.connect_timeout, Some(Duration::from_secs(10)));
+        let config = json!({"url":"mysql://localhost/test","min_connections":1,"max_connections":8,"keep_sec":30,"connect_timeout_sec":0});
+        assert!(PoolConfig::from_json(&config).is_err());
+
+        let config = json!({"url":"mysql://localhost/test","min_connections":10,"max_connections":8,"keep_sec":30});
+        assert!(PoolConfig::from_json(&config).is_err());
+        let config = json!({"url":"mysql://localhost/test","min_connections":0,"max_connections":0,"keep_sec":30});
+        assert!(PoolConfig::from_json(&config).is_err());
+        let config = json!({"min_connections":1,"max_connections":8,"keep_sec":30});
+        assert!(PoolConfig::from_json(&config).is_err());
+    }
+
+    #[test]
+    fn test_pool_config_check_max_connections() {
+        let config = json!({"url":"mysql://localhost/test","min_connections":30,"max_connections":50,"keep_sec":30});
+        let pc = PoolConfig::from_json(&config).unwrap();
+        assert!(pc
+            .check_max_connections(TOOLFORGE_MAX_DB_CONNECTIONS)
+            .is_err());
+        let config = json!({"url":"mysql://localhost/test","min_connections":1,"max_connections":20,"keep_sec":30});
+        let pc = PoolConfig::from_json(&config).unwrap();
+        assert_eq!(
+            pc.check_max_connections(TOOLFORGE_MAX_DB_CONNECTIONS)
+                .unwrap()
+                .max_connections,
+            TOOLFORGE_MAX_DB_CONNECTIONS
+        );
+    }
+}
@@ -0,0 +1,157 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+/// `kv_catalog` key; if "1", `automatch_by_search` also searches for normalized forms and the other name order
+/// of names that have no search results as they are, eg "Muller" for "Müller"
+pub const KV_NORMALIZED_NAME_SEARCH: &str = "normalized_name_search";
+
+/// Letters that do not decompose into a base letter and combining marks
+const NON_DECOMPOSING_LETTERS: &[(char, &str)] = &[
+    ('ß', "ss"),
+    ('ẞ', "SS"),
+    ('æ', "ae"),
+    ('Æ', "AE"),
+    ('œ', "oe"),
+    ('Œ', "OE"),
+    ('ø', "o"),
+    ('Ø', "O"),
+    ('ł', "l"),
+    ('Ł', "L"),
+    ('đ', "d"),
+    ('Đ', "D"),
+    ('ð', "d"),
+    ('Ð', "D"),
+    ('þ', "th"),
+    ('Þ', "Th"),
+    ('ı', "i"),
+];
+
+lazy_static! {
+    static ref RE_PUNCTUATION: Regex =
+        Regex::new(r#"[\.,;:!?"“”„«»()\[\]{}/\\_*]+"#).expect("Regexp construction");
+    static ref RE_WHITESPACE: Regex = Regex::new(r"\s+").expect("Regexp construction");
+    static ref RE_LAST_FIRST: Regex =
+        Regex::new(r"^([^,]+?)\s*,\s*([^,]+)$").expect("Regexp construction");
+}
+
+/// Removes diacritics, eg "Dvořák" => "Dvorak"
+pub fn strip_diacritics(s: &str) -> String {
+    s.nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .map(|c| {
+            match NON_DECOMPOSING_LETTERS
+                .iter()
+                .find(|(letter, _)| *letter == c)
+            {
+                Some((_, replacement)) => replacement.to_string(),
+                None => c.to_string(),
+            }
+        })
+        .collect()
+}
+
+/// Replaces punctuation (but not apostrophes and hyphens) with spaces, and collapses whitespace
+pub fn normalize_whitespace_and_punctuation(s: &str) -> String {
+    let s = RE_PUNCTUATION.replace_all(s, " ");
+    RE_WHITESPACE.replace_all(&s, " ").trim().to_string()
+}
+
+/// The form of a name without diacritics and punctuation, eg "Müller, J." => "Muller J"
+pub fn normalize_name(name: &str) -> String {
+    normalize_whitespace_and_punctuation(&strip_diacritics(name))
+}
+
+/// "Lastname, Firstname" => "Firstname Lastname", and "Firstname Lastname" => "Lastname, Firstname".
+/// Returns `None` for names that have a single word, or several commas.
+pub fn swap_name_order(name: &str) -> Option<String> {
+    let name = RE_WHITESPACE.replace_all(name.trim(), " ");
+    if let Some(caps) = RE_LAST_FIRST.captures(&name) {
+        return Some(format!("{} {}", &caps[2], &caps[1]));
+    }
+    if name.contains(',') {
+        return None;
+    }
+    let (first, last) = name.rsplit_once(' ')?;
+    Some(format!("{last}, {first}"))
+}
+
+/// Alternative search strings for a name, most specific first, without the name itself:
+/// the other name order ("Firstname Lastname" for "Lastname, Firstname" and vice versa), and the normalized forms.
+pub fn search_variants(name: &str) -> Vec<String> {
+    let candidates = match swap_name_order(name) {
+        Some(swapped) if name.contains(',') => vec![
+            normalize_whitespace_and_punctuation(&swapped),
+            normalize_name(&swapped),
+            normalize_name(name),
+        ],
+        Some(swapped) => vec![
+            normalize_name(name),
+            swapped.to_owned(),
+            strip_diacritics(&swapped),
+        ],
+        None => vec![normalize_name(name)],
+    };
+    let mut ret: Vec<String> = vec![];
+    for candidate in candidates {
+        if !candidate.is_empty() && candidate != name && !ret.contains(&candidate) {
+            ret.push(candidate);
+        }
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_diacritics() {
+        assert_eq!(strip_diacritics("Müller"), "Muller");
+        assert_eq!(strip_diacritics("Antonín Dvořák"), "Antonin Dvorak");
+        assert_eq!(strip_diacritics("Søren Kierkegaard"), "Soren Kierkegaard");
+        assert_eq!(strip_diacritics("Łódź"), "Lodz");
+        assert_eq!(strip_diacritics("Straße"), "Strasse");
+        assert_eq!(strip_diacritics("John Smith"), "John Smith");
+    }
+
+    #[test]
+    fn test_normalize_name() {
+        assert_eq!(normalize_name("  Müller,  J.  "), "Muller J");
+        assert_eq!(normalize_name("O'Brien-Smith"), "O'Brien-Smith");
+        assert_eq!(normalize_name("\"Foo\" (Bar)"), "Foo Bar");
+    }
+
+    #[test]
+    fn test_swap_name_order() {
+        assert_eq!(
+            swap_name_order("Müller, Hans"),
+            Some("Hans Müller".to_string())
+        );
+        assert_eq!(
+            swap_name_order("Hans Peter Müller"),
+            Some("Müller, Hans Peter".to_string())
+        );
+        assert_eq!(swap_name_order("Müller"), None);
+        assert_eq!(swap_name_order("A, B, C"), None);
+    }
+
+    #[test]
+    fn test_search_variants() {
+        assert_eq!(
+            search_variants("Müller, Hans"),
+            vec![
+                "Hans Müller".to_string(),
+                "Hans Muller".to_string(),
+                "Muller Hans".to_string()
+            ]
+        );
+        assert_eq!(
+            search_variants("Hans Müller"),
+            vec!["Hans Muller", "Müller, Hans", "Muller, Hans"]
+        );
+        assert_eq!(search_variants("Hans Muller"), vec!["Muller, Hans"]);
+        assert!(search_variants("Muller").is_empty());
+    }
+}
@@ -0,0 +1,66 @@
+use crate::app_state::AppState;
+use crate::catalog::Catalog;
+use anyhow::{anyhow, Result};
+use regex::Regex;
+
+/// `kv_catalog` key for the rewrite rules of a catalog.
+/// The value is a JSON array of `[pattern, replacement]` pairs, applied in order, eg
+/// `[["^\\(attributed to\\) *",""],["^The (.+)$","$1"]]`
+pub const KV_NAME_REWRITE: &str = "name_rewrite";
+
+/// Per-catalog regular expression rewrites of `ext_name`, applied before search-based matching.
+#[derive(Debug, Clone, Default)]
+pub struct NameRewrite {
+    rules: Vec<(Regex, String)>,
+}
+
+impl NameRewrite {
+    /// Loads the rewrite rules for a catalog. No rules means names are passed through.
+    pub async fn from_catalog_id(catalog_id: usize, app: &AppState) -> Result<Self> {
+        let catalog = Catalog::from_id(catalog_id, app).await?;
+        match catalog.get_key_value_pairs().await?.get(KV_NAME_REWRITE) {
+            Some(json) => Self::from_json(json),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        let pairs: Vec<(String, String)> = serde_json::from_str(json)
+            .map_err(|e| anyhow!("Invalid {KV_NAME_REWRITE} rules '{json}': {e}"))?;
+        let rules = pairs
+            .into_iter()
+            .map(|(pattern, replacement)| Ok((Regex::new(&pattern)?, replacement)))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { rules })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn rewrite(&self, name: &str) -> String {
+        let mut name = name.to_string();
+        for (re, replacement) in &self.rules {
+            name = re.replace_all(&name, replacement.as_str()).to_string();
+        }
+        name.trim().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_name_rewrite() {
+        let nr = NameRewrite::from_json(r#"[["^\\(attributed to\\) *",""],["^The (.+)$","$1"]]"#)
+            .unwrap();
+        assert_eq!(nr.rewrite("(attributed to) John Doe"), "John Doe");
+        assert_eq!(nr.rewrite("The Beatles"), "Beatles");
+        assert_eq!(nr.rewrite("Theodor"), "Theodor");
+        assert!(NameRewrite::default().is_empty());
+        assert_eq!(NameRewrite::default().rewrite("The Beatles"), "The Beatles");
+        assert!(NameRewrite::from_json("[[\"(\",\"\"]]").is_err());
+        assert!(NameRewrite::from_json("foo").is_err());
+    }
+}
@@ -0,0 +1,92 @@
+use crate::app_state::AppState;
+use anyhow::Result;
+use std::collections::HashMap;
+
+/// Default number of matches after which a batch matcher rebuilds the catalog overview
+pub const DEFAULT_OVERVIEW_REBUILD_THRESHOLD: usize = 5000;
+
+/// Counts the matches a batch matcher sets via `Entry::set_match_without_overview`,
+/// and rebuilds the overview of a catalog every `threshold` matches,
+/// so progress is visible during long runs without per-entry overview updates.
+/// The matchers still call `Storage::finalize_catalog_overview` once when done.
+#[derive(Debug, Clone, Default)]
+pub struct OverviewRebuild {
+    /// 0 means no intermediate rebuilds
+    threshold: usize,
+    matches_since_rebuild: HashMap<usize, usize>,
+}
+
+impl OverviewRebuild {
+    /// Uses the `overview_rebuild_threshold` task setting; 0 disables intermediate rebuilds.
+    pub fn new(app: &AppState) -> Self {
+        let threshold = *app
+            .task_specific_usize()
+            .get("overview_rebuild_threshold")
+            .unwrap_or(&DEFAULT_OVERVIEW_REBUILD_THRESHOLD);
+        Self::with_threshold(threshold)
+    }
+
+    pub fn with_threshold(threshold: usize) -> Self {
+        Self {
+            threshold,
+            matches_since_rebuild: HashMap::new(),
+        }
+    }
+
+    /// Counts a match in a catalog, and rebuilds that catalog overview if the threshold was reached.
+    pub async fn match_set(&mut self, app: &AppState, catalog_id: usize) -> Result<()> {
+        self.matches_set(app, catalog_id, 1).await
+    }
+
+    /// Counts several matches in a catalog, eg from a batch that was processed concurrently,
+    /// and rebuilds that catalog overview if the threshold was reached.
+    pub async fn matches_set(
+        &mut self,
+        app: &AppState,
+        catalog_id: usize,
+        count: usize,
+    ) -> Result<()> {
+        let mut due = false;
+        for _ in 0..count {
+            due |= self.count_match(catalog_id);
+        }
+        if due {
+            app.storage().finalize_catalog_overview(catalog_id).await?;
+        }
+        Ok(())
+    }
+
+    /// Counts a match in a catalog. Returns `true` (and resets the counter) if the overview is due for a rebuild.
+    fn count_match(&mut self, catalog_id: usize) -> bool {
+        if self.threshold == 0 {
+            return false;
+        }
+        let count = self.matches_since_rebuild.entry(catalog_id).or_default();
+        *count += 1;
+        if *count < self.threshold {
+            return false;
+        }
+        *count = 0;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_match() {
+        let mut orb = OverviewRebuild::with_threshold(3);
+        assert!(!orb.count_match(1));
+        assert!(!orb.count_match(1));
+        assert!(!orb.count_match(2));
+        assert!(orb.count_match(1));
+        assert!(!orb.count_match(1));
+        assert!(!orb.count_match(2));
+        assert!(orb.count_match(2));
+
+        let mut disabled = OverviewRebuild::with_threshold(0);
+        assert!((0..10).all(|_| !disabled.count_match(1)));
+    }
+}
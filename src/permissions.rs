@@ -0,0 +1,98 @@
+use crate::app_state::AppState;
+use crate::catalog::Catalog;
+use anyhow::Result;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PermissionError {
+    PermissionDenied { user_id: usize, catalog_id: usize },
+}
+
+impl Error for PermissionError {}
+
+impl fmt::Display for PermissionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PermissionError::PermissionDenied {
+                user_id,
+                catalog_id,
+            } => write!(
+                f,
+                "User {user_id} is not allowed to modify catalog {catalog_id}"
+            ),
+        }
+    }
+}
+
+/// Checks whether a user may modify a catalog; that is, the user owns the catalog, or is a catalog admin.
+#[derive(Debug, Clone)]
+pub struct Permissions {
+    app: AppState,
+}
+
+impl Permissions {
+    pub fn new(app: &AppState) -> Self {
+        Self { app: app.clone() }
+    }
+
+    pub async fn can_edit_catalog(&self, user_id: usize, catalog: &Catalog) -> Result<bool> {
+        if Self::is_owner(user_id, catalog) {
+            return Ok(true);
+        }
+        if user_id == 0 {
+            return Ok(false);
+        }
+        match self.app.storage().get_user_by_id(user_id).await? {
+            Some(user) => Ok(user.is_catalog_admin()),
+            None => Ok(false),
+        }
+    }
+
+    /// Returns `PermissionError::PermissionDenied` if the user may not modify the catalog.
+    pub async fn check_can_edit_catalog(&self, user_id: usize, catalog: &Catalog) -> Result<()> {
+        if self.can_edit_catalog(user_id, catalog).await? {
+            Ok(())
+        } else {
+            Err(PermissionError::PermissionDenied {
+                user_id,
+                catalog_id: catalog.id,
+            }
+            .into())
+        }
+    }
+
+    fn is_owner(user_id: usize, catalog: &Catalog) -> bool {
+        user_id > 0 && catalog.owner == user_id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app_state::get_test_app;
+
+    const TEST_CATALOG_ID: usize = 5526;
+
+    #[tokio::test]
+    async fn test_check_can_edit_catalog() {
+        let app = get_test_app();
+        let mut catalog = Catalog::from_id(TEST_CATALOG_ID, &app).await.unwrap();
+        let permissions = Permissions::new(&app);
+        catalog.owner = 12345;
+        assert!(permissions.can_edit_catalog(12345, &catalog).await.unwrap());
+
+        catalog.owner = 0;
+        let err = permissions
+            .check_can_edit_catalog(0, &catalog)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<PermissionError>(),
+            Some(&PermissionError::PermissionDenied {
+                user_id: 0,
+                catalog_id: TEST_CATALOG_ID
+            })
+        );
+    }
+}
@@ -1,5 +1,58 @@
+use crate::app_state::AppState;
+use anyhow::{anyhow, Result};
 use lazy_static::lazy_static;
+use log::warn;
 use regex::Regex;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use wikimisc::wikibase::{Entity, EntityTrait};
+
+/// `kv` key for a JSON object of additional occupation words and their items, eg `{"luthier":"Q1639825"}`.
+/// An empty item removes a built-in word.
+pub const KV_OCCUPATION_WORDS: &str = "occupation_words";
+
+/// Built-in occupation words (lower case) and their Wikidata items
+const DEFAULT_OCCUPATION_WORDS: &[(&str, &str)] = &[
+    ("actor", "Q33999"),
+    ("actress", "Q33999"),
+    ("architect", "Q42973"),
+    ("astronomer", "Q11063"),
+    ("botanist", "Q2374149"),
+    ("chemist", "Q593644"),
+    ("composer", "Q36834"),
+    ("conductor", "Q158852"),
+    ("diplomat", "Q193391"),
+    ("economist", "Q188094"),
+    ("engineer", "Q81096"),
+    ("engraver", "Q329439"),
+    ("entomologist", "Q3055126"),
+    ("historian", "Q201788"),
+    ("illustrator", "Q644687"),
+    ("journalist", "Q1930187"),
+    ("lawyer", "Q40348"),
+    ("librarian", "Q182436"),
+    ("mathematician", "Q170790"),
+    ("musician", "Q639669"),
+    ("novelist", "Q6625963"),
+    ("painter", "Q1028181"),
+    ("philosopher", "Q4964182"),
+    ("photographer", "Q33231"),
+    ("physician", "Q39631"),
+    ("physicist", "Q169470"),
+    ("pianist", "Q486748"),
+    ("playwright", "Q214917"),
+    ("poet", "Q49757"),
+    ("politician", "Q82955"),
+    ("priest", "Q42603"),
+    ("printmaker", "Q11569986"),
+    ("sculptor", "Q1281618"),
+    ("singer", "Q177220"),
+    ("teacher", "Q37226"),
+    ("translator", "Q333634"),
+    ("violinist", "Q1259917"),
+    ("writer", "Q36180"),
+    ("zoologist", "Q350979"),
+];
 
 lazy_static! {
     static ref SANITIZE_NAME_RES: Vec<Regex> = vec![
@@ -52,10 +105,163 @@ impl Person {
     }
 }
 
+/// Finds occupations named in entry descriptions, to tell apart people with the same name and dates by their occupation (P106).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OccupationDisambiguation {
+    /// Word(s) => item
+    words: HashMap<String, String>,
+}
+
+impl Default for OccupationDisambiguation {
+    fn default() -> Self {
+        let words = DEFAULT_OCCUPATION_WORDS
+            .iter()
+            .map(|(word, q)| (word.to_string(), q.to_string()))
+            .collect();
+        Self { words }
+    }
+}
+
+impl OccupationDisambiguation {
+    /// The built-in words, with those from `kv` added; malformed `kv` JSON is logged, and the built-in words are used
+    pub async fn from_app(app: &AppState) -> Result<Self> {
+        let mut ret = Self::default();
+        if let Some(json) = app.storage().get_kv_value(KV_OCCUPATION_WORDS).await? {
+            if let Err(e) = ret.add_words_from_json(&json) {
+                warn!("Ignoring the {KV_OCCUPATION_WORDS} kv value: {e}");
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Adds words from a JSON object of word => item; an empty item removes the word
+    pub fn add_words_from_json(&mut self, json: &str) -> Result<()> {
+        let json: Value = serde_json::from_str(json)?;
+        let words = json
+            .as_object()
+            .ok_or_else(|| anyhow!("Occupation words are not a JSON object"))?;
+        for (word, q) in words {
+            let word = word.trim().to_lowercase();
+            match q.as_str().map(|q| q.trim()) {
+                Some("") => {
+                    self.words.remove(&word);
+                }
+                Some(q) if AppState::item2numeric(q).is_some() => {
+                    self.words.insert(word, q.to_string());
+                }
+                _ => {} // Ignore invalid values
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the occupation items for the (whole) words in a text
+    pub fn occupations_in(&self, text: &str) -> HashSet<String> {
+        let text = text.to_lowercase();
+        let words: Vec<&str> = text
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .collect();
+        self.words
+            .iter()
+            .filter(|(phrase, _)| {
+                let phrase: Vec<&str> = phrase.split_whitespace().collect();
+                !phrase.is_empty() && words.windows(phrase.len()).any(|w| w == phrase)
+            })
+            .map(|(_, q)| q.to_owned())
+            .collect()
+    }
+
+    /// Returns the only candidate with an occupation that is named in the description, if there is exactly one
+    pub fn pick_candidate(
+        &self,
+        description: &str,
+        candidates: &[String],
+        occupations_of: impl Fn(&str) -> Vec<String>,
+    ) -> Option<String> {
+        let occupations = self.occupations_in(description);
+        if occupations.is_empty() {
+            return None;
+        }
+        let mut picked = candidates
+            .iter()
+            .filter(|q| occupations_of(q).iter().any(|o| occupations.contains(o)));
+        match (picked.next(), picked.next()) {
+            (Some(q), None) => Some(q.to_owned()),
+            _ => None,
+        }
+    }
+
+    /// Returns the occupation (P106) items of an entity
+    pub fn item_occupations(entity: &Entity) -> Vec<String> {
+        Self::item_property_values(entity, 106)
+    }
+
+    /// Returns the item values of the statements of an entity with a property
+    pub fn item_property_values(entity: &Entity, property: usize) -> Vec<String> {
+        entity
+            .claims_with_property(&format!("P{property}"))
+            .iter()
+            .filter_map(|claim| match claim.main_snak().data_value() {
+                Some(datavalue) => match datavalue.value() {
+                    wikimisc::wikibase::Value::Entity(e) => Some(e.id().to_string()),
+                    _ => None,
+                },
+                None => None,
+            })
+            .collect()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_occupations_in() {
+        let od = OccupationDisambiguation::default();
+        let expected: HashSet<String> = ["Q1028181".to_string(), "Q1281618".to_string()].into();
+        assert_eq!(od.occupations_in("Dutch Painter and sculptor"), expected);
+        assert!(od.occupations_in("painters' guild").is_empty());
+        assert!(od.occupations_in("").is_empty());
+    }
+
+    #[test]
+    fn test_add_words_from_json() {
+        let mut od = OccupationDisambiguation::default();
+        od.add_words_from_json(r#"{"Film Director":"Q2526255","painter":"","foo":"bar"}"#)
+            .unwrap();
+        let expected: HashSet<String> = ["Q2526255".to_string()].into();
+        assert_eq!(od.occupations_in("painter, film director"), expected);
+        assert!(od.occupations_in("foo").is_empty());
+        assert!(od.add_words_from_json("[]").is_err());
+        assert!(od.add_words_from_json("{not json").is_err());
+        assert_eq!(od.occupations_in("film director"), expected); // Unchanged by the failures
+    }
+
+    #[test]
+    fn test_pick_candidate() {
+        let od = OccupationDisambiguation::default();
+        let candidates = vec!["Q1".to_string(), "Q2".to_string()];
+        let occupations_of = |q: &str| match q {
+            "Q1" => vec!["Q82955".to_string()],
+            "Q2" => vec!["Q1028181".to_string(), "Q36180".to_string()],
+            _ => vec![],
+        };
+        assert_eq!(
+            od.pick_candidate("German painter", &candidates, occupations_of),
+            Some("Q2".to_string())
+        );
+        assert_eq!(
+            od.pick_candidate("politician and writer", &candidates, occupations_of),
+            None
+        );
+        assert_eq!(
+            od.pick_candidate("German", &candidates, occupations_of),
+            None
+        );
+    }
+
     // #lizard forgives the complexity
     #[test]
     fn test_sanitize_name() {
@@ -0,0 +1,313 @@
+use crate::app_state::AppState;
+use crate::coordinate_matcher::distance_meters;
+use crate::entry::{CoordinateLocation, Entry};
+use crate::maintenance::Maintenance;
+use anyhow::Result;
+use wikimisc::wikibase::entity_container::EntityContainer;
+use wikimisc::wikibase::{Entity, Value};
+
+const DEFAULT_MAX_RESULTS: usize = 10;
+const DEFAULT_LANGUAGE: &str = "en";
+const DEFAULT_MIN_AUTO_MATCHES: usize = 1;
+
+#[derive(Debug, Clone)]
+pub struct QuickCompareOptions {
+    pub require_image: bool,
+    pub require_coordinates: bool,
+    pub max_distance_km: Option<f64>,
+    pub max_results: usize,
+    pub language: String,
+}
+
+impl Default for QuickCompareOptions {
+    fn default() -> Self {
+        Self {
+            require_image: false,
+            require_coordinates: false,
+            max_distance_km: None,
+            max_results: DEFAULT_MAX_RESULTS,
+            language: DEFAULT_LANGUAGE.to_string(),
+        }
+    }
+}
+
+/// An auto-matched entry, together with the relevant data of the item it is matched to.
+#[derive(Debug, Clone)]
+pub struct QuickCompareResult {
+    pub entry: Entry,
+    pub entry_location: Option<CoordinateLocation>,
+    pub item: String,
+    pub label: Option<String>,
+    pub description: Option<String>,
+    pub image: Option<String>,
+    pub item_location: Option<CoordinateLocation>,
+    pub distance_km: Option<f64>,
+}
+
+/// A catalog with auto-matches that can be reviewed via quick-compare.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuickCompareCatalog {
+    pub catalog_id: usize,
+    pub name: String,
+    pub auto_matches: usize,
+    pub has_locations: bool,
+    pub has_person_date: bool,
+}
+
+impl QuickCompareCatalog {
+    /// From (catalog ID, name, auto-matches, has locations, `has_person_date`); name and `has_person_date` may be NULL
+    pub fn from_row(row: (usize, Option<String>, usize, u8, Option<String>)) -> Self {
+        Self {
+            catalog_id: row.0,
+            name: row.1.unwrap_or_default(),
+            auto_matches: row.2,
+            has_locations: row.3 > 0,
+            has_person_date: row.4.as_deref() == Some("yes"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct QuickCompare {
+    app: AppState,
+}
+
+impl QuickCompare {
+    pub fn new(app: &AppState) -> Self {
+        Self { app: app.clone() }
+    }
+
+    /// Returns active catalogs with at least `min_auto_matches` (default 1) auto-matches,
+    /// most auto-matches first, minus the maintenance catalog exclusion list.
+    pub async fn get_catalogs(
+        &self,
+        min_auto_matches: Option<usize>,
+    ) -> Result<Vec<QuickCompareCatalog>> {
+        let excluded_catalogs = Maintenance::new(&self.app).get_excluded_catalogs().await?;
+        self.app
+            .storage()
+            .get_quick_compare_catalogs(
+                min_auto_matches.unwrap_or(DEFAULT_MIN_AUTO_MATCHES),
+                &excluded_catalogs,
+            )
+            .await
+    }
+
+    /// Returns random auto-matched (user=0) entries from a catalog, alongside their matched item's
+    /// label, description, image, and coordinates, filtered by `options`.
+    pub async fn get_results(
+        &self,
+        catalog_id: usize,
+        options: &QuickCompareOptions,
+    ) -> Result<Vec<QuickCompareResult>> {
+        // Get more entries than required, as some will be filtered out
+        let entries = self
+            .app
+            .storage()
+            .quick_compare_get_entries(
+                catalog_id,
+                options.require_coordinates,
+                options.max_results * 5,
+            )
+            .await?;
+        let items: Vec<String> = entries
+            .iter()
+            .filter_map(|entry| entry.q)
+            .filter(|q| *q > 0)
+            .map(|q| format!("Q{q}"))
+            .collect();
+        let mw_api = self.app.wikidata().get_mw_api().await?;
+        let entities = EntityContainer::new();
+        let _ = entities.load_entities(&mw_api, &items).await; // Missing items will be skipped
+        let entry_ids: Vec<usize> = entries.iter().map(|entry| entry.id).collect();
+        let mut locations = self
+            .app
+            .storage()
+            .entries_get_coordinate_locations(&entry_ids)
+            .await?;
+
+        let mut ret = vec![];
+        for mut entry in entries {
+            if ret.len() >= options.max_results {
+                break;
+            }
+            entry.set_app(&self.app);
+            let entry_location = locations.remove(&entry.id);
+            if let Some(result) = Self::get_result(entry, entry_location, &entities, options) {
+                ret.push(result);
+            }
+        }
+        Ok(ret)
+    }
+
+    fn get_result(
+        entry: Entry,
+        entry_location: Option<CoordinateLocation>,
+        entities: &EntityContainer,
+        options: &QuickCompareOptions,
+    ) -> Option<QuickCompareResult> {
+        let item = match entry.q {
+            Some(q) if q > 0 => format!("Q{q}"),
+            _ => return None,
+        };
+        let entity = entities.get_entity(item.to_owned())?; // Item does not exist (anymore?)
+        let image = Self::get_image(&entity);
+        if options.require_image && image.is_none() {
+            return None;
+        }
+        let item_location = Self::get_coordinates(&entity);
+        if options.require_coordinates && (item_location.is_none() || entry_location.is_none()) {
+            return None;
+        }
+        let distance_km = match (&entry_location, &item_location) {
+            (Some(l1), Some(l2)) => Some(Self::distance_km(l1, l2)),
+            _ => None,
+        };
+        if let (Some(max_distance_km), Some(distance_km)) = (options.max_distance_km, distance_km) {
+            if distance_km > max_distance_km {
+                return None;
+            }
+        }
+        Some(QuickCompareResult {
+            label: entity
+                .label_in_locale(&options.language)
+                .map(|s| s.to_string()),
+            description: entity
+                .description_in_locale(&options.language)
+                .map(|s| s.to_string()),
+            entry,
+            entry_location,
+            item,
+            image,
+            item_location,
+            distance_km,
+        })
+    }
+
+    /// Returns the first P18 (image) file name of an entity, if any
+    pub fn get_image(entity: &Entity) -> Option<String> {
+        entity
+            .claims_with_property("P18")
+            .iter()
+            .filter_map(|claim| match claim.main_snak().data_value() {
+                Some(dv) => match dv.value() {
+                    Value::StringValue(s) => Some(s.to_string()),
+                    _ => None,
+                },
+                None => None,
+            })
+            .next()
+    }
+
+    /// Returns the first P625 (coordinate location) of an entity, if any
+    pub fn get_coordinates(entity: &Entity) -> Option<CoordinateLocation> {
+        entity
+            .claims_with_property("P625")
+            .iter()
+            .filter_map(|claim| match claim.main_snak().data_value() {
+                Some(dv) => match dv.value() {
+                    Value::Coordinate(c) => Some(CoordinateLocation {
+                        lat: c.latitude().to_owned(),
+                        lon: c.longitude().to_owned(),
+                    }),
+                    _ => None,
+                },
+                None => None,
+            })
+            .next()
+    }
+
+    /// Great-circle distance between two locations, in km
+    fn distance_km(l1: &CoordinateLocation, l2: &CoordinateLocation) -> f64 {
+        distance_meters(l1.lat, l1.lon, l2.lat, l2.lon) / 1000.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app_state::{get_test_app, TEST_MUTEX};
+
+    const TEST_ENTRY_ID: usize = 143962196;
+
+    #[tokio::test]
+    async fn test_confirm_reject_auto_matches() {
+        let _test_lock = TEST_MUTEX.lock();
+        let app = get_test_app();
+        let mut entry = Entry::from_id(TEST_ENTRY_ID, &app).await.unwrap();
+
+        // Confirm
+        entry.unmatch().await.unwrap();
+        entry.set_match("Q12345", 0).await.unwrap();
+        let confirmed = app
+            .storage()
+            .confirm_auto_matches(&[TEST_ENTRY_ID], 2)
+            .await
+            .unwrap();
+        assert_eq!(confirmed, 1);
+        let entry2 = Entry::from_id(TEST_ENTRY_ID, &app).await.unwrap();
+        assert_eq!(entry2.q, Some(12345));
+        assert_eq!(entry2.user, Some(2));
+
+        // Only auto-matches are confirmed or rejected
+        let rejected = app
+            .storage()
+            .reject_auto_matches(&[TEST_ENTRY_ID], 2)
+            .await
+            .unwrap();
+        assert_eq!(rejected, 0);
+
+        // Reject
+        entry.unmatch().await.unwrap();
+        entry.set_match("Q12345", 0).await.unwrap();
+        let rejected = app
+            .storage()
+            .reject_auto_matches(&[TEST_ENTRY_ID], 2)
+            .await
+            .unwrap();
+        assert_eq!(rejected, 1);
+        let entry2 = Entry::from_id(TEST_ENTRY_ID, &app).await.unwrap();
+        assert!(entry2.is_unmatched());
+
+        // Cleanup
+        app.storage()
+            .clear_removed_matches_log(TEST_ENTRY_ID)
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn test_quick_compare_catalog_from_row() {
+        let qcc = QuickCompareCatalog::from_row((
+            123,
+            Some("Test".to_string()),
+            45,
+            1,
+            Some("yes".to_string()),
+        ));
+        assert_eq!(qcc.catalog_id, 123);
+        assert_eq!(qcc.auto_matches, 45);
+        assert!(qcc.has_locations);
+        assert!(qcc.has_person_date);
+        let qcc = QuickCompareCatalog::from_row((
+            123,
+            Some("Test".to_string()),
+            45,
+            0,
+            Some("no".to_string()),
+        ));
+        assert!(!qcc.has_locations);
+        assert!(!qcc.has_person_date);
+        let qcc = QuickCompareCatalog::from_row((123, None, 45, 0, None));
+        assert_eq!(qcc.name, "");
+        assert!(!qcc.has_person_date);
+    }
+
+    #[test]
+    fn test_distance_km() {
+        let l1 = CoordinateLocation { lat: 0.0, lon: 0.0 };
+        assert_eq!(QuickCompare::distance_km(&l1, &l1), 0.0);
+        let l2 = CoordinateLocation { lat: 0.0, lon: 1.0 };
+        assert!((QuickCompare::distance_km(&l1, &l2) - 111.19).abs() < 0.1);
+    }
+}
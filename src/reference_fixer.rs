@@ -0,0 +1,196 @@
+use crate::app_state::AppState;
+use crate::job::{Job, Jobbable};
+use crate::wikidata_commands::{WikidataCommandPropertyValue, WikidataCommandValue};
+use anyhow::Result;
+use log::error;
+use wikimisc::wikibase::entity_container::EntityContainer;
+use wikimisc::wikibase::{Entity, EntityTrait, Snak, Statement};
+
+const REFERENCE_FIXER_BATCH_SIZE: usize = 100;
+const REFERENCE_FIXER_SUMMARY: &str = "Mix'n'match: adding reference";
+/// How often the `reference_fixer` job works through the queue; the job is added when the bot starts
+pub const REFERENCE_FIXER_REPEAT_SEC: usize = 60 * 60;
+
+/// A fully matched entry of a queued item, in an active catalog with a property (and no qualifier)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReferenceFixerMatch {
+    pub ext_id: String,
+    pub wd_prop: usize,
+    pub source_item: Option<usize>,
+}
+
+impl ReferenceFixerMatch {
+    pub fn from_row(row: (String, usize, Option<usize>)) -> Self {
+        Self {
+            ext_id: row.0,
+            wd_prop: row.1,
+            source_item: row.2,
+        }
+    }
+}
+
+/// Works through the `reference_fixer` queue, which is filled when entries are matched.
+/// For each queued item, the statements of the catalog properties with the ext_id of a matched entry
+/// get a "stated in" reference to the source item of the catalog, if they don't have it already.
+#[derive(Debug, Clone)]
+pub struct ReferenceFixer {
+    app: AppState,
+    job: Option<Job>,
+}
+
+impl Jobbable for ReferenceFixer {
+    fn set_current_job(&mut self, job: &Job) {
+        self.job = Some(job.clone());
+    }
+
+    fn get_current_job(&self) -> Option<&Job> {
+        self.job.as_ref()
+    }
+
+    fn get_current_job_mut(&mut self) -> Option<&mut Job> {
+        self.job.as_mut()
+    }
+}
+
+impl ReferenceFixer {
+    pub fn new(app: &AppState) -> Self {
+        Self {
+            app: app.clone(),
+            job: None,
+        }
+    }
+
+    /// Processes all queued items, in batches. Items that fail stay in the queue for the next run,
+    /// and do not stop the others; neither does a batch that could not be loaded.
+    /// Returns the number of items that were processed.
+    pub async fn run(&mut self) -> Result<usize> {
+        let mw_api = self.app.wikidata().get_mw_api().await?;
+        let mut last_q = 0;
+        let mut processed = 0;
+        loop {
+            let qs = self
+                .app
+                .storage()
+                .reference_fixer_get_queued(last_q, REFERENCE_FIXER_BATCH_SIZE)
+                .await?;
+            let items: Vec<String> = qs.iter().map(|q| format!("Q{q}")).collect();
+            let entities = EntityContainer::new();
+            match entities.load_entities(&mw_api, &items).await {
+                Ok(_) => {
+                    for q in &qs {
+                        match self.fix_item(*q, &entities).await {
+                            Ok(()) => {
+                                self.app.storage().reference_fixer_set_done(*q).await?;
+                                processed += 1;
+                            }
+                            Err(e) => error!("ReferenceFixer: Q{q} failed: {e}"),
+                        }
+                    }
+                }
+                Err(e) => error!("ReferenceFixer: could not load items after Q{last_q}: {e}"),
+            }
+            last_q = match qs.last() {
+                Some(q) => *q,
+                None => break,
+            };
+            if qs.len() < REFERENCE_FIXER_BATCH_SIZE {
+                break;
+            }
+        }
+        Ok(processed)
+    }
+
+    async fn fix_item(&mut self, q: isize, entities: &EntityContainer) -> Result<()> {
+        let entity = match entities.get_entity(format!("Q{q}")) {
+            Some(entity) => entity,
+            None => return Ok(()), // Deleted, or not an item; nothing to fix
+        };
+        let matches = self.app.storage().reference_fixer_get_matches(q).await?;
+        for (statement_id, reference) in Self::missing_references(&entity, &matches) {
+            self.app
+                .wikidata_mut()
+                .add_reference(&statement_id, &reference, REFERENCE_FIXER_SUMMARY)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Returns the statement IDs that lack a "stated in" reference for a match, with the reference to add
+    fn missing_references(
+        entity: &Entity,
+        matches: &[ReferenceFixerMatch],
+    ) -> Vec<(String, Vec<WikidataCommandPropertyValue>)> {
+        let mut ret = vec![];
+        for m in matches {
+            let source_item = match m.source_item {
+                Some(source_item) => source_item,
+                None => continue, // Nothing to reference
+            };
+            let prop = format!("P{}", m.wd_prop);
+            for claim in entity.claims_with_property(&prop) {
+                if !Self::claim_needs_reference(claim, m, source_item) {
+                    continue;
+                }
+                if let Some(statement_id) = claim.id() {
+                    let reference = vec![WikidataCommandPropertyValue {
+                        property: 248,
+                        value: WikidataCommandValue::Item(source_item),
+                    }];
+                    ret.push((statement_id.to_string(), reference));
+                }
+            }
+        }
+        ret
+    }
+
+    /// Checks if a statement has the ext_id of the match, but no "stated in" reference to the source item
+    fn claim_needs_reference(
+        claim: &Statement,
+        m: &ReferenceFixerMatch,
+        source_item: usize,
+    ) -> bool {
+        let main_snak = Snak::new_external_id(&format!("P{}", m.wd_prop), &m.ext_id);
+        if *claim.main_snak() != main_snak {
+            return false;
+        }
+        let stated_in = Snak::new_item("P248", &format!("Q{source_item}"));
+        !claim
+            .references()
+            .iter()
+            .any(|reference| reference.snaks().contains(&stated_in))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wikimisc::wikibase::Reference;
+
+    #[test]
+    fn test_reference_fixer_match_from_row() {
+        let m = ReferenceFixerMatch::from_row(("abc".to_string(), 214, Some(54919)));
+        assert_eq!(m.ext_id, "abc");
+        assert_eq!(m.wd_prop, 214);
+        assert_eq!(m.source_item, Some(54919));
+    }
+
+    #[test]
+    fn test_claim_needs_reference() {
+        let m = ReferenceFixerMatch::from_row(("abc".to_string(), 214, Some(54919)));
+        let snak = Snak::new_external_id("P214", "abc");
+        let claim = Statement::new_normal(snak.clone(), vec![], vec![]);
+        assert!(ReferenceFixer::claim_needs_reference(&claim, &m, 54919));
+
+        let stated_in = Reference::new(vec![Snak::new_item("P248", "Q54919")]);
+        let claim = Statement::new_normal(snak.clone(), vec![], vec![stated_in]);
+        assert!(!ReferenceFixer::claim_needs_reference(&claim, &m, 54919));
+
+        let other_source = Reference::new(vec![Snak::new_item("P248", "Q1")]);
+        let claim = Statement::new_normal(snak, vec![], vec![other_source]);
+        assert!(ReferenceFixer::claim_needs_reference(&claim, &m, 54919));
+
+        let other_id = Snak::new_external_id("P214", "xyz");
+        let claim = Statement::new_normal(other_id, vec![], vec![]);
+        assert!(!ReferenceFixer::claim_needs_reference(&claim, &m, 54919));
+    }
+}
@@ -0,0 +1,248 @@
+use crate::app_state::AppState;
+use crate::entry_query::{EntryQuery, EntryQueryOrder};
+use crate::job::{Job, Jobbable};
+use crate::person::OccupationDisambiguation;
+use anyhow::Result;
+use std::collections::HashMap;
+use wikimisc::wikibase::entity_container::EntityContainer;
+use wikimisc::wikibase::EntityTrait;
+
+/// Description labels that are recognized by the extractor, and their properties
+const STATEMENT_TEXT_LABELS: &[(&str, usize)] = &[
+    ("occupation", 106),
+    ("profession", 106),
+    ("nationality", 27),
+    ("citizenship", 27),
+    ("place of birth", 19),
+    ("born in", 19),
+    ("place of death", 20),
+    ("died in", 20),
+    ("employer", 108),
+    ("genre", 136),
+    ("field of work", 101),
+];
+const STATEMENT_TEXT_BATCH_SIZE: usize = 5000;
+
+/// A free-text statement for an entry, eg "occupation: painter" as (P106, "painter"),
+/// as stored in `statement_text`. `q` is the item the text was resolved to, once it is found
+/// as a statement value on the matched item; `in_wikidata` is set then.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatementText {
+    pub id: usize,
+    pub entry_id: usize,
+    pub property: usize,
+    pub text: String,
+    pub q: Option<isize>,
+    pub in_wikidata: bool,
+    pub entry_is_matched: bool,
+}
+
+impl StatementText {
+    pub fn from_row(row: (usize, usize, usize, String, Option<isize>, u8, u8)) -> Self {
+        Self {
+            id: row.0,
+            entry_id: row.1,
+            property: row.2,
+            text: row.3,
+            q: row.4,
+            in_wikidata: row.5 == 1,
+            entry_is_matched: row.6 == 1,
+        }
+    }
+
+    /// Extracts (property, text) from "label: value" parts of a description, eg
+    /// "occupation: painter, sculptor; nationality: Dutch". Parts are separated by `;`, `|`, or newlines;
+    /// values by commas. Unknown labels are ignored.
+    pub fn extract(description: &str) -> Vec<(usize, String)> {
+        let mut ret = vec![];
+        for part in description.split([';', '|', '\n']) {
+            let (label, values) = match part.split_once(':') {
+                Some(lv) => lv,
+                None => continue,
+            };
+            let label = label.trim().to_lowercase();
+            let property = match STATEMENT_TEXT_LABELS.iter().find(|(l, _)| *l == label) {
+                Some((_, property)) => *property,
+                None => continue,
+            };
+            for value in values.split(',') {
+                let value = value.trim();
+                if !value.is_empty() && !ret.contains(&(property, value.to_string())) {
+                    ret.push((property, value.to_string()));
+                }
+            }
+        }
+        ret
+    }
+}
+
+/// Populates `statement_text` from entry descriptions, and resolves the texts against the statements of matched items.
+#[derive(Debug, Clone)]
+pub struct StatementTextMatcher {
+    app: AppState,
+    job: Option<Job>,
+}
+
+impl Jobbable for StatementTextMatcher {
+    fn set_current_job(&mut self, job: &Job) {
+        self.job = Some(job.clone());
+    }
+
+    fn get_current_job(&self) -> Option<&Job> {
+        self.job.as_ref()
+    }
+
+    fn get_current_job_mut(&mut self) -> Option<&mut Job> {
+        self.job.as_mut()
+    }
+}
+
+impl StatementTextMatcher {
+    pub fn new(app: &AppState) -> Self {
+        Self {
+            app: app.clone(),
+            job: None,
+        }
+    }
+
+    /// Adds the statement texts extracted from the descriptions of all entries in a catalog.
+    /// Returns the number of statement texts found; existing ones are not added again.
+    pub async fn populate_catalog(&mut self, catalog_id: usize) -> Result<usize> {
+        let mut offset = self.get_last_job_offset().await;
+        let mut found = 0;
+        loop {
+            let query = EntryQuery::default()
+                .with_catalog_id(catalog_id)
+                .with_order_by(EntryQueryOrder::Id)
+                .with_limit(STATEMENT_TEXT_BATCH_SIZE)
+                .with_offset(offset);
+            let entries = self.app.storage().get_entry_query(&query).await?;
+            for entry in &entries {
+                for (property, text) in StatementText::extract(&entry.ext_desc) {
+                    if self
+                        .app
+                        .storage()
+                        .entry_add_statement_text(entry.id, property, &text)
+                        .await?
+                    {
+                        found += 1;
+                    }
+                }
+            }
+            if entries.len() < STATEMENT_TEXT_BATCH_SIZE {
+                break;
+            }
+            offset += entries.len();
+            let _ = self.remember_offset(offset).await;
+        }
+        let _ = self.clear_offset().await;
+        Ok(found)
+    }
+
+    /// Resolves the unresolved statement texts of fully matched entries in a catalog, where the matched item
+    /// has a statement with that property whose value item has the text as a label or alias.
+    /// Returns the number of statement texts resolved.
+    pub async fn match_catalog(&mut self, catalog_id: usize) -> Result<usize> {
+        let mw_api = self.app.wikidata().get_mw_api().await?;
+        let mut offset = self.get_last_job_offset().await;
+        let mut resolved = 0;
+        loop {
+            let results = self
+                .app
+                .storage()
+                .statement_text_get_unresolved(catalog_id, offset, STATEMENT_TEXT_BATCH_SIZE)
+                .await?;
+            let items: Vec<String> = results.iter().map(|(_, q)| format!("Q{q}")).collect();
+            let entities = EntityContainer::new();
+            entities.load_entities(&mw_api, &items).await?;
+            let mut batch_resolved = 0;
+            let mut candidates = HashMap::new();
+            for (st, q) in &results {
+                if let Some(entity) = entities.get_entity(format!("Q{q}")) {
+                    candidates.insert(
+                        st.id,
+                        OccupationDisambiguation::item_property_values(&entity, st.property),
+                    );
+                }
+            }
+            let values: Vec<String> = candidates.values().flatten().cloned().collect();
+            let value_entities = EntityContainer::new();
+            value_entities.load_entities(&mw_api, &values).await?;
+            for (st, _q) in &results {
+                let values = match candidates.get(&st.id) {
+                    Some(values) => values,
+                    None => continue,
+                };
+                if let Some(value_q) = Self::find_value_for_text(&value_entities, values, &st.text)
+                {
+                    self.app
+                        .storage()
+                        .statement_text_set_resolved(st.id, value_q)
+                        .await?;
+                    batch_resolved += 1;
+                }
+            }
+            resolved += batch_resolved;
+            if results.len() < STATEMENT_TEXT_BATCH_SIZE {
+                break;
+            }
+            // Resolved statement texts drop out of the next batch
+            offset += results.len() - batch_resolved;
+            let _ = self.remember_offset(offset).await;
+        }
+        let _ = self.clear_offset().await;
+        Ok(resolved)
+    }
+
+    /// Returns the numeric item of the first value that has the text as a label or alias, ignoring case
+    fn find_value_for_text(
+        value_entities: &EntityContainer,
+        values: &[String],
+        text: &str,
+    ) -> Option<isize> {
+        let text = text.trim().to_lowercase();
+        values
+            .iter()
+            .find(|value| match value_entities.get_entity(value.to_string()) {
+                Some(entity) => entity
+                    .labels()
+                    .iter()
+                    .chain(entity.aliases().iter())
+                    .any(|ls| ls.value().to_lowercase() == text),
+                None => false,
+            })
+            .and_then(|value| AppState::item2numeric(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract() {
+        assert_eq!(
+            StatementText::extract("Occupation: painter, sculptor; nationality: Dutch"),
+            vec![
+                (106, "painter".to_string()),
+                (106, "sculptor".to_string()),
+                (27, "Dutch".to_string())
+            ]
+        );
+        assert_eq!(
+            StatementText::extract("born in: Leiden|profession: painter|occupation: painter"),
+            vec![(19, "Leiden".to_string()), (106, "painter".to_string())]
+        );
+        assert!(StatementText::extract("Dutch painter, 1606-1669").is_empty());
+        assert!(StatementText::extract("hair colour: brown").is_empty());
+    }
+
+    #[test]
+    fn test_from_row() {
+        let st = StatementText::from_row((1, 2, 106, "painter".to_string(), Some(1028181), 1, 0));
+        assert_eq!(st.property, 106);
+        assert_eq!(st.q, Some(1028181));
+        assert!(st.in_wikidata);
+        assert!(!st.entry_is_matched);
+    }
+}
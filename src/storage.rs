@@ -1,23 +1,102 @@
 use crate::{
     automatch::{ResultInOriginalCatalog, ResultInOtherCatalog},
+    autoscrape_config::AutoscrapeConfig,
     auxiliary_matcher::AuxiliaryResults,
-    catalog::Catalog,
+    catalog::{Catalog, CatalogDefaultStatement},
     coordinate_matcher::LocationRow,
-    entry::{AuxiliaryRow, CoordinateLocation, Entry},
-    issue::Issue,
+    creation_candidates::CreationCandidateQuery,
+    entry::{
+        AuxiliaryRow, CoordinateLocation, Entry, EntryError, EntryProvenance, MatchEvent,
+        RemovedMatch,
+    },
+    entry_query::EntryQuery,
+    issue::{Issue, IssueType},
     job_row::JobRow,
     job_status::JobStatus,
     match_state::MatchState,
+    quick_compare::QuickCompareCatalog,
+    reference_fixer::ReferenceFixerMatch,
+    statement_text::StatementText,
     task_size::TaskSize,
     taxon_matcher::{RankedNames, TaxonNameField},
-    update_catalog::UpdateInfo,
+    update_catalog::{CatalogExtId, UpdateInfo},
+    user::User,
     PropTodo,
 };
 use anyhow::Result;
 use async_trait::async_trait;
 use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
 use wikimisc::wikibase::LocaleString;
 
+/// MySQL server error codes for duplicate keys and foreign key violations
+const MYSQL_CONSTRAINT_ERROR_CODES: &[u16] = &[1062, 1451, 1452];
+
+/// Errors at the storage boundary, so callers can tell a missing row from a database failure.
+/// Entry-specific storage failures (eg no insert ID for a new entry) are wrapped as `Entry`.
+#[derive(Debug)]
+pub enum StorageError {
+    NotFound(String),
+    Db(mysql_async::Error),
+    Constraint(String),
+    Invalid(String),
+    Entry(EntryError),
+}
+
+pub type StorageResult<T> = std::result::Result<T, StorageError>;
+
+impl StorageError {
+    /// Checks if an error is a `StorageError::NotFound`, eg from `Entry::from_id` for a deleted entry
+    pub fn is_not_found(error: &anyhow::Error) -> bool {
+        matches!(
+            error.downcast_ref::<StorageError>(),
+            Some(StorageError::NotFound(_))
+        )
+    }
+}
+
+impl Error for StorageError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            StorageError::Db(e) => Some(e),
+            StorageError::Entry(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StorageError::NotFound(s) => write!(f, "StorageError::NotFound: {s}"),
+            StorageError::Db(e) => write!(f, "StorageError::Db: {e}"),
+            StorageError::Constraint(s) => write!(f, "StorageError::Constraint: {s}"),
+            StorageError::Invalid(s) => write!(f, "StorageError::Invalid: {s}"),
+            StorageError::Entry(e) => write!(f, "StorageError::Entry: {e}"),
+        }
+    }
+}
+
+impl From<EntryError> for StorageError {
+    fn from(e: EntryError) -> Self {
+        StorageError::Entry(e)
+    }
+}
+
+impl From<mysql_async::Error> for StorageError {
+    fn from(e: mysql_async::Error) -> Self {
+        match &e {
+            mysql_async::Error::Server(server_error)
+                if MYSQL_CONSTRAINT_ERROR_CODES.contains(&server_error.code) =>
+            {
+                StorageError::Constraint(server_error.message.to_owned())
+            }
+            _ => StorageError::Db(e),
+        }
+    }
+}
+
 #[async_trait]
 pub trait Storage: std::fmt::Debug + Send + Sync {
     // fn new(j: &Value) -> impl Storage;
@@ -32,8 +111,8 @@ pub trait Storage: std::fmt::Debug + Send + Sync {
         field: &TaxonNameField,
         catalog_id: usize,
         batch_size: usize,
-        offset: usize,
-    ) -> Result<(usize, RankedNames)>;
+        after_entry_id: usize,
+    ) -> Result<(usize, usize, RankedNames)>;
 
     // Coordinate matcher
 
@@ -53,17 +132,61 @@ pub trait Storage: std::fmt::Debug + Send + Sync {
         catalog_id: usize,
         ext_ids: &[String],
     ) -> Result<Vec<String>>;
+    /// The values of the entries of a catalog with the given ext_ids, by ext_id
+    async fn get_external_ids(
+        &self,
+        catalog_id: usize,
+        ext_ids: &[String],
+    ) -> Result<HashMap<String, CatalogExtId>>;
+    /// (entry ID, ext_id) of the next `batch_size` entries of a catalog after `after_entry_id`, ordered by entry ID
+    async fn get_ext_ids_batch(
+        &self,
+        catalog_id: usize,
+        after_entry_id: usize,
+        batch_size: usize,
+    ) -> Result<Vec<(usize, String)>>;
     async fn update_catalog_get_update_info(&self, catalog_id: usize) -> Result<Vec<UpdateInfo>>;
 
     // Catalog
 
     async fn number_of_entries_in_catalog(&self, catalog_id: usize) -> Result<usize>;
-    async fn get_catalog_from_id(&self, catalog_id: usize) -> Result<Catalog>;
+    async fn get_catalog_from_id(&self, catalog_id: usize) -> StorageResult<Catalog>;
+    async fn get_catalogs_by_property(
+        &self,
+        prop_numeric: usize,
+        active_only: bool,
+    ) -> Result<Vec<Catalog>>;
     async fn get_catalog_key_value_pairs(
         &self,
         catalog_id: usize,
     ) -> Result<HashMap<String, String>>;
+    async fn set_catalog_key_value_pair(
+        &self,
+        catalog_id: usize,
+        key: &str,
+        value: &str,
+    ) -> Result<()>;
+    async fn delete_catalog_key_value_pair(&self, catalog_id: usize, key: &str) -> Result<()>;
     async fn catalog_refresh_overview_table(&self, catalog_id: usize) -> Result<()>;
+    async fn refresh_all_overview_tables(&self) -> Result<usize>;
+    async fn finalize_catalog_overview(&self, catalog_id: usize) -> Result<()>;
+    async fn get_catalog_id_by_name(&self, name: &str) -> Result<Option<usize>>;
+    async fn get_catalog_from_source_item(&self, source_item: usize) -> Result<Option<Catalog>>;
+    async fn get_active_catalogs_with_property(&self) -> Result<Vec<(usize, usize)>>;
+    async fn create_catalog(&self, catalog: &Catalog) -> Result<usize>;
+    async fn catalog_set_active(&self, catalog_id: usize, active: bool) -> Result<()>;
+    async fn catalog_delete(&self, catalog_id: usize) -> Result<()>;
+    async fn get_catalog_default_statements(
+        &self,
+        catalog_id: usize,
+    ) -> Result<Vec<CatalogDefaultStatement>>;
+    async fn add_catalog_default_statement(&self, ds: &CatalogDefaultStatement) -> Result<usize>;
+    async fn delete_catalog_default_statement(&self, catalog_id: usize, id: usize) -> Result<()>;
+
+    // User
+
+    async fn get_user_by_id(&self, user_id: usize) -> Result<Option<User>>;
+    async fn get_or_create_user(&self, name: &str) -> Result<usize>;
 
     // Microsync
 
@@ -90,25 +213,102 @@ pub trait Storage: std::fmt::Debug + Send + Sync {
         q: Option<isize>,
     ) -> Result<()>;
     async fn queue_reference_fixer(&self, q_numeric: isize) -> Result<()>;
+    /// Returns up to `limit` queued items greater than `after_q`, in order
+    async fn reference_fixer_get_queued(&self, after_q: isize, limit: usize) -> Result<Vec<isize>>;
+    async fn reference_fixer_set_done(&self, q_numeric: isize) -> Result<()>;
+    async fn reference_fixer_get_matches(
+        &self,
+        q_numeric: isize,
+    ) -> Result<Vec<ReferenceFixerMatch>>;
     async fn avoid_auto_match(&self, entry_id: usize, q_numeric: Option<isize>) -> Result<bool>;
-    async fn get_random_active_catalog_id_with_property(&self) -> Option<usize>;
+    async fn get_removed_matches_log(&self, catalog_id: usize) -> Result<Vec<RemovedMatch>>;
+    async fn clear_removed_matches_log(&self, entry_id: usize) -> Result<()>;
+    /// Returns manual matches and match removals, optionally for one catalog and since a timestamp, newest first
+    async fn get_recent_match_events(
+        &self,
+        catalog_id: Option<usize>,
+        since_ts: Option<String>,
+        limit: usize,
+    ) -> Result<Vec<MatchEvent>>;
+    /// Returns the first active catalog with a property (and no qualifier) after a catalog ID, in ID order,
+    /// or the first one overall if there is none after it
+    async fn get_next_active_catalog_id_with_property(
+        &self,
+        after_catalog_id: usize,
+    ) -> Result<Option<usize>>;
     async fn get_kv_value(&self, key: &str) -> Result<Option<String>>;
     async fn set_kv_value(&self, key: &str, value: &str) -> Result<()>;
 
+    // Quick compare
+
+    async fn quick_compare_get_entries(
+        &self,
+        catalog_id: usize,
+        require_coordinates: bool,
+        max_results: usize,
+    ) -> Result<Vec<Entry>>;
+    async fn get_quick_compare_catalogs(
+        &self,
+        min_auto_matches: usize,
+        excluded_catalogs: &[usize],
+    ) -> Result<Vec<QuickCompareCatalog>>;
+    async fn confirm_auto_matches(&self, entry_ids: &[usize], user_id: usize) -> Result<usize>;
+    async fn reject_auto_matches(&self, entry_ids: &[usize], user_id: usize) -> Result<usize>;
+    /// Removes all matches by `user_id` in a catalog; the removals are logged as done by `operator_user_id`
+    async fn unmatch_by_user_in_catalog(
+        &self,
+        catalog_id: usize,
+        user_id: usize,
+        operator_user_id: usize,
+    ) -> Result<usize>;
+
     // Issue
 
     async fn issue_insert(&self, issue: &Issue) -> Result<()>;
+    async fn get_entries_with_open_issues(
+        &self,
+        issue_type: IssueType,
+        catalog_id: Option<usize>,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<(Entry, Issue)>>;
+    async fn get_issue_counts_by_catalog(
+        &self,
+    ) -> Result<HashMap<usize, HashMap<IssueType, usize>>>;
 
     // Autoscrape
 
     async fn autoscrape_get_for_catalog(&self, catalog_id: usize) -> Result<Vec<(usize, String)>>;
+    async fn get_autoscrape_config(&self, catalog_id: usize) -> Result<Option<AutoscrapeConfig>>;
+    async fn autoscrape_get_owner(&self, catalog_id: usize) -> Result<Option<usize>>;
+    async fn save_autoscrape_config(
+        &self,
+        catalog_id: usize,
+        config: &AutoscrapeConfig,
+        owner: usize,
+    ) -> Result<()>;
     async fn autoscrape_get_entry_ids_for_ext_ids(
         &self,
         catalog_id: usize,
         ext_ids: &[String],
     ) -> Result<Vec<(String, usize)>>;
     async fn autoscrape_start(&self, autoscrape_id: usize) -> Result<()>;
+    async fn autoscrape_save_progress(&self, autoscrape_id: usize, position: &str) -> Result<()>;
+    async fn autoscrape_get_progress(&self, autoscrape_id: usize) -> Result<Option<String>>;
     async fn autoscrape_finish(&self, autoscrape_id: usize, last_run_urls: usize) -> Result<()>;
+    async fn autoscrape_record_run_result(
+        &self,
+        autoscrape_id: usize,
+        entries_found: usize,
+        is_test: bool,
+    ) -> Result<()>;
+    async fn autoscrape_get_recent_run_results(
+        &self,
+        autoscrape_id: usize,
+        limit: usize,
+    ) -> Result<Vec<usize>>;
+    async fn autoscrape_get_active(&self) -> Result<Vec<(usize, usize)>>;
+    async fn autoscrape_set_status(&self, autoscrape_id: usize, status: &str) -> Result<()>;
 
     // Auxiliary matcher
 
@@ -120,6 +320,15 @@ pub trait Storage: std::fmt::Debug + Send + Sync {
         extid_props: &[String],
         blacklisted_catalogs: &[String],
     ) -> Result<Vec<AuxiliaryResults>>;
+    /// The auxiliary values for `properties` of the next `batch_size` entries after `after_entry_id` that are not fully matched,
+    /// and have values for at least two of these properties; ordered by entry ID
+    async fn auxiliary_matcher_match_via_multiple_aux(
+        &self,
+        catalog_id: usize,
+        properties: &[usize],
+        after_entry_id: usize,
+        batch_size: usize,
+    ) -> Result<Vec<AuxiliaryResults>>;
     async fn auxiliary_matcher_add_auxiliary_to_wikidata(
         &self,
         blacklisted_properties: &[String],
@@ -134,6 +343,8 @@ pub trait Storage: std::fmt::Debug + Send + Sync {
     async fn add_props_todo(&self, new_props: Vec<PropTodo>) -> Result<()>;
     async fn mark_props_todo_as_has_catalog(&self) -> Result<()>;
     async fn set_props_todo_items_using(&self, prop_numeric: u64, cnt: u64) -> Result<()>;
+    async fn get_active_catalog_properties(&self) -> Result<Vec<u64>>;
+    async fn set_props_todo_number_of_records_floor(&self) -> Result<()>;
     async fn remove_p17_for_humans(&self) -> Result<()>;
     async fn cleanup_mnm_relations(&self) -> Result<()>;
     async fn create_match_person_dates_jobs_for_catalogs(&self) -> Result<()>;
@@ -148,12 +359,31 @@ pub trait Storage: std::fmt::Debug + Send + Sync {
     ) -> Result<Vec<(usize, String, Option<usize>, Option<usize>)>>;
     async fn maintenance_fix_redirects(&self, from: isize, to: isize) -> Result<()>;
     async fn maintenance_unlink_item_matches(&self, items: Vec<String>) -> Result<()>;
-    async fn maintenance_automatch(&self) -> Result<()>;
-    async fn maintenance_automatch_people_via_year_born(&self) -> Result<()>;
+    async fn maintenance_automatch(
+        &self,
+        excluded_catalogs: &[usize],
+        limit: usize,
+    ) -> Result<usize>;
+    async fn maintenance_fill_missing_random(&self, batch_size: usize) -> Result<usize>;
+    async fn maintenance_automatch_people_via_year_born(
+        &self,
+        excluded_catalogs: &[usize],
+    ) -> Result<()>;
     async fn maintenance_match_people_via_name_and_full_dates(
         &self,
         batch_size: usize,
+        excluded_catalogs: &[usize],
     ) -> Result<Vec<(usize, usize)>>;
+    /// Returns the next `limit` distinct items after `after_q` that entries are matched to by a user, optionally in a catalog,
+    /// ordered by item
+    async fn get_distinct_matched_items(
+        &self,
+        catalog_id: Option<usize>,
+        after_q: isize,
+        limit: usize,
+    ) -> Result<Vec<isize>>;
+    /// The ext_ids that more than one entry in a catalog has
+    async fn maintenance_get_duplicate_ext_ids(&self, catalog_id: usize) -> Result<Vec<String>>;
     async fn get_items(
         &self,
         catalog_id: usize,
@@ -174,6 +404,22 @@ pub trait Storage: std::fmt::Debug + Send + Sync {
         status: &str,
         timestamp: String,
     ) -> Result<usize>;
+    /// Adds a repeating job (as TODO); for an existing job, only the interval is updated
+    async fn jobs_queue_repeating_job(
+        &self,
+        catalog_id: usize,
+        action: &str,
+        repeat_after_sec: usize,
+        timestamp: String,
+    ) -> Result<()>;
+    /// Queues a job (as TODO) with initial job data, replacing the data of an existing job
+    async fn jobs_queue_job_with_json(
+        &self,
+        catalog_id: usize,
+        action: &str,
+        json_string: String,
+        timestamp: String,
+    ) -> Result<()>;
     async fn jobs_reset_json(&self, job_id: usize, timestamp: String) -> Result<()>;
     async fn jobs_set_json(
         &self,
@@ -189,6 +435,24 @@ pub trait Storage: std::fmt::Debug + Send + Sync {
         timestamp: String,
     ) -> Result<()>;
     async fn jobs_set_note(&self, note: Option<String>, job_id: usize) -> Result<Option<String>>;
+    async fn jobs_set_error(&self, job_id: usize, category: &str, details: String) -> Result<()>;
+    async fn jobs_get_error(&self, job_id: usize) -> Result<Option<(String, String)>>;
+    async fn jobs_increment_failures(&self, job_id: usize) -> Result<usize>;
+    async fn jobs_reset_failures(&self, job_id: usize) -> Result<()>;
+    async fn jobs_get_dead_letter(&self, min_failures: usize) -> Result<Vec<usize>>;
+    /// The number of jobs for each status
+    async fn jobs_get_status_counts(&self) -> Result<Vec<(String, usize)>>;
+    /// Number of RUNNING jobs with activity (`last_ts`) since `ts`
+    async fn jobs_count_running_since(&self, ts: &str) -> Result<usize>;
+    /// The `last_ts` of the most recently completed job
+    async fn jobs_get_last_done_ts(&self) -> Result<Option<String>>;
+    /// Sets the status of a job that is not RUNNING, and clears its failure counter, note, error, and `next_ts`
+    async fn jobs_requeue(
+        &self,
+        job_id: usize,
+        status: &JobStatus,
+        timestamp: String,
+    ) -> Result<()>;
     async fn jobs_update_next_ts(&self, job_id: usize, next_ts: String) -> Result<()>;
     async fn jobs_get_next_job(
         &self,
@@ -215,9 +479,7 @@ pub trait Storage: std::fmt::Debug + Send + Sync {
     ) -> Result<Vec<(usize, String)>>;
     async fn automatch_by_search_get_results(
         &self,
-        catalog_id: usize,
-        offset: usize,
-        batch_size: usize,
+        query: &EntryQuery,
     ) -> Result<Vec<(usize, String, String, String)>>;
     async fn automatch_creations_get_results(
         &self,
@@ -225,9 +487,7 @@ pub trait Storage: std::fmt::Debug + Send + Sync {
     ) -> Result<Vec<(String, usize, String)>>;
     async fn automatch_simple_get_results(
         &self,
-        catalog_id: usize,
-        offset: usize,
-        batch_size: usize,
+        query: &EntryQuery,
     ) -> Result<Vec<(usize, String, String, String)>>;
     async fn automatch_from_other_catalogs_get_results(
         &self,
@@ -240,6 +500,11 @@ pub trait Storage: std::fmt::Debug + Send + Sync {
         results_in_original_catalog: &[ResultInOriginalCatalog],
         ext_names: Vec<String>,
     ) -> Result<Vec<ResultInOtherCatalog>>;
+    /// Fully matched entries in active catalogs with one of these `ext_url`s, as (ext_url, catalog ID, q)
+    async fn find_matched_entries_by_ext_url(
+        &self,
+        urls: &[String],
+    ) -> Result<Vec<(String, usize, isize)>>;
     async fn purge_automatches(&self, catalog_id: usize) -> Result<()>;
     async fn match_person_by_dates_get_results(
         &self,
@@ -261,21 +526,56 @@ pub trait Storage: std::fmt::Debug + Send + Sync {
         offset: usize,
         batch_size: usize,
     ) -> Result<Vec<(usize, String)>>;
+    async fn automatch_get_max_entry_id(&self, catalog_id: usize) -> Result<Option<usize>>;
+    /// Remembers the highest entry ID an automatch action has seen for a catalog
+    async fn automatch_set_watermark(
+        &self,
+        catalog_id: usize,
+        action: &str,
+        max_entry_id: usize,
+    ) -> Result<()>;
+    /// Returns (catalog ID, action, watermark) for active catalogs that have entries beyond the watermark of an action,
+    /// and no queued or running job for that action
+    async fn automatch_get_catalogs_with_new_entries(&self) -> Result<Vec<(usize, String, usize)>>;
 
     // Entry
 
-    async fn entry_from_id(&self, entry_id: usize) -> Result<Entry>;
+    async fn entry_from_id(&self, entry_id: usize) -> StorageResult<Entry>;
     async fn entry_from_ext_id(&self, catalog_id: usize, ext_id: &str) -> Result<Entry>;
     async fn multiple_from_ids(&self, entry_ids: &[usize]) -> Result<HashMap<usize, Entry>>;
+    async fn get_entry_ids_by_ext_id(
+        &self,
+        catalog_ids: &[usize],
+        ext_id: &str,
+    ) -> Result<Vec<usize>>;
+    async fn get_entry_ids_by_aux(
+        &self,
+        prop_numeric: usize,
+        value: &str,
+        active_only: bool,
+    ) -> Result<Vec<usize>>;
     async fn get_entry_batch(
         &self,
         catalog_id: usize,
         limit: usize,
         offset: usize,
     ) -> Result<Vec<Entry>>;
-    async fn entry_insert_as_new(&self, entry: &Entry) -> Result<usize>;
+    async fn get_entry_batch_after(
+        &self,
+        catalog_id: usize,
+        after_id: usize,
+        limit: usize,
+    ) -> Result<Vec<Entry>>;
+    async fn get_entry_query(&self, query: &EntryQuery) -> Result<Vec<Entry>>;
+    async fn get_entries_for_creation(
+        &self,
+        query: &CreationCandidateQuery,
+    ) -> Result<Vec<(Entry, usize)>>;
+    /// Inserts a new entry; `import_id` is the `update_info` ID of the import that created it, if any
+    async fn entry_insert_as_new(&self, entry: &Entry, import_id: Option<usize>) -> Result<usize>;
     async fn entry_delete(&self, entry_id: usize) -> Result<()>;
     async fn entry_get_creation_time(&self, entry_id: usize) -> Option<String>;
+    async fn get_entry_provenance(&self, entry_id: usize) -> Result<Option<EntryProvenance>>;
     async fn entry_set_ext_name(&self, ext_name: &str, entry_id: usize) -> Result<()>;
     async fn entry_set_auxiliary_in_wikidata(&self, in_wikidata: bool, aux_id: usize)
         -> Result<()>;
@@ -311,6 +611,29 @@ pub trait Storage: std::fmt::Debug + Send + Sync {
         &self,
         entry_id: usize,
     ) -> Result<HashMap<String, String>>;
+    async fn entries_get_aliases(
+        &self,
+        entry_ids: &[usize],
+    ) -> Result<HashMap<usize, Vec<LocaleString>>>;
+    async fn entries_get_language_descriptions(
+        &self,
+        entry_ids: &[usize],
+    ) -> Result<HashMap<usize, HashMap<String, String>>>;
+    /// (born, died) of several entries, as `entry_get_person_dates`; entries without dates are missing
+    async fn entries_get_person_dates(
+        &self,
+        entry_ids: &[usize],
+    ) -> Result<HashMap<usize, (Option<String>, Option<String>)>>;
+    /// Auxiliary data of several entries, as `entry_get_aux`; entries without auxiliary data are missing
+    async fn entries_get_aux(
+        &self,
+        entry_ids: &[usize],
+    ) -> Result<HashMap<usize, Vec<AuxiliaryRow>>>;
+    /// Coordinates of several entries, as `entry_get_coordinate_location`; entries without coordinates are missing
+    async fn entries_get_coordinate_locations(
+        &self,
+        entry_ids: &[usize],
+    ) -> Result<HashMap<usize, CoordinateLocation>>;
     async fn entry_remove_auxiliary(&self, entry_id: usize, prop_numeric: usize) -> Result<()>;
     async fn entry_set_auxiliary(
         &self,
@@ -330,6 +653,21 @@ pub trait Storage: std::fmt::Debug + Send + Sync {
         entry_id: usize,
     ) -> Result<Option<CoordinateLocation>>;
     async fn entry_get_aux(&self, entry_id: usize) -> Result<Vec<AuxiliaryRow>>;
+    async fn entry_get_statement_texts(&self, entry_id: usize) -> Result<Vec<StatementText>>;
+    /// Adds a statement text to an entry, unless it already has it. Returns `true` if it was added.
+    async fn entry_add_statement_text(
+        &self,
+        entry_id: usize,
+        property: usize,
+        text: &str,
+    ) -> Result<bool>;
+    async fn statement_text_get_unresolved(
+        &self,
+        catalog_id: usize,
+        offset: usize,
+        batch_size: usize,
+    ) -> Result<Vec<(StatementText, isize)>>;
+    async fn statement_text_set_resolved(&self, id: usize, q: isize) -> Result<()>;
     async fn entry_set_match(
         &self,
         entry: &Entry,
@@ -337,6 +675,18 @@ pub trait Storage: std::fmt::Debug + Send + Sync {
         q_numeric: isize,
         timestamp: &str,
     ) -> Result<bool>;
+    async fn entry_set_match_batch(
+        &self,
+        matches: &[(Entry, isize)],
+        user_id: usize,
+    ) -> Result<usize>;
+    async fn entry_set_match_without_overview(
+        &self,
+        entry: &Entry,
+        user_id: usize,
+        q_numeric: isize,
+        timestamp: &str,
+    ) -> Result<bool>;
     async fn entry_set_match_status(
         &self,
         entry_id: usize,
@@ -346,11 +696,58 @@ pub trait Storage: std::fmt::Debug + Send + Sync {
     async fn entry_remove_multi_match(&self, entry_id: usize) -> Result<()>;
     async fn entry_unmatch(&self, entry_id: usize) -> Result<()>;
     async fn entry_get_multi_matches(&self, entry_id: usize) -> Result<Vec<String>>;
+    async fn entry_set_kv(&self, entry_id: usize, key: &str, value: &str) -> Result<()>;
+    async fn entry_set_no_automatch(&self, entry_id: usize, no_automatch: bool) -> Result<()>;
+    async fn entry_get_no_automatch(&self, entry_id: usize) -> Result<bool>;
     async fn entry_set_multi_match(
         &self,
         entry_id: usize,
         candidates: String,
         candidates_count: usize,
     ) -> Result<()>;
-    async fn app_state_seppuku_get_running(&self, ts: &str) -> (usize, usize);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_storage_error_from_mysql_error() {
+        let server_error = mysql_async::ServerError {
+            code: 1062,
+            message: "Duplicate entry".to_string(),
+            state: "23000".to_string(),
+        };
+        let error: StorageError = mysql_async::Error::Server(server_error).into();
+        assert!(matches!(error, StorageError::Constraint(ref s) if s == "Duplicate entry"));
+
+        let server_error = mysql_async::ServerError {
+            code: 1146,
+            message: "Table doesn't exist".to_string(),
+            state: "42S02".to_string(),
+        };
+        let error: StorageError = mysql_async::Error::Server(server_error).into();
+        assert!(matches!(error, StorageError::Db(_)));
+    }
+
+    #[test]
+    fn test_storage_error_display() {
+        let error = StorageError::NotFound("No entry #123".to_string());
+        assert_eq!(error.to_string(), "StorageError::NotFound: No entry #123");
+        let error: StorageError = EntryError::EntryInsertFailed.into();
+        assert_eq!(
+            error.to_string(),
+            "StorageError::Entry: EntryError::EntryInsertFailed"
+        );
+    }
+
+    #[test]
+    fn test_storage_error_is_not_found() {
+        let e = anyhow::Error::from(StorageError::NotFound("No entry #123".to_string()));
+        assert!(StorageError::is_not_found(&e));
+        let e = anyhow::Error::from(StorageError::Invalid("Bad row".to_string()));
+        assert!(!StorageError::is_not_found(&e));
+        let e = anyhow::anyhow!("No entry #123");
+        assert!(!StorageError::is_not_found(&e));
+    }
 }
@@ -1,32 +1,72 @@
 pub use crate::storage::Storage;
 use crate::{
-    app_state::USER_AUTO,
+    app_state::{USER_AUTO, USER_AUX_MATCH, USER_DATE_MATCH},
     automatch::{ResultInOriginalCatalog, ResultInOtherCatalog},
+    autoscrape_config::AutoscrapeConfig,
     auxiliary_matcher::AuxiliaryResults,
-    catalog::Catalog,
+    catalog::{Catalog, CatalogDefaultStatement, CatalogError},
     coordinate_matcher::LocationRow,
-    entry::{AuxiliaryRow, CoordinateLocation, Entry, EntryError},
-    issue::Issue,
+    creation_candidates::CreationCandidateQuery,
+    entry::{
+        AuxiliaryRow, CoordinateLocation, Entry, EntryError, EntryProvenance, MatchEvent,
+        RemovedMatch, KV_NO_AUTOMATCH,
+    },
+    entry_query::EntryQuery,
+    issue::{Issue, IssueRow, IssueType},
     job_row::JobRow,
     job_status::JobStatus,
     match_state::MatchState,
     microsync::EXT_URL_UNIQUE_SEPARATOR,
-    mysql_misc::MySQLMisc,
+    mysql_misc::{get_conn_with_timeout, MySQLMisc, PoolConfig, TOOLFORGE_MAX_DB_CONNECTIONS},
+    quick_compare::QuickCompareCatalog,
+    reference_fixer::ReferenceFixerMatch,
+    statement_text::StatementText,
+    storage::{StorageError, StorageResult},
     task_size::TaskSize,
     taxon_matcher::{RankedNames, TaxonMatcher, TaxonNameField, TAXON_RANKS},
-    update_catalog::UpdateInfo,
+    update_catalog::{CatalogExtId, UpdateInfo},
+    user::User,
     PropTodo,
 };
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
 use itertools::Itertools;
 use mysql_async::Params::Empty;
-use mysql_async::{from_row, futures::GetConn, prelude::*, Params, Row};
+use mysql_async::{from_row, prelude::*, Conn, Params, Row, TxOpts};
 use rand::prelude::*;
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::time::Duration;
+use tracing::{info, warn};
 use wikimisc::{timestamp::TimeStamp, wikibase::LocaleString};
 
+/// Maximum number of matches written by a single `entry_set_match_batch` UPDATE
+const ENTRY_SET_MATCH_BATCH_SIZE: usize = 1000;
+
+/// Recomputes the overview table row of `:catalog_id` from its entries
+const OVERVIEW_REFRESH_SQL: &str = r"REPLACE INTO `overview` (catalog,total,noq,autoq,na,manual,nowd,multi_match,types) VALUES (
+	        :catalog_id,
+	        (SELECT count(*) FROM `entry` WHERE `catalog`=:catalog_id),
+	        (SELECT count(*) FROM `entry` WHERE `catalog`=:catalog_id AND `q` IS NULL),
+	        (SELECT count(*) FROM `entry` WHERE `catalog`=:catalog_id AND `user`=0),
+	        (SELECT count(*) FROM `entry` WHERE `catalog`=:catalog_id AND `q`=0),
+	        (SELECT count(*) FROM `entry` WHERE `catalog`=:catalog_id AND `q` IS NOT NULL AND `user`>0),
+	        (SELECT count(*) FROM `entry` WHERE `catalog`=:catalog_id AND `q`=-1),
+	        (SELECT count(*) FROM `multi_match` WHERE `catalog`=:catalog_id),
+	        (SELECT group_concat(DISTINCT `type` SEPARATOR '|') FROM `entry` WHERE `catalog`=:catalog_id)
+	        )";
+
+/// Tables with a flag that mirrors whether the entry is fully matched, with the name of that column
+const ENTRY_IS_MATCHED_COLUMNS: &[(&str, &str)] = &[
+    ("person_dates", "is_matched"),
+    ("auxiliary", "entry_is_matched"),
+    ("statement_text", "entry_is_matched"),
+];
+
+/// `kv_catalog` key for the position of a running autoscraper of the catalog, see `autoscrape_save_progress`
+pub const KV_AUTOSCRAPE_PROGRESS: &str = "autoscrape_progress";
+
 pub const TABLES_WITH_ENTRY_ID_FIELDS: &[&str] = &[
     "aliases",
     "descriptions",
@@ -43,10 +83,22 @@ pub const TABLES_WITH_ENTRY_ID_FIELDS: &[&str] = &[
     "statement_text",
 ];
 
+/// A match in `entry_set_match_batch_chunk` that changes an entry, with the state it changes from
+#[derive(Debug, Clone, Copy)]
+struct OverviewChange {
+    entry_id: usize,
+    catalog_id: usize,
+    old_user: Option<usize>,
+    old_q: Option<isize>,
+    q: isize,
+}
+
 #[derive(Debug)]
 pub struct StorageMySQL {
     pool: mysql_async::Pool,
     pool_ro: mysql_async::Pool,
+    connect_timeout: Option<Duration>,
+    connect_timeout_ro: Option<Duration>,
 }
 
 impl MySQLMisc for StorageMySQL {
@@ -56,43 +108,60 @@ impl MySQLMisc for StorageMySQL {
 }
 
 impl StorageMySQL {
+    /// `AND column NOT IN (...)`, or nothing for an empty list, which would be invalid SQL
+    fn sql_and_not_in(column: &str, values: &[String]) -> String {
+        if values.is_empty() {
+            String::new()
+        } else {
+            format!(" AND {column} NOT IN ({})", values.join(","))
+        }
+    }
+
     pub fn new(j: &Value, j_ro: &Value) -> Self {
+        // The read-write pool counts against the per-user connection limit of the tool database
+        let pool_config = PoolConfig::from_json(j)
+            .and_then(|pc| pc.check_max_connections(TOOLFORGE_MAX_DB_CONNECTIONS))
+            .expect("Invalid DB pool config");
+        let pool_config_ro = PoolConfig::from_json(j_ro).expect("Invalid DB pool config");
         Self {
-            pool: Self::create_pool(j),
-            pool_ro: Self::create_pool(j_ro),
+            pool: Self::create_pool_from_config(&pool_config),
+            pool_ro: Self::create_pool_from_config(&pool_config_ro),
+            connect_timeout: pool_config.connect_timeout,
+            connect_timeout_ro: pool_config_ro.connect_timeout,
         }
     }
 
-    fn get_conn(&self) -> GetConn {
-        self.pool.get_conn()
+    fn get_conn(&self) -> impl Future<Output = Result<Conn, mysql_async::Error>> {
+        get_conn_with_timeout(self.pool.clone(), self.connect_timeout)
     }
 
-    fn get_conn_ro(&self) -> GetConn {
-        self.pool_ro.get_conn()
+    fn get_conn_ro(&self) -> impl Future<Output = Result<Conn, mysql_async::Error>> {
+        get_conn_with_timeout(self.pool_ro.clone(), self.connect_timeout_ro)
     }
 
+    /// Builds the main coordinate matcher query. `random` is only used without a catalog ID,
+    /// to pick a random sample of `max_results` locations outside `bad_catalogs`.
     fn coordinate_matcher_main_query_sql(
         catalog_id: &Option<usize>,
         bad_catalogs: &[usize],
         max_results: usize,
+        random: f64,
     ) -> String {
-        let conditions = match catalog_id {
-            Some(catalog_id) => format!("`catalog`={catalog_id}"),
+        let mut sql = "SELECT `lat`,`lon`,`id`,`catalog`,`ext_name`,`type`,`q` FROM `vw_location` WHERE `ext_name`!=''".to_string();
+        let order_limit = match catalog_id {
+            Some(catalog_id) => {
+                sql += &format!(" AND `catalog`={catalog_id}");
+                String::new()
+            }
             None => {
-                let r: f64 = rand::thread_rng().gen();
-                let mut sql = format!("`random`>={r} ORDER BY `random` LIMIT {max_results}");
-                if !bad_catalogs.is_empty() {
-                    let s = bad_catalogs
-                        .iter()
-                        .map(|id| format!("{id}"))
-                        .collect::<Vec<String>>()
-                        .join(",");
-                    sql += &format!("AND `catalog` NOT IN ({s})");
-                }
-                sql
+                sql += &format!(" AND `random`>={random}");
+                sql += &Self::catalog_exclusion_sql("`catalog`", bad_catalogs);
+                format!(" ORDER BY `random` LIMIT {max_results}")
             }
-        } + &MatchState::not_fully_matched().get_sql();
-        format!("SELECT `lat`,`lon`,`id`,`catalog`,`ext_name`,`type`,`q` FROM `vw_location` WHERE `ext_name`!='' AND {conditions}",)
+        };
+        sql += &MatchState::not_fully_matched().get_sql();
+        sql += &order_limit;
+        sql
     }
 
     fn location_row_from_row(row: &Row) -> Option<LocationRow> {
@@ -107,6 +176,10 @@ impl StorageMySQL {
         })
     }
 
+    fn catalog_sql_select() -> String {
+        r"SELECT id,`name`,url,`desc`,`type`,wd_prop,wd_qual,search_wp,active,owner,note,source_item,has_person_date,taxon_run FROM `catalog`".into()
+    }
+
     // #lizard forgives
     fn catalog_from_row(row: &Row) -> Option<Catalog> {
         Some(Catalog {
@@ -125,6 +198,7 @@ impl StorageMySQL {
             has_person_date: row.get(12)?,
             taxon_run: row.get(13)?,
             app: None,
+            kv_pairs: Default::default(),
         })
     }
 
@@ -133,10 +207,13 @@ impl StorageMySQL {
         entry: &Entry,
         user_id: usize,
         q_numeric: isize,
+        update_overview: bool,
     ) -> Result<bool> {
         // Update overview table and misc cleanup
-        self.update_overview_table(entry, Some(user_id), Some(q_numeric))
-            .await?;
+        if update_overview {
+            self.update_overview_table(entry, Some(user_id), Some(q_numeric))
+                .await?;
+        }
         let is_full_match = user_id > 0 && q_numeric > 0;
         let is_matched = if is_full_match { 1 } else { 0 };
         self.entry_set_match_status(entry.id, "UNKNOWN", is_matched)
@@ -148,6 +225,283 @@ impl StorageMySQL {
         Ok(true)
     }
 
+    async fn entry_set_match_batch_chunk(
+        &self,
+        matches: &[(Entry, isize)],
+        user_id: usize,
+    ) -> Result<usize> {
+        let matches = self.entry_set_match_batch_filter(matches, user_id).await?;
+        if matches.is_empty() {
+            return Ok(0);
+        }
+        let timestamp = TimeStamp::now();
+        let mut conn = self.get_conn().await?;
+        let mut tx = conn.start_transaction(TxOpts::default()).await?;
+
+        // The current state of the entries, locked; other writers may have changed them since they were loaded
+        let placeholders = Self::sql_placeholders(matches.len());
+        let sql = format!(
+            "SELECT `id`,`catalog`,`user`,`q` FROM `entry` WHERE `id` IN ({placeholders}) FOR UPDATE"
+        );
+        let entry_ids: Vec<usize> = matches.iter().map(|(entry, _q)| entry.id).collect();
+        let current: HashMap<usize, (usize, Option<usize>, Option<isize>)> = tx
+            .exec_iter(sql, entry_ids)
+            .await?
+            .map_and_drop(from_row::<(usize, usize, Option<usize>, Option<isize>)>)
+            .await?
+            .into_iter()
+            .map(|(id, catalog, user, q)| (id, (catalog, user, q)))
+            .collect();
+        let changes: Vec<OverviewChange> = matches
+            .iter()
+            .filter_map(|(entry, q)| {
+                let (catalog, old_user, old_q) = current.get(&entry.id)?;
+                Self::entry_set_match_batch_changes_row(user_id, *q, *old_user, *old_q).then_some(
+                    OverviewChange {
+                        entry_id: entry.id,
+                        catalog_id: *catalog,
+                        old_user: *old_user,
+                        old_q: *old_q,
+                        q: *q,
+                    },
+                )
+            })
+            .collect();
+        if changes.is_empty() {
+            tx.commit().await?;
+            return Ok(0);
+        }
+
+        let placeholders = Self::sql_placeholders(changes.len());
+        let cases = changes.iter().map(|_| "WHEN ? THEN ?").join(" ");
+        let mut sql = format!("UPDATE `entry` SET `q`=CASE `id` {cases} END,`user`=?,`timestamp`=? WHERE `id` IN ({placeholders})");
+        if user_id == USER_AUTO {
+            sql += &MatchState::not_fully_matched().get_sql();
+        }
+        let mut params: Vec<mysql_async::Value> = vec![];
+        for change in &changes {
+            params.push(change.entry_id.into());
+            params.push(change.q.into());
+        }
+        params.push(user_id.into());
+        params.push(timestamp.clone().into());
+        params.extend(changes.iter().map(|change| change.entry_id.into()));
+        tx.exec_drop(sql, Params::Positional(params)).await?;
+        let changed = tx.affected_rows() as usize;
+        for (catalog_id, set) in self.entry_set_match_batch_overview_sql(&changes, user_id) {
+            let sql = format!("UPDATE overview SET {set} WHERE catalog=:catalog_id");
+            tx.exec_drop(sql, params! {catalog_id}).await?;
+        }
+        tx.commit().await?;
+        drop(conn);
+
+        let changed_ids: HashSet<usize> = changes.iter().map(|change| change.entry_id).collect();
+        let matches: Vec<&(Entry, isize)> = matches
+            .into_iter()
+            .filter(|(entry, _q)| changed_ids.contains(&entry.id))
+            .collect();
+        self.entry_set_match_batch_cleanup(&matches, user_id, &timestamp)
+            .await?;
+        Ok(changed)
+    }
+
+    /// Checks if setting a match changes an entry in its current (locked) state;
+    /// the same rules as `entry_set_match_batch_filter`, and the `MatchState::not_fully_matched` condition for `USER_AUTO`
+    fn entry_set_match_batch_changes_row(
+        user_id: usize,
+        q: isize,
+        old_user: Option<usize>,
+        old_q: Option<isize>,
+    ) -> bool {
+        if old_q == Some(q) && old_user == Some(user_id) {
+            return false;
+        }
+        if user_id == USER_AUTO {
+            return old_q.is_none() || (old_q.unwrap_or(0) > 0 && old_user == Some(0));
+        }
+        true
+    }
+
+    /// Removes entries from a batch of matches that would not change, or that must not be auto-matched.
+    /// For `USER_AUTO`, this skips fully matched entries, entries flagged with `KV_NO_AUTOMATCH`,
+    /// and those where the log has a removed match (see `avoid_auto_match`).
+    async fn entry_set_match_batch_filter<'a>(
+        &self,
+        matches: &'a [(Entry, isize)],
+        user_id: usize,
+    ) -> Result<Vec<&'a (Entry, isize)>> {
+        let mut seen_entry_ids = HashSet::new();
+        let mut ret: Vec<&(Entry, isize)> = matches
+            .iter()
+            .filter(|(entry, q)| entry.q != Some(*q) || entry.user != Some(user_id))
+            .filter(|(entry, _q)| seen_entry_ids.insert(entry.id))
+            .collect();
+        if user_id != USER_AUTO || ret.is_empty() {
+            return Ok(ret);
+        }
+        ret.retain(|(entry, _q)| !entry.is_fully_matched());
+        if ret.is_empty() {
+            return Ok(ret);
+        }
+        let entry_ids: Vec<usize> = ret.iter().map(|(entry, _q)| entry.id).collect();
+        let placeholders = Self::sql_placeholders(entry_ids.len());
+        let sql = format!("SELECT `entry_id`,`q` FROM `log` WHERE `entry_id` IN ({placeholders})");
+        let removed: Vec<(usize, Option<isize>)> = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, entry_ids)
+            .await?
+            .map_and_drop(from_row::<(usize, Option<isize>)>)
+            .await?;
+        ret.retain(|(entry, q)| {
+            !removed.iter().any(|(entry_id, log_q)| {
+                *entry_id == entry.id && (log_q.is_none() || *log_q == Some(*q))
+            })
+        });
+        if ret.is_empty() {
+            return Ok(ret);
+        }
+        let entry_ids: Vec<mysql_async::Value> = ret
+            .iter()
+            .map(|(entry, _q)| entry.id.into())
+            .chain(std::iter::once(KV_NO_AUTOMATCH.into()))
+            .collect();
+        let placeholders = Self::sql_placeholders(entry_ids.len() - 1);
+        let sql = format!("SELECT `entry_id` FROM `kv_entry` WHERE `entry_id` IN ({placeholders}) AND `kv_key`=? AND `kv_value`='1'");
+        let no_automatch: HashSet<usize> = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, entry_ids)
+            .await?
+            .map_and_drop(from_row::<usize>)
+            .await?
+            .into_iter()
+            .collect();
+        ret.retain(|(entry, _q)| !no_automatch.contains(&entry.id));
+        Ok(ret)
+    }
+
+    /// Returns (catalog ID, SET clause) for the overview table changes of a batch of matches, one per catalog.
+    fn entry_set_match_batch_overview_sql(
+        &self,
+        changes: &[OverviewChange],
+        user_id: usize,
+    ) -> Vec<(usize, String)> {
+        let mut deltas: HashMap<usize, HashMap<&str, i64>> = HashMap::new();
+        for change in changes {
+            let add_column =
+                self.get_overview_column_name_for_user_and_q(&Some(user_id), &Some(change.q));
+            let reduce_column =
+                self.get_overview_column_name_for_user_and_q(&change.old_user, &change.old_q);
+            let catalog_deltas = deltas.entry(change.catalog_id).or_default();
+            *catalog_deltas.entry(add_column).or_default() += 1;
+            *catalog_deltas.entry(reduce_column).or_default() -= 1;
+        }
+        deltas
+            .into_iter()
+            .filter_map(|(catalog_id, catalog_deltas)| {
+                let set = catalog_deltas
+                    .iter()
+                    .filter(|(_column, delta)| **delta != 0)
+                    .map(|(column, delta)| format!("{column}={column}+({delta})"))
+                    .join(",");
+                (!set.is_empty()).then_some((catalog_id, set))
+            })
+            .collect()
+    }
+
+    /// The batch version of `entry_set_match_cleanup`, without the overview update.
+    async fn entry_set_match_batch_cleanup(
+        &self,
+        matches: &[&(Entry, isize)],
+        user_id: usize,
+        timestamp: &str,
+    ) -> Result<()> {
+        let entry_ids: Vec<usize> = matches.iter().map(|(entry, _q)| entry.id).collect();
+        let placeholders = Self::sql_placeholders(entry_ids.len());
+        let mut conn = self.get_conn().await?;
+
+        let values = matches.iter().map(|_| "(?,'UNKNOWN',?,?)").join(",");
+        let sql = format!("INSERT INTO `wd_matches` (`entry_id`,`status`,`timestamp`,`catalog`) VALUES {values} ON DUPLICATE KEY UPDATE `status`=VALUES(`status`),`timestamp`=VALUES(`timestamp`)");
+        let params: Vec<mysql_async::Value> = matches
+            .iter()
+            .flat_map(|(entry, _q)| {
+                [
+                    entry.id.into(),
+                    timestamp.to_string().into(),
+                    entry.catalog.into(),
+                ]
+            })
+            .collect();
+        conn.exec_drop(sql, Params::Positional(params)).await?;
+
+        let (full, other): (Vec<_>, Vec<_>) = matches
+            .iter()
+            .partition(|(_entry, q)| user_id > 0 && *q > 0);
+        for (group, is_matched) in [(full, 1), (other, 0)] {
+            if group.is_empty() {
+                continue;
+            }
+            let group_ids: Vec<usize> = group.iter().map(|(entry, _q)| entry.id).collect();
+            let group_placeholders = Self::sql_placeholders(group_ids.len());
+            for (table, column) in [
+                ("person_dates", "is_matched"),
+                ("auxiliary", "entry_is_matched"),
+                ("statement_text", "entry_is_matched"),
+            ] {
+                let sql = format!("UPDATE `{table}` SET `{column}`={is_matched} WHERE `entry_id` IN ({group_placeholders})");
+                conn.exec_drop(sql, group_ids.clone()).await?;
+            }
+        }
+
+        if user_id != USER_AUTO {
+            let sql = format!("DELETE FROM `multi_match` WHERE `entry_id` IN ({placeholders})");
+            conn.exec_drop(sql, entry_ids).await?;
+        }
+
+        let qs: Vec<isize> = matches.iter().map(|(_entry, q)| *q).unique().collect();
+        Self::queue_reference_fixer_batch(&mut conn, &qs).await?;
+        Ok(())
+    }
+
+    /// Queues items for the reference fixer, `ENTRY_SET_MATCH_BATCH_SIZE` per query; works in a transaction as well
+    async fn queue_reference_fixer_batch(conn: &mut impl Queryable, qs: &[isize]) -> Result<()> {
+        for chunk in qs.chunks(ENTRY_SET_MATCH_BATCH_SIZE) {
+            let values = chunk.iter().map(|_| "(?,0)").join(",");
+            let sql = format!("INSERT INTO `reference_fixer` (`q`,`done`) VALUES {values} ON DUPLICATE KEY UPDATE `done`=0");
+            conn.exec_drop(sql, chunk.to_vec()).await?;
+        }
+        Ok(())
+    }
+
+    async fn entry_set_match_internal(
+        &self,
+        entry: &Entry,
+        user_id: usize,
+        q_numeric: isize,
+        timestamp: &str,
+        update_overview: bool,
+    ) -> Result<bool> {
+        let entry_id = entry.id;
+        let mut sql = "UPDATE `entry` SET `q`=:q_numeric,`user`=:user_id,`timestamp`=:timestamp WHERE `id`=:entry_id AND (`q` IS NULL OR `q`!=:q_numeric OR `user`!=:user_id)".to_string();
+        if user_id == USER_AUTO {
+            if self.avoid_auto_match(entry_id, Some(q_numeric)).await? {
+                return Ok(false); // Nothing wrong but shouldn't be matched
+            }
+            sql += &MatchState::not_fully_matched().get_sql();
+        }
+        let mut conn = self.get_conn().await?;
+        conn.exec_drop(sql, params! {q_numeric,user_id,timestamp,entry_id})
+            .await?;
+        let nothing_changed = conn.affected_rows() == 0;
+        drop(conn);
+        if nothing_changed {
+            return Ok(false);
+        }
+        self.entry_set_match_cleanup(entry, user_id, q_numeric, update_overview)
+            .await
+    }
+
     /// Computes the column of the overview table that is affected, given a user ID and item ID
     fn get_overview_column_name_for_user_and_q(
         &self,
@@ -164,6 +518,28 @@ impl StorageMySQL {
         }
     }
 
+    /// Returns an SQL condition excluding the given catalogs for `field`, or an empty string.
+    fn catalog_exclusion_sql(field: &str, excluded_catalogs: &[usize]) -> String {
+        if excluded_catalogs.is_empty() {
+            return String::new();
+        }
+        let catalogs = excluded_catalogs.iter().map(|id| format!("{id}")).join(",");
+        format!(" AND {field} NOT IN ({catalogs})")
+    }
+
+    /// Returns the distinct catalogs of the given entries
+    async fn get_catalogs_for_entry_ids(&self, entry_ids_str: &str) -> Result<Vec<usize>> {
+        let sql = format!("SELECT DISTINCT `catalog` FROM `entry` WHERE `id` IN ({entry_ids_str})");
+        let ret = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, ())
+            .await?
+            .map_and_drop(from_row::<usize>)
+            .await?;
+        Ok(ret)
+    }
+
     fn jobs_get_next_job_construct_sql(
         &self,
         status: JobStatus,
@@ -223,13 +599,123 @@ impl StorageMySQL {
         })
     }
 
+    /// Returns the `WHERE` clause (possibly empty) and positional parameters for an `EntryQuery`.
+    fn get_entry_query_sql_where(query: &EntryQuery) -> (String, Vec<mysql_async::Value>) {
+        let mut conditions = vec![];
+        let mut params: Vec<mysql_async::Value> = vec![];
+        if let Some(catalog_id) = query.catalog_id {
+            conditions.push("`catalog`=?".to_string());
+            params.push(catalog_id.into());
+        }
+        if let Some(match_state) = &query.match_state {
+            conditions.push(match_state.get_sql_condition());
+        }
+        if let Some(type_name) = &query.type_name {
+            conditions.push("`type`=?".to_string());
+            params.push(type_name.to_owned().into());
+        }
+        if let Some(name_regexp) = &query.name_regexp {
+            conditions.push("`ext_name` RLIKE ?".to_string());
+            params.push(name_regexp.to_owned().into());
+        }
+        if let Some(name_not_regexp) = &query.name_not_regexp {
+            conditions.push("`ext_name` NOT RLIKE ?".to_string());
+            params.push(name_not_regexp.to_owned().into());
+        }
+        if let Some(min_id_exclusive) = query.min_id_exclusive {
+            conditions.push("`id`>?".to_string());
+            params.push(min_id_exclusive.into());
+        }
+        if let Some(has_signal) = query.has_signal {
+            let signal = "(EXISTS (SELECT * FROM `person_dates` WHERE `entry_id`=`entry`.`id`) OR EXISTS (SELECT * FROM `auxiliary` WHERE `entry_id`=`entry`.`id`) OR EXISTS (SELECT * FROM `location` WHERE `entry_id`=`entry`.`id`) OR `ext_desc`!='')";
+            if has_signal {
+                conditions.push(signal.to_string());
+            } else {
+                conditions.push(format!("NOT {signal}"));
+            }
+        }
+        if conditions.is_empty() {
+            return (String::new(), params);
+        }
+        (format!(" WHERE {}", conditions.join(" AND ")), params)
+    }
+
+    /// Returns the query and positional parameters for `get_recent_match_events`.
+    /// Each part is limited on its own first, so the database does not have to merge and sort all events.
+    fn get_recent_match_events_sql(
+        catalog_id: Option<usize>,
+        since_ts: Option<String>,
+        limit: usize,
+    ) -> (String, Vec<mysql_async::Value>) {
+        let mut params: Vec<mysql_async::Value> = vec![];
+        let add_conditions = |table: &str, params: &mut Vec<mysql_async::Value>| {
+            let mut sql = String::new();
+            if let Some(catalog_id) = catalog_id {
+                sql += " AND `entry`.`catalog`=?";
+                params.push(catalog_id.into());
+            }
+            if let Some(since_ts) = &since_ts {
+                sql += &format!(" AND `{table}`.`timestamp`>=?");
+                params.push(since_ts.to_owned().into());
+            }
+            params.push(limit.into());
+            sql
+        };
+        let matches_conditions = add_conditions("entry", &mut params);
+        let removals_conditions = add_conditions("log", &mut params);
+        params.push(limit.into());
+        let sql = format!(
+            "(SELECT 'match',`id`,`catalog`,`ext_id`,`ext_name`,`q`,`user`,`timestamp` FROM `entry`
+            WHERE `q` IS NOT NULL AND `user` NOT IN ({USER_AUTO},{USER_DATE_MATCH},{USER_AUX_MATCH}) AND `timestamp` IS NOT NULL{matches_conditions}
+            ORDER BY `timestamp` DESC LIMIT ?)
+            UNION ALL
+            (SELECT 'remove_q',`entry`.`id`,`entry`.`catalog`,`entry`.`ext_id`,`entry`.`ext_name`,`log`.`q`,`log`.`user`,`log`.`timestamp` FROM `log`,`entry`
+            WHERE `log`.`entry_id`=`entry`.`id` AND `log`.`action`='remove_q' AND `log`.`timestamp` IS NOT NULL{removals_conditions}
+            ORDER BY `log`.`timestamp` DESC LIMIT ?)
+            ORDER BY 8 DESC LIMIT ?"
+        );
+        (sql, params)
+    }
+
+    /// Returns the `WHERE`, `ORDER BY`, and `LIMIT` clauses and positional parameters for an `EntryQuery`.
+    fn get_entry_query_sql(query: &EntryQuery) -> (String, Vec<mysql_async::Value>) {
+        let (mut sql, mut params) = Self::get_entry_query_sql_where(query);
+        if let Some(order_by) = &query.order_by {
+            sql += &format!(" ORDER BY {}", order_by.as_sql());
+        }
+        if let Some(limit) = query.limit {
+            sql += " LIMIT ? OFFSET ?";
+            params.push(limit.into());
+            params.push(query.offset.into());
+        }
+        (sql, params)
+    }
+
+    async fn automatch_entry_query_get_results(
+        &self,
+        query: &EntryQuery,
+    ) -> Result<Vec<(usize, String, String, String)>> {
+        let (sql_where, params) = Self::get_entry_query_sql(query);
+        let sql = format!("SELECT `id`,`ext_name`,`type`,
+                IFNULL((SELECT group_concat(DISTINCT `label` SEPARATOR '|') FROM aliases WHERE entry_id=entry.id),'') AS `aliases`
+                FROM `entry`{sql_where}");
+        let results = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, Params::Positional(params))
+            .await?
+            .map_and_drop(from_row::<(usize, String, String, String)>)
+            .await?;
+        Ok(results)
+    }
+
     async fn match_taxa_get_ranked_names_batch_get_results(
         &self,
         ranks: &[&str],
         field: &TaxonNameField,
         catalog_id: usize,
         batch_size: usize,
-        offset: usize,
+        after_entry_id: usize,
     ) -> Result<Vec<(usize, String, String)>> {
         let taxon_name_column = field.as_str();
         let sql = format!(
@@ -237,7 +723,9 @@ impl StorageMySQL {
             	WHERE `catalog` IN ({catalog_id})
              	AND (`q` IS NULL OR `user`=0)
               	AND `type` IN ('{}')
-            	LIMIT {batch_size} OFFSET {offset}",
+              	AND `id`>{after_entry_id}
+            	ORDER BY `id`
+            	LIMIT {batch_size}",
             ranks.join("','")
         );
         let results = self
@@ -249,6 +737,20 @@ impl StorageMySQL {
             .await?;
         Ok(results)
     }
+
+    /// Returns `false` if the catalog does not exist (anymore), or is not active
+    async fn catalog_is_active(&self, catalog_id: usize) -> Result<bool> {
+        let sql = r"SELECT `id` FROM `catalog` WHERE `id`=:catalog_id AND `active`=1";
+        let ret = !self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, params! {catalog_id})
+            .await?
+            .map_and_drop(from_row::<usize>)
+            .await?
+            .is_empty();
+        Ok(ret)
+    }
 }
 
 // STORAGE TRAIT IMPLEMENTATION
@@ -276,13 +778,18 @@ impl Storage for StorageMySQL {
         field: &TaxonNameField,
         catalog_id: usize,
         batch_size: usize,
-        offset: usize,
-    ) -> Result<(usize, RankedNames)> {
+        after_entry_id: usize,
+    ) -> Result<(usize, usize, RankedNames)> {
         let results = self
             .match_taxa_get_ranked_names_batch_get_results(
-                ranks, field, catalog_id, batch_size, offset,
+                ranks,
+                field,
+                catalog_id,
+                batch_size,
+                after_entry_id,
             )
             .await?;
+        let last_entry_id = results.last().map_or(after_entry_id, |result| result.0);
         let mut ranked_names: RankedNames = HashMap::new();
         for result in &results {
             let entry_id = result.0;
@@ -300,7 +807,7 @@ impl Storage for StorageMySQL {
                 .or_default()
                 .push((entry_id, taxon_name));
         }
-        Ok((results.len(), ranked_names))
+        Ok((results.len(), last_entry_id, ranked_names))
     }
 
     // Coordinate Matcher
@@ -311,7 +818,8 @@ impl Storage for StorageMySQL {
         bad_catalogs: &[usize],
         max_results: usize,
     ) -> Result<Vec<LocationRow>> {
-        let sql = Self::coordinate_matcher_main_query_sql(catalog_id, bad_catalogs, max_results);
+        let r: f64 = rand::thread_rng().gen();
+        let sql = Self::coordinate_matcher_main_query_sql(catalog_id, bad_catalogs, max_results, r);
         let mut conn = self.get_conn_ro().await?;
         let rows: Vec<LocationRow> = conn
             .exec_iter(sql, ())
@@ -362,6 +870,55 @@ impl Storage for StorageMySQL {
         Ok(existing_ext_ids)
     }
 
+    async fn get_external_ids(
+        &self,
+        catalog_id: usize,
+        ext_ids: &[String],
+    ) -> Result<HashMap<String, CatalogExtId>> {
+        if ext_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let placeholders = Self::sql_placeholders(ext_ids.len());
+        let sql = format!("SELECT `ext_id`,`id`,`ext_name`,`ext_desc`,`ext_url`,`q` FROM `entry` WHERE `ext_id` IN ({placeholders}) AND `catalog`={catalog_id}");
+        let ret = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, ext_ids.to_vec())
+            .await?
+            .map_and_drop(from_row::<(String, usize, String, String, String, Option<isize>)>)
+            .await?
+            .into_iter()
+            .map(|(ext_id, entry_id, ext_name, ext_desc, ext_url, q)| {
+                let values = CatalogExtId {
+                    entry_id,
+                    ext_name,
+                    ext_desc,
+                    ext_url,
+                    q,
+                };
+                (ext_id, values)
+            })
+            .collect();
+        Ok(ret)
+    }
+
+    async fn get_ext_ids_batch(
+        &self,
+        catalog_id: usize,
+        after_entry_id: usize,
+        batch_size: usize,
+    ) -> Result<Vec<(usize, String)>> {
+        let sql = "SELECT `id`,`ext_id` FROM `entry` WHERE `catalog`=:catalog_id AND `id`>:after_entry_id ORDER BY `id` LIMIT :batch_size";
+        let ret = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, params! {catalog_id,after_entry_id,batch_size})
+            .await?
+            .map_and_drop(from_row::<(usize, String)>)
+            .await?;
+        Ok(ret)
+    }
+
     async fn update_catalog_get_update_info(&self, catalog_id: usize) -> Result<Vec<UpdateInfo>> {
         let results = "SELECT id, catalog, json, note, user_id, is_current FROM `update_info` WHERE `catalog`=:catalog_id AND `is_current`=1 LIMIT 1"
             .with(params!{catalog_id})
@@ -383,22 +940,47 @@ impl Storage for StorageMySQL {
         Ok(*results.first().unwrap_or(&0))
     }
 
-    async fn get_catalog_from_id(&self, catalog_id: usize) -> Result<Catalog> {
-        let sql = r"SELECT id,`name`,url,`desc`,`type`,wd_prop,wd_qual,search_wp,active,owner,note,source_item,has_person_date,taxon_run FROM `catalog` WHERE `id`=:catalog_id";
+    async fn get_catalog_from_id(&self, catalog_id: usize) -> StorageResult<Catalog> {
+        let sql = format!("{} WHERE `id`=:catalog_id", Self::catalog_sql_select());
         let mut conn = self.get_conn_ro().await?;
-        let mut rows: Vec<Catalog> = conn
+        let mut rows = conn
             .exec_iter(sql, params! {catalog_id})
             .await?
             .map_and_drop(|row| Self::catalog_from_row(&row))
+            .await?;
+        drop(conn);
+        match rows.pop() {
+            Some(Some(catalog)) => Ok(catalog),
+            Some(None) => Err(StorageError::Invalid(format!(
+                "Catalog #{catalog_id} could not be read"
+            ))),
+            None => Err(StorageError::NotFound(format!("No catalog #{catalog_id}"))),
+        }
+    }
+
+    /// Returns the catalogs that use a Wikidata property (without qualifier) for their external IDs
+    async fn get_catalogs_by_property(
+        &self,
+        prop_numeric: usize,
+        active_only: bool,
+    ) -> Result<Vec<Catalog>> {
+        let mut sql = format!(
+            "{} WHERE `wd_prop`=:prop_numeric AND `wd_qual` IS NULL",
+            Self::catalog_sql_select()
+        );
+        if active_only {
+            sql += " AND `active`=1";
+        }
+        let ret = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, params! {prop_numeric})
+            .await?
+            .map_and_drop(|row| Self::catalog_from_row(&row))
             .await?
-            .iter()
-            .filter_map(|row| row.to_owned())
+            .into_iter()
+            .flatten()
             .collect();
-        drop(conn);
-        let ret = rows
-            .pop()
-            .ok_or(anyhow!("No catalog #{}", catalog_id))?
-            .to_owned();
         Ok(ret)
     }
 
@@ -417,23 +999,304 @@ impl Storage for StorageMySQL {
         Ok(ret)
     }
 
+    async fn set_catalog_key_value_pair(
+        &self,
+        catalog_id: usize,
+        key: &str,
+        value: &str,
+    ) -> Result<()> {
+        let sql = r"INSERT INTO `kv_catalog` (`catalog_id`,`kv_key`,`kv_value`) VALUES (:catalog_id,:key,:value) ON DUPLICATE KEY UPDATE `kv_value`=:value";
+        self.get_conn()
+            .await?
+            .exec_drop(sql, params! {catalog_id,key,value})
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_catalog_key_value_pair(&self, catalog_id: usize, key: &str) -> Result<()> {
+        let sql = r"DELETE FROM `kv_catalog` WHERE `catalog_id`=:catalog_id AND `kv_key`=:key";
+        self.get_conn()
+            .await?
+            .exec_drop(sql, params! {catalog_id,key})
+            .await?;
+        Ok(())
+    }
+
+    /// Recomputes the overview table row for a catalog from scratch.
+    /// Batch matchers call this once at the end of a run, instead of incrementing counts per entry.
+    async fn finalize_catalog_overview(&self, catalog_id: usize) -> Result<()> {
+        self.catalog_refresh_overview_table(catalog_id).await
+    }
+
     async fn catalog_refresh_overview_table(&self, catalog_id: usize) -> Result<()> {
-        let sql = r"REPLACE INTO `overview` (catalog,total,noq,autoq,na,manual,nowd,multi_match,types) VALUES (
-	        :catalog_id,
-	        (SELECT count(*) FROM `entry` WHERE `catalog`=:catalog_id),
-	        (SELECT count(*) FROM `entry` WHERE `catalog`=:catalog_id AND `q` IS NULL),
-	        (SELECT count(*) FROM `entry` WHERE `catalog`=:catalog_id AND `user`=0),
-	        (SELECT count(*) FROM `entry` WHERE `catalog`=:catalog_id AND `q`=0),
-	        (SELECT count(*) FROM `entry` WHERE `catalog`=:catalog_id AND `q` IS NOT NULL AND `user`>0),
-	        (SELECT count(*) FROM `entry` WHERE `catalog`=:catalog_id AND `q`=-1),
-	        (SELECT count(*) FROM `multi_match` WHERE `catalog`=:catalog_id),
-	        (SELECT group_concat(DISTINCT `type` SEPARATOR '|') FROM `entry` WHERE `catalog`=:catalog_id)
-	        )";
         let mut conn = self.get_conn().await?;
-        conn.exec_drop(sql, params! {catalog_id}).await?;
+        conn.exec_drop(OVERVIEW_REFRESH_SQL, params! {catalog_id})
+            .await?;
+        Ok(())
+    }
+
+    /// Recomputes the overview table rows of all active catalogs, one catalog at a time,
+    /// so there is no single long-running query. Catalogs deleted or deactivated during the run are skipped.
+    /// Returns the number of catalogs refreshed.
+    async fn refresh_all_overview_tables(&self) -> Result<usize> {
+        const BATCH_SIZE: usize = 100;
+        let sql = r"SELECT `id` FROM `catalog` WHERE `active`=1 AND `id`>:after_id ORDER BY `id` LIMIT :limit";
+        let limit = BATCH_SIZE;
+        let mut after_id = 0;
+        let mut refreshed = 0;
+        loop {
+            let catalog_ids = self
+                .get_conn_ro()
+                .await?
+                .exec_iter(sql, params! {after_id,limit})
+                .await?
+                .map_and_drop(from_row::<usize>)
+                .await?;
+            for catalog_id in &catalog_ids {
+                if !self.catalog_is_active(*catalog_id).await? {
+                    continue; // Deleted or deactivated since the batch was loaded
+                }
+                match self.catalog_refresh_overview_table(*catalog_id).await {
+                    Ok(()) => refreshed += 1,
+                    Err(e) => warn!(catalog_id, "refresh_all_overview_tables: {e}"),
+                }
+            }
+            after_id = match catalog_ids.last() {
+                Some(catalog_id) => *catalog_id,
+                None => break,
+            };
+            info!(
+                refreshed,
+                after_id, "refresh_all_overview_tables: batch done"
+            );
+            if catalog_ids.len() < BATCH_SIZE {
+                break;
+            }
+        }
+        info!(refreshed, "refresh_all_overview_tables: done");
+        Ok(refreshed)
+    }
+
+    async fn get_catalog_id_by_name(&self, name: &str) -> Result<Option<usize>> {
+        let sql = r"SELECT `id` FROM `catalog` WHERE `name`=:name LIMIT 1";
+        let ret = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, params! {name})
+            .await?
+            .map_and_drop(from_row::<usize>)
+            .await?
+            .pop();
+        Ok(ret)
+    }
+
+    /// Returns the catalog with the given `source_item` (numeric item ID).
+    /// If there are several, active catalogs are preferred, then the oldest one.
+    async fn get_catalog_from_source_item(&self, source_item: usize) -> Result<Option<Catalog>> {
+        let sql = format!(
+            "{} WHERE `source_item`=:source_item ORDER BY `active` DESC,`id` LIMIT 1",
+            Self::catalog_sql_select()
+        );
+        let ret = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, params! {source_item})
+            .await?
+            .map_and_drop(|row| Self::catalog_from_row(&row))
+            .await?
+            .into_iter()
+            .flatten()
+            .next();
+        Ok(ret)
+    }
+
+    /// Returns (catalog ID, numeric property) for all active catalogs that have a property.
+    async fn get_active_catalogs_with_property(&self) -> Result<Vec<(usize, usize)>> {
+        let sql = "SELECT `id`,`wd_prop` FROM `catalog` WHERE `active`=1 AND `wd_prop` IS NOT NULL AND `wd_prop`>0";
+        let ret = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, ())
+            .await?
+            .map_and_drop(from_row::<(usize, usize)>)
+            .await?;
+        Ok(ret)
+    }
+
+    /// Creates a new catalog, and returns its ID.
+    /// Fails with `CatalogError::NameExists` if a catalog with the same name exists already.
+    /// Concurrent creation of the same catalog is rejected by the unique key on `name` (`sql/catalog_name_unique.sql`).
+    async fn create_catalog(&self, catalog: &Catalog) -> Result<usize> {
+        let name = catalog.name.to_owned().ok_or(CatalogError::NoName)?;
+        let sql = r"INSERT INTO `catalog` (`name`,`url`,`desc`,`type`,`wd_prop`,`wd_qual`,`search_wp`,`active`,`owner`,`note`,`source_item`,`has_person_date`,`taxon_run`)
+            SELECT :name,:url,:desc,:type_name,:wd_prop,:wd_qual,:search_wp,:active,:owner,:note,:source_item,:has_person_date,:taxon_run FROM DUAL
+            WHERE NOT EXISTS (SELECT * FROM `catalog` WHERE `name`=:name)";
+        let mut conn = self.get_conn().await?;
+        let result = conn
+            .exec_drop(
+                sql,
+                params! {
+                    "name" => &name,
+                    "url" => &catalog.url,
+                    "desc" => &catalog.desc,
+                    "type_name" => &catalog.type_name,
+                    "wd_prop" => catalog.wd_prop,
+                    "wd_qual" => catalog.wd_qual,
+                    "search_wp" => &catalog.search_wp,
+                    "active" => catalog.active,
+                    "owner" => catalog.owner,
+                    "note" => &catalog.note,
+                    "source_item" => catalog.source_item,
+                    "has_person_date" => &catalog.has_person_date,
+                    "taxon_run" => catalog.taxon_run,
+                },
+            )
+            .await;
+        let catalog_id = match result.map_err(StorageError::from) {
+            Ok(()) if conn.affected_rows() > 0 => conn.last_insert_id(),
+            Ok(()) => None,
+            Err(StorageError::Constraint(_)) => None, // Unique key on the name
+            Err(e) => return Err(e.into()),
+        };
+        drop(conn);
+        match catalog_id {
+            Some(catalog_id) => Ok(catalog_id as usize),
+            None => match self.get_catalog_id_by_name(&name).await? {
+                Some(catalog_id) => Err(CatalogError::NameExists(catalog_id).into()),
+                None => Err(anyhow!("Could not create catalog '{name}'")),
+            },
+        }
+    }
+
+    async fn catalog_set_active(&self, catalog_id: usize, active: bool) -> Result<()> {
+        let active = active as u8;
+        let sql = "UPDATE `catalog` SET `active`=:active WHERE `id`=:catalog_id";
+        self.get_conn()
+            .await?
+            .exec_drop(sql, params! {catalog_id, active})
+            .await?;
+        Ok(())
+    }
+
+    /// Deletes a catalog, its entries, all data associated with those entries, its autoscraper and its jobs.
+    /// All deletions are made in one transaction.
+    async fn catalog_delete(&self, catalog_id: usize) -> Result<()> {
+        let mut conn = self.get_conn().await?;
+        let mut tx = conn.start_transaction(TxOpts::default()).await?;
+        for table in TABLES_WITH_ENTRY_ID_FIELDS {
+            let sql = format!("DELETE `{table}` FROM `{table}` INNER JOIN `entry` ON `{table}`.`entry_id`=`entry`.`id` WHERE `entry`.`catalog`=:catalog_id");
+            tx.exec_drop(sql, params! {catalog_id}).await?;
+        }
+        let sqls = [
+            "DELETE FROM `entry` WHERE `catalog`=:catalog_id",
+            "DELETE FROM `overview` WHERE `catalog`=:catalog_id",
+            "DELETE FROM `kv_catalog` WHERE `catalog_id`=:catalog_id",
+            "DELETE FROM `catalog_default_statement` WHERE `catalog_id`=:catalog_id",
+            "DELETE FROM `autoscrape` WHERE `catalog`=:catalog_id",
+            "DELETE FROM `jobs` WHERE `catalog`=:catalog_id",
+            "DELETE FROM `catalog` WHERE `id`=:catalog_id",
+        ];
+        for sql in sqls {
+            tx.exec_drop(sql, params! {catalog_id}).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn get_catalog_default_statements(
+        &self,
+        catalog_id: usize,
+    ) -> Result<Vec<CatalogDefaultStatement>> {
+        let sql = "SELECT `id`,`catalog_id`,`property`,`value`,`as_reference` FROM `catalog_default_statement` WHERE `catalog_id`=:catalog_id ORDER BY `id`";
+        let ret = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, params! {catalog_id})
+            .await?
+            .map_and_drop(from_row::<(usize, usize, usize, String, u8)>)
+            .await?
+            .into_iter()
+            .map(CatalogDefaultStatement::from_row)
+            .collect();
+        Ok(ret)
+    }
+
+    async fn add_catalog_default_statement(&self, ds: &CatalogDefaultStatement) -> Result<usize> {
+        let sql = "INSERT INTO `catalog_default_statement` (`catalog_id`,`property`,`value`,`as_reference`) VALUES (:catalog_id,:property,:value,:as_reference)";
+        let mut conn = self.get_conn().await?;
+        conn.exec_drop(
+            sql,
+            params! {
+                "catalog_id" => ds.catalog_id,
+                "property" => ds.property,
+                "value" => &ds.value,
+                "as_reference" => ds.as_reference as u8,
+            },
+        )
+        .await?;
+        let id = conn.last_insert_id().ok_or_else(|| {
+            anyhow!(
+                "Could not add default statement to catalog {}",
+                ds.catalog_id
+            )
+        })? as usize;
+        Ok(id)
+    }
+
+    async fn delete_catalog_default_statement(&self, catalog_id: usize, id: usize) -> Result<()> {
+        let sql =
+            "DELETE FROM `catalog_default_statement` WHERE `id`=:id AND `catalog_id`=:catalog_id";
+        self.get_conn()
+            .await?
+            .exec_drop(sql, params! {id, catalog_id})
+            .await?;
         Ok(())
     }
 
+    // User
+
+    async fn get_user_by_id(&self, user_id: usize) -> Result<Option<User>> {
+        let sql = "SELECT `id`,`name`,`is_catalog_admin` FROM `user` WHERE `id`=:user_id";
+        let mut rows = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, params! {user_id})
+            .await?
+            .map_and_drop(from_row::<(usize, String, u8)>)
+            .await?;
+        Ok(rows.pop().map(User::from_row))
+    }
+
+    /// Returns the ID for a user name, creating a new user row if there is none yet
+    async fn get_or_create_user(&self, name: &str) -> Result<usize> {
+        let name = User::normalize_name(name);
+        if name.is_empty() {
+            return Err(anyhow!("get_or_create_user: empty user name"));
+        }
+        let sql = "SELECT `id` FROM `user` WHERE `name`=:name";
+        let mut conn = self.get_conn().await?;
+        let ids = conn
+            .exec_iter(sql, params! {"name" => &name})
+            .await?
+            .map_and_drop(from_row::<usize>)
+            .await?;
+        if let Some(user_id) = ids.first() {
+            return Ok(*user_id);
+        }
+        // INSERT IGNORE in case the user was just created elsewhere
+        let sql = "INSERT IGNORE INTO `user` (`name`) VALUES (:name)";
+        conn.exec_drop(sql, params! {"name" => &name}).await?;
+        let sql = "SELECT `id` FROM `user` WHERE `name`=:name";
+        let ids = conn
+            .exec_iter(sql, params! {"name" => &name})
+            .await?
+            .map_and_drop(from_row::<usize>)
+            .await?;
+        ids.first()
+            .copied()
+            .ok_or_else(|| anyhow!("get_or_create_user: could not create user '{name}'"))
+    }
+
     // Microsync
 
     async fn microsync_load_entry_names(
@@ -510,44 +1373,152 @@ impl Storage for StorageMySQL {
         Ok(())
     }
 
-    async fn queue_reference_fixer(&self, q_numeric: isize) -> Result<()> {
-        self.get_conn().await?.exec_drop(r"INSERT INTO `reference_fixer` (`q`,`done`) VALUES (:q_numeric,0) ON DUPLICATE KEY UPDATE `done`=0",params! {q_numeric}).await?;
+    async fn queue_reference_fixer(&self, q_numeric: isize) -> Result<()> {
+        self.get_conn().await?.exec_drop(r"INSERT INTO `reference_fixer` (`q`,`done`) VALUES (:q_numeric,0) ON DUPLICATE KEY UPDATE `done`=0",params! {q_numeric}).await?;
+        Ok(())
+    }
+
+    async fn reference_fixer_get_queued(&self, after_q: isize, limit: usize) -> Result<Vec<isize>> {
+        let sql = r"SELECT `q` FROM `reference_fixer` WHERE `done`=0 AND `q`>:after_q ORDER BY `q` LIMIT :limit";
+        let ret = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, params! {after_q, limit})
+            .await?
+            .map_and_drop(from_row::<isize>)
+            .await?;
+        Ok(ret)
+    }
+
+    async fn reference_fixer_set_done(&self, q_numeric: isize) -> Result<()> {
+        let sql = r"UPDATE `reference_fixer` SET `done`=1 WHERE `q`=:q_numeric";
+        self.get_conn()
+            .await?
+            .exec_drop(sql, params! {q_numeric})
+            .await?;
+        Ok(())
+    }
+
+    async fn reference_fixer_get_matches(
+        &self,
+        q_numeric: isize,
+    ) -> Result<Vec<ReferenceFixerMatch>> {
+        let sql = r"SELECT DISTINCT `ext_id`,`wd_prop`,`source_item` FROM `entry`,`catalog`
+            WHERE `q`=:q_numeric AND `user`>0 AND `catalog`.`id`=`entry`.`catalog`
+            AND `active`=1 AND `wd_prop` IS NOT NULL AND `wd_qual` IS NULL";
+        let ret = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, params! {q_numeric})
+            .await?
+            .map_and_drop(from_row::<(String, usize, Option<usize>)>)
+            .await?
+            .into_iter()
+            .map(ReferenceFixerMatch::from_row)
+            .collect();
+        Ok(ret)
+    }
+
+    /// Checks if the entry is flagged with `KV_NO_AUTOMATCH`, or the log already has a removed match for this entry.
+    /// If a q_numeric item is given, and a specific one is in the log entry, it will only trigger on this combination.
+    async fn avoid_auto_match(&self, entry_id: usize, q_numeric: Option<isize>) -> Result<bool> {
+        if self.entry_get_no_automatch(entry_id).await? {
+            return Ok(true);
+        }
+        let mut sql = format!("SELECT id FROM `log` WHERE `entry_id`={entry_id}");
+        if let Some(q) = q_numeric {
+            sql += &format!(" AND (q IS NULL OR q={})", &q)
+        }
+        sql += " LIMIT 1";
+        let has_rows = !self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, Empty)
+            .await?
+            .map_and_drop(from_row::<usize>)
+            .await?
+            .is_empty();
+        Ok(has_rows)
+    }
+
+    /// Returns all matches in a catalog that were removed by a user, as recorded in the `log` table.
+    async fn get_removed_matches_log(&self, catalog_id: usize) -> Result<Vec<RemovedMatch>> {
+        let sql = r"SELECT `log`.`id`,`log`.`entry_id`,`log`.`q`,`log`.`user`,`log`.`timestamp`
+            FROM `log`,`entry`
+            WHERE `log`.`entry_id`=`entry`.`id`
+            AND `log`.`action`='remove_q'
+            AND `entry`.`catalog`=:catalog_id
+            ORDER BY `log`.`entry_id`,`log`.`id`";
+        let ret = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, params! {catalog_id})
+            .await?
+            .map_and_drop(from_row::<(usize, usize, Option<isize>, Option<usize>, Option<String>)>)
+            .await?
+            .into_iter()
+            .map(RemovedMatch::from_row)
+            .collect();
+        Ok(ret)
+    }
+
+    async fn get_recent_match_events(
+        &self,
+        catalog_id: Option<usize>,
+        since_ts: Option<String>,
+        limit: usize,
+    ) -> Result<Vec<MatchEvent>> {
+        let (sql, params) = Self::get_recent_match_events_sql(catalog_id, since_ts, limit);
+        let ret = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, Params::Positional(params))
+            .await?
+            .map_and_drop(
+                from_row::<(
+                    String,
+                    usize,
+                    usize,
+                    String,
+                    String,
+                    Option<isize>,
+                    Option<usize>,
+                    String,
+                )>,
+            )
+            .await?
+            .into_iter()
+            .map(MatchEvent::from_row)
+            .collect();
+        Ok(ret)
+    }
+
+    /// Removes the `remove_q` log entries for an entry, so it can be auto-matched again.
+    async fn clear_removed_matches_log(&self, entry_id: usize) -> Result<()> {
+        let sql = r"DELETE FROM `log` WHERE `entry_id`=:entry_id AND `action`='remove_q'";
+        self.get_conn()
+            .await?
+            .exec_drop(sql, params! {entry_id})
+            .await?;
         Ok(())
     }
 
-    /// Checks if the log already has a removed match for this entry.
-    /// If a q_numeric item is given, and a specific one is in the log entry, it will only trigger on this combination.
-    async fn avoid_auto_match(&self, entry_id: usize, q_numeric: Option<isize>) -> Result<bool> {
-        let mut sql = format!("SELECT id FROM `log` WHERE `entry_id`={entry_id}");
-        if let Some(q) = q_numeric {
-            sql += &format!(" AND (q IS NULL OR q={})", &q)
-        }
-        sql += " LIMIT 1";
-        let has_rows = !self
+    async fn get_next_active_catalog_id_with_property(
+        &self,
+        after_catalog_id: usize,
+    ) -> Result<Option<usize>> {
+        let sql =
+            "SELECT id FROM catalog WHERE active=1 AND wd_prop IS NOT NULL and wd_qual IS NULL
+            ORDER BY id>:after_catalog_id DESC,id LIMIT 1";
+        let ret = self
             .get_conn_ro()
             .await?
-            .exec_iter(sql, Empty)
+            .exec_iter(sql, params! {after_catalog_id})
             .await?
             .map_and_drop(from_row::<usize>)
             .await?
-            .is_empty();
-        Ok(has_rows)
-    }
-
-    //TODO test
-    async fn get_random_active_catalog_id_with_property(&self) -> Option<usize> {
-        let sql = "SELECT id FROM catalog WHERE active=1 AND wd_prop IS NOT NULL and wd_qual IS NULL ORDER by rand() LIMIT 1" ;
-        self.get_conn_ro()
-            .await
-            .ok()?
-            .exec_iter(sql, ())
-            .await
-            .ok()?
-            .map_and_drop(from_row::<usize>)
-            .await
-            .ok()?
-            .first()
-            .map(|x| x.to_owned())
+            .pop();
+        Ok(ret)
     }
 
     async fn get_kv_value(&self, key: &str) -> Result<Option<String>> {
@@ -571,6 +1542,201 @@ impl Storage for StorageMySQL {
         Ok(())
     }
 
+    // Quick compare
+
+    /// Returns random auto-matched entries of a catalog, optionally only those with coordinates.
+    async fn quick_compare_get_entries(
+        &self,
+        catalog_id: usize,
+        require_coordinates: bool,
+        max_results: usize,
+    ) -> Result<Vec<Entry>> {
+        let mut sql = format!(
+            "{} WHERE `catalog`=:catalog_id AND `user`=0 AND `q`>0",
+            Self::entry_sql_select()
+        );
+        if require_coordinates {
+            sql +=
+                " AND EXISTS (SELECT * FROM `location` WHERE `location`.`entry_id`=`entry`.`id`)";
+        }
+        // Start at a random point; wrap around to the start if there are not enough entries after it
+        let r: f64 = rand::thread_rng().gen();
+        let mut conn = self.get_conn_ro().await?;
+        let mut ret: Vec<Entry> = conn
+            .exec_iter(
+                format!("{sql} AND `random`>={r} ORDER BY `random` LIMIT :max_results"),
+                params! {catalog_id,max_results},
+            )
+            .await?
+            .map_and_drop(|row| Self::entry_from_row(&row))
+            .await?
+            .into_iter()
+            .flatten()
+            .collect();
+        if ret.len() < max_results {
+            let max_results = max_results - ret.len();
+            let mut wrapped: Vec<Entry> = conn
+                .exec_iter(
+                    format!("{sql} AND `random`<{r} ORDER BY `random` LIMIT :max_results"),
+                    params! {catalog_id,max_results},
+                )
+                .await?
+                .map_and_drop(|row| Self::entry_from_row(&row))
+                .await?
+                .into_iter()
+                .flatten()
+                .collect();
+            ret.append(&mut wrapped);
+        }
+        Ok(ret)
+    }
+
+    async fn get_quick_compare_catalogs(
+        &self,
+        min_auto_matches: usize,
+        excluded_catalogs: &[usize],
+    ) -> Result<Vec<QuickCompareCatalog>> {
+        let sql = format!(
+            r"SELECT `catalog`.`id`,`catalog`.`name`,`overview`.`autoq`,
+            EXISTS (SELECT * FROM `location`,`entry` WHERE `location`.`entry_id`=`entry`.`id` AND `entry`.`catalog`=`catalog`.`id`) AS `has_locations`,
+            `catalog`.`has_person_date`
+            FROM `catalog`,`overview`
+            WHERE `catalog`.`id`=`overview`.`catalog` AND `catalog`.`active`=1 AND `overview`.`autoq`>=:min_auto_matches{}
+            ORDER BY `overview`.`autoq` DESC",
+            Self::catalog_exclusion_sql("`catalog`.`id`", excluded_catalogs)
+        );
+        let ret = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, params! {min_auto_matches})
+            .await?
+            .map_and_drop(from_row::<(usize, Option<String>, usize, u8, Option<String>)>)
+            .await?
+            .into_iter()
+            .map(QuickCompareCatalog::from_row)
+            .collect();
+        Ok(ret)
+    }
+
+    /// Promotes the auto-matches (user=0) of the given entries to matches by `user_id`, and queues the items for the reference fixer.
+    /// Entries that are not auto-matched are ignored. All changes, including the overview rows, are made in one transaction.
+    /// Returns the number of confirmed matches.
+    async fn confirm_auto_matches(&self, entry_ids: &[usize], user_id: usize) -> Result<usize> {
+        if entry_ids.is_empty() || user_id == USER_AUTO {
+            return Ok(0);
+        }
+        let entry_ids_str = entry_ids.iter().map(|id| format!("{id}")).join(",");
+        let catalogs = self.get_catalogs_for_entry_ids(&entry_ids_str).await?;
+        let timestamp = TimeStamp::now();
+        let mut conn = self.get_conn().await?;
+        let mut tx = conn.start_transaction(TxOpts::default()).await?;
+        let sql = format!("UPDATE `entry` SET `user`=:user_id,`timestamp`=:timestamp WHERE `id` IN ({entry_ids_str}) AND `user`=0 AND `q`>0");
+        tx.exec_drop(sql, params! {user_id,"timestamp" => &timestamp})
+            .await?;
+        let confirmed = tx.affected_rows() as usize;
+        // As in `entry_set_match_status`
+        let sql = format!("INSERT INTO `wd_matches` (`entry_id`,`status`,`timestamp`,`catalog`) SELECT `id`,'UNKNOWN',:timestamp,`catalog` FROM `entry` WHERE `id` IN ({entry_ids_str}) AND `user`=:user_id AND `q`>0 ON DUPLICATE KEY UPDATE `status`=VALUES(`status`),`timestamp`=VALUES(`timestamp`)");
+        tx.exec_drop(sql, params! {user_id,timestamp}).await?;
+        for (table, column) in ENTRY_IS_MATCHED_COLUMNS {
+            let sql = format!("UPDATE `{table}`,`entry` SET `{table}`.`{column}`=1 WHERE `{table}`.`entry_id`=`entry`.`id` AND `entry`.`id` IN ({entry_ids_str}) AND `entry`.`user`=:user_id AND `entry`.`q`>0");
+            tx.exec_drop(sql, params! {user_id}).await?;
+        }
+        let sql = format!("DELETE FROM `multi_match` WHERE `entry_id` IN ({entry_ids_str})");
+        tx.exec_drop(sql, ()).await?;
+        let sql = format!("SELECT DISTINCT `q` FROM `entry` WHERE `id` IN ({entry_ids_str}) AND `user`=:user_id AND `q`>0");
+        let qs = tx
+            .exec_iter(sql, params! {user_id})
+            .await?
+            .map_and_drop(from_row::<isize>)
+            .await?;
+        Self::queue_reference_fixer_batch(&mut tx, &qs).await?;
+        for catalog_id in catalogs {
+            tx.exec_drop(OVERVIEW_REFRESH_SQL, params! {catalog_id})
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(confirmed)
+    }
+
+    /// Removes the auto-matches (user=0) of the given entries, and logs them as `remove_q` by `user_id`
+    /// so they won't be auto-matched to the same item again.
+    /// Entries that are not auto-matched are ignored. All changes, including the overview rows, are made in one transaction.
+    /// Returns the number of rejected matches.
+    async fn reject_auto_matches(&self, entry_ids: &[usize], user_id: usize) -> Result<usize> {
+        if entry_ids.is_empty() {
+            return Ok(0);
+        }
+        let entry_ids_str = entry_ids.iter().map(|id| format!("{id}")).join(",");
+        let catalogs = self.get_catalogs_for_entry_ids(&entry_ids_str).await?;
+        let timestamp = TimeStamp::now();
+        let mut conn = self.get_conn().await?;
+        let mut tx = conn.start_transaction(TxOpts::default()).await?;
+        let sql = format!("INSERT INTO `log` (`action`,`entry_id`,`user`,`timestamp`,`q`) SELECT 'remove_q',`id`,:user_id,:timestamp,`q` FROM `entry` WHERE `id` IN ({entry_ids_str}) AND `user`=0 AND `q`>0");
+        tx.exec_drop(sql, params! {user_id,"timestamp" => &timestamp})
+            .await?;
+        // As in `entry_unmatch`
+        let sql = format!("INSERT INTO `wd_matches` (`entry_id`,`status`,`timestamp`,`catalog`) SELECT `id`,'UNKNOWN',:timestamp,`catalog` FROM `entry` WHERE `id` IN ({entry_ids_str}) AND `user`=0 AND `q`>0 ON DUPLICATE KEY UPDATE `status`=VALUES(`status`),`timestamp`=VALUES(`timestamp`)");
+        tx.exec_drop(sql, params! {timestamp}).await?;
+        for (table, column) in ENTRY_IS_MATCHED_COLUMNS {
+            let sql = format!("UPDATE `{table}`,`entry` SET `{table}`.`{column}`=0 WHERE `{table}`.`entry_id`=`entry`.`id` AND `entry`.`id` IN ({entry_ids_str}) AND `entry`.`user`=0 AND `entry`.`q`>0");
+            tx.exec_drop(sql, ()).await?;
+        }
+        let sql = format!("UPDATE `entry` SET `q`=NULL,`user`=NULL,`timestamp`=NULL WHERE `id` IN ({entry_ids_str}) AND `user`=0 AND `q`>0");
+        tx.exec_drop(sql, ()).await?;
+        let rejected = tx.affected_rows() as usize;
+        for catalog_id in catalogs {
+            tx.exec_drop(OVERVIEW_REFRESH_SQL, params! {catalog_id})
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(rejected)
+    }
+
+    /// Removes all matches of a user in a catalog, eg to revert a bad import session.
+    /// The removals are logged as `remove_q` by that user, so the entries won't be auto-matched to the same items again,
+    /// and the items are queued for the reference fixer. All changes are made in one transaction.
+    /// Returns the number of unmatched entries.
+    async fn unmatch_by_user_in_catalog(
+        &self,
+        catalog_id: usize,
+        user_id: usize,
+        operator_user_id: usize,
+    ) -> Result<usize> {
+        let sql = r"SELECT DISTINCT `q` FROM `entry` WHERE `catalog`=:catalog_id AND `user`=:user_id AND `q`>0";
+        let qs = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, params! {catalog_id,user_id})
+            .await?
+            .map_and_drop(from_row::<isize>)
+            .await?;
+        let timestamp = TimeStamp::now();
+        let mut conn = self.get_conn().await?;
+        let mut tx = conn.start_transaction(TxOpts::default()).await?;
+        let sql = r"INSERT INTO `log` (`action`,`entry_id`,`user`,`timestamp`,`q`) SELECT 'remove_q',`id`,:operator_user_id,:timestamp,`q` FROM `entry` WHERE `catalog`=:catalog_id AND `user`=:user_id AND `q` IS NOT NULL";
+        tx.exec_drop(
+            sql,
+            params! {catalog_id,user_id,operator_user_id,"timestamp" => &timestamp},
+        )
+        .await?;
+        // As in `entry_set_match_status`, so the other matchers see these entries as unmatched again
+        let sql = r"INSERT INTO `wd_matches` (`entry_id`,`status`,`timestamp`,`catalog`) SELECT `id`,'UNKNOWN',:timestamp,`catalog` FROM `entry` WHERE `catalog`=:catalog_id AND `user`=:user_id AND `q` IS NOT NULL ON DUPLICATE KEY UPDATE `status`=VALUES(`status`),`timestamp`=VALUES(`timestamp`)";
+        tx.exec_drop(sql, params! {catalog_id,user_id,timestamp})
+            .await?;
+        for (table, column) in ENTRY_IS_MATCHED_COLUMNS {
+            let sql = format!("UPDATE `{table}`,`entry` SET `{table}`.`{column}`=0 WHERE `{table}`.`entry_id`=`entry`.`id` AND `entry`.`catalog`=:catalog_id AND `entry`.`user`=:user_id AND `entry`.`q` IS NOT NULL");
+            tx.exec_drop(sql, params! {catalog_id,user_id}).await?;
+        }
+        let sql = r"UPDATE `entry` SET `q`=NULL,`user`=NULL,`timestamp`=NULL WHERE `catalog`=:catalog_id AND `user`=:user_id AND `q` IS NOT NULL";
+        tx.exec_drop(sql, params! {catalog_id,user_id}).await?;
+        let unmatched = tx.affected_rows() as usize;
+        Self::queue_reference_fixer_batch(&mut tx, &qs).await?;
+        tx.commit().await?;
+        drop(conn);
+        self.catalog_refresh_overview_table(catalog_id).await?;
+        Ok(unmatched)
+    }
+
     // Issue
 
     async fn issue_insert(&self, issue: &Issue) -> Result<()> {
@@ -586,6 +1752,81 @@ impl Storage for StorageMySQL {
         Ok(())
     }
 
+    /// Open issues of a type, oldest first, with their entries; optionally for a single catalog
+    async fn get_entries_with_open_issues(
+        &self,
+        issue_type: IssueType,
+        catalog_id: Option<usize>,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<(Entry, Issue)>> {
+        let catalog_condition = match catalog_id {
+            Some(_) => "AND `issues`.`catalog`=:catalog_id",
+            None => "",
+        };
+        let sql = format!(
+            "SELECT `entry`.`id`,`entry`.`catalog`,`ext_id`,`ext_url`,`ext_name`,`ext_desc`,`q`,`user`,`timestamp`,
+            if(isnull(`entry`.`random`),rand(),`entry`.`random`) AS `random`,`entry`.`type`,
+            `issues`.`id`,`entry_id`,`issues`.`type`,`json`,`status`,`user_id`,`resolved_ts`,`issues`.`catalog`
+            FROM `issues`,`entry`
+            WHERE `issues`.`entry_id`=`entry`.`id` AND `issues`.`status`='OPEN' AND `issues`.`type`=:issue_type
+            {catalog_condition}
+            ORDER BY `issues`.`id`
+            LIMIT :limit OFFSET :offset"
+        );
+        let rows = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(
+                sql,
+                params! {"issue_type" => issue_type.to_str(), "catalog_id" => catalog_id.unwrap_or(0), limit, offset},
+            )
+            .await?
+            .map_and_drop(|row| {
+                let entry = Self::entry_from_row(&row)?;
+                let issue_row: IssueRow = (
+                    row.get(11)?,
+                    row.get(12)?,
+                    row.get(13)?,
+                    row.get(14)?,
+                    row.get(15)?,
+                    row.get(16)?,
+                    row.get(17)?,
+                    row.get(18)?,
+                );
+                Some((entry, issue_row))
+            })
+            .await?;
+        rows.into_iter()
+            .flatten()
+            .map(|(entry, issue_row)| Ok((entry, Issue::from_row(issue_row)?)))
+            .collect()
+    }
+
+    /// Open issue counts, by catalog and type. Unknown issue types are ignored.
+    async fn get_issue_counts_by_catalog(
+        &self,
+    ) -> Result<HashMap<usize, HashMap<IssueType, usize>>> {
+        let sql = "SELECT `catalog`,`type`,count(*) AS `cnt` FROM `issues` WHERE `status`='OPEN' GROUP BY `catalog`,`type`";
+        let rows = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, ())
+            .await?
+            .map_and_drop(from_row::<(usize, String, usize)>)
+            .await?;
+        let mut ret: HashMap<usize, HashMap<IssueType, usize>> = HashMap::new();
+        for (catalog_id, issue_type, count) in rows {
+            if let Ok(issue_type) = IssueType::new(&issue_type) {
+                *ret.entry(catalog_id)
+                    .or_default()
+                    .entry(issue_type)
+                    .or_default() += count;
+            }
+        }
+        Ok(ret)
+    }
+
     // Autoscrape
 
     async fn autoscrape_get_for_catalog(&self, catalog_id: usize) -> Result<Vec<(usize, String)>> {
@@ -601,6 +1842,60 @@ impl Storage for StorageMySQL {
             .await?)
     }
 
+    /// The validated autoscrape config of a catalog; an error if the stored JSON is not valid
+    async fn get_autoscrape_config(&self, catalog_id: usize) -> Result<Option<AutoscrapeConfig>> {
+        match self.autoscrape_get_for_catalog(catalog_id).await?.first() {
+            Some((_id, json)) => Ok(Some(AutoscrapeConfig::from_json_str(json)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn autoscrape_get_owner(&self, catalog_id: usize) -> Result<Option<usize>> {
+        let sql = "SELECT `owner` FROM `autoscrape` WHERE `catalog`=:catalog_id";
+        let ret = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, params! {catalog_id})
+            .await?
+            .map_and_drop(from_row::<Option<usize>>)
+            .await?
+            .pop()
+            .flatten();
+        Ok(ret)
+    }
+
+    /// Validates and stores the autoscrape config of a catalog with the given owner, and sets its status to `IMPORT`.
+    /// Queues a high-priority `autoscrape` job for the catalog, in the same transaction.
+    /// Like the PHP `query_save_scraper`; permissions are checked by `Autoscrape::save_config`.
+    async fn save_autoscrape_config(
+        &self,
+        catalog_id: usize,
+        config: &AutoscrapeConfig,
+        owner: usize,
+    ) -> Result<()> {
+        AutoscrapeConfig::from_json(&serde_json::to_value(config)?)?;
+        let json = serde_json::to_string(config)?;
+        let timestamp = TimeStamp::now();
+        let mut conn = self.get_conn().await?;
+        let mut tx = conn.start_transaction(TxOpts::default()).await?;
+        let existing: Option<usize> = tx
+            .exec_first(
+                "SELECT `id` FROM `autoscrape` WHERE `catalog`=:catalog_id FOR UPDATE",
+                params! {catalog_id},
+            )
+            .await?;
+        let sql = match existing {
+            Some(_) => "UPDATE `autoscrape` SET `json`=:json,`owner`=:owner,`status`='IMPORT' WHERE `catalog`=:catalog_id",
+            None => "INSERT INTO `autoscrape` (`catalog`,`json`,`owner`,`status`) VALUES (:catalog_id,:json,:owner,'IMPORT')",
+        };
+        tx.exec_drop(sql, params! {catalog_id,json,owner}).await?;
+        let sql = "INSERT INTO `jobs` (catalog,action,status,last_ts) VALUES (:catalog_id,'autoscrape','HIGH_PRIORITY',:timestamp)
+            ON DUPLICATE KEY UPDATE status='HIGH_PRIORITY',last_ts=:timestamp";
+        tx.exec_drop(sql, params! {catalog_id,timestamp}).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
     async fn autoscrape_get_entry_ids_for_ext_ids(
         &self,
         catalog_id: usize,
@@ -620,24 +1915,116 @@ impl Storage for StorageMySQL {
         Ok(existing_ext_ids)
     }
 
+    /// Marks the autoscraper as running. The checkpoint of an interrupted run (`KV_AUTOSCRAPE_PROGRESS`) is kept.
     async fn autoscrape_start(&self, autoscrape_id: usize) -> Result<()> {
-        let sql = "UPDATE `autoscrape` SET `status`='RUNNING'`last_run_min`=NULL,`last_run_urls`=NULL WHERE `id`=:autoscrape_id" ;
+        let sql = "UPDATE `autoscrape` SET `status`='RUNNING',`last_run_urls`=NULL WHERE `id`=:autoscrape_id" ;
         if let Ok(mut conn) = self.get_conn().await {
             let _ = conn.exec_drop(sql, params! {autoscrape_id}).await; // Ignore error
         }
         Ok(())
     }
 
+    /// Stores the position (JSON) of a running autoscraper in the `KV_AUTOSCRAPE_PROGRESS` key of its catalog,
+    /// so an interrupted run can resume.
+    async fn autoscrape_save_progress(&self, autoscrape_id: usize, position: &str) -> Result<()> {
+        let sql = "INSERT INTO `kv_catalog` (`catalog_id`,`kv_key`,`kv_value`)
+            SELECT `catalog`,:key,:position FROM `autoscrape` WHERE `id`=:autoscrape_id
+            ON DUPLICATE KEY UPDATE `kv_value`=VALUES(`kv_value`)";
+        let key = KV_AUTOSCRAPE_PROGRESS;
+        self.get_conn()
+            .await?
+            .exec_drop(sql, params! {autoscrape_id,key,position})
+            .await?;
+        Ok(())
+    }
+
+    async fn autoscrape_get_progress(&self, autoscrape_id: usize) -> Result<Option<String>> {
+        let sql = "SELECT `kv_value` FROM `kv_catalog`,`autoscrape`
+            WHERE `autoscrape`.`id`=:autoscrape_id AND `kv_catalog`.`catalog_id`=`autoscrape`.`catalog`
+            AND `kv_key`=:key AND `kv_value`!=''";
+        let key = KV_AUTOSCRAPE_PROGRESS;
+        let ret = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, params! {autoscrape_id,key})
+            .await?
+            .map_and_drop(from_row::<String>)
+            .await?
+            .pop();
+        Ok(ret)
+    }
+
     async fn autoscrape_finish(&self, autoscrape_id: usize, last_run_urls: usize) -> Result<()> {
         let sql = "UPDATE `autoscrape` SET `status`='OK',`last_run_min`=NULL,`last_run_urls`=:last_run_urls WHERE `id`=:autoscrape_id" ;
         if let Ok(mut conn) = self.get_conn().await {
             let _ = conn
                 .exec_drop(sql, params! {autoscrape_id,last_run_urls})
                 .await;
+            let sql = "DELETE `kv_catalog` FROM `kv_catalog`,`autoscrape`
+                WHERE `autoscrape`.`id`=:autoscrape_id AND `kv_catalog`.`catalog_id`=`autoscrape`.`catalog` AND `kv_key`=:key";
+            let key = KV_AUTOSCRAPE_PROGRESS;
+            let _ = conn.exec_drop(sql, params! {autoscrape_id,key}).await;
         }
         Ok(())
     }
 
+    /// Records the number of entries a run or test fetch (`is_test`) of an autoscraper found, in `autoscrape_run`
+    async fn autoscrape_record_run_result(
+        &self,
+        autoscrape_id: usize,
+        entries_found: usize,
+        is_test: bool,
+    ) -> Result<()> {
+        let sql = "INSERT INTO `autoscrape_run` (`autoscrape_id`,`entries_found`,`is_test`,`timestamp`) VALUES (:autoscrape_id,:entries_found,:is_test,:timestamp)";
+        let timestamp = TimeStamp::now();
+        self.get_conn()
+            .await?
+            .exec_drop(sql, params! {autoscrape_id,entries_found,is_test,timestamp})
+            .await?;
+        Ok(())
+    }
+
+    /// Returns the number of entries found by the last real (not test) runs of an autoscraper, newest first
+    async fn autoscrape_get_recent_run_results(
+        &self,
+        autoscrape_id: usize,
+        limit: usize,
+    ) -> Result<Vec<usize>> {
+        let sql = "SELECT `entries_found` FROM `autoscrape_run` WHERE `autoscrape_id`=:autoscrape_id AND `is_test`=0 ORDER BY `id` DESC LIMIT :limit";
+        let ret = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, params! {autoscrape_id,limit})
+            .await?
+            .map_and_drop(from_row::<usize>)
+            .await?;
+        Ok(ret)
+    }
+
+    /// Returns (autoscrape ID, catalog ID) for autoscrapers of active catalogs that are neither running nor broken
+    async fn autoscrape_get_active(&self) -> Result<Vec<(usize, usize)>> {
+        let sql = "SELECT `autoscrape`.`id`,`autoscrape`.`catalog` FROM `autoscrape`,`catalog`
+            WHERE `catalog`.`id`=`autoscrape`.`catalog` AND `catalog`.`active`=1
+            AND `autoscrape`.`status` NOT IN ('RUNNING','BROKEN')";
+        let ret = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, ())
+            .await?
+            .map_and_drop(from_row::<(usize, usize)>)
+            .await?;
+        Ok(ret)
+    }
+
+    async fn autoscrape_set_status(&self, autoscrape_id: usize, status: &str) -> Result<()> {
+        let sql = "UPDATE `autoscrape` SET `status`=:status WHERE `id`=:autoscrape_id";
+        self.get_conn()
+            .await?
+            .exec_drop(sql, params! {autoscrape_id,status})
+            .await?;
+        Ok(())
+    }
+
     // Auxiliary matcher
 
     async fn auxiliary_matcher_match_via_aux(
@@ -654,12 +2041,12 @@ impl Storage for StorageMySQL {
         {}
         AND in_wikidata=0
         AND aux_p IN ({})
-        AND catalog NOT IN ({})
+        {}
         /* ORDER BY auxiliary.id */
         LIMIT :batch_size OFFSET :offset",
             MatchState::not_fully_matched().get_sql(),
             extid_props.join(","),
-            blacklisted_catalogs.join(",")
+            Self::sql_and_not_in("catalog", blacklisted_catalogs)
         );
         let results = self
             .get_conn_ro()
@@ -673,6 +2060,45 @@ impl Storage for StorageMySQL {
         Ok(results)
     }
 
+    async fn auxiliary_matcher_match_via_multiple_aux(
+        &self,
+        catalog_id: usize,
+        properties: &[usize],
+        after_entry_id: usize,
+        batch_size: usize,
+    ) -> Result<Vec<AuxiliaryResults>> {
+        if properties.is_empty() {
+            return Ok(vec![]);
+        }
+        let properties = properties.iter().map(|p| format!("{p}")).join(",");
+        // The nested subquery works around MySQL not supporting LIMIT in IN subqueries
+        let sql = format!(
+            "SELECT auxiliary.id,entry_id,0,aux_p,aux_name FROM auxiliary
+            WHERE aux_p IN ({properties})
+            AND entry_id IN (SELECT * FROM (SELECT entry.id FROM entry,auxiliary
+                WHERE entry_id=entry.id AND catalog=:catalog_id
+                {}
+                AND aux_p IN ({properties})
+                AND entry.id>:after_entry_id
+                GROUP BY entry.id
+                HAVING count(DISTINCT aux_p)>=2
+                ORDER BY entry.id
+                LIMIT :batch_size) AS entry_ids)
+            ORDER BY entry_id",
+            MatchState::not_fully_matched().get_sql(),
+        );
+        let results = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, params! {catalog_id,after_entry_id,batch_size})
+            .await?
+            .map_and_drop(from_row::<(usize, usize, usize, usize, String)>)
+            .await?;
+        let results: Vec<AuxiliaryResults> =
+            results.iter().map(AuxiliaryResults::from_result).collect();
+        Ok(results)
+    }
+
     async fn auxiliary_matcher_add_auxiliary_to_wikidata(
         &self,
         blacklisted_properties: &[String],
@@ -685,11 +2111,11 @@ impl Storage for StorageMySQL {
             WHERE entry_id=entry.id AND catalog=:catalog_id
             {}
             AND in_wikidata=0
-            AND aux_p NOT IN ({})
+            {}
             AND (aux_p!=17 OR `type`!='Q5')
             ORDER BY auxiliary.id LIMIT :batch_size OFFSET :offset",
             MatchState::fully_matched().get_sql(),
-            blacklisted_properties.join(",")
+            Self::sql_and_not_in("aux_p", blacklisted_properties)
         );
         let results = self
             .get_conn_ro()
@@ -758,6 +2184,28 @@ impl Storage for StorageMySQL {
         Ok(())
     }
 
+    /// Uses `items_using` as a lower bound for `number_of_records`, as every item using the
+    /// property points to a record in the external database.
+    async fn set_props_todo_number_of_records_floor(&self) -> Result<()> {
+        let sql = r"UPDATE `props_todo` SET `number_of_records`=`items_using`
+            WHERE `items_using` IS NOT NULL AND (`number_of_records` IS NULL OR `number_of_records`<`items_using`)";
+        self.get_conn().await?.exec_drop(sql, Empty).await?;
+        Ok(())
+    }
+
+    /// Returns the (numeric) properties of active catalogs without a qualifier
+    async fn get_active_catalog_properties(&self) -> Result<Vec<u64>> {
+        let sql = r"SELECT DISTINCT `wd_prop` FROM `catalog` WHERE `active`=1 AND `wd_qual` IS NULL AND `wd_prop` IS NOT NULL";
+        let ret = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, ())
+            .await?
+            .map_and_drop(from_row::<u64>)
+            .await?;
+        Ok(ret)
+    }
+
     /// Removes P17 auxiliary values for entryies of type Q5 (human)
     async fn remove_p17_for_humans(&self) -> Result<()> {
         let sql = r#"DELETE FROM auxiliary WHERE aux_p=17 AND EXISTS (SELECT * FROM entry WHERE entry_id=entry.id AND `type`="Q5")"#;
@@ -765,7 +2213,10 @@ impl Storage for StorageMySQL {
         Ok(())
     }
 
-    async fn maintenance_automatch_people_via_year_born(&self) -> Result<()> {
+    async fn maintenance_automatch_people_via_year_born(
+        &self,
+        excluded_catalogs: &[usize],
+    ) -> Result<()> {
         let mut conn = self.get_conn().await?;
 
         // DEACTIVATED THIS TAKES TOO LONG
@@ -775,7 +2226,8 @@ impl Storage for StorageMySQL {
         conn.exec_drop(sql, Empty).await?;
 
         // Generate sub-list of potential matches
-        let sql = r#"CREATE table tmp_automatches
+        let sql = format!(
+            r#"CREATE table tmp_automatches
 	       SELECT DISTINCT e2.id AS entry_id,e1.q AS q
 	       FROM entry e1,entry e2,person_dates p1,person_dates p2,catalog c1,catalog c2
 	       WHERE p1.entry_id=e1.id AND p2.entry_id=e2.id AND p1.year_born=p2.year_born
@@ -785,7 +2237,11 @@ impl Storage for StorageMySQL {
 	       AND e1.q!=e2.q
 	       AND e1.catalog=c1.id AND c1.active=1
 	       AND e2.catalog=c2.id AND c2.active=1
-	       limit 1000"#;
+	       {}{}
+	       limit 1000"#,
+            Self::catalog_exclusion_sql("e1.catalog", excluded_catalogs),
+            Self::catalog_exclusion_sql("e2.catalog", excluded_catalogs)
+        );
         conn.exec_drop(sql, Empty).await?;
 
         // Apply sub-list
@@ -915,27 +2371,46 @@ impl Storage for StorageMySQL {
 
     /// Finds some unmatched (Q5) entries where there is a (unique) full match for that name,
     /// and uses it as an auto-match
-    async fn maintenance_automatch(&self) -> Result<()> {
+    async fn maintenance_automatch(
+        &self,
+        excluded_catalogs: &[usize],
+        limit: usize,
+    ) -> Result<usize> {
         let mut conn = self.get_conn().await?;
-        let sql = "SELECT e1.id,e2.q FROM entry e1,entry e2
+        let sql = format!("SELECT e1.id,e2.q FROM entry e1,entry e2
             WHERE e1.ext_name=e2.ext_name AND e1.id!=e2.id
             AND e1.type='Q5' AND e2.type='Q5'
             AND e1.q IS NULL
             AND e2.type IS NOT NULL AND e2.user>0
+            {}{}
             HAVING
             (SELECT count(DISTINCT q) FROM entry e3 WHERE e3.ext_name=e2.ext_name AND e3.type=e2.type AND e3.q IS NOT NULL AND e3.user>0)=1
-            LIMIT 500";
+            LIMIT {limit}",
+            Self::catalog_exclusion_sql("e1.catalog", excluded_catalogs),
+            Self::catalog_exclusion_sql("e2.catalog", excluded_catalogs)
+        );
         let new_automatches = conn
             .exec_iter(sql, ())
             .await?
             .map_and_drop(from_row::<(usize, isize)>)
             .await?;
         let sql = "UPDATE `entry` SET `q`=:q,`user`=0,`timestamp`=:timestamp WHERE `id`=:entry_id AND `q` IS NULL" ;
+        let mut matched = 0;
         for (entry_id, q) in &new_automatches {
             let timestamp = TimeStamp::now();
             conn.exec_drop(sql, params! {entry_id,q,timestamp}).await?;
+            matched += conn.affected_rows() as usize;
         }
-        Ok(())
+        Ok(matched)
+    }
+
+    /// Sets a random value for up to `batch_size` entries that have none.
+    /// Returns the number of entries that were changed.
+    async fn maintenance_fill_missing_random(&self, batch_size: usize) -> Result<usize> {
+        let sql = "UPDATE `entry` SET `random`=rand() WHERE `random` IS NULL LIMIT :batch_size";
+        let mut conn = self.get_conn().await?;
+        conn.exec_drop(sql, params! {batch_size}).await?;
+        Ok(conn.affected_rows() as usize)
     }
 
     /// Returns tuples of entry IDs and their prospective q matches,
@@ -944,8 +2419,15 @@ impl Storage for StorageMySQL {
     async fn maintenance_match_people_via_name_and_full_dates(
         &self,
         batch_size: usize,
+        excluded_catalogs: &[usize],
     ) -> Result<Vec<(usize, usize)>> {
         let mut conn = self.get_conn().await?;
+        let exclude = if excluded_catalogs.is_empty() {
+            String::new()
+        } else {
+            let catalogs = excluded_catalogs.iter().map(|id| format!("{id}")).join(",");
+            format!("AND NOT EXISTS (SELECT * FROM entry e WHERE e.id IN (d1.entry_id,d2.entry_id) AND e.catalog IN ({catalogs}))")
+        };
         let sql = format!(
             "SELECT d1.entry_id,d2.q
 			FROM vw_dates d1,vw_dates d2
@@ -954,6 +2436,7 @@ impl Storage for StorageMySQL {
 			AND d1.ext_name=d2.ext_name
 			AND (d1.user=0 OR d1.user is null)
 			AND d2.user>0 and d2.user is not null
+			{exclude}
 			HAVING d2.q>0
 			limit {batch_size}
 			"
@@ -966,6 +2449,19 @@ impl Storage for StorageMySQL {
         Ok(results)
     }
 
+    /// Returns the external IDs that more than one entry of the catalog has
+    async fn maintenance_get_duplicate_ext_ids(&self, catalog_id: usize) -> Result<Vec<String>> {
+        let sql = r"SELECT `ext_id` FROM `entry` WHERE `catalog`=:catalog_id GROUP BY `ext_id` HAVING count(*)>1";
+        let ret = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, params! {catalog_id})
+            .await?
+            .map_and_drop(from_row::<String>)
+            .await?;
+        Ok(ret)
+    }
+
     /// Retrieves a batch of (unique) Wikidata items, in a given matching state.
     async fn get_items(
         &self,
@@ -989,6 +2485,30 @@ impl Storage for StorageMySQL {
         Ok(ret)
     }
 
+    /// Items that have a full match, optionally only in one catalog, paged by item
+    async fn get_distinct_matched_items(
+        &self,
+        catalog_id: Option<usize>,
+        after_q: isize,
+        limit: usize,
+    ) -> Result<Vec<isize>> {
+        let after_q = after_q.max(0);
+        let mut sql =
+            "SELECT DISTINCT `q` FROM `entry` WHERE `q`>:after_q AND `user`>0".to_string();
+        if let Some(catalog_id) = catalog_id {
+            sql += &format!(" AND `catalog`={catalog_id}");
+        }
+        sql += " ORDER BY `q` LIMIT :limit";
+        let ret = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, params! {after_q,limit})
+            .await?
+            .map_and_drop(from_row::<isize>)
+            .await?;
+        Ok(ret)
+    }
+
     // Jobs
 
     async fn jobs_get_tasks(&self) -> Result<HashMap<String, TaskSize>> {
@@ -1020,11 +2540,13 @@ impl Storage for StorageMySQL {
         Ok(())
     }
 
-    /// Resets all FAILED jobs of certain types to TODO. Used when bot restarts.
+    /// Resets FAILED jobs without a scheduled retry (empty `next_ts`) to TODO. Used when bot restarts.
+    /// FAILED jobs with a scheduled retry run when their `next_ts` is due, and BLOCKED jobs
+    /// (too many failures) are not affected.
     //TODO test
     async fn reset_failed_jobs(&self) -> Result<()> {
         let sql = format!(
-            "UPDATE `jobs` SET `status`='{}' WHERE `status`='{}'",
+            "UPDATE `jobs` SET `status`='{}' WHERE `status`='{}' AND `next_ts`=''",
             JobStatus::Todo.as_str(),
             JobStatus::Failed.as_str()
         );
@@ -1046,10 +2568,44 @@ impl Storage for StorageMySQL {
         let mut conn = self.get_conn().await?;
         conn.exec_drop(sql, params! {catalog_id,action,depends_on,status,timestamp})
             .await?;
-        let last_id = conn.last_insert_id().ok_or(EntryError::EntryInsertFailed)? as usize;
+        let last_id =
+            conn.last_insert_id()
+                .ok_or(StorageError::Entry(EntryError::EntryInsertFailed))? as usize;
         Ok(last_id)
     }
 
+    async fn jobs_queue_repeating_job(
+        &self,
+        catalog_id: usize,
+        action: &str,
+        repeat_after_sec: usize,
+        timestamp: String,
+    ) -> Result<()> {
+        let sql = "INSERT INTO `jobs` (catalog,action,status,repeat_after_sec,last_ts) VALUES (:catalog_id,:action,'TODO',:repeat_after_sec,:timestamp)
+            ON DUPLICATE KEY UPDATE repeat_after_sec=:repeat_after_sec";
+        self.get_conn()
+            .await?
+            .exec_drop(sql, params! {catalog_id,action,repeat_after_sec,timestamp})
+            .await?;
+        Ok(())
+    }
+
+    async fn jobs_queue_job_with_json(
+        &self,
+        catalog_id: usize,
+        action: &str,
+        json_string: String,
+        timestamp: String,
+    ) -> Result<()> {
+        let sql = "INSERT INTO `jobs` (catalog,action,status,json,last_ts) VALUES (:catalog_id,:action,'TODO',:json_string,:timestamp)
+            ON DUPLICATE KEY UPDATE status='TODO',json=:json_string,last_ts=:timestamp";
+        self.get_conn()
+            .await?
+            .exec_drop(sql, params! {catalog_id,action,json_string,timestamp})
+            .await?;
+        Ok(())
+    }
+
     async fn jobs_reset_json(&self, job_id: usize, timestamp: String) -> Result<()> {
         let sql = "UPDATE `jobs` SET `json`=NULL,last_ts=:timestamp WHERE `id`=:job_id";
         let mut conn = self.get_conn().await?;
@@ -1071,7 +2627,7 @@ impl Storage for StorageMySQL {
     }
 
     async fn jobs_row_from_id(&self, job_id: usize) -> Result<JobRow> {
-        let sql = r"SELECT id,action,catalog,json,depends_on,status,last_ts,note,repeat_after_sec,next_ts,user_id FROM `jobs` WHERE `id`=:job_id";
+        let sql = r"SELECT id,action,catalog,json,depends_on,status,last_ts,note,repeat_after_sec,next_ts,user_id,failures FROM `jobs` WHERE `id`=:job_id";
         let mut conn = self.get_conn().await?;
         let row = conn
             .exec_iter(sql, params! {job_id})
@@ -1089,6 +2645,7 @@ impl Storage for StorageMySQL {
                     Option<usize>,
                     String,
                     usize,
+                    usize,
                 )>,
             )
             .await?
@@ -1105,7 +2662,12 @@ impl Storage for StorageMySQL {
         timestamp: String,
     ) -> Result<()> {
         let status_str = status.as_str();
-        let sql = "UPDATE `jobs` SET `status`=:status_str,`last_ts`=:timestamp,`note`=NULL WHERE `id`=:job_id";
+        // The note was cleared when the job started RUNNING; a DONE job keeps the note it set, eg a summary
+        let clear_note = match status {
+            JobStatus::Done => "",
+            _ => ",`note`=NULL",
+        };
+        let sql = format!("UPDATE `jobs` SET `status`=:status_str,`last_ts`=:timestamp{clear_note} WHERE `id`=:job_id");
         let mut conn = self.get_conn().await?;
         conn.exec_drop(sql, params! {job_id,timestamp,status_str})
             .await?;
@@ -1120,6 +2682,124 @@ impl Storage for StorageMySQL {
         Ok(note_cloned)
     }
 
+    /// Stores the full error text and its category for a failed job; unlike `note`, this is not truncated.
+    async fn jobs_set_error(&self, job_id: usize, category: &str, details: String) -> Result<()> {
+        let sql = "UPDATE `jobs` SET `error_category`=:category,`error_details`=:details WHERE `id`=:job_id";
+        let mut conn = self.get_conn().await?;
+        conn.exec_drop(sql, params! {job_id,category,details})
+            .await?;
+        Ok(())
+    }
+
+    /// Returns the (category,details) of the last error of a job, if any.
+    async fn jobs_get_error(&self, job_id: usize) -> Result<Option<(String, String)>> {
+        let sql = "SELECT `error_category`,`error_details` FROM `jobs` WHERE `id`=:job_id AND `error_category` IS NOT NULL";
+        let ret = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, params! {job_id})
+            .await?
+            .map_and_drop(from_row::<(String, String)>)
+            .await?
+            .pop();
+        Ok(ret)
+    }
+
+    /// Increments the consecutive failure counter of a job, and returns the new value.
+    async fn jobs_increment_failures(&self, job_id: usize) -> Result<usize> {
+        let sql = "UPDATE `jobs` SET `failures`=`failures`+1 WHERE `id`=:job_id";
+        let mut conn = self.get_conn().await?;
+        conn.exec_drop(sql, params! {job_id}).await?;
+        let sql = "SELECT `failures` FROM `jobs` WHERE `id`=:job_id";
+        let failures = conn
+            .exec_iter(sql, params! {job_id})
+            .await?
+            .map_and_drop(from_row::<usize>)
+            .await?
+            .pop()
+            .ok_or(anyhow!("No job with ID {}", job_id))?;
+        Ok(failures)
+    }
+
+    async fn jobs_reset_failures(&self, job_id: usize) -> Result<()> {
+        let sql = "UPDATE `jobs` SET `failures`=0 WHERE `id`=:job_id";
+        let mut conn = self.get_conn().await?;
+        conn.exec_drop(sql, params! {job_id}).await?;
+        Ok(())
+    }
+
+    /// Returns the IDs of all jobs that were BLOCKED after failing at least `min_failures` times in a row.
+    async fn jobs_get_dead_letter(&self, min_failures: usize) -> Result<Vec<usize>> {
+        let sql = "SELECT `id` FROM `jobs` WHERE `status`=:status AND `failures`>=:min_failures ORDER BY `id`";
+        let status = JobStatus::Blocked.as_str();
+        let ret = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, params! {status,min_failures})
+            .await?
+            .map_and_drop(from_row::<usize>)
+            .await?;
+        Ok(ret)
+    }
+
+    async fn jobs_get_status_counts(&self) -> Result<Vec<(String, usize)>> {
+        let sql = "SELECT `status`,count(*) FROM `jobs` GROUP BY `status`";
+        let ret = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, ())
+            .await?
+            .map_and_drop(from_row::<(String, usize)>)
+            .await?;
+        Ok(ret)
+    }
+
+    async fn jobs_count_running_since(&self, ts: &str) -> Result<usize> {
+        let sql = "SELECT count(*) FROM `jobs` WHERE `status`=:status AND `last_ts`>=:ts";
+        let status = JobStatus::Running.as_str();
+        let ret = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, params! {status,ts})
+            .await?
+            .map_and_drop(from_row::<usize>)
+            .await?
+            .pop()
+            .unwrap_or(0);
+        Ok(ret)
+    }
+
+    async fn jobs_get_last_done_ts(&self) -> Result<Option<String>> {
+        let sql = "SELECT max(`last_ts`) FROM `jobs` WHERE `status`=:status";
+        let status = JobStatus::Done.as_str();
+        let ret = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, params! {status})
+            .await?
+            .map_and_drop(from_row::<Option<String>>)
+            .await?
+            .pop()
+            .flatten();
+        Ok(ret)
+    }
+
+    async fn jobs_requeue(
+        &self,
+        job_id: usize,
+        status: &JobStatus,
+        timestamp: String,
+    ) -> Result<()> {
+        let status_str = status.as_str();
+        let sql = r"UPDATE `jobs` SET `status`=:status_str,`last_ts`=:timestamp,
+            `next_ts`='',`failures`=0,
+            `note`=NULL,`error_category`=NULL,`error_details`=NULL WHERE `id`=:job_id AND `status`!='RUNNING'";
+        let mut conn = self.get_conn().await?;
+        conn.exec_drop(sql, params! {job_id,timestamp,status_str})
+            .await?;
+        Ok(())
+    }
+
     async fn jobs_update_next_ts(&self, job_id: usize, next_ts: String) -> Result<()> {
         let sql = "UPDATE `jobs` SET `next_ts`=:next_ts WHERE `id`=:job_id";
         let mut conn = self.get_conn().await?;
@@ -1168,22 +2848,9 @@ impl Storage for StorageMySQL {
 
     async fn automatch_by_search_get_results(
         &self,
-        catalog_id: usize,
-        offset: usize,
-        batch_size: usize,
+        query: &EntryQuery,
     ) -> Result<Vec<(usize, String, String, String)>> {
-        let sql = format!("SELECT `id`,`ext_name`,`type`,
-	            IFNULL((SELECT group_concat(DISTINCT `label` SEPARATOR '|') FROM aliases WHERE entry_id=entry.id),'') AS `aliases`
-	            FROM `entry` WHERE `catalog`=:catalog_id {}
-	            /* ORDER BY `id` */
-	            LIMIT :batch_size OFFSET :offset",MatchState::not_fully_matched().get_sql());
-        let mut conn = self.get_conn_ro().await?;
-        let results = conn
-            .exec_iter(sql.clone(), params! {catalog_id,offset,batch_size})
-            .await?
-            .map_and_drop(from_row::<(usize, String, String, String)>)
-            .await?;
-        Ok(results)
+        self.automatch_entry_query_get_results(query).await
     }
 
     async fn automatch_creations_get_results(
@@ -1204,22 +2871,9 @@ impl Storage for StorageMySQL {
 
     async fn automatch_simple_get_results(
         &self,
-        catalog_id: usize,
-        offset: usize,
-        batch_size: usize,
+        query: &EntryQuery,
     ) -> Result<Vec<(usize, String, String, String)>> {
-        let sql = format!("SELECT `id`,`ext_name`,`type`,
-                IFNULL((SELECT group_concat(DISTINCT `label` SEPARATOR '|') FROM aliases WHERE entry_id=entry.id),'') AS `aliases`
-                FROM `entry` WHERE `catalog`=:catalog_id {}
-                /* ORDER BY `id` */
-                LIMIT :batch_size OFFSET :offset",MatchState::not_fully_matched().get_sql());
-        let mut conn = self.get_conn_ro().await?;
-        let results = conn
-            .exec_iter(sql.clone(), params! {catalog_id,offset,batch_size})
-            .await?
-            .map_and_drop(from_row::<(usize, String, String, String)>)
-            .await?;
-        Ok(results)
+        self.automatch_entry_query_get_results(query).await
     }
 
     async fn automatch_from_other_catalogs_get_results(
@@ -1277,6 +2931,30 @@ impl Storage for StorageMySQL {
         Ok(results_in_other_catalogs)
     }
 
+    async fn find_matched_entries_by_ext_url(
+        &self,
+        urls: &[String],
+    ) -> Result<Vec<(String, usize, isize)>> {
+        if urls.is_empty() {
+            return Ok(vec![]);
+        }
+        let placeholders = Self::sql_placeholders(urls.len());
+        let sql = format!(
+            "SELECT `ext_url`,`catalog`,`q` FROM `entry`
+            WHERE `ext_url` IN ({placeholders})
+            AND `q`>0 AND `user`>0
+            AND `catalog` IN (SELECT `id` FROM `catalog` WHERE `active`=1)"
+        );
+        let ret = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, urls.to_vec())
+            .await?
+            .map_and_drop(from_row::<(String, usize, isize)>)
+            .await?;
+        Ok(ret)
+    }
+
     async fn purge_automatches(&self, catalog_id: usize) -> Result<()> {
         let mut conn = self.get_conn().await?;
         conn.exec_drop("UPDATE entry SET q=NULL,user=NULL,`timestamp`=NULL WHERE catalog=:catalog_id AND user=0", params! {catalog_id}).await?;
@@ -1356,22 +3034,67 @@ impl Storage for StorageMySQL {
         Ok(el_chunk)
     }
 
+    async fn automatch_get_max_entry_id(&self, catalog_id: usize) -> Result<Option<usize>> {
+        let sql = "SELECT max(`id`) FROM `entry` WHERE `catalog`=:catalog_id";
+        let ret = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, params! {catalog_id})
+            .await?
+            .map_and_drop(from_row::<Option<usize>>)
+            .await?
+            .pop()
+            .flatten();
+        Ok(ret)
+    }
+
+    async fn automatch_set_watermark(
+        &self,
+        catalog_id: usize,
+        action: &str,
+        max_entry_id: usize,
+    ) -> Result<()> {
+        let sql = "REPLACE INTO `automatch_watermark` (`catalog_id`,`action`,`max_entry_id`) VALUES (:catalog_id,:action,:max_entry_id)";
+        self.get_conn()
+            .await?
+            .exec_drop(sql, params! {catalog_id,action,max_entry_id})
+            .await?;
+        Ok(())
+    }
+
+    async fn automatch_get_catalogs_with_new_entries(&self) -> Result<Vec<(usize, String, usize)>> {
+        let sql = "SELECT `catalog_id`,`action`,`max_entry_id` FROM `automatch_watermark`,`catalog`
+            WHERE `catalog`.`id`=`catalog_id` AND `catalog`.`active`=1
+            AND EXISTS (SELECT * FROM `entry` WHERE `entry`.`catalog`=`catalog_id` AND `entry`.`id`>`max_entry_id`)
+            AND NOT EXISTS (SELECT * FROM `jobs` WHERE `jobs`.`catalog`=`catalog_id` AND `jobs`.`action`=`automatch_watermark`.`action` AND `jobs`.`status` IN ('TODO','RUNNING'))";
+        let ret = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, ())
+            .await?
+            .map_and_drop(from_row::<(usize, String, usize)>)
+            .await?;
+        Ok(ret)
+    }
+
     // Entry
 
-    async fn entry_from_id(&self, entry_id: usize) -> Result<Entry> {
+    async fn entry_from_id(&self, entry_id: usize) -> StorageResult<Entry> {
         let sql = format!("{} WHERE `id`=:entry_id", Self::entry_sql_select());
         let mut conn = self.get_conn_ro().await?;
-        let ret = conn
+        let mut rows = conn
             .exec_iter(sql, params! {entry_id})
             .await?
             .map_and_drop(|row| Self::entry_from_row(&row))
-            .await?
-            .iter()
-            .filter_map(|row| row.to_owned())
-            .next()
-            .ok_or(anyhow!("No entry #{}", entry_id))?
-            .to_owned();
-        Ok(ret)
+            .await?;
+        drop(conn);
+        match rows.pop() {
+            Some(Some(entry)) => Ok(entry),
+            Some(None) => Err(StorageError::Invalid(format!(
+                "Entry #{entry_id} could not be read"
+            ))),
+            None => Err(StorageError::NotFound(format!("No entry #{entry_id}"))),
+        }
     }
 
     async fn entry_from_ext_id(&self, catalog_id: usize, ext_id: &str) -> Result<Entry> {
@@ -1415,6 +3138,96 @@ impl Storage for StorageMySQL {
             .collect())
     }
 
+    /// Keyset-paginated variant of `get_entry_batch`, for walking large catalogs without deep OFFSET scans
+    async fn get_entry_batch_after(
+        &self,
+        catalog_id: usize,
+        after_id: usize,
+        limit: usize,
+    ) -> Result<Vec<Entry>> {
+        let sql = format!(
+            "{} WHERE `catalog`=:catalog_id AND `id`>:after_id ORDER BY `id` LIMIT :limit",
+            Self::entry_sql_select()
+        );
+        Ok(self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, params! {catalog_id,after_id,limit})
+            .await?
+            .map_and_drop(|row| Self::entry_from_row(&row))
+            .await?
+            .iter()
+            .filter_map(|row| row.to_owned())
+            .collect())
+    }
+
+    async fn get_entry_query(&self, query: &EntryQuery) -> Result<Vec<Entry>> {
+        let (sql_where, params) = Self::get_entry_query_sql(query);
+        let sql = format!("{}{sql_where}", Self::entry_sql_select());
+        Ok(self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, Params::Positional(params))
+            .await?
+            .map_and_drop(|row| Self::entry_from_row(&row))
+            .await?
+            .into_iter()
+            .flatten()
+            .collect())
+    }
+
+    /// Returns (entry, shared name count) for unmatched and "not on Wikidata" entries of a catalog, most shared names first.
+    async fn get_entries_for_creation(
+        &self,
+        query: &CreationCandidateQuery,
+    ) -> Result<Vec<(Entry, usize)>> {
+        // The name counts are grouped once, for the names in the catalog only
+        let mut params: Vec<mysql_async::Value> =
+            vec![query.catalog_id.into(), query.min_shared_names.into()];
+        let mut conditions = vec![
+            "`entry`.`catalog`=?".to_string(),
+            "(`entry`.`q` IS NULL OR (`entry`.`q`=-1 AND `entry`.`user`>0))".to_string(),
+        ];
+        params.push(query.catalog_id.into());
+        if let Some(type_name) = &query.type_name {
+            conditions.push("`entry`.`type`=?".to_string());
+            params.push(type_name.to_owned().into());
+        }
+        if query.with_dates {
+            conditions.push("EXISTS (SELECT * FROM `person_dates` WHERE `entry_id`=`entry`.`id` AND (`born`!='' OR `died`!=''))".to_string());
+        }
+        params.push(query.limit.into());
+        params.push(query.offset.into());
+        let sql = format!(
+            "SELECT `entry`.`id`,`entry`.`catalog`,`ext_id`,`ext_url`,`entry`.`ext_name`,`ext_desc`,`q`,`user`,`timestamp`,
+            if(isnull(`random`),rand(),`random`) AS `random`,`type`,`shared`.`shared_names`
+            FROM `entry`
+            JOIN (
+                SELECT e2.`ext_name`,count(*) AS `shared_names` FROM `entry` e2
+                WHERE e2.`ext_name` IN (SELECT e3.`ext_name` FROM `entry` e3 WHERE e3.`catalog`=?)
+                AND (e2.`q` IS NULL OR (e2.`q`=-1 AND e2.`user`>0))
+                GROUP BY e2.`ext_name`
+                HAVING `shared_names`>=?
+            ) AS `shared` ON `shared`.`ext_name`=`entry`.`ext_name`
+            WHERE {}
+            ORDER BY `shared`.`shared_names` DESC,`entry`.`id`
+            LIMIT ? OFFSET ?",
+            conditions.join(" AND ")
+        );
+        let rows = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, Params::Positional(params))
+            .await?
+            .map_and_drop(|row| {
+                let entry = Self::entry_from_row(&row)?;
+                let shared_names: usize = row.get(11)?;
+                Some((entry, shared_names))
+            })
+            .await?;
+        Ok(rows.into_iter().flatten().collect())
+    }
+
     async fn multiple_from_ids(&self, entry_ids: &[usize]) -> Result<HashMap<usize, Entry>> {
         if entry_ids.is_empty() {
             return Ok(HashMap::new());
@@ -1429,16 +3242,62 @@ impl Storage for StorageMySQL {
         let rows: Vec<Entry> = conn
             .exec_iter(sql, ())
             .await?
-            .map_and_drop(|row| Self::entry_from_row(&row))
+            .map_and_drop(|row| Self::entry_from_row(&row))
+            .await?
+            .iter()
+            .filter_map(|row| row.to_owned())
+            .collect();
+        let ret = rows.into_iter().map(|entry| (entry.id, entry)).collect();
+        Ok(ret)
+    }
+
+    async fn get_entry_ids_by_ext_id(
+        &self,
+        catalog_ids: &[usize],
+        ext_id: &str,
+    ) -> Result<Vec<usize>> {
+        if catalog_ids.is_empty() {
+            return Ok(vec![]);
+        }
+        let catalog_ids = catalog_ids.iter().map(|id| format!("{id}")).join(",");
+        let sql = format!(
+            "SELECT `id` FROM `entry` WHERE `catalog` IN ({catalog_ids}) AND `ext_id`=:ext_id"
+        );
+        let ret = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, params! {ext_id})
+            .await?
+            .map_and_drop(from_row::<usize>)
+            .await?;
+        Ok(ret)
+    }
+
+    /// Returns the IDs of entries that have an auxiliary value for a property
+    async fn get_entry_ids_by_aux(
+        &self,
+        prop_numeric: usize,
+        value: &str,
+        active_only: bool,
+    ) -> Result<Vec<usize>> {
+        let mut sql = r"SELECT DISTINCT `entry_id` FROM `auxiliary`,`entry`,`catalog`
+            WHERE `aux_p`=:prop_numeric AND `aux_name`=:value
+            AND `entry`.`id`=`auxiliary`.`entry_id` AND `catalog`.`id`=`entry`.`catalog`"
+            .to_string();
+        if active_only {
+            sql += " AND `catalog`.`active`=1";
+        }
+        let ret = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, params! {prop_numeric,value})
             .await?
-            .iter()
-            .filter_map(|row| row.to_owned())
-            .collect();
-        let ret = rows.into_iter().map(|entry| (entry.id, entry)).collect();
+            .map_and_drop(from_row::<usize>)
+            .await?;
         Ok(ret)
     }
 
-    async fn entry_insert_as_new(&self, entry: &Entry) -> Result<usize> {
+    async fn entry_insert_as_new(&self, entry: &Entry, import_id: Option<usize>) -> Result<usize> {
         let sql = "INSERT IGNORE INTO `entry` (`catalog`,`ext_id`,`ext_url`,`ext_name`,`ext_desc`,`q`,`user`,`timestamp`,`random`,`type`) VALUES (:catalog,:ext_id,:ext_url,:ext_name,:ext_desc,:q,:user,:timestamp,:random,:type_name)";
         let params = params! {
             "catalog" => entry.catalog,
@@ -1454,7 +3313,16 @@ impl Storage for StorageMySQL {
         };
         let mut conn = self.get_conn().await?;
         conn.exec_drop(sql, params).await?;
-        let id = conn.last_insert_id().ok_or(EntryError::EntryInsertFailed)? as usize;
+        let id = conn
+            .last_insert_id()
+            .ok_or(StorageError::Entry(EntryError::EntryInsertFailed))? as usize;
+        if let Some(update_info_id) = import_id {
+            let sql = r"INSERT INTO `entry_creation` (`entry_id`,`timestamp`,`update_info_id`) VALUES (:id,:timestamp,:update_info_id)
+                ON DUPLICATE KEY UPDATE `update_info_id`=VALUES(`update_info_id`)";
+            let timestamp = TimeStamp::now();
+            conn.exec_drop(sql, params! {id,timestamp,update_info_id})
+                .await?;
+        }
         Ok(id)
     }
 
@@ -1469,6 +3337,22 @@ impl Storage for StorageMySQL {
         Ok(())
     }
 
+    async fn get_entry_provenance(&self, entry_id: usize) -> Result<Option<EntryProvenance>> {
+        let sql = r"SELECT `update_info_id`,`entry_creation`.`timestamp`,`update_info`.`user_id`
+            FROM `entry_creation` LEFT JOIN `update_info` ON `update_info`.`id`=`entry_creation`.`update_info_id`
+            WHERE `entry_id`=:entry_id";
+        let ret = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, params! {entry_id})
+            .await?
+            .map_and_drop(from_row::<(Option<usize>, Option<String>, Option<usize>)>)
+            .await?
+            .pop()
+            .map(|row| EntryProvenance::from_row(entry_id, row));
+        Ok(ret)
+    }
+
     async fn entry_get_creation_time(&self, entry_id: usize) -> Option<String> {
         let mut conn = self.get_conn_ro().await.ok()?;
         let results = conn
@@ -1641,6 +3525,142 @@ impl Storage for StorageMySQL {
         Ok(map)
     }
 
+    async fn entries_get_aliases(
+        &self,
+        entry_ids: &[usize],
+    ) -> Result<HashMap<usize, Vec<LocaleString>>> {
+        if entry_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let entry_ids = entry_ids
+            .iter()
+            .map(|id| format!("{id}"))
+            .collect::<Vec<String>>()
+            .join(",");
+        let sql = format!(
+            "SELECT `entry_id`,`language`,`label` FROM `aliases` WHERE `entry_id` IN ({entry_ids})"
+        );
+        let rows: Vec<(usize, String, String)> = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, ())
+            .await?
+            .map_and_drop(from_row::<(usize, String, String)>)
+            .await?;
+        let mut ret: HashMap<usize, Vec<LocaleString>> = HashMap::new();
+        for (entry_id, language, label) in rows {
+            ret.entry(entry_id)
+                .or_default()
+                .push(LocaleString::new(&language, &label));
+        }
+        Ok(ret)
+    }
+
+    async fn entries_get_language_descriptions(
+        &self,
+        entry_ids: &[usize],
+    ) -> Result<HashMap<usize, HashMap<String, String>>> {
+        if entry_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let entry_ids = entry_ids
+            .iter()
+            .map(|id| format!("{id}"))
+            .collect::<Vec<String>>()
+            .join(",");
+        let sql = format!(
+            "SELECT `entry_id`,`language`,`label` FROM `descriptions` WHERE `entry_id` IN ({entry_ids})"
+        );
+        let rows: Vec<(usize, String, String)> = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, ())
+            .await?
+            .map_and_drop(from_row::<(usize, String, String)>)
+            .await?;
+        let mut ret: HashMap<usize, HashMap<String, String>> = HashMap::new();
+        for (entry_id, language, label) in rows {
+            ret.entry(entry_id).or_default().insert(language, label);
+        }
+        Ok(ret)
+    }
+
+    async fn entries_get_person_dates(
+        &self,
+        entry_ids: &[usize],
+    ) -> Result<HashMap<usize, (Option<String>, Option<String>)>> {
+        if entry_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let entry_ids = entry_ids.iter().map(|id| format!("{id}")).join(",");
+        let sql = format!(
+            "SELECT `entry_id`,`born`,`died` FROM `person_dates` WHERE `entry_id` IN ({entry_ids})"
+        );
+        let rows: Vec<(usize, String, String)> = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, ())
+            .await?
+            .map_and_drop(from_row::<(usize, String, String)>)
+            .await?;
+        let ret = rows
+            .into_iter()
+            .map(|(entry_id, born, died)| {
+                let born = if born.is_empty() { None } else { Some(born) };
+                let died = if died.is_empty() { None } else { Some(died) };
+                (entry_id, (born, died))
+            })
+            .collect();
+        Ok(ret)
+    }
+
+    async fn entries_get_aux(
+        &self,
+        entry_ids: &[usize],
+    ) -> Result<HashMap<usize, Vec<AuxiliaryRow>>> {
+        if entry_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let entry_ids = entry_ids.iter().map(|id| format!("{id}")).join(",");
+        let sql = format!("SELECT `id`,`aux_p`,`aux_name`,`in_wikidata`,`entry_is_matched`,`entry_id` FROM `auxiliary` WHERE `entry_id` IN ({entry_ids}) ORDER BY `id`");
+        let rows = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, ())
+            .await?
+            .map_and_drop(|row| Some((row.get::<usize, _>(5)?, AuxiliaryRow::from_row(&row)?)))
+            .await?;
+        let mut ret: HashMap<usize, Vec<AuxiliaryRow>> = HashMap::new();
+        for (entry_id, aux) in rows.into_iter().flatten() {
+            ret.entry(entry_id).or_default().push(aux);
+        }
+        Ok(ret)
+    }
+
+    async fn entries_get_coordinate_locations(
+        &self,
+        entry_ids: &[usize],
+    ) -> Result<HashMap<usize, CoordinateLocation>> {
+        if entry_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let entry_ids = entry_ids.iter().map(|id| format!("{id}")).join(",");
+        let sql = format!(
+            "SELECT `entry_id`,`lat`,`lon` FROM `location` WHERE `entry_id` IN ({entry_ids})"
+        );
+        let ret = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, ())
+            .await?
+            .map_and_drop(from_row::<(usize, f64, f64)>)
+            .await?
+            .into_iter()
+            .map(|(entry_id, lat, lon)| (entry_id, CoordinateLocation { lat, lon }))
+            .collect();
+        Ok(ret)
+    }
+
     async fn entry_remove_auxiliary(&self, entry_id: usize, prop_numeric: usize) -> Result<()> {
         let sql = "DELETE FROM `auxiliary` WHERE `entry_id`=:entry_id AND `aux_p`=:prop_numeric";
         let mut conn = self.get_conn().await?;
@@ -1707,6 +3727,86 @@ impl Storage for StorageMySQL {
         Ok(ret)
     }
 
+    async fn entry_get_statement_texts(&self, entry_id: usize) -> Result<Vec<StatementText>> {
+        let sql = "SELECT `id`,`entry_id`,`property`,`text`,`q`,`in_wikidata`,`entry_is_matched` FROM `statement_text` WHERE `entry_id`=:entry_id ORDER BY `id`";
+        let ret = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, params! {entry_id})
+            .await?
+            .map_and_drop(from_row::<(usize, usize, usize, String, Option<isize>, u8, u8)>)
+            .await?
+            .into_iter()
+            .map(StatementText::from_row)
+            .collect();
+        Ok(ret)
+    }
+
+    /// Adds a statement text, unless the entry already has it.
+    /// `entry_is_matched` is set from the current match of the entry.
+    async fn entry_add_statement_text(
+        &self,
+        entry_id: usize,
+        property: usize,
+        text: &str,
+    ) -> Result<bool> {
+        let sql = "INSERT INTO `statement_text` (`entry_id`,`property`,`text`,`entry_is_matched`)
+            SELECT `id`,:property,:text,IF(`q`>0 AND `user`>0,1,0) FROM `entry`
+            WHERE `id`=:entry_id
+            AND NOT EXISTS (SELECT * FROM `statement_text` WHERE `entry_id`=:entry_id AND `property`=:property AND `text`=:text)";
+        let mut conn = self.get_conn().await?;
+        conn.exec_drop(sql, params! {entry_id, property, text})
+            .await?;
+        Ok(conn.affected_rows() > 0)
+    }
+
+    /// Returns unresolved statement texts of fully matched entries in a catalog, with the matched item.
+    async fn statement_text_get_unresolved(
+        &self,
+        catalog_id: usize,
+        offset: usize,
+        batch_size: usize,
+    ) -> Result<Vec<(StatementText, isize)>> {
+        let sql = "SELECT `statement_text`.`id`,`entry_id`,`property`,`text`,`statement_text`.`q`,`in_wikidata`,`entry_is_matched`,`entry`.`q`
+            FROM `statement_text`,`entry`
+            WHERE `entry_id`=`entry`.`id` AND `entry`.`catalog`=:catalog_id
+            AND `entry`.`q`>0 AND `entry`.`user`>0 AND `in_wikidata`=0
+            ORDER BY `statement_text`.`id`
+            LIMIT :batch_size OFFSET :offset";
+        let ret = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, params! {catalog_id, offset, batch_size})
+            .await?
+            .map_and_drop(|row| {
+                let q: isize = row.get(7)?;
+                let st = StatementText::from_row((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                    row.get(5)?,
+                    row.get(6)?,
+                ));
+                Some((st, q))
+            })
+            .await?
+            .into_iter()
+            .flatten()
+            .collect();
+        Ok(ret)
+    }
+
+    async fn statement_text_set_resolved(&self, id: usize, q: isize) -> Result<()> {
+        let sql = "UPDATE `statement_text` SET `q`=:q,`in_wikidata`=1 WHERE `id`=:id";
+        self.get_conn()
+            .await?
+            .exec_drop(sql, params! {id, q})
+            .await?;
+        Ok(())
+    }
+
     // Returns "was changed" (true/false)
     async fn entry_set_match(
         &self,
@@ -1715,26 +3815,38 @@ impl Storage for StorageMySQL {
         q_numeric: isize,
         timestamp: &str,
     ) -> Result<bool> {
-        let entry_id = entry.id;
-        let mut sql = "UPDATE `entry` SET `q`=:q_numeric,`user`=:user_id,`timestamp`=:timestamp WHERE `id`=:entry_id AND (`q` IS NULL OR `q`!=:q_numeric OR `user`!=:user_id)".to_string();
-        if user_id == USER_AUTO {
-            if self.avoid_auto_match(entry_id, Some(q_numeric)).await? {
-                return Ok(false); // Nothing wrong but shouldn't be matched
-            }
-            sql += &MatchState::not_fully_matched().get_sql();
-        }
-        let mut conn = self.get_conn().await?;
-        conn.exec_drop(sql, params! {q_numeric,user_id,timestamp,entry_id})
-            .await?;
-        let nothing_changed = conn.affected_rows() == 0;
-        drop(conn);
-        if nothing_changed {
-            return Ok(false);
-        }
-        self.entry_set_match_cleanup(entry, user_id, q_numeric)
+        self.entry_set_match_internal(entry, user_id, q_numeric, timestamp, true)
+            .await
+    }
+
+    async fn entry_set_match_without_overview(
+        &self,
+        entry: &Entry,
+        user_id: usize,
+        q_numeric: isize,
+        timestamp: &str,
+    ) -> Result<bool> {
+        self.entry_set_match_internal(entry, user_id, q_numeric, timestamp, false)
             .await
     }
 
+    /// Sets the matches (entry, numeric item ID) with one UPDATE on `entry` per `ENTRY_SET_MATCH_BATCH_SIZE` matches,
+    /// and aggregated overview and cleanup queries.
+    /// For `USER_AUTO`, fully matched entries and previously removed matches are skipped.
+    /// The entries are re-read and locked before the update, so the overview changes and the returned number
+    /// are based on the rows that were actually changed, even with concurrent writers.
+    async fn entry_set_match_batch(
+        &self,
+        matches: &[(Entry, isize)],
+        user_id: usize,
+    ) -> Result<usize> {
+        let mut ret = 0;
+        for chunk in matches.chunks(ENTRY_SET_MATCH_BATCH_SIZE) {
+            ret += self.entry_set_match_batch_chunk(chunk, user_id).await?;
+        }
+        Ok(ret)
+    }
+
     async fn entry_set_match_status(
         &self,
         entry_id: usize,
@@ -1814,6 +3926,41 @@ impl Storage for StorageMySQL {
             .await?)
     }
 
+    async fn entry_set_kv(&self, entry_id: usize, key: &str, value: &str) -> Result<()> {
+        let sql = r"REPLACE INTO `kv_entry` (`entry_id`,`kv_key`,`kv_value`) VALUES (:entry_id,:key,:value)";
+        self.get_conn()
+            .await?
+            .exec_drop(sql, params! {entry_id,key,value})
+            .await?;
+        Ok(())
+    }
+
+    async fn entry_set_no_automatch(&self, entry_id: usize, no_automatch: bool) -> Result<()> {
+        if no_automatch {
+            return self.entry_set_kv(entry_id, KV_NO_AUTOMATCH, "1").await;
+        }
+        let sql = r"DELETE FROM `kv_entry` WHERE `entry_id`=:entry_id AND `kv_key`=:key";
+        let key = KV_NO_AUTOMATCH;
+        self.get_conn()
+            .await?
+            .exec_drop(sql, params! {entry_id,key})
+            .await?;
+        Ok(())
+    }
+
+    async fn entry_get_no_automatch(&self, entry_id: usize) -> Result<bool> {
+        let sql = r"SELECT `kv_value` FROM `kv_entry` WHERE `entry_id`=:entry_id AND `kv_key`=:key";
+        let key = KV_NO_AUTOMATCH;
+        let values = self
+            .get_conn_ro()
+            .await?
+            .exec_iter(sql, params! {entry_id,key})
+            .await?
+            .map_and_drop(from_row::<String>)
+            .await?;
+        Ok(values.iter().any(|value| value == "1"))
+    }
+
     async fn entry_set_multi_match(
         &self,
         entry_id: usize,
@@ -1826,23 +3973,6 @@ impl Storage for StorageMySQL {
             .await?;
         Ok(())
     }
-
-    async fn app_state_seppuku_get_running(&self, ts: &str) -> (usize, usize) {
-        let sql = format!("SELECT
-                        (SELECT count(*) FROM jobs WHERE `status` IN ('RUNNING')) AS running,
-                        (SELECT count(*) FROM jobs WHERE `status` IN ('RUNNING') AND last_ts>='{ts}') AS running_recent");
-        let mut conn = self.get_conn_ro().await.expect("seppuku: No DB connection");
-        let (running, running_recent) = *conn
-            .exec_iter(sql, ())
-            .await
-            .expect("seppuku: No results")
-            .map_and_drop(from_row::<(usize, usize)>)
-            .await
-            .expect("seppuku: Result retrieval failure")
-            .first()
-            .expect("seppuku: No DB results");
-        (running, running_recent)
-    }
 }
 
 #[cfg(test)]
@@ -1850,6 +3980,202 @@ mod tests {
     use std::{env, fs::File};
 
     use super::*;
+    use crate::entry_query::EntryQueryOrder;
+
+    #[test]
+    fn test_sql_and_not_in() {
+        assert_eq!(StorageMySQL::sql_and_not_in("catalog", &[]), "");
+        assert_eq!(
+            StorageMySQL::sql_and_not_in("catalog", &["506".to_string(), "7".to_string()]),
+            " AND catalog NOT IN (506,7)"
+        );
+    }
+
+    #[test]
+    fn test_entry_set_match_batch_changes_row() {
+        assert!(!StorageMySQL::entry_set_match_batch_changes_row(
+            4,
+            1,
+            Some(4),
+            Some(1)
+        ));
+        assert!(StorageMySQL::entry_set_match_batch_changes_row(
+            4,
+            1,
+            Some(2),
+            Some(1)
+        ));
+        assert!(StorageMySQL::entry_set_match_batch_changes_row(
+            4,
+            2,
+            Some(4),
+            Some(1)
+        ));
+        assert!(StorageMySQL::entry_set_match_batch_changes_row(
+            USER_AUTO, 1, None, None
+        ));
+        assert!(StorageMySQL::entry_set_match_batch_changes_row(
+            USER_AUTO,
+            2,
+            Some(0),
+            Some(1)
+        ));
+        assert!(!StorageMySQL::entry_set_match_batch_changes_row(
+            USER_AUTO,
+            2,
+            Some(4),
+            Some(1)
+        ));
+        assert!(!StorageMySQL::entry_set_match_batch_changes_row(
+            USER_AUTO,
+            2,
+            Some(4),
+            Some(0)
+        ));
+    }
+
+    #[test]
+    fn test_coordinate_matcher_main_query_sql() {
+        let select = "SELECT `lat`,`lon`,`id`,`catalog`,`ext_name`,`type`,`q` FROM `vw_location` WHERE `ext_name`!=''";
+        let not_fully_matched = MatchState::not_fully_matched().get_sql();
+
+        // Catalog
+        let sql = StorageMySQL::coordinate_matcher_main_query_sql(&Some(123), &[4, 5], 50, 0.5);
+        assert_eq!(
+            sql,
+            format!("{select} AND `catalog`=123{not_fully_matched}")
+        );
+
+        // No catalog
+        let sql = StorageMySQL::coordinate_matcher_main_query_sql(&None, &[], 50, 0.5);
+        assert_eq!(
+            sql,
+            format!("{select} AND `random`>=0.5{not_fully_matched} ORDER BY `random` LIMIT 50")
+        );
+
+        // No catalog, bad catalogs
+        let sql = StorageMySQL::coordinate_matcher_main_query_sql(&None, &[4, 5], 50, 0.5);
+        assert_eq!(
+            sql,
+            format!("{select} AND `random`>=0.5 AND `catalog` NOT IN (4,5){not_fully_matched} ORDER BY `random` LIMIT 50")
+        );
+    }
+
+    #[test]
+    fn test_get_entry_query_sql() {
+        let (sql, params) = StorageMySQL::get_entry_query_sql(&EntryQuery::default());
+        assert_eq!(sql, "");
+        assert!(params.is_empty());
+
+        let query = EntryQuery::default()
+            .with_catalog_id(123)
+            .with_match_state(MatchState::unmatched())
+            .with_type("Q5")
+            .with_limit(10)
+            .with_offset(20);
+        let (sql, params) = StorageMySQL::get_entry_query_sql(&query);
+        assert_eq!(
+            sql,
+            " WHERE `catalog`=? AND (`q` IS NULL) AND `type`=? LIMIT ? OFFSET ?"
+        );
+        assert_eq!(
+            params,
+            vec![
+                mysql_async::Value::from(123_usize),
+                mysql_async::Value::from("Q5".to_string()),
+                mysql_async::Value::from(10_usize),
+                mysql_async::Value::from(20_usize),
+            ]
+        );
+
+        let (sql, _) =
+            StorageMySQL::get_entry_query_sql(&EntryQuery::default().with_has_signal(Some(false)));
+        assert!(sql.starts_with(" WHERE NOT (EXISTS (SELECT * FROM `person_dates`"));
+    }
+
+    #[test]
+    fn test_get_entry_query_sql_name_regexp() {
+        let query = EntryQuery::default()
+            .with_match_state(MatchState::unmatched())
+            .with_name_regexp("^[A-Z]")
+            .with_name_not_regexp("^\\S+$");
+        let (sql, params) = StorageMySQL::get_entry_query_sql(&query);
+        assert_eq!(
+            sql,
+            " WHERE (`q` IS NULL) AND `ext_name` RLIKE ? AND `ext_name` NOT RLIKE ?"
+        );
+        assert_eq!(
+            params,
+            vec![
+                mysql_async::Value::from("^[A-Z]".to_string()),
+                mysql_async::Value::from("^\\S+$".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_get_recent_match_events_sql() {
+        let (sql, params) = StorageMySQL::get_recent_match_events_sql(None, None, 50);
+        assert!(!sql.contains("`catalog`=?"));
+        assert_eq!(params, vec![mysql_async::Value::from(50_usize); 3]);
+
+        let (sql, params) = StorageMySQL::get_recent_match_events_sql(
+            Some(123),
+            Some("20240101000000".to_string()),
+            10,
+        );
+        assert!(sql.contains("`entry`.`timestamp`>=?"));
+        assert!(sql.contains("`log`.`timestamp`>=?"));
+        assert_eq!(params.len(), 7);
+        assert_eq!(params[0], mysql_async::Value::from(123_usize));
+        assert_eq!(
+            params[1],
+            mysql_async::Value::from("20240101000000".to_string())
+        );
+        assert_eq!(params[2], mysql_async::Value::from(10_usize));
+    }
+
+    #[test]
+    fn test_get_entry_query_sql_min_id_exclusive() {
+        let query = EntryQuery::default()
+            .with_catalog_id(123)
+            .with_min_id_exclusive(Some(456));
+        let (sql, params) = StorageMySQL::get_entry_query_sql(&query);
+        assert_eq!(sql, " WHERE `catalog`=? AND `id`>?");
+        assert_eq!(
+            params,
+            vec![
+                mysql_async::Value::from(123_usize),
+                mysql_async::Value::from(456_usize),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_get_entry_query_sql_order_by() {
+        let expected = [
+            (EntryQueryOrder::Id, "ORDER BY `entry`.`id`"),
+            (
+                EntryQueryOrder::ExtName,
+                "ORDER BY `entry`.`ext_name`,`entry`.`id`",
+            ),
+            (EntryQueryOrder::Random, "ORDER BY `entry`.`random`"),
+            (
+                EntryQueryOrder::Timestamp,
+                "ORDER BY `entry`.`timestamp`,`entry`.`id`",
+            ),
+        ];
+        for (order_by, clause) in expected {
+            let query = EntryQuery::default()
+                .with_catalog_id(123)
+                .with_order_by(order_by)
+                .with_limit(10);
+            let (sql, _) = StorageMySQL::get_entry_query_sql(&query);
+            assert_eq!(sql, format!(" WHERE `catalog`=? {clause} LIMIT ? OFFSET ?"));
+        }
+        let (sql, _) = StorageMySQL::get_entry_query_sql(&EntryQuery::default().with_limit(10));
+        assert!(!sql.contains("ORDER BY"));
+    }
 
     // #lizard forgives
     #[test]
@@ -1861,6 +4187,8 @@ mod tests {
         let storage = StorageMySQL {
             pool: StorageMySQL::create_pool(&config["wikidata"]),
             pool_ro: StorageMySQL::create_pool(&config["wikidata"]),
+            connect_timeout: None,
+            connect_timeout_ro: None,
         };
 
         let catalog_filter =
@@ -1931,6 +4259,15 @@ mod tests {
         );
         assert_eq!(sql, expected);
     }
+
+    #[test]
+    fn test_catalog_exclusion_sql() {
+        assert_eq!(StorageMySQL::catalog_exclusion_sql("e1.catalog", &[]), "");
+        assert_eq!(
+            StorageMySQL::catalog_exclusion_sql("e1.catalog", &[4837, 5580]),
+            " AND e1.catalog NOT IN (4837,5580)"
+        );
+    }
 }
 
 /* TODO
@@ -2012,6 +4349,7 @@ async fn test_match_via_auxiliary() {
 mod tests {
 
     use super::*;
+    use crate::issue::IssuePayload;
     use mysql_async::from_row;
     use serde_json::json;
 
@@ -2054,7 +4392,11 @@ mod tests {
             .unwrap();
         assert_eq!(issues_for_entry, 0);
 
-        let issue = Issue::new(entry_id, IssueType::Mismatch, json!("!"), &mnm)
+        let issue = Issue::new(
+            entry_id,
+            IssuePayload::Other(IssueType::Mismatch, json!("!")),
+            &mnm,
+        )
             .await
             .unwrap();
         issue.insert().await.unwrap();
@@ -3,12 +3,25 @@ use crate::app_state::USER_AUX_MATCH;
 use crate::catalog::Catalog;
 use crate::entry::*;
 use crate::job::*;
+use crate::name_rewrite::NameRewrite;
+use crate::overview_rebuild::OverviewRebuild;
 use anyhow::Result;
+use futures::stream::{self, StreamExt};
 use lazy_static::lazy_static;
 use regex::{Regex, RegexBuilder};
 use std::collections::HashMap;
 
 pub type RankedNames = HashMap<String, Vec<(usize, String)>>;
+type PrefetchedTaxonNames = HashMap<String, Vec<(String, Option<String>)>>;
+
+const TAXON_MATCHER_BATCH_SIZE: usize = 5000;
+/// Default number of batches that are matched at the same time;
+/// can be set via `task_specific_usize.taxon_matcher_concurrency` in the config
+const DEFAULT_TAXON_MATCHER_CONCURRENCY: usize = 4;
+/// `kv_catalog` key; if "1", species without a Wikidata item get their genus item as a multi-match candidate
+pub const KV_TAXON_GENUS_FALLBACK: &str = "taxon_genus_fallback";
+const RANK_SPECIES: &str = "Q7432";
+const RANK_GENUS: &str = "Q34740";
 
 lazy_static! {
     pub static ref TAXON_RANKS: HashMap<&'static str, &'static str> = {
@@ -65,6 +78,9 @@ impl Jobbable for TaxonMatcher {
 pub struct TaxonMatcher {
     app: AppState,
     job: Option<Job>,
+    overview_rebuild: OverviewRebuild,
+    /// Record the genus item for species names that have no item; see `KV_TAXON_GENUS_FALLBACK`
+    genus_fallback: bool,
 }
 
 impl TaxonMatcher {
@@ -72,6 +88,8 @@ impl TaxonMatcher {
         Self {
             app: app.clone(),
             job: None,
+            overview_rebuild: OverviewRebuild::new(app),
+            genus_fallback: false,
         }
     }
 
@@ -95,10 +113,12 @@ impl TaxonMatcher {
         Some(taxon_name)
     }
 
-    /// Tries to find full matches for entries that are a taxon
+    /// Tries to find full matches for entries that are a taxon.
+    /// Batches of entries are loaded one after the other, by entry ID, as matching shrinks the set of unmatched entries;
+    /// they are then matched concurrently. The catalog is only marked as taxon-matched if all batches succeed,
+    /// and the job offset (the last entry ID done) only advances past batches that are done.
     pub async fn match_taxa(&mut self, catalog_id: usize) -> Result<()> {
         let mut catalog = Catalog::from_id(catalog_id, &self.app).await?;
-        let mw_api = self.app.wikidata().get_mw_api().await?;
         let use_desc = USE_DESCRIPTIONS_FOR_TAXON_NAME_CATALOGS.contains(&catalog_id);
         let mut ranks: Vec<&str> = TAXON_RANKS.clone().into_values().collect();
         ranks.push("Q16521"); // taxon item
@@ -107,87 +127,257 @@ impl TaxonMatcher {
         } else {
             TaxonNameField::Name
         };
-        let mut offset = self.get_last_job_offset().await;
-        let batch_size = 5000;
-        loop {
-            let (results_len, ranked_names) = self
-                .app
-                .storage()
-                .match_taxa_get_ranked_names_batch(
-                    &ranks,
-                    &taxon_name_field,
-                    catalog_id,
-                    batch_size,
-                    offset,
-                )
-                .await?;
-
-            for (rank, v) in ranked_names.iter() {
-                self.match_taxa_name_to_entry(rank, v, &mw_api).await?;
-            }
-
-            if results_len < batch_size {
-                break;
-            }
-            offset += results_len;
-            let _ = self.remember_offset(offset).await;
+        let name_rewrite = NameRewrite::from_catalog_id(catalog_id, &self.app).await?;
+        self.genus_fallback = catalog
+            .get_key_value_pairs()
+            .await?
+            .get(KV_TAXON_GENUS_FALLBACK)
+            .map(|v| v.trim() == "1")
+            .unwrap_or(false);
+        let genus_fallback = self.genus_fallback;
+        let concurrency = *self
+            .app
+            .task_specific_usize()
+            .get("taxon_matcher_concurrency")
+            .unwrap_or(&DEFAULT_TAXON_MATCHER_CONCURRENCY);
+        let after_entry_id = self.get_last_job_offset().await;
+        let app = self.app.clone();
+        let app = &app;
+        let ranks = &ranks;
+        let taxon_name_field = &taxon_name_field;
+        let name_rewrite = &name_rewrite;
+        let loaded_batches = stream::try_unfold(Some(after_entry_id), move |after_entry_id| {
+            Self::load_taxa_batch(app, ranks, taxon_name_field, catalog_id, after_entry_id)
+        })
+        .boxed();
+        // `buffered` runs up to `concurrency` batches at once, but returns the results in order
+        let mut batches = loaded_batches
+            .map(|batch| async move {
+                let (last_entry_id, ranked_names) = batch?;
+                let matches_set =
+                    Self::match_taxa_batch(app, name_rewrite, ranked_names, genus_fallback).await?;
+                Ok::<_, anyhow::Error>((last_entry_id, matches_set))
+            })
+            .buffered(concurrency.max(1));
+        while let Some(result) = batches.next().await {
+            let (last_entry_id, matches_set) = result?; // A failed batch fails the job, before the catalog is marked
+            let _ = self
+                .overview_rebuild
+                .matches_set(&self.app, catalog_id, matches_set)
+                .await;
+            let _ = self.remember_offset(last_entry_id).await;
         }
+        drop(batches);
         let _ = self.clear_offset().await;
+        self.app
+            .storage()
+            .finalize_catalog_overview(catalog_id)
+            .await?;
 
         // Update catalog as "done at least once" if necessary
         catalog.set_taxon_run(&self.app, true).await?;
         Ok(())
     }
 
-    async fn match_taxa_name_to_entry(
-        &mut self,
-        rank: &str,
-        v: &[(usize, String)],
-        mw_api: &mediawiki::api::Api,
+    /// Loads the next batch of entries after `after_entry_id`, with the last entry ID of the batch, and the ID to continue after.
+    /// `None` means there are no more batches.
+    #[allow(clippy::type_complexity)]
+    async fn load_taxa_batch(
+        app: &AppState,
+        ranks: &[&str],
+        taxon_name_field: &TaxonNameField,
+        catalog_id: usize,
+        after_entry_id: Option<usize>,
+    ) -> Result<Option<((usize, RankedNames), Option<usize>)>> {
+        let after_entry_id = match after_entry_id {
+            Some(id) => id,
+            None => return Ok(None),
+        };
+        let (results_len, last_entry_id, ranked_names) = app
+            .storage()
+            .match_taxa_get_ranked_names_batch(
+                ranks,
+                taxon_name_field,
+                catalog_id,
+                TAXON_MATCHER_BATCH_SIZE,
+                after_entry_id,
+            )
+            .await?;
+        if results_len == 0 {
+            return Ok(None);
+        }
+        let next = (results_len >= TAXON_MATCHER_BATCH_SIZE).then_some(last_entry_id);
+        Ok(Some(((last_entry_id, ranked_names), next)))
+    }
+
+    /// Matches a batch of entries; returns the number of full matches set.
+    async fn match_taxa_batch(
+        app: &AppState,
+        name_rewrite: &NameRewrite,
+        mut ranked_names: RankedNames,
+        genus_fallback: bool,
+    ) -> Result<usize> {
+        Self::rewrite_ranked_names(name_rewrite, &mut ranked_names);
+        let prefetched = Self::prefetch_taxon_names(app, &ranked_names).await?;
+        let mut matches_set = 0;
+        for (rank, v) in ranked_names.iter() {
+            matches_set += Self::match_taxa_name_to_entry(app, rank, v, &prefetched).await?;
+        }
+        if genus_fallback {
+            Self::match_taxa_genus_fallback(app, &ranked_names, &prefetched).await?;
+        }
+        Ok(matches_set)
+    }
+
+    /// For species that have no item, looks up the genus from the first word of the name, and
+    /// records the genus item as a multi-match candidate. This is not a match, as the genus is not the species.
+    async fn match_taxa_genus_fallback(
+        app: &AppState,
+        ranked_names: &RankedNames,
+        prefetched: &PrefetchedTaxonNames,
     ) -> Result<()> {
-        let all_names: Vec<String> = v
-            .iter()
-            .map(|(_entry_id, name)| format!("\"{name}\""))
-            .collect();
-        let name2entry_id: HashMap<String, usize> = v
+        let entry2genus: Vec<(usize, String)> = ranked_names
             .iter()
-            .map(|(entry_id, name)| (name.to_owned(), *entry_id))
+            .flat_map(|(rank, v)| Self::genus_fallback_names(rank, v, prefetched))
             .collect();
-        for names in all_names.chunks(50) {
-            // Prepare SPARQL
-            let mut name2q: HashMap<String, Vec<String>> = HashMap::new();
-            let names = names.join(" ");
-            let sparql = format!("SELECT DISTINCT ?q ?name {{
-                        VALUES ?name {{ {} }} VALUES ?instance {{ wd:Q16521 wd:Q4886 }}
-                        {{ {{ SELECT DISTINCT ?q ?name ?instance {{ ?q wdt:P225 ?name ; wdt:P31 ?instance {rank} }} }} UNION
-                        {{ SELECT DISTINCT ?q ?name ?instance {{ ?q wdt:P1420 ?name ; wdt:P31 ?instance {rank} }} }} }} }}",names);
-
-            // Run SPARQL
-            if let Ok(sparql_result) = mw_api.sparql_query(&sparql).await {
-                if let Some(bindings) = sparql_result["results"]["bindings"].as_array() {
-                    for b in bindings {
-                        if let (Some(entity_url), Some(name)) =
-                            (b["q"]["value"].as_str(), b["name"]["value"].as_str())
-                        {
-                            if let Ok(q) = mw_api.extract_entity_from_uri(entity_url) {
-                                name2q.entry(name.to_string()).or_default().push(q);
-                            }
-                        }
-                    }
+        if entry2genus.is_empty() {
+            return Ok(());
+        }
+        let mut genera: Vec<String> = entry2genus.iter().map(|(_, g)| g.to_owned()).collect();
+        genera.sort();
+        genera.dedup();
+        let prefetched_genera = app.wikidata().get_items_for_taxon_names(&genera).await?;
+        let v: Vec<(usize, String)> = genera.into_iter().map(|genus| (0, genus)).collect();
+        let genus2q = Self::filter_prefetched_for_rank(
+            &format!(" ; wdt:P105 {RANK_GENUS}"),
+            &v,
+            &prefetched_genera,
+        );
+        for (entry_id, genus) in entry2genus {
+            if let Some(qs) = genus2q.get(&genus) {
+                let entry = Entry::from_id(entry_id, app).await?;
+                if !entry.get_multi_match().await?.is_empty() {
+                    continue; // Keep existing candidates
                 }
+                entry.set_multi_match(qs).await?;
             }
-
-            self.match_taxa_filter_name2q(name2q, &name2entry_id)
-                .await?;
         }
         Ok(())
     }
 
+    /// Returns (entry ID, genus) for the species names of a rank group that have no candidate item.
+    /// Groups with a rank other than species, including unranked names, are ignored.
+    fn genus_fallback_names(
+        rank: &str,
+        v: &[(usize, String)],
+        prefetched: &PrefetchedTaxonNames,
+    ) -> Vec<(usize, String)> {
+        if Self::rank_item_from_key(rank) != Some(RANK_SPECIES) {
+            return vec![];
+        }
+        let name2q = Self::filter_prefetched_for_rank(rank, v, prefetched);
+        v.iter()
+            .filter(|(_entry_id, name)| !name2q.contains_key(name))
+            .filter_map(|(entry_id, name)| {
+                Self::genus_from_binomial(name).map(|genus| (*entry_id, genus.to_string()))
+            })
+            .collect()
+    }
+
+    /// The genus of a binomial name like "Carphophis amoenus", ie the first word;
+    /// `None` if the name does not look like a binomial
+    fn genus_from_binomial(name: &str) -> Option<&str> {
+        let mut parts = name.split_whitespace();
+        let genus = parts.next()?;
+        let epithet = parts.next()?;
+        let is_genus =
+            genus.chars().next()?.is_uppercase() && genus.chars().all(char::is_alphabetic);
+        let is_epithet = epithet.chars().all(|c| c.is_lowercase() || c == '-');
+        if is_genus && is_epithet {
+            Some(genus)
+        } else {
+            None
+        }
+    }
+
+    /// Applies the catalog name rewrite rules, on top of the bespoke `rewrite_taxon_name` fixes
+    fn rewrite_ranked_names(name_rewrite: &NameRewrite, ranked_names: &mut RankedNames) {
+        if name_rewrite.is_empty() {
+            return;
+        }
+        for (_entry_id, name) in ranked_names.values_mut().flatten() {
+            *name = name_rewrite.rewrite(name);
+        }
+    }
+
+    /// Loads all candidate items for the names in this batch in bulk, for local matching
+    async fn prefetch_taxon_names(
+        app: &AppState,
+        ranked_names: &RankedNames,
+    ) -> Result<PrefetchedTaxonNames> {
+        let mut names: Vec<String> = ranked_names
+            .values()
+            .flat_map(|v| v.iter().map(|(_entry_id, name)| name.to_owned()))
+            .collect();
+        names.sort();
+        names.dedup();
+        app.wikidata().get_items_for_taxon_names(&names).await
+    }
+
+    /// Returns the rank item from a `RankedNames` key (" ; wdt:P105 Q7432"), if any
+    fn rank_item_from_key(rank: &str) -> Option<&str> {
+        rank.trim()
+            .strip_prefix("; wdt:P105 ")
+            .map(|s| s.trim_start_matches("wd:"))
+    }
+
+    /// Filters the prefetched candidates for a given rank
+    fn filter_prefetched_for_rank(
+        rank: &str,
+        v: &[(usize, String)],
+        prefetched: &PrefetchedTaxonNames,
+    ) -> HashMap<String, Vec<String>> {
+        let rank_item = Self::rank_item_from_key(rank);
+        let mut name2q: HashMap<String, Vec<String>> = HashMap::new();
+        for (_entry_id, name) in v {
+            let candidates = match prefetched.get(name) {
+                Some(candidates) => candidates,
+                None => continue,
+            };
+            for (q, item_rank) in candidates {
+                if rank_item.is_none() || rank_item == item_rank.as_deref() {
+                    name2q
+                        .entry(name.to_owned())
+                        .or_default()
+                        .push(q.to_owned());
+                }
+            }
+        }
+        name2q
+    }
+
+    /// Returns the number of full matches set
+    async fn match_taxa_name_to_entry(
+        app: &AppState,
+        rank: &str,
+        v: &[(usize, String)],
+        prefetched: &PrefetchedTaxonNames,
+    ) -> Result<usize> {
+        let name2entry_id: HashMap<String, usize> = v
+            .iter()
+            .map(|(entry_id, name)| (name.to_owned(), *entry_id))
+            .collect();
+        let name2q = Self::filter_prefetched_for_rank(rank, v, prefetched);
+        Self::match_taxa_filter_name2q(app, name2q, &name2entry_id).await
+    }
+
+    /// Returns the number of full matches set
     async fn match_taxa_filter_name2q(
-        &mut self,
+        app: &AppState,
         name2q: HashMap<String, Vec<String>>,
         name2entry_id: &HashMap<String, usize>,
-    ) -> Result<()> {
+    ) -> Result<usize> {
+        let mut matches_set = 0;
         for (name, mut qs) in name2q {
             if let Some(entry_id) = name2entry_id.get(&name) {
                 qs.sort();
@@ -197,14 +387,18 @@ impl TaxonMatcher {
                     std::cmp::Ordering::Less => {}
                     std::cmp::Ordering::Equal => {
                         if let Some(q) = qs.pop() {
-                            let _ = Entry::from_id(*entry_id, &self.app)
-                                .await?
-                                .set_match(&q, USER_AUX_MATCH)
-                                .await;
+                            let mut entry = Entry::from_id(*entry_id, app).await?;
+                            if entry
+                                .set_match_without_overview(&q, USER_AUX_MATCH)
+                                .await
+                                .is_ok()
+                            {
+                                matches_set += 1;
+                            }
                         }
                     }
                     std::cmp::Ordering::Greater => {
-                        let _ = Entry::from_id(*entry_id, &self.app)
+                        let _ = Entry::from_id(*entry_id, app)
                             .await?
                             .set_multi_match(&qs)
                             .await;
@@ -212,7 +406,7 @@ impl TaxonMatcher {
                 }
             }
         }
-        Ok(())
+        Ok(matches_set)
     }
 }
 
@@ -242,6 +436,63 @@ mod tests {
         ); // Britannica desc
     }
 
+    #[test]
+    fn test_filter_prefetched_for_rank() {
+        let mut prefetched = HashMap::new();
+        prefetched.insert(
+            "Foo bar".to_string(),
+            vec![
+                ("Q1".to_string(), Some("Q7432".to_string())),
+                ("Q2".to_string(), Some("Q34740".to_string())),
+            ],
+        );
+        let v = vec![(1, "Foo bar".to_string()), (2, "Baz".to_string())];
+        let name2q = TaxonMatcher::filter_prefetched_for_rank(" ; wdt:P105 Q7432", &v, &prefetched);
+        assert_eq!(name2q.get("Foo bar"), Some(&vec!["Q1".to_string()]));
+        assert!(!name2q.contains_key("Baz"));
+        let name2q = TaxonMatcher::filter_prefetched_for_rank("", &v, &prefetched);
+        assert_eq!(name2q.get("Foo bar").map(|v| v.len()), Some(2));
+    }
+
+    #[test]
+    fn test_genus_from_binomial() {
+        assert_eq!(
+            TaxonMatcher::genus_from_binomial("Carphophis amoenus"),
+            Some("Carphophis")
+        );
+        assert_eq!(
+            TaxonMatcher::genus_from_binomial("Carphophis amoenus amoenus"),
+            Some("Carphophis")
+        );
+        assert_eq!(TaxonMatcher::genus_from_binomial("Carphophis"), None);
+        assert_eq!(
+            TaxonMatcher::genus_from_binomial("carphophis amoenus"),
+            None
+        );
+        assert_eq!(
+            TaxonMatcher::genus_from_binomial("Carphophis Amoenus"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_genus_fallback_names() {
+        let mut prefetched = HashMap::new();
+        prefetched.insert(
+            "Foo bar".to_string(),
+            vec![("Q1".to_string(), Some(RANK_SPECIES.to_string()))],
+        );
+        let v = vec![(1, "Foo bar".to_string()), (2, "Foo baz".to_string())];
+        let species = format!(" ; wdt:P105 {RANK_SPECIES}");
+        assert_eq!(
+            TaxonMatcher::genus_fallback_names(&species, &v, &prefetched),
+            vec![(2, "Foo".to_string())]
+        );
+        assert!(TaxonMatcher::genus_fallback_names("", &v, &prefetched).is_empty());
+        let family = " ; wdt:P105 Q35409";
+        assert!(TaxonMatcher::genus_fallback_names(family, &v, &prefetched).is_empty());
+    }
+
     #[tokio::test]
     async fn test_match_taxa() {
         let app = get_test_app();
@@ -1,15 +1,20 @@
 use crate::app_state::AppState;
 use crate::catalog::Catalog;
-use crate::datasource::DataSource;
+use crate::datasource::{DataSource, DataSourceLocation};
 use crate::entry::*;
 use crate::extended_entry::ExtendedEntry;
+use crate::issue::{Issue, IssuePayload};
 use crate::job::*;
 use anyhow::Result;
 use csv::StringRecord;
-use std::collections::HashSet;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fmt;
 
+/// Number of entries per batch when looking for ext_ids that were removed from the source
+const REMOVED_EXT_IDS_BATCH_SIZE: usize = 10000;
+
 #[derive(Debug)]
 pub enum UpdateCatalogError {
     NoUpdateInfoForCatalog,
@@ -19,6 +24,8 @@ pub enum UpdateCatalogError {
     NotEnoughColumns(usize),
     UnknownColumnLabel(String),
     BadPattern,
+    BadColumnRanges,
+    NotAUrlSource,
 }
 
 impl Error for UpdateCatalogError {}
@@ -40,6 +47,12 @@ impl fmt::Display for UpdateCatalogError {
             UpdateCatalogError::NotEnoughColumns(v) => write!(f, "NotEnoughColumns {v}"),
             UpdateCatalogError::UnknownColumnLabel(s) => write!(f, "UnknownColumnLabel {s}"),
             UpdateCatalogError::BadPattern => write!(f, "UpdateCatalogError::BadPattern"),
+            UpdateCatalogError::BadColumnRanges => {
+                write!(f, "UpdateCatalogError::BadColumnRanges")
+            }
+            UpdateCatalogError::NotAUrlSource => {
+                write!(f, "UpdateCatalogError::NotAUrlSource")
+            }
         }
     }
 }
@@ -61,6 +74,109 @@ impl UpdateInfo {
     }
 }
 
+/// How `update_from_tabbed_file` treats existing entries, from the `mode` key of the update info JSON
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UpdateMode {
+    /// Imports all rows; existing entries are updated, unless `just_add` is set
+    #[default]
+    Full,
+    /// Compares the rows to the entries already in the catalog. Only new ext_ids are imported,
+    /// and only a changed name, description, or URL is updated for existing ones.
+    Incremental,
+}
+
+impl UpdateMode {
+    pub fn from_json(json: &serde_json::Value) -> Self {
+        match json.get("mode").and_then(|mode| mode.as_str()) {
+            Some("incremental") => Self::Incremental,
+            _ => Self::Full,
+        }
+    }
+}
+
+/// The values of an existing entry that an incremental update compares against, see `Storage::get_external_ids`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CatalogExtId {
+    pub entry_id: usize,
+    pub ext_name: String,
+    pub ext_desc: String,
+    pub ext_url: String,
+    pub q: Option<isize>,
+}
+
+/// Row counts of a catalog update
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct UpdateSummary {
+    pub added: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    /// Entries whose ext_id is no longer in the source; only counted by incremental updates
+    pub removed: usize,
+}
+
+impl fmt::Display for UpdateSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} added, {} updated, {} unchanged, {} removed",
+            self.added, self.updated, self.unchanged, self.removed
+        )
+    }
+}
+
+/// What an incremental update does with a row
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum IncrementalAction {
+    Add,
+    Update(usize),
+    Skip,
+}
+
+/// The ext_ids seen in the source so far by an incremental update, and the existing entries for the current batch of rows
+#[derive(Debug, Clone, Default)]
+struct IncrementalUpdate {
+    existing: HashMap<String, CatalogExtId>,
+    seen: HashSet<String>,
+    /// Create a `REMOVED_FROM_SOURCE` issue for entries whose ext_id is not in the source (`"flag_removed":true`)
+    flag_removed: bool,
+}
+
+impl IncrementalUpdate {
+    /// Remembers an ext_id of the source, before its row is parsed, so a row that fails to parse does not flag the entry as removed
+    fn see(&mut self, ext_id: &str) {
+        self.seen.insert(ext_id.to_string());
+    }
+
+    /// Compares an incoming entry to the existing one. Empty incoming values are not considered a change.
+    /// A match from the source that differs from the current one is a change; `ExtendedEntry::update_existing`
+    /// decides if it is applied.
+    fn action_for(&self, entry: &Entry) -> IncrementalAction {
+        let existing = match self.existing.get(&entry.ext_id) {
+            Some(existing) => existing,
+            None => return IncrementalAction::Add,
+        };
+        let changed = |new: &str, old: &str| !new.is_empty() && new != old;
+        if changed(&entry.ext_name, &existing.ext_name)
+            || changed(&entry.ext_desc, &existing.ext_desc)
+            || changed(&entry.ext_url, &existing.ext_url)
+            || (entry.q.is_some() && entry.q != existing.q)
+        {
+            IncrementalAction::Update(existing.entry_id)
+        } else {
+            IncrementalAction::Skip
+        }
+    }
+
+    /// Returns (ext_id, entry ID) of the (entry ID, ext_id) pairs that were not seen in the source
+    fn removed<'a>(&self, ext_ids: &'a [(usize, String)]) -> Vec<(&'a String, usize)> {
+        ext_ids
+            .iter()
+            .filter(|(_entry_id, ext_id)| !self.seen.contains(ext_id))
+            .map(|(entry_id, ext_id)| (ext_id, *entry_id))
+            .collect()
+    }
+}
+
 impl Jobbable for UpdateCatalog {
     //TODO test
     fn set_current_job(&mut self, job: &Job) {
@@ -80,6 +196,14 @@ impl Jobbable for UpdateCatalog {
 pub struct UpdateCatalog {
     app: AppState,
     job: Option<Job>,
+    summary: UpdateSummary,
+    incremental: Option<IncrementalUpdate>,
+    /// Overrides the `mode` of the update info JSON
+    mode: Option<UpdateMode>,
+    /// The `update_info` ID of the running import, recorded as the provenance of new entries
+    update_info_id: Option<usize>,
+    /// Set if the type change of an entry removed an automatic match, so the catalog needs re-matching
+    automatch_removed: bool,
 }
 
 impl UpdateCatalog {
@@ -87,6 +211,11 @@ impl UpdateCatalog {
         Self {
             app: app.clone(),
             job: None,
+            summary: UpdateSummary::default(),
+            incremental: None,
+            mode: None,
+            update_info_id: None,
+            automatch_removed: false,
         }
     }
 
@@ -96,7 +225,7 @@ impl UpdateCatalog {
         datasource: &mut DataSource,
     ) -> Result<Option<StringRecord>> {
         let result = match result {
-            Ok(result) => result,
+            Ok(result) => datasource.normalize_record(result),
             Err(e) => {
                 if datasource.fail_on_error {
                     return Err(e.into());
@@ -131,9 +260,12 @@ impl UpdateCatalog {
         Ok(Some(result))
     }
 
-    /// Updates a catalog by reading a tabbed file.
-    pub async fn update_from_tabbed_file(&mut self, catalog_id: usize) -> Result<()> {
+    /// Updates a catalog by reading a tabbed file, see `UpdateMode`.
+    /// The summary is also set as the job note.
+    pub async fn update_from_tabbed_file(&mut self, catalog_id: usize) -> Result<UpdateSummary> {
         let batch_size = 5000;
+        self.summary = UpdateSummary::default();
+        self.automatch_removed = false;
         let mut datasource = self
             .update_from_tabbed_file_get_datasource(catalog_id)
             .await?;
@@ -146,13 +278,92 @@ impl UpdateCatalog {
             }
         }
         datasource.clear_tmp_file();
+        self.flag_removed_ext_ids(catalog_id).await?;
+        if self.automatch_removed {
+            Job::queue_simple_job(&self.app, catalog_id, "automatch_by_search", None).await?;
+        }
         let _ = self.clear_offset().await;
+        if let Some(job) = self.get_current_job_mut() {
+            let _ = job.set_note(Some(self.summary.to_string())).await;
+        }
         /*
         // TODO?
         $this->app->queue_job($this->catalog_id(),'microsync');
         $this->app->queue_job($this->catalog_id(),'automatch_by_search');
         if ( $this->has_born_died ) $this->app->queue_job($this->catalog_id(),'match_person_dates');
         */
+        Ok(self.summary)
+    }
+
+    /// Re-downloads the source of a catalog that was imported from a URL, and applies the differences
+    /// as an incremental update: new ext_ids are added, changed entries updated, and removed ext_ids
+    /// flagged if `flag_removed` is set in the update info. The summary is set as the job note.
+    /// Meant to be run as a repeating job, see `schedule_refresh_from_source`.
+    pub async fn refresh_from_source(&mut self, catalog_id: usize) -> Result<UpdateSummary> {
+        self.check_refreshable(catalog_id).await?;
+        self.mode = Some(UpdateMode::Incremental);
+        self.update_from_tabbed_file(catalog_id).await
+    }
+
+    /// Adds (or changes the interval of) the repeating `refresh_from_source` job of a catalog.
+    /// Used by the `schedule_refresh` command.
+    pub async fn schedule_refresh_from_source(
+        &self,
+        catalog_id: usize,
+        repeat_after_sec: usize,
+    ) -> Result<()> {
+        self.check_refreshable(catalog_id).await?;
+        Job::queue_repeating_job(
+            &self.app,
+            catalog_id,
+            "refresh_from_source",
+            repeat_after_sec,
+        )
+        .await
+    }
+
+    /// Only catalogs imported from a URL or SPARQL query can be refreshed from their source
+    async fn check_refreshable(&self, catalog_id: usize) -> Result<()> {
+        let json = self.get_update_info(catalog_id).await?.json()?;
+        let datasource = DataSource::new(catalog_id, &json)?;
+        match datasource.get_source_location(&self.app)? {
+            DataSourceLocation::Url(_) | DataSourceLocation::Sparql { .. } => Ok(()),
+            DataSourceLocation::FilePath(_) => Err(UpdateCatalogError::NotAUrlSource.into()),
+        }
+    }
+
+    /// Counts the entries whose ext_id was not in the source of an incremental update,
+    /// and creates an issue for each if requested. The entries of the catalog are checked in batches.
+    async fn flag_removed_ext_ids(&mut self, catalog_id: usize) -> Result<()> {
+        let incremental = match self.incremental.take() {
+            Some(incremental) => incremental,
+            None => return Ok(()),
+        };
+        let mut after_entry_id = 0;
+        loop {
+            let ext_ids = self
+                .app
+                .storage()
+                .get_ext_ids_batch(catalog_id, after_entry_id, REMOVED_EXT_IDS_BATCH_SIZE)
+                .await?;
+            let removed = incremental.removed(&ext_ids);
+            self.summary.removed += removed.len();
+            if incremental.flag_removed {
+                for (ext_id, entry_id) in removed {
+                    let payload = IssuePayload::removed_from_source(ext_id);
+                    Issue::new(entry_id, payload, &self.app)
+                        .await?
+                        .insert()
+                        .await?;
+                }
+            }
+            match ext_ids.last() {
+                Some((entry_id, _)) if ext_ids.len() >= REMOVED_EXT_IDS_BATCH_SIZE => {
+                    after_entry_id = *entry_id
+                }
+                _ => break,
+            }
+        }
         Ok(())
     }
 
@@ -182,12 +393,30 @@ impl UpdateCatalog {
         catalog_id: usize,
     ) -> Result<DataSource> {
         let update_info = self.get_update_info(catalog_id).await?;
+        self.update_info_id = Some(update_info.id);
         let json = update_info.json()?;
         let catalog = Catalog::from_id(catalog_id, &self.app).await?;
         let entries_already_in_catalog = catalog.number_of_entries().await?;
         let mut datasource = DataSource::new(catalog_id, &json)?;
-        datasource.offset = self.get_last_job_offset().await;
+        datasource.load_catalog_options(&catalog).await?;
         datasource.just_add = entries_already_in_catalog == 0 || datasource.just_add;
+        let mode = self.mode.unwrap_or_else(|| UpdateMode::from_json(&json));
+        self.incremental = match mode {
+            UpdateMode::Full => {
+                datasource.offset = self.get_last_job_offset().await;
+                None
+            }
+            UpdateMode::Incremental => {
+                // Always reads the whole file, as all ext_ids need to be seen to find the removed ones
+                Some(IncrementalUpdate {
+                    flag_removed: json
+                        .get("flag_removed")
+                        .and_then(|v| v.as_bool())
+                        .unwrap_or(false),
+                    ..Default::default()
+                })
+            }
+        };
         Ok(datasource)
     }
 
@@ -208,10 +437,16 @@ impl UpdateCatalog {
 
     //TODO test
     async fn process_rows(
-        &self,
+        &mut self,
         rows: &mut Vec<csv::StringRecord>,
         datasource: &mut DataSource,
     ) -> Result<()> {
+        if datasource.prematch.validate {
+            self.validate_prematch_items(rows, datasource).await?;
+        }
+        if self.incremental.is_some() {
+            return self.process_rows_incremental(rows, datasource).await;
+        }
         let mut existing_ext_ids = HashSet::new();
         if datasource.just_add {
             let ext_ids: Vec<String> = rows
@@ -234,6 +469,7 @@ impl UpdateCatalog {
             };
             if existing_ext_ids.contains(ext_id) {
                 // An entry with this ext_id already exists, and we only know that because just_add==true, so skip this
+                self.summary.unchanged += 1;
             } else if let Err(e) = self.process_row(row, datasource).await {
                 if datasource.fail_on_error {
                     return Err(e);
@@ -244,9 +480,99 @@ impl UpdateCatalog {
         Ok(())
     }
 
+    /// Finds redirected and deleted items in the `q` column of the rows, see `PrematchOptions`
+    async fn validate_prematch_items(
+        &self,
+        rows: &[csv::StringRecord],
+        datasource: &mut DataSource,
+    ) -> Result<()> {
+        let unique_qs: Vec<String> = datasource
+            .get_prematch_items(rows)
+            .iter()
+            .filter(|q| !datasource.prematch.replacements.contains_key(*q))
+            .map(|q| format!("Q{q}"))
+            .collect();
+        if unique_qs.is_empty() {
+            return Ok(());
+        }
+        let wikidata = self.app.wikidata();
+        let replacements = &mut datasource.prematch.replacements;
+        for (from, to) in wikidata.get_redirected_items(&unique_qs).await? {
+            if let Some(from) = AppState::item2numeric(&from) {
+                replacements.insert(from, AppState::item2numeric(&to));
+            }
+        }
+        for q in wikidata.get_deleted_items(&unique_qs).await? {
+            if let Some(q) = AppState::item2numeric(&q) {
+                replacements.entry(q).or_insert(None);
+            }
+        }
+        Ok(())
+    }
+
+    async fn process_rows_incremental(
+        &mut self,
+        rows: &mut Vec<csv::StringRecord>,
+        datasource: &mut DataSource,
+    ) -> Result<()> {
+        let ext_ids: Vec<String> = rows
+            .iter()
+            .filter_map(|row| row.get(datasource.ext_id_column))
+            .map(|s| s.to_string())
+            .collect();
+        let existing = self
+            .app
+            .storage()
+            .get_external_ids(datasource.catalog_id, &ext_ids)
+            .await?;
+        if let Some(incremental) = self.incremental.as_mut() {
+            ext_ids.iter().for_each(|ext_id| incremental.see(ext_id));
+            incremental.existing = existing;
+        }
+        for row in rows.iter() {
+            if let Err(e) = self.process_row_incremental(row, datasource).await {
+                if datasource.fail_on_error {
+                    return Err(e);
+                }
+            }
+        }
+        rows.clear();
+        Ok(())
+    }
+
+    async fn process_row_incremental(
+        &mut self,
+        row: &csv::StringRecord,
+        datasource: &mut DataSource,
+    ) -> Result<()> {
+        let mut extended_entry = ExtendedEntry::from_row(row, datasource)?;
+        let action = match self.incremental.as_ref() {
+            Some(incremental) => incremental.action_for(&extended_entry.entry),
+            None => return Ok(()),
+        };
+        match action {
+            IncrementalAction::Add => {
+                extended_entry
+                    .insert_new_from_import(&self.app, self.update_info_id)
+                    .await?;
+                self.summary.added += 1;
+            }
+            IncrementalAction::Update(entry_id) => {
+                // Same as a full import, including the match from the source, see `PrematchOptions`
+                let mut entry = Entry::from_id(entry_id, &self.app).await?;
+                self.automatch_removed |= extended_entry
+                    .update_existing(&mut entry, &self.app)
+                    .await?;
+                self.summary.updated += 1;
+            }
+            IncrementalAction::Skip => self.summary.unchanged += 1,
+        }
+        Ok(())
+    }
+
     //TODO test
     async fn process_row(
-        &self,
+        &mut self,
         row: &csv::StringRecord,
         datasource: &mut DataSource,
     ) -> Result<()> {
@@ -256,16 +582,22 @@ impl UpdateCatalog {
         };
         match Entry::from_ext_id(datasource.catalog_id, ext_id, &self.app).await {
             Ok(mut entry) => {
-                if !datasource.just_add {
+                if datasource.just_add {
+                    self.summary.unchanged += 1;
+                } else {
                     let mut extended_entry = ExtendedEntry::from_row(row, datasource)?;
-                    extended_entry
+                    self.automatch_removed |= extended_entry
                         .update_existing(&mut entry, &self.app)
                         .await?;
+                    self.summary.updated += 1;
                 }
             }
             _ => {
                 let mut extended_entry = ExtendedEntry::from_row(row, datasource)?;
-                extended_entry.insert_new(&self.app).await?;
+                extended_entry
+                    .insert_new_from_import(&self.app, self.update_info_id)
+                    .await?;
+                self.summary.added += 1;
             }
         }
         Ok(())
@@ -311,7 +643,7 @@ mod tests {
     use super::*;
     use crate::{
         app_state::{get_test_app, TEST_MUTEX},
-        datasource::DataSourceLocation,
+        datasource::PrematchOptions,
         extended_entry::ExtendedEntry,
     };
 
@@ -342,6 +674,20 @@ mod tests {
             ds.get_source_location(&app).unwrap(),
             DataSourceLocation::FilePath(format!("{}/{}", app.import_file_path(), uuid))
         );
+
+        let query = "SELECT ?item ?name { ?item wdt:P31 wd:Q5 }";
+        let ds = DataSource::new(
+            TEST_CATALOG_ID,
+            &json!({"sparql":query,"sparql_column_map":{"id":"item"}}),
+        )
+        .unwrap();
+        assert_eq!(
+            ds.get_source_location(&app).unwrap(),
+            DataSourceLocation::Sparql {
+                query: query.to_string(),
+                column_map: [("id".to_string(), "item".to_string())].into(),
+            }
+        );
     }
 
     #[tokio::test]
@@ -355,6 +701,68 @@ mod tests {
         assert_eq!(type_name, "Q5");
     }
 
+    #[test]
+    fn test_incremental_update() {
+        let existing = CatalogExtId {
+            entry_id: 1,
+            ext_name: "Foo".to_string(),
+            ext_desc: "A foo".to_string(),
+            ext_url: "http://example.org/a".to_string(),
+            q: None,
+        };
+        let mut incremental = IncrementalUpdate {
+            existing: HashMap::from([
+                ("a".to_string(), existing.to_owned()),
+                (
+                    "b".to_string(),
+                    CatalogExtId {
+                        entry_id: 2,
+                        ..existing.to_owned()
+                    },
+                ),
+                (
+                    "c".to_string(),
+                    CatalogExtId {
+                        entry_id: 3,
+                        ..existing
+                    },
+                ),
+            ]),
+            ..Default::default()
+        };
+        let mut entry = Entry::new_from_catalog_and_ext_id(TEST_CATALOG_ID, "a");
+        incremental.see("a");
+        incremental.see("b");
+        incremental.see("d");
+        entry.ext_name = "Foo".to_string();
+        entry.ext_url = "http://example.org/a".to_string();
+        assert_eq!(incremental.action_for(&entry), IncrementalAction::Skip);
+        entry.ext_id = "b".to_string();
+        entry.ext_name = "Bar".to_string();
+        assert_eq!(incremental.action_for(&entry), IncrementalAction::Update(2));
+        entry.ext_id = "a".to_string();
+        entry.ext_name = "Foo".to_string();
+        entry.q = Some(42);
+        assert_eq!(incremental.action_for(&entry), IncrementalAction::Update(1));
+        entry.ext_id = "d".to_string();
+        assert_eq!(incremental.action_for(&entry), IncrementalAction::Add);
+        let ext_ids = vec![
+            (1, "a".to_string()),
+            (2, "b".to_string()),
+            (3, "c".to_string()),
+        ];
+        assert_eq!(incremental.removed(&ext_ids), vec![(&"c".to_string(), 3)]);
+    }
+
+    #[test]
+    fn test_update_mode() {
+        assert_eq!(
+            UpdateMode::from_json(&json!({"mode":"incremental"})),
+            UpdateMode::Incremental
+        );
+        assert_eq!(UpdateMode::from_json(&json!({})), UpdateMode::Full);
+    }
+
     #[test]
     fn test_extended_entry() {
         assert_eq!(
@@ -379,6 +787,68 @@ mod tests {
         assert_eq!(ExtendedEntry::parse_date(""), None);
     }
 
+    #[test]
+    fn test_extended_entry_language_columns() {
+        let json = json!({"columns": ["id", "name:de", "name:en", "desc:en"]});
+        let mut datasource = DataSource::new(1, &json).unwrap();
+        assert_eq!(datasource.name_language, Some("de".to_string()));
+        let row = StringRecord::from(vec!["123", "Köln", "Cologne", "A city"]);
+        let ee = ExtendedEntry::from_row(&row, &mut datasource).unwrap();
+        assert_eq!(ee.entry.ext_name, "Köln");
+        assert_eq!(ee.entry.ext_desc, "A city");
+        let aliases: Vec<(&str, &str)> = ee
+            .aliases
+            .iter()
+            .map(|a| (a.language(), a.value()))
+            .collect();
+        assert_eq!(aliases, vec![("en", "Cologne")]);
+        assert_eq!(ee.descriptions.get("en"), Some(&"A city".to_string()));
+
+        // Explicit name column and language take precedence
+        let json = json!({"columns": ["id", "name", "name:de", "name:en"], "language": "en"});
+        let mut datasource = DataSource::new(1, &json).unwrap();
+        let row = StringRecord::from(vec!["123", "Cologne", "Köln", "Cologne"]);
+        let ee = ExtendedEntry::from_row(&row, &mut datasource).unwrap();
+        assert_eq!(ee.entry.ext_name, "Cologne");
+        let aliases: Vec<(&str, &str)> = ee
+            .aliases
+            .iter()
+            .map(|a| (a.language(), a.value()))
+            .collect();
+        assert_eq!(aliases, vec![("de", "Köln")]);
+
+        let json = json!({"columns": ["id", "desc:en"]});
+        assert!(DataSource::new(1, &json).is_err());
+    }
+
+    #[test]
+    fn test_extended_entry_prematch() {
+        let json =
+            json!({"columns": ["id", "name", "q"], "q_user_id": 0, "q_only_unmatched": false});
+        let mut datasource = DataSource::new(1, &json).unwrap();
+        assert_eq!(datasource.prematch.user_id, 0);
+        let rows = vec![
+            StringRecord::from(vec!["1", "Foo", "Q42"]),
+            StringRecord::from(vec!["2", "Bar", "Q13"]),
+            StringRecord::from(vec!["3", "Baz", "Q42"]),
+            StringRecord::from(vec!["4", "Baz", "N/A"]),
+        ];
+        assert_eq!(datasource.get_prematch_items(&rows), vec![13, 42]);
+
+        datasource.prematch.replacements.insert(13, None);
+        let ee = ExtendedEntry::from_row(&rows[0], &mut datasource).unwrap();
+        assert_eq!(ee.entry.q, Some(42));
+        assert_eq!(ee.entry.user, Some(0));
+        assert!(ee.replace_automatic_match);
+        let ee = ExtendedEntry::from_row(&rows[1], &mut datasource).unwrap();
+        assert_eq!(ee.entry.q, None);
+        assert_eq!(ee.entry.user, None);
+
+        let datasource = DataSource::new(1, &json!({"columns": ["id", "name", "q"]})).unwrap();
+        assert_eq!(datasource.prematch, PrematchOptions::default());
+        assert_eq!(datasource.prematch.resolve(42), Some(42));
+    }
+
     // #lizard forgives
     #[tokio::test]
     async fn test_update_from_tabbed_file() {
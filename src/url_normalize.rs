@@ -0,0 +1,66 @@
+/// The form of an external URL used to compare URLs from different catalogs: without scheme, leading `www.`,
+/// and trailing slashes, and with a lowercase host; eg "https://www.Example.org/foo/" => "example.org/foo".
+/// Returns an empty string for URLs that are empty after normalization.
+pub fn normalize_ext_url(url: &str) -> String {
+    let url = url.trim();
+    let url = match url.split_once("://") {
+        Some((_scheme, rest)) => rest,
+        None => url,
+    };
+    let (host, path) = match url.find('/') {
+        Some(pos) => url.split_at(pos),
+        None => (url, ""),
+    };
+    let host = host.to_lowercase();
+    let host = host.strip_prefix("www.").unwrap_or(&host);
+    format!("{host}{path}").trim_end_matches('/').to_string()
+}
+
+/// The forms in which a URL with this normalized form is likely stored: `http` and `https`,
+/// with and without `www.`, and with and without a trailing slash
+pub fn ext_url_variants(normalized: &str) -> Vec<String> {
+    if normalized.is_empty() {
+        return vec![];
+    }
+    let mut ret = vec![];
+    for scheme in ["https", "http"] {
+        for www in ["", "www."] {
+            for slash in ["", "/"] {
+                ret.push(format!("{scheme}://{www}{normalized}{slash}"));
+            }
+        }
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_ext_url() {
+        assert_eq!(
+            normalize_ext_url("https://www.Example.org/Foo/"),
+            "example.org/Foo"
+        );
+        assert_eq!(normalize_ext_url("http://example.org"), "example.org");
+        assert_eq!(
+            normalize_ext_url(" example.org/a?b=1 "),
+            "example.org/a?b=1"
+        );
+        assert_eq!(normalize_ext_url("https://"), "");
+        assert_eq!(normalize_ext_url(""), "");
+    }
+
+    #[test]
+    fn test_ext_url_variants() {
+        let variants = ext_url_variants("example.org/foo");
+        assert_eq!(variants.len(), 8);
+        assert!(variants.contains(&"https://www.example.org/foo/".to_string()));
+        assert!(variants.contains(&"http://example.org/foo".to_string()));
+        assert!(variants
+            .iter()
+            .all(|url| normalize_ext_url(url) == "example.org/foo"));
+        assert!(ext_url_variants("").is_empty());
+    }
+}
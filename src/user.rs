@@ -0,0 +1,120 @@
+use crate::app_state::AppState;
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+
+/// A row in the `user` table. User names are Wikidata user names, with spaces rather than underscores.
+#[derive(Debug, Clone)]
+pub struct User {
+    pub id: usize,
+    pub name: String,
+    pub is_catalog_admin: bool,
+    app: Option<AppState>,
+}
+
+impl User {
+    pub async fn from_id(user_id: usize, app: &AppState) -> Result<Self> {
+        let mut ret = app
+            .storage()
+            .get_user_by_id(user_id)
+            .await?
+            .ok_or_else(|| anyhow!("No user with ID {user_id}"))?;
+        ret.set_app(app);
+        Ok(ret)
+    }
+
+    /// Returns the ID of the user with that (Wikidata) name, creating the user if necessary
+    pub async fn get_or_create_id(name: &str, app: &AppState) -> Result<usize> {
+        app.storage().get_or_create_user(name).await
+    }
+
+    /// Normalizes a user name the way the PHP version did: underscores become spaces, and surrounding space is removed.
+    pub fn normalize_name(name: &str) -> String {
+        name.replace('_', " ").trim().to_string()
+    }
+
+    /// Creates a user from `id`,`name`,`is_catalog_admin`
+    pub fn from_row(row: (usize, String, u8)) -> Self {
+        Self {
+            id: row.0,
+            name: row.1,
+            is_catalog_admin: row.2 > 0,
+            app: None,
+        }
+    }
+
+    pub fn set_app(&mut self, app: &AppState) {
+        self.app = Some(app.clone());
+    }
+
+    fn app(&self) -> Result<&AppState> {
+        self.app
+            .as_ref()
+            .ok_or_else(|| anyhow!("User {}: app not set", self.id))
+    }
+
+    pub fn is_catalog_admin(&self) -> bool {
+        self.is_catalog_admin
+    }
+
+    /// Checks if the user is currently blocked on Wikidata
+    pub async fn is_blocked(&self) -> Result<bool> {
+        let api = self.app()?.wikidata().get_mw_api().await?;
+        let params = api.params_into(&[
+            ("action", "query"),
+            ("list", "blocks"),
+            ("bkusers", &self.name),
+        ]);
+        let result = api.get_query_api_json(&params).await?;
+        Ok(Self::has_blocks(&result))
+    }
+
+    fn has_blocks(result: &Value) -> bool {
+        result["query"]["blocks"]
+            .as_array()
+            .map(|blocks| !blocks.is_empty())
+            .unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app_state::get_test_app;
+    use serde_json::json;
+
+    #[test]
+    fn test_user_from_row() {
+        let user = User::from_row((2, "Magnus Manske".to_string(), 1));
+        assert_eq!(user.id, 2);
+        assert!(user.is_catalog_admin());
+        assert!(!User::from_row((3, "Foo".to_string(), 0)).is_catalog_admin());
+        assert!(user.app().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_get_or_create_id() {
+        let app = get_test_app();
+        let user_id = User::get_or_create_id("Magnus_Manske", &app).await.unwrap();
+        let user_id2 = User::get_or_create_id("Magnus Manske", &app).await.unwrap();
+        assert_eq!(user_id, user_id2);
+        let user = User::from_id(user_id, &app).await.unwrap();
+        assert_eq!(user.name, "Magnus Manske");
+        assert!(User::get_or_create_id(" _ ", &app).await.is_err());
+    }
+
+    #[test]
+    fn test_normalize_name() {
+        assert_eq!(User::normalize_name("Magnus_Manske"), "Magnus Manske");
+        assert_eq!(User::normalize_name(" Magnus Manske "), "Magnus Manske");
+        assert_eq!(User::normalize_name("_"), "");
+    }
+
+    #[test]
+    fn test_has_blocks() {
+        let result = json!({"query":{"blocks":[{"id":1,"user":"Vandal"}]}});
+        assert!(User::has_blocks(&result));
+        let result = json!({"query":{"blocks":[]}});
+        assert!(!User::has_blocks(&result));
+        assert!(!User::has_blocks(&json!({})));
+    }
+}
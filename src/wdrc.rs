@@ -148,15 +148,6 @@ impl WDRC {
         Ok(deletions)
     }
 
-    async fn get_prop2catalog_ids(&self, app: &AppState) -> Result<HashMap<usize, Vec<usize>>> {
-        let mut ret: HashMap<usize, Vec<usize>> = HashMap::new();
-        let results = app.storage().maintenance_get_prop2catalog_ids().await?;
-        for (catalog_id, property) in results {
-            ret.entry(property).or_default().push(catalog_id);
-        }
-        Ok(ret)
-    }
-
     async fn sync_property_propval2item(
         &self,
         property: usize,
@@ -247,7 +238,7 @@ impl WDRC {
             .get_kv_value("wdrc_sync_properties")
             .await?
             .unwrap_or_else(Self::yesterday);
-        let prop2catalog_ids = self.get_prop2catalog_ids(app).await?;
+        let prop2catalog_ids = app.prop2catalogs().await?;
         let results = app
             .wdrc()
             .get_item_property_ts(&prop2catalog_ids, &last_ts)
@@ -311,7 +302,10 @@ impl WDRC {
         wd_item_q: isize,
         app: &AppState,
     ) -> Result<()> {
-        let mut entry = Entry::from_id(entry_id, app).await?;
+        let mut entry = match Entry::from_id_if_exists(entry_id, app).await? {
+            Some(entry) => entry,
+            None => return Ok(()), // Entry was deleted in the meantime
+        };
         if !entry.is_fully_matched() {
             entry
                 .set_match(&format!("Q{wd_item_q}"), USER_AUX_MATCH)
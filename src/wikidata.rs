@@ -1,17 +1,54 @@
-use crate::{mysql_misc::MySQLMisc, wikidata_commands::WikidataCommand};
+use crate::{
+    mysql_misc::MySQLMisc,
+    wikidata_commands::{WikidataCommand, WikidataCommandPropertyValue},
+};
 use anyhow::{anyhow, Result};
+use futures::{stream::BoxStream, Stream, StreamExt};
 use itertools::Itertools;
+use lazy_static::lazy_static;
 use log::error;
 use mysql_async::{from_row, prelude::*};
 use serde_json::{json, Value};
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{HashMap, HashSet, VecDeque},
     fs::File,
-    time::Duration,
+    sync::Mutex,
+    time::{Duration, Instant},
 };
 use urlencoding::encode;
+use wikimisc::wikibase::entity_container::EntityContainer;
+use wikimisc::wikibase::{Entity, EntityTrait, Value as WikibaseValue};
+
+/// The kind of item term that matched a name, see `Wikidata::search_db_terms`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TermMatch {
+    Label,
+    Alias,
+}
+
+impl TermMatch {
+    /// `wbt_term_in_lang.wbtl_type_id`
+    const fn type_id(&self) -> u8 {
+        match self {
+            TermMatch::Label => 1,
+            TermMatch::Alias => 3,
+        }
+    }
+
+    const fn from_type_id(type_id: u8) -> Option<Self> {
+        match type_id {
+            1 => Some(TermMatch::Label),
+            3 => Some(TermMatch::Alias),
+            _ => None,
+        }
+    }
+}
 
 pub const WIKIDATA_API_URL: &str = "https://www.wikidata.org/w/api.php";
+const WDQS_SPARQL_URL: &str = "https://query.wikidata.org/sparql";
+const TAXON_NAMES_PER_SPARQL: usize = 1000;
+/// Classes that mark a property as deprecated, via "instance of"
+pub const DEPRECATED_PROPERTY_CLASSES: &[&str] = &["Q18644427"];
 pub const META_ITEMS: &[&str] = &[
     "Q4167410",  // Wikimedia disambiguation page
     "Q11266439", // Wikimedia template
@@ -21,6 +58,125 @@ pub const META_ITEMS: &[&str] = &[
     "Q17362920", // Wikimedia duplicated page
 ];
 
+/// Incrementally parses SPARQL TSV results, as they arrive in arbitrary chunks.
+/// The header row is skipped; URIs lose their `<>`, literals their quotes and type/language tags.
+#[derive(Debug, Default)]
+pub struct SparqlTsvParser {
+    buffer: Vec<u8>,
+    header_skipped: bool,
+}
+
+impl SparqlTsvParser {
+    /// Adds a chunk of data, and returns all rows completed by it
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<Vec<String>> {
+        self.buffer.extend_from_slice(chunk);
+        let mut ret = vec![];
+        let mut offset = 0; // Start of the first line not yet parsed
+        while let Some(pos) = self.buffer[offset..].iter().position(|b| *b == b'\n') {
+            let end = offset + pos + 1;
+            let line = &self.buffer[offset..end];
+            if let Some(row) = Self::parse_line(&mut self.header_skipped, line) {
+                ret.push(row);
+            }
+            offset = end;
+        }
+        self.buffer.drain(..offset);
+        ret
+    }
+
+    /// Returns the last row, if the data did not end with a newline
+    pub fn finish(&mut self) -> Option<Vec<String>> {
+        let line = std::mem::take(&mut self.buffer);
+        Self::parse_line(&mut self.header_skipped, &line)
+    }
+
+    fn parse_line(header_skipped: &mut bool, line: &[u8]) -> Option<Vec<String>> {
+        let line = String::from_utf8_lossy(line);
+        let line = line.trim_end_matches(['\n', '\r']);
+        if !*header_skipped {
+            *header_skipped = true;
+            return None;
+        }
+        if line.is_empty() {
+            return None;
+        }
+        Some(line.split('\t').map(Self::parse_value).collect())
+    }
+
+    fn parse_value(value: &str) -> String {
+        if value.starts_with('<') && value.ends_with('>') && value.len() > 1 {
+            return value[1..value.len() - 1].to_string();
+        }
+        if let Some(rest) = value.strip_prefix('"') {
+            if let Some(end) = rest.rfind('"') {
+                return Self::unescape(&rest[..end]);
+            }
+        }
+        value.to_string()
+    }
+
+    /// Undoes the escaping of a literal in one pass, so eg `\\n` becomes `\n` and not a newline
+    fn unescape(s: &str) -> String {
+        let mut ret = String::with_capacity(s.len());
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                ret.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('t') => ret.push('\t'),
+                Some('n') => ret.push('\n'),
+                Some('r') => ret.push('\r'),
+                Some('"') => ret.push('"'),
+                Some('\\') => ret.push('\\'),
+                Some(other) => {
+                    ret.push('\\');
+                    ret.push(other);
+                }
+                None => ret.push('\\'),
+            }
+        }
+        ret
+    }
+}
+
+struct SparqlTsvStreamState {
+    bytes: BoxStream<'static, reqwest::Result<Vec<u8>>>,
+    parser: SparqlTsvParser,
+    rows: VecDeque<Vec<String>>,
+    done: bool,
+}
+
+/// Whether a property a catalog uses is still fit for matching
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PropertyStatus {
+    Ok,
+    Deprecated,
+    Missing,
+}
+
+lazy_static! {
+    /// Wikipedia language code => site, loaded once from the sitematrix
+    static ref SITEMATRIX: Mutex<HashMap<String, SiteInfo>> = Mutex::new(HashMap::new());
+    /// Numeric property => (time of lookup, status), see `Wikidata::get_property_status`
+    static ref PROPERTY_STATUS: Mutex<HashMap<usize, (Instant, PropertyStatus)>> = Mutex::new(HashMap::new());
+}
+
+/// How long a property status is cached; properties rarely get deleted or deprecated
+const PROPERTY_STATUS_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A Wikipedia, as listed in the sitematrix
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SiteInfo {
+    /// Database name, eg `be_x_oldwiki`
+    pub dbname: String,
+    /// Global site ID, as used in sitelinks; the same as the database name for Wikipedias
+    pub site_id: String,
+    /// eg `https://be-tarask.wikipedia.org`
+    pub url: String,
+}
+
 #[derive(Debug, Clone)]
 pub struct Wikidata {
     pool: mysql_async::Pool,
@@ -49,6 +205,76 @@ impl Wikidata {
         *crate::app_state::TESTING.lock().unwrap()
     }
 
+    /// Returns the Wikipedia for a language code (eg a catalog `search_wp`), or `None` if there is none.
+    /// The language code can also be the database name prefix, eg both `be-tarask` and `be_x_old` work;
+    /// underscores are treated as dashes.
+    pub async fn lang_to_site(&self, language: &str) -> Result<Option<SiteInfo>> {
+        let language = language.trim().to_lowercase().replace('_', "-");
+        if language.is_empty() {
+            return Ok(None);
+        }
+        if SITEMATRIX.lock().map_err(|e| anyhow!("{e}"))?.is_empty() {
+            let sitematrix = self.load_sitematrix().await?;
+            *SITEMATRIX.lock().map_err(|e| anyhow!("{e}"))? = sitematrix;
+        }
+        Ok(SITEMATRIX
+            .lock()
+            .map_err(|e| anyhow!("{e}"))?
+            .get(&language)
+            .cloned())
+    }
+
+    async fn load_sitematrix(&self) -> Result<HashMap<String, SiteInfo>> {
+        let mw_api = self.get_mw_api().await?;
+        let params = mw_api.params_into(&[
+            ("action", "sitematrix"),
+            ("smtype", "language"),
+            ("smsiteprop", "url|dbname|code"),
+            ("smstate", "all"),
+        ]);
+        let json = mw_api.get_query_api_json(&params).await?;
+        Ok(Self::parse_sitematrix(&json))
+    }
+
+    /// Returns language code => Wikipedia, for all open Wikipedias in a sitematrix API result.
+    /// Each site is also listed under its database name prefix, with `-` for `_`.
+    fn parse_sitematrix(json: &Value) -> HashMap<String, SiteInfo> {
+        let mut ret = HashMap::new();
+        let languages = match json["sitematrix"].as_object() {
+            Some(languages) => languages,
+            None => return ret,
+        };
+        for language in languages.values() {
+            let code = match language["code"].as_str() {
+                Some(code) => code.to_lowercase(),
+                None => continue, // "count"
+            };
+            let sites = match language["site"].as_array() {
+                Some(sites) => sites,
+                None => continue,
+            };
+            for site in sites {
+                if site["code"].as_str() != Some("wiki") || site.get("closed").is_some() {
+                    continue;
+                }
+                let (dbname, url) = match (site["dbname"].as_str(), site["url"].as_str()) {
+                    (Some(dbname), Some(url)) => (dbname.to_string(), url.to_string()),
+                    _ => continue,
+                };
+                let site_info = SiteInfo {
+                    site_id: dbname.to_owned(),
+                    dbname: dbname.to_owned(),
+                    url,
+                };
+                if let Some(prefix) = dbname.strip_suffix("wiki") {
+                    ret.insert(prefix.replace('_', "-"), site_info.to_owned());
+                }
+                ret.insert(code.to_owned(), site_info);
+            }
+        }
+        ret
+    }
+
     // Database things
 
     /// Returns [(`item_id`, `page`)]
@@ -116,32 +342,66 @@ impl Wikidata {
         Ok(results)
     }
 
-    pub async fn search_with_type(&self, name: &str) -> Result<Vec<String>> {
-        let sql = "SELECT concat('Q',wbit_item_id) AS q
-        			FROM wbt_text,wbt_item_terms,wbt_term_in_lang,wbt_text_in_lang
-           			WHERE wbit_term_in_lang_id=wbtl_id AND wbtl_text_in_lang_id=wbxl_id AND wbxl_text_id=wbx_id  AND wbx_text=:name
-	                AND EXISTS (SELECT * FROM page,pagelinks,linktarget WHERE page_title=concat('Q',wbit_item_id) AND page_namespace=0 AND pl_target_id=lt_id AND pl_from=page_id AND lt_namespace=0 AND lt_title=:type_q)
-					GROUP BY name,q";
+    /// Finds items that have a name as a label, or optionally as an alias, in any language.
+    /// If `type_q` is not empty, only items that link to it (eg via P31) are returned.
+    /// Returns the items with the kind of term that matched; labels win over aliases.
+    pub async fn search_db_terms(
+        &self,
+        name: &str,
+        type_q: &str,
+        include_aliases: bool,
+    ) -> Result<Vec<(String, TermMatch)>> {
+        if name.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut type_ids = vec![TermMatch::Label.type_id()];
+        if include_aliases {
+            type_ids.push(TermMatch::Alias.type_id());
+        }
+        let type_ids = type_ids.iter().map(|id| id.to_string()).join(",");
+        let mut sql = format!(
+            "SELECT concat('Q',wbit_item_id) AS q,wbtl_type_id
+            FROM wbt_text,wbt_item_terms,wbt_term_in_lang,wbt_text_in_lang
+            WHERE wbit_term_in_lang_id=wbtl_id AND wbtl_text_in_lang_id=wbxl_id AND wbxl_text_id=wbx_id AND wbx_text=:name
+            AND wbtl_type_id IN ({type_ids})"
+        );
+        if !type_q.is_empty() {
+            sql += " AND EXISTS (SELECT * FROM page,pagelinks,linktarget WHERE page_title=concat('Q',wbit_item_id) AND page_namespace=0 AND pl_target_id=lt_id AND pl_from=page_id AND lt_namespace=0 AND lt_title=:type_q)";
+        }
+        sql += " GROUP BY q,wbtl_type_id";
+        let params = if type_q.is_empty() {
+            params! {name}
+        } else {
+            params! {name,type_q}
+        };
         let results = self
             .get_conn()
             .await?
-            .exec_iter(sql, params! {name})
+            .exec_iter(sql, params)
             .await?
-            .map_and_drop(from_row::<String>)
+            .map_and_drop(from_row::<(String, u8)>)
             .await?;
-        Ok(results)
+        Ok(Self::collapse_term_matches(results))
     }
 
-    pub async fn search_without_type(&self, name: &str) -> Result<Vec<String>> {
-        let sql = "SELECT concat('Q',wbit_item_id) AS q FROM wbt_text,wbt_item_terms,wbt_term_in_lang,wbt_text_in_lang WHERE wbit_term_in_lang_id=wbtl_id AND wbtl_text_in_lang_id=wbxl_id AND wbxl_text_id=wbx_id  AND wbx_text=:name GROUP BY name,q";
-        let results = self
-            .get_conn()
-            .await?
-            .exec_iter(sql, params! {name})
-            .await?
-            .map_and_drop(from_row::<String>)
-            .await?;
-        Ok(results)
+    /// Converts (item, `wbtl_type_id`) rows to one (item, term) per item, in order of first appearance
+    fn collapse_term_matches(rows: Vec<(String, u8)>) -> Vec<(String, TermMatch)> {
+        let mut ret: Vec<(String, TermMatch)> = vec![];
+        for (q, type_id) in rows {
+            let term = match TermMatch::from_type_id(type_id) {
+                Some(term) => term,
+                None => continue,
+            };
+            match ret.iter_mut().find(|(item, _)| *item == q) {
+                Some(existing) => {
+                    if term == TermMatch::Label {
+                        existing.1 = term;
+                    }
+                }
+                None => ret.push((q, term)),
+            }
+        }
+        ret
     }
 
     /// Returns a list of redirected items, with their redirect tatget.
@@ -187,26 +447,94 @@ impl Wikidata {
         Ok(not_found)
     }
 
-    pub async fn search_db_with_type(&self, name: &str, type_q: &str) -> Result<Vec<String>> {
-        if name.is_empty() {
-            return Ok(vec![]);
+    /// Returns the status of the given (numeric) properties; properties that can not be loaded are `PropertyStatus::Missing`.
+    /// Results are cached for `PROPERTY_STATUS_TTL`; only properties not in the cache are loaded from Wikidata.
+    pub async fn get_property_status(
+        &self,
+        props: &[usize],
+    ) -> Result<HashMap<usize, PropertyStatus>> {
+        let mut ret = HashMap::new();
+        let mut to_load = vec![];
+        {
+            let cache = PROPERTY_STATUS.lock().map_err(|e| anyhow!("{e}"))?;
+            for prop in props {
+                match cache.get(prop) {
+                    Some((ts, status)) if ts.elapsed() < PROPERTY_STATUS_TTL => {
+                        ret.insert(*prop, *status);
+                    }
+                    _ => to_load.push(*prop),
+                }
+            }
         }
-        let items = if type_q.is_empty() {
-            self.search_without_type(name).await?
-        } else {
-            self.search_with_type(name).await?
-        };
+        let loaded = self.load_property_status(&to_load).await?;
+        let now = Instant::now();
+        let mut cache = PROPERTY_STATUS.lock().map_err(|e| anyhow!("{e}"))?;
+        for (prop, status) in loaded {
+            cache.insert(prop, (now, status));
+            ret.insert(prop, status);
+        }
+        Ok(ret)
+    }
+
+    /// Loads the status of the given (numeric) properties from Wikidata.
+    /// A property is deprecated if it is an "instance of" one of `DEPRECATED_PROPERTY_CLASSES`.
+    async fn load_property_status(
+        &self,
+        props: &[usize],
+    ) -> Result<HashMap<usize, PropertyStatus>> {
+        let mut ret: HashMap<usize, PropertyStatus> = props
+            .iter()
+            .map(|prop| (*prop, PropertyStatus::Missing))
+            .collect();
+        if props.is_empty() {
+            return Ok(ret);
+        }
+        let api = self.get_mw_api().await?;
+        let entities = EntityContainer::new();
+        let titles: Vec<String> = props.iter().map(|prop| format!("P{prop}")).collect();
+        entities.load_entities(&api, &titles).await?;
+        for prop in props {
+            if let Some(entity) = entities.get_entity(format!("P{prop}")) {
+                let status = if Self::is_deprecated_property(&entity) {
+                    PropertyStatus::Deprecated
+                } else {
+                    PropertyStatus::Ok
+                };
+                ret.insert(*prop, status);
+            }
+        }
+        Ok(ret)
+    }
+
+    fn is_deprecated_property(entity: &Entity) -> bool {
+        entity
+            .values_for_property("P31")
+            .iter()
+            .any(|value| match value {
+                WikibaseValue::Entity(e) => DEPRECATED_PROPERTY_CLASSES.contains(&e.id()),
+                _ => false,
+            })
+    }
+
+    /// Finds items that have a name as a label or alias, see `search_db_terms`
+    pub async fn search_db_with_type(&self, name: &str, type_q: &str) -> Result<Vec<String>> {
+        let items = self
+            .search_db_terms(name, type_q, true)
+            .await?
+            .into_iter()
+            .map(|(q, _)| q)
+            .collect();
         Ok(items)
     }
 
     /// Removes "meta items" (eg disambiguation pages) from an item list.
-    /// Items are in format "Qxxx".
+    /// Items are in format "Qxxx". Duplicates are removed as well; the order of the remaining items is kept.
     pub async fn remove_meta_items(&self, items: &mut Vec<String>) -> Result<()> {
         if items.is_empty() {
             return Ok(());
         }
-        items.sort();
-        items.dedup();
+        let mut seen = HashSet::new();
+        items.retain(|item| seen.insert(item.to_owned()));
         let meta_items: HashSet<String> = self.get_meta_items(items).await?.into_iter().collect();
         if !meta_items.is_empty() {
             items.retain(|item| !meta_items.contains(item));
@@ -305,6 +633,46 @@ impl Wikidata {
         Ok(())
     }
 
+    /// Adds a reference with the given values to an existing statement
+    pub async fn add_reference(
+        &mut self,
+        statement_id: &str,
+        reference: &[WikidataCommandPropertyValue],
+        summary: &str,
+    ) -> Result<()> {
+        if Self::testing() {
+            error!("SKIPPING REFERENCE {reference:?} FOR {statement_id}");
+            return Ok(());
+        }
+        let mut snaks: HashMap<String, Vec<Value>> = HashMap::new();
+        for rv in reference {
+            snaks
+                .entry(format!("P{}", rv.property))
+                .or_default()
+                .push(WikidataCommand::value_as_snak(rv.property, &rv.value));
+        }
+        self.api_log_in().await?;
+        if let Some(mw_api) = self.mw_api.as_mut() {
+            let mut params: HashMap<String, String> = HashMap::new();
+            params.insert("action".to_string(), "wbsetreference".to_string());
+            params.insert("statement".to_string(), statement_id.to_string());
+            params.insert("snaks".to_string(), json!(snaks).to_string());
+            params.insert("summary".to_string(), summary.to_string());
+            params.insert("token".to_string(), mw_api.get_edit_token().await?);
+            let result = mw_api
+                .post_query_api_json_mut(&params)
+                .await
+                .map_err(|e| anyhow!("wbsetreference failed for {statement_id}: {e}"))?;
+            if let Some(error) = result.get("error") {
+                return Err(anyhow!("wbsetreference failed for {statement_id}: {error}"));
+            }
+            return Ok(());
+        }
+        Err(anyhow!(
+            "wbsetreference failed for {statement_id}: no API connection"
+        ))
+    }
+
     //TODO test
     pub async fn execute_commands(&mut self, commands: Vec<WikidataCommand>) -> Result<()> {
         if Self::testing() {
@@ -388,6 +756,98 @@ impl Wikidata {
             .await
     }
 
+    /// Queries SPARQL and streams the result rows as they arrive, without buffering the whole result.
+    /// Use this instead of `load_sparql_csv` for queries with millions of rows.
+    pub async fn load_sparql_tsv_stream(
+        &self,
+        sparql: &str,
+    ) -> Result<impl Stream<Item = Result<Vec<String>>>> {
+        let response = wikimisc::wikidata::Wikidata::new()
+            .reqwest_client()?
+            .post(WDQS_SPARQL_URL)
+            .header(reqwest::header::ACCEPT, "text/tab-separated-values")
+            .form(&[("query", sparql)])
+            .send()
+            .await?
+            .error_for_status()?;
+        let state = SparqlTsvStreamState {
+            bytes: response
+                .bytes_stream()
+                .map(|chunk| chunk.map(|bytes| bytes.to_vec()))
+                .boxed(),
+            parser: SparqlTsvParser::default(),
+            rows: VecDeque::new(),
+            done: false,
+        };
+        Ok(futures::stream::unfold(state, |mut state| async move {
+            loop {
+                if let Some(row) = state.rows.pop_front() {
+                    return Some((Ok(row), state));
+                }
+                if state.done {
+                    return None;
+                }
+                match state.bytes.next().await {
+                    Some(Ok(chunk)) => state.rows.extend(state.parser.push(&chunk)),
+                    Some(Err(e)) => {
+                        state.done = true;
+                        return Some((Err(anyhow!(e)), state));
+                    }
+                    None => {
+                        state.done = true;
+                        state.rows.extend(state.parser.finish());
+                    }
+                }
+            }
+        }))
+    }
+
+    /// Loads all taxon items (instance of taxon or cultivar) that have one of the given names
+    /// as P225 (taxon name) or P1420 (taxon synonym), in bulk via SPARQL.
+    /// Returns name => [(item, rank item)]
+    pub async fn get_items_for_taxon_names(
+        &self,
+        names: &[String],
+    ) -> Result<HashMap<String, Vec<(String, Option<String>)>>> {
+        let mut ret: HashMap<String, Vec<(String, Option<String>)>> = HashMap::new();
+        for chunk in names.chunks(TAXON_NAMES_PER_SPARQL) {
+            let values = chunk
+                .iter()
+                .map(|name| format!("\"{}\"", name.replace('"', "\\\"")))
+                .join(" ");
+            let sparql = format!(
+                "SELECT DISTINCT ?q ?name ?rank {{
+                VALUES ?name {{ {values} }} VALUES ?instance {{ wd:Q16521 wd:Q4886 }}
+                {{ ?q wdt:P225 ?name }} UNION {{ ?q wdt:P1420 ?name }}
+                ?q wdt:P31 ?instance .
+                OPTIONAL {{ ?q wdt:P105 ?rank }} }}"
+            );
+            let mut reader = self.load_sparql_csv(&sparql).await?;
+            for record in reader.records().filter_map(|r| r.ok()) {
+                let q = match record.get(0).and_then(Self::entity_from_uri) {
+                    Some(q) => q,
+                    None => continue,
+                };
+                let name = match record.get(1) {
+                    Some(name) => name.to_string(),
+                    None => continue,
+                };
+                let rank = record.get(2).and_then(Self::entity_from_uri);
+                ret.entry(name).or_default().push((q, rank));
+            }
+        }
+        Ok(ret)
+    }
+
+    fn entity_from_uri(uri: &str) -> Option<String> {
+        let entity = uri.rsplit('/').next()?;
+        if entity.is_empty() {
+            None
+        } else {
+            Some(entity.to_string())
+        }
+    }
+
     async fn search_with_limit_run_query(
         query: &str,
         srlimit: Option<usize>,
@@ -421,6 +881,117 @@ impl Wikidata {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use wikimisc::wikibase::{PropertyEntity, Snak, SnakDataType, Statement};
+
+    fn property_with_p31(q: &str) -> Entity {
+        let claim = Statement::new_normal(Snak::new_item("P31", q), vec![], vec![]);
+        Entity::Property(PropertyEntity::new(
+            "P12345".to_string(),
+            vec![],
+            vec![],
+            vec![],
+            vec![claim],
+            Some(SnakDataType::ExternalId),
+            false,
+        ))
+    }
+
+    #[test]
+    fn test_is_deprecated_property() {
+        assert!(Wikidata::is_deprecated_property(&property_with_p31(
+            "Q18644427"
+        )));
+        assert!(!Wikidata::is_deprecated_property(&property_with_p31(
+            "Q19847637"
+        )));
+    }
+
+    #[test]
+    fn test_collapse_term_matches() {
+        let rows = vec![
+            ("Q2".to_string(), 3),
+            ("Q1".to_string(), 1),
+            ("Q2".to_string(), 1),
+            ("Q3".to_string(), 3),
+            ("Q4".to_string(), 2),
+        ];
+        assert_eq!(
+            Wikidata::collapse_term_matches(rows),
+            vec![
+                ("Q2".to_string(), TermMatch::Label),
+                ("Q1".to_string(), TermMatch::Label),
+                ("Q3".to_string(), TermMatch::Alias),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sparql_tsv_parser() {
+        let mut parser = SparqlTsvParser::default();
+        assert!(parser
+            .push(b"?q\t?name\n<http://www.wikidata.org/ent")
+            .is_empty());
+        let rows = parser.push(b"ity/Q42>\t\"Douglas \\\"DNA\\\" Adams\"@en\r\n<http://www.wikidata.org/entity/Q1>\t\"12\"^^<http://www.w3.org/2001/XMLSchema#integer>");
+        assert_eq!(
+            rows,
+            vec![vec![
+                "http://www.wikidata.org/entity/Q42".to_string(),
+                "Douglas \"DNA\" Adams".to_string()
+            ]]
+        );
+        assert_eq!(
+            parser.finish(),
+            Some(vec![
+                "http://www.wikidata.org/entity/Q1".to_string(),
+                "12".to_string()
+            ])
+        );
+        assert_eq!(parser.finish(), None);
+    }
+
+    #[test]
+    fn test_sparql_tsv_parser_unescape() {
+        assert_eq!(SparqlTsvParser::unescape("a\\tb\\nc"), "a\tb\nc");
+        assert_eq!(SparqlTsvParser::unescape("C:\\\\new"), "C:\\new");
+        assert_eq!(SparqlTsvParser::unescape("\\\"x\\\""), "\"x\"");
+        assert_eq!(SparqlTsvParser::unescape("\\u00e9"), "\\u00e9");
+    }
+
+    #[test]
+    fn test_parse_sitematrix() {
+        let json = json!({"sitematrix":{
+            "count": 3,
+            "0": {"code":"en","site":[
+                {"url":"https://en.wikipedia.org","dbname":"enwiki","code":"wiki"},
+                {"url":"https://en.wiktionary.org","dbname":"enwiktionary","code":"wiktionary"}
+            ]},
+            "1": {"code":"be-tarask","site":[
+                {"url":"https://be-tarask.wikipedia.org","dbname":"be_x_oldwiki","code":"wiki"}
+            ]},
+            "2": {"code":"aa","site":[
+                {"url":"https://aa.wikipedia.org","dbname":"aawiki","code":"wiki","closed":""}
+            ]}
+        }});
+        let sites = Wikidata::parse_sitematrix(&json);
+        assert_eq!(sites.get("en").unwrap().site_id, "enwiki");
+        assert_eq!(sites.get("en").unwrap().url, "https://en.wikipedia.org");
+        let be_tarask = sites.get("be-tarask").unwrap();
+        assert_eq!(be_tarask.dbname, "be_x_oldwiki");
+        assert_eq!(sites.get("be-x-old"), Some(be_tarask));
+        assert_eq!(sites.get("aa"), None);
+    }
+
+    #[tokio::test]
+    async fn test_lang_to_site() {
+        let wd = get_test_wd();
+        let site = wd.lang_to_site("de").await.unwrap().unwrap();
+        assert_eq!(site.site_id, "dewiki");
+        let site = wd.lang_to_site("be_x_old").await.unwrap().unwrap();
+        assert_eq!(site.dbname, "be_x_oldwiki");
+        assert_eq!(wd.lang_to_site("be-tarask").await.unwrap(), Some(site));
+        assert_eq!(wd.lang_to_site("").await.unwrap(), None);
+        assert_eq!(wd.lang_to_site("not-a-language").await.unwrap(), None);
+    }
 
     fn get_test_wd() -> Wikidata {
         let app = crate::app_state::get_test_app();
@@ -1,8 +1,17 @@
+use anyhow::{anyhow, Result};
+use lazy_static::lazy_static;
+use regex::Regex;
 use serde_json::{json, Value};
 use std::collections::HashMap;
 
 use crate::entry::CoordinateLocation;
 
+lazy_static! {
+    static ref RE_QS_ITEM: Regex = Regex::new(r"^(Q\d+|LAST)$").expect("Regex failure");
+    static ref RE_QS_PROPERTY: Regex = Regex::new(r"^P\d+$").expect("Regex failure");
+    static ref RE_QS_QUALIFIER_OR_SOURCE: Regex = Regex::new(r"^[PS]\d+$").expect("Regex failure");
+}
+
 pub type WikidataCommandPropertyValueGroup = Vec<WikidataCommandPropertyValue>;
 pub type WikidataCommandPropertyValueGroups = Vec<WikidataCommandPropertyValueGroup>;
 
@@ -15,8 +24,9 @@ pub enum WikidataCommandWhat {
 pub enum WikidataCommandValue {
     String(String),
     Item(usize),
-    //Time(String),
+    Time { time: String, precision: u64 }, // time like "+1967-01-17T00:00:00Z"
     Location(CoordinateLocation),
+    MonolingualText { language: String, text: String },
     //SomeValue,
     //NoValue,
 }
@@ -120,7 +130,7 @@ impl WikidataCommand {
     }
 
     //TODO test
-    fn value_as_snak(property: usize, value: &WikidataCommandValue) -> Value {
+    pub fn value_as_snak(property: usize, value: &WikidataCommandValue) -> Value {
         let datavalue = Self::as_datavalue(value);
         Self::datavalue_as_snak(property, datavalue)
     }
@@ -140,8 +150,14 @@ impl WikidataCommand {
             WikidataCommandValue::Item(q) => {
                 json!({"value":{"entity-type":"item","numeric-id":q,"id":format!("Q{}",q)},"type":"wikibase-entityid"})
             }
+            WikidataCommandValue::Time { time, precision } => {
+                json!({"value":{"time":time,"timezone":0,"before":0,"after":0,"precision":precision,"calendarmodel":"http://www.wikidata.org/entity/Q1985727"},"type":"time"})
+            }
             WikidataCommandValue::Location(cl) => {
                 json!({"value":{"latitude":cl.lat,"longitude":cl.lon,"globe":"http://www.wikidata.org/entity/Q2"},"type":"globecoordinate"})
+            }
+            WikidataCommandValue::MonolingualText { language, text } => {
+                json!({"value":{"text":text,"language":language},"type":"monolingualtext"})
             } //_ => {panic!("WikidataCommand::as_datavalue: not implemented: {:?}",&self)}
         }
     }
@@ -156,6 +172,154 @@ impl WikidataCommand {
     }
 }
 
+/// Serializes commands as QuickStatements V1, one statement (with one reference group) per line.
+/// Commands for the same item are grouped, and identical statements are merged into one,
+/// with the combined qualifiers and references. An `item_id` of 0 is a new item, written as `CREATE` and then `LAST`.
+/// Ranks are not supported by QuickStatements V1, and are ignored.
+pub fn to_quickstatements_v1(commands: &[WikidataCommand]) -> String {
+    let mut merged: Vec<WikidataCommand> = vec![];
+    let mut item_order: Vec<usize> = vec![];
+    for command in commands {
+        if !item_order.contains(&command.item_id) {
+            item_order.push(command.item_id);
+        }
+        let WikidataCommandWhat::Property(property) = command.what;
+        let existing = merged.iter_mut().find(|c| {
+            let WikidataCommandWhat::Property(p) = c.what;
+            c.item_id == command.item_id && p == property && c.value == command.value
+        });
+        match existing {
+            Some(existing) => {
+                for qualifier in &command.qualifiers {
+                    if !existing.qualifiers.contains(qualifier) {
+                        existing.qualifiers.push(qualifier.to_owned());
+                    }
+                }
+                for reference_group in &command.references {
+                    if !existing.references.contains(reference_group) {
+                        existing.references.push(reference_group.to_owned());
+                    }
+                }
+                if existing.comment.is_none() {
+                    existing.comment = command.comment.to_owned();
+                }
+            }
+            None => merged.push(command.to_owned()),
+        }
+    }
+    let mut lines: Vec<String> = vec![];
+    for item_id in item_order {
+        if item_id == 0 {
+            lines.push("CREATE".to_string());
+        }
+        merged
+            .iter()
+            .filter(|c| c.item_id == item_id)
+            .for_each(|command| lines.extend(command.as_quickstatements_v1_lines()));
+    }
+    lines.join("\n")
+}
+
+/// Checks that a QuickStatements V1 statement line is well-formed:
+/// item, property, value, then pairs of qualifier (P) or source (S) property and value,
+/// optionally followed by a `/* comment */`. A `CREATE` line is also accepted.
+pub fn validate_quickstatements_v1_line(line: &str) -> Result<()> {
+    if line == "CREATE" {
+        return Ok(());
+    }
+    let mut parts: Vec<&str> = line.split('\t').collect();
+    if let Some(last) = parts.last() {
+        if last.starts_with("/*") && last.ends_with("*/") {
+            parts.pop();
+        }
+    }
+    if parts.len() < 3 || parts.len() % 2 == 0 {
+        return Err(anyhow!(
+            "Bad number of fields in QuickStatements line '{line}'"
+        ));
+    }
+    if !RE_QS_ITEM.is_match(parts[0]) {
+        return Err(anyhow!(
+            "Bad item '{}' in QuickStatements line '{line}'",
+            parts[0]
+        ));
+    }
+    if !RE_QS_PROPERTY.is_match(parts[1]) {
+        return Err(anyhow!(
+            "Bad property '{}' in QuickStatements line '{line}'",
+            parts[1]
+        ));
+    }
+    for pair in parts[3..].chunks(2) {
+        if !RE_QS_QUALIFIER_OR_SOURCE.is_match(pair[0]) {
+            return Err(anyhow!(
+                "Bad qualifier/source property '{}' in QuickStatements line '{line}'",
+                pair[0]
+            ));
+        }
+    }
+    if parts.iter().any(|part| part.is_empty()) {
+        return Err(anyhow!("Empty field in QuickStatements line '{line}'"));
+    }
+    Ok(())
+}
+
+impl WikidataCommand {
+    fn as_quickstatements_v1_lines(&self) -> Vec<String> {
+        let WikidataCommandWhat::Property(property) = self.what;
+        let item = match self.item_id {
+            0 => "LAST".to_string(),
+            q => format!("Q{q}"),
+        };
+        let mut statement = vec![item, format!("P{property}"), Self::as_qs_value(&self.value)];
+        for qualifier in &self.qualifiers {
+            statement.push(format!("P{}", qualifier.property));
+            statement.push(Self::as_qs_value(&qualifier.value));
+        }
+        let reference_groups: Vec<&WikidataCommandPropertyValueGroup> =
+            self.references.iter().filter(|g| !g.is_empty()).collect();
+        let mut lines: Vec<Vec<String>> = if reference_groups.is_empty() {
+            vec![statement]
+        } else {
+            reference_groups
+                .iter()
+                .map(|group| {
+                    let mut line = statement.clone();
+                    for reference in group.iter() {
+                        line.push(format!("S{}", reference.property));
+                        line.push(Self::as_qs_value(&reference.value));
+                    }
+                    line
+                })
+                .collect()
+        };
+        if let Some(comment) = &self.comment {
+            let comment = comment.replace("*/", "* /").replace(['\t', '\n'], " ");
+            lines
+                .iter_mut()
+                .for_each(|line| line.push(format!("/* {comment} */")));
+        }
+        lines.into_iter().map(|line| line.join("\t")).collect()
+    }
+
+    fn as_qs_value(value: &WikidataCommandValue) -> String {
+        match value {
+            WikidataCommandValue::String(s) => format!("\"{}\"", Self::qs_escape(s)),
+            WikidataCommandValue::Item(q) => format!("Q{q}"),
+            WikidataCommandValue::Time { time, precision } => format!("{time}/{precision}"),
+            WikidataCommandValue::Location(cl) => format!("@{}/{}", cl.lat, cl.lon),
+            WikidataCommandValue::MonolingualText { language, text } => {
+                format!("{language}:\"{}\"", Self::qs_escape(text))
+            }
+        }
+    }
+
+    /// Tabs and newlines would break the QuickStatements line, and quotes the quoted value
+    fn qs_escape(s: &str) -> String {
+        s.replace(['\t', '\n', '\r'], " ").replace('"', "\\\"")
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -268,4 +432,102 @@ mod tests {
             "rank_as_str failed"
         );
     }
+
+    fn qs_command(item_id: usize, property: usize, value: WikidataCommandValue) -> WikidataCommand {
+        WikidataCommand {
+            item_id,
+            what: WikidataCommandWhat::Property(property),
+            value,
+            references: vec![],
+            qualifiers: vec![],
+            comment: None,
+            rank: None,
+        }
+    }
+
+    #[test]
+    fn test_as_datavalue_time_monolingual() {
+        let value = WikidataCommandValue::Time {
+            time: "+1967-01-17T00:00:00Z".to_string(),
+            precision: 11,
+        };
+        assert_eq!(
+            WikidataCommand::as_datavalue(&value),
+            json!({"value":{"time":"+1967-01-17T00:00:00Z","timezone":0,"before":0,"after":0,"precision":11,"calendarmodel":"http://www.wikidata.org/entity/Q1985727"},"type":"time"})
+        );
+        let value = WikidataCommandValue::MonolingualText {
+            language: "en".to_string(),
+            text: "foo".to_string(),
+        };
+        assert_eq!(
+            WikidataCommand::as_datavalue(&value),
+            json!({"value":{"text":"foo","language":"en"},"type":"monolingualtext"})
+        );
+    }
+
+    #[test]
+    fn test_to_quickstatements_v1() {
+        let source = vec![WikidataCommandPropertyValue {
+            property: 248,
+            value: WikidataCommandValue::Item(123),
+        }];
+        let mut c1 = qs_command(1, 31, WikidataCommandValue::Item(5));
+        c1.references.push(source.clone());
+        let c2 = qs_command(
+            0,
+            1476,
+            WikidataCommandValue::MonolingualText {
+                language: "en".to_string(),
+                text: "A\ttitle".to_string(),
+            },
+        );
+        let mut c3 = qs_command(
+            1,
+            569,
+            WikidataCommandValue::Time {
+                time: "+1967-01-17T00:00:00Z".to_string(),
+                precision: 11,
+            },
+        );
+        c3.comment = Some("Mix'n'match".to_string());
+        let mut c4 = qs_command(1, 31, WikidataCommandValue::Item(5)); // Merged with c1
+        c4.references.push(source);
+        c4.qualifiers.push(WikidataCommandPropertyValue {
+            property: 625,
+            value: WikidataCommandValue::Location(CoordinateLocation {
+                lat: 1.5,
+                lon: -2.0,
+            }),
+        });
+        let qs = to_quickstatements_v1(&[c1, c2, c3, c4]);
+        assert_eq!(
+            qs,
+            "Q1\tP31\tQ5\tP625\t@1.5/-2\tS248\tQ123\n\
+             Q1\tP569\t+1967-01-17T00:00:00Z/11\t/* Mix'n'match */\n\
+             CREATE\n\
+             LAST\tP1476\ten:\"A title\""
+        );
+        for line in qs.split('\n') {
+            validate_quickstatements_v1_line(line).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_as_qs_value_escapes() {
+        let value = WikidataCommandValue::String("say \"hi\"\tnow".to_string());
+        assert_eq!(WikidataCommand::as_qs_value(&value), r#""say \"hi\" now""#);
+    }
+
+    #[test]
+    fn test_validate_quickstatements_v1_line() {
+        assert!(validate_quickstatements_v1_line("CREATE").is_ok());
+        assert!(validate_quickstatements_v1_line("Q1\tP31\tQ5").is_ok());
+        assert!(validate_quickstatements_v1_line("Q1\tP31\tQ5\tS248\tQ123\t/* foo */").is_ok());
+        assert!(validate_quickstatements_v1_line("Q1\tP31").is_err());
+        assert!(validate_quickstatements_v1_line("Q1\tP31\tQ5\tS248").is_err());
+        assert!(validate_quickstatements_v1_line("X1\tP31\tQ5").is_err());
+        assert!(validate_quickstatements_v1_line("Q1\tQ31\tQ5").is_err());
+        assert!(validate_quickstatements_v1_line("Q1\tP31\tQ5\tX248\tQ123").is_err());
+        assert!(validate_quickstatements_v1_line("Q1\tP31\t").is_err());
+    }
 }